@@ -0,0 +1,155 @@
+use std::fs;
+use std::path::Path;
+use std::process;
+
+use super::link::{self, Config};
+use super::{parse_number, Format, Object, Parser};
+
+fn usage() -> ! {
+    eprintln!("usage: asm <source> [-o <file>] [--format raw|ines|hex|srec|prg] [--at <addr>] [--fill <byte>] [--chr <file>] [--listing <file>] [--dbg <file>] [--symbols <file>] [--object]");
+    process::exit(1);
+}
+
+fn link_usage() -> ! {
+    eprintln!("usage: link <object>... -C <config> [-o <file>]");
+    process::exit(1);
+}
+
+fn fail(msg: String) -> ! {
+    eprintln!("{}", msg);
+    process::exit(1);
+}
+
+// `asm <source> [-o <file>] [--format raw|ines|hex|srec|prg] [--at <addr>] [--fill <byte>] [--chr <file>]
+// [--listing <file>] [--dbg <file>] [--symbols <file>] [--object]`. the output goes next to the source with the
+// format's extension unless `-o` says otherwise; `--at` is where the program starts if it doesn't begin with an
+// .org, `--chr` is the chr rom for an iNES image, `--listing` is where to write a listing of the source with its
+// addresses, bytes and cycle counts, `--dbg` and `--symbols` are where to write the source map and symbols the
+// debugger reads, and `--object` makes an object file for `link` instead of an image
+pub fn main(args: &[String]) {
+    let mut source = None;
+    let mut output = None;
+    let mut format = Format::Raw;
+    let mut origin = 0;
+    let mut fill = 0;
+    let mut chr = None;
+    let mut listing = None;
+    let mut dbg = None;
+    let mut symbols = None;
+    let mut object = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output = Some(args.next().unwrap_or_else(|| usage()).clone()),
+            "--format" => format = args.next().and_then(|name| Format::from_name(name)).unwrap_or_else(|| usage()),
+            "--at" => {
+                origin = match args.next().and_then(|addr| parse_number(addr)) {
+                    Some(addr) if (0..=0xffff).contains(&addr) => addr as u16,
+                    _ => usage(),
+                }
+            }
+            "--fill" => {
+                fill = match args.next().and_then(|byte| parse_number(byte)) {
+                    Some(byte) if (0..=0xff).contains(&byte) => byte as u8,
+                    _ => usage(),
+                }
+            }
+            "--chr" => chr = Some(args.next().unwrap_or_else(|| usage()).clone()),
+            "--listing" => listing = Some(args.next().unwrap_or_else(|| usage()).clone()),
+            "--dbg" => dbg = Some(args.next().unwrap_or_else(|| usage()).clone()),
+            "--symbols" => symbols = Some(args.next().unwrap_or_else(|| usage()).clone()),
+            "--object" => object = true,
+            _ if source.is_none() => source = Some(arg.clone()),
+            _ => usage(),
+        }
+    }
+
+    let source = source.unwrap_or_else(|| usage());
+
+    // an object's addresses aren't known until it's linked
+    if object && (dbg.is_some() || symbols.is_some()) {
+        fail(String::from("--dbg and --symbols need an image, not an object"));
+    }
+
+    let mut parser = Parser::new(origin);
+    let result = match object {
+        true => parser.assemble_object_file(&source).map(Some),
+        false => parser.assemble_file(&source).map(|_| None),
+    };
+
+    for diagnostic in parser.diagnostics.iter() {
+        eprintln!("{}\n", diagnostic.render());
+    }
+
+    let object = match result {
+        Ok(object) => object,
+        Err(_) => process::exit(1),
+    };
+
+    if let Some(path) = listing {
+        fs::write(&path, parser.listing()).unwrap_or_else(|err| fail(format!("{}: {}", path, err)));
+    }
+
+    if let Some(path) = dbg {
+        fs::write(&path, parser.source_map()).unwrap_or_else(|err| fail(format!("{}: {}", path, err)));
+    }
+
+    if let Some(path) = symbols {
+        fs::write(&path, parser.symbol_file()).unwrap_or_else(|err| fail(format!("{}: {}", path, err)));
+    }
+
+    if let Some(object) = object {
+        let output = output.unwrap_or_else(|| Path::new(&source).with_extension("o").display().to_string());
+        fs::write(&output, object.save()).unwrap_or_else(|err| fail(format!("{}: {}", output, err)));
+
+        return;
+    }
+
+    let chr = match chr {
+        Some(path) => fs::read(&path).unwrap_or_else(|err| fail(format!("{}: {}", path, err))),
+        None => Vec::new(),
+    };
+
+    let bytes = format.write(&parser.chunks, &parser.ines, &chr, fill).unwrap_or_else(|err| fail(err));
+
+    let output = output.unwrap_or_else(|| Path::new(&source).with_extension(format.extension()).display().to_string());
+    fs::write(&output, bytes).unwrap_or_else(|err| fail(format!("{}: {}", output, err)));
+}
+
+// `link <object>... -C <config> [-o <file>]`: places the objects' segments in memory as the config says and writes
+// out the memory areas, by default next to the first object
+pub fn link(args: &[String]) {
+    let mut paths = Vec::new();
+    let mut config = None;
+    let mut output = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-C" => config = Some(args.next().unwrap_or_else(|| link_usage()).clone()),
+            "-o" => output = Some(args.next().unwrap_or_else(|| link_usage()).clone()),
+            _ => paths.push(arg.clone()),
+        }
+    }
+
+    let config = config.unwrap_or_else(|| link_usage());
+    if paths.is_empty() {
+        link_usage();
+    }
+
+    let text = fs::read_to_string(&config).unwrap_or_else(|err| fail(format!("{}: {}", config, err)));
+    let config = Config::parse(&text).unwrap_or_else(|err| fail(format!("{}: {}", config, err)));
+
+    let objects: Vec<Object> = paths.iter()
+        .map(|path| {
+            let bytes = fs::read(path).unwrap_or_else(|err| fail(format!("{}: {}", path, err)));
+            Object::load(&bytes).unwrap_or_else(|err| fail(format!("{}: {}", path, err)))
+        })
+        .collect();
+
+    let linked = link::link(&objects, &config).unwrap_or_else(|errors| fail(errors.join("\n")));
+
+    let output = output.unwrap_or_else(|| Path::new(&paths[0]).with_extension("bin").display().to_string());
+    fs::write(&output, linked.bytes()).unwrap_or_else(|err| fail(format!("{}: {}", output, err)));
+}
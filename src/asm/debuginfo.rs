@@ -0,0 +1,60 @@
+use std::collections::HashMap;
+use std::fs;
+
+use super::{Line, Placement};
+
+// the source map the debugger reads: `$<addr> <file>:<line>` for every instruction. files are written out in full
+// so the map can be read from anywhere, and lines from `assemble` with no file behind them are left out
+pub fn source_map(lines: &[Line], placements: &[Placement], encoded: &[Vec<u8>]) -> String {
+    let mut text = String::new();
+
+    for (i, line) in lines.iter().enumerate() {
+        let file = match line.source.file {
+            Some(ref file) if line.directive.is_none() && !encoded[i].is_empty() => file,
+            _ => continue,
+        };
+
+        let file = fs::canonicalize(file.as_str()).map_or(file.to_string(), |path| path.display().to_string());
+        text.push_str(&format!("${:04x} {}:{}\n", placements[i].0, file, line.source.line));
+    }
+
+    text
+}
+
+// `name = value` for every symbol, sorted, which is what the debugger takes for --symbols
+pub fn symbols(symbols: &HashMap<String, i64>) -> String {
+    // anonymous labels only have the numbers the reader gave them
+    let mut names: Vec<&String> = symbols.keys().filter(|name| !name.starts_with(':')).collect();
+    names.sort();
+
+    names.iter()
+        .map(|name| match symbols[*name] {
+            value @ 0..=0xffff => format!("{} = ${:04x}\n", name, value),
+            value => format!("{} = {}\n", name, value),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+
+    use asm::Parser;
+
+    #[test]
+    fn source_map_and_symbols() {
+        let dir = env::temp_dir().join("sixty-five-oh-too-asm-debuginfo");
+        fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("main.s");
+        fs::write(&path, "count = 3\n.org $6000\nstart:  ldx #count\n        .byte 1\n        rts\n").unwrap();
+
+        let mut parser = Parser::new(0);
+        parser.assemble_file(&path.display().to_string()).unwrap();
+
+        let file = fs::canonicalize(&path).unwrap().display().to_string();
+        assert_eq!(parser.source_map(), format!("$6000 {0}:3\n$6003 {0}:5\n", file));
+        assert_eq!(parser.symbol_file(), "count = $0003\nstart = $6000\n");
+    }
+}
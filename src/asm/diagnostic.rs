@@ -0,0 +1,190 @@
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+// 0-based columns, in characters, end exclusive
+pub type Span = (usize, usize);
+
+// the line something was read from
+#[derive(Clone, Debug, PartialEq)]
+pub struct Source {
+    // the file, if the source didn't come straight from `assemble`
+    pub file: Option<Rc<String>>,
+    // 1-based
+    pub line: usize,
+    pub text: Rc<String>,
+    // the part of the line that isn't indentation or a comment
+    pub code: Span,
+}
+
+impl Source {
+    // the columns `part` covers, if it's a slice of this line's text. anything else (a line out of a macro, say)
+    // covers the code of the whole line
+    pub fn span(&self, part: &str) -> Span {
+        let base = self.text.as_ptr() as usize;
+        let start = part.as_ptr() as usize;
+
+        if start < base || start + part.len() > base + self.text.len() {
+            return self.code;
+        }
+
+        let col = self.text[..start - base].chars().count();
+        (col, col + part.chars().count())
+    }
+
+    // where `needle` first shows up inside `within`
+    pub fn find(&self, within: Span, needle: &str) -> Option<Span> {
+        let chars: Vec<char> = self.text.chars().collect();
+        let needle: Vec<char> = needle.chars().collect();
+        if needle.is_empty() || within.1 > chars.len() || within.1 < within.0 + needle.len() {
+            return None;
+        }
+
+        (within.0..within.1 - needle.len() + 1)
+            .find(|&col| chars[col..col + needle.len()] == needle[..])
+            .map(|col| (col, col + needle.len()))
+    }
+
+    pub fn error(&self, span: Span, message: String) -> Diagnostic {
+        self.diagnostic(Severity::Error, span, message)
+    }
+
+    pub fn warning(&self, span: Span, message: String) -> Diagnostic {
+        self.diagnostic(Severity::Warning, span, message)
+    }
+
+    fn diagnostic(&self, severity: Severity, span: Span, message: String) -> Diagnostic {
+        Diagnostic {
+            severity: severity,
+            file: self.file.clone(),
+            line: self.line,
+            span: span,
+            message: message,
+            text: self.text.clone(),
+        }
+    }
+}
+
+// an error or warning about part of a line
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub file: Option<Rc<String>>,
+    // 1-based
+    pub line: usize,
+    pub span: Span,
+    pub message: String,
+    // the whole line, to quote
+    pub text: Rc<String>,
+}
+
+impl Diagnostic {
+    // for a file that couldn't be read at all
+    pub fn about_file(file: &str, message: String) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            file: Some(Rc::new(String::from(file))),
+            line: 0,
+            span: (0, 0),
+            message: message,
+            text: Rc::new(String::new()),
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    // the message, then the line it's about with the span underlined:
+    //
+    //     error: undefined symbol 'lop'
+    //      --> main.s:3:9
+    //       |
+    //     3 |     bne lop
+    //       |         ^^^
+    pub fn render(&self) -> String {
+        if self.line == 0 {
+            return format!("{}: {}\n --> {}", self.severity_name(), self.message, self.location());
+        }
+
+        let number = self.line.to_string();
+        let gutter = " ".repeat(number.len());
+
+        // tabs would throw the carets off, whatever width the terminal gives them
+        let text: String = self.text.chars().map(|c| if c == '\t' { ' ' } else { c }).collect();
+        let carets = "^".repeat((self.span.1 - self.span.0).max(1));
+
+        format!("{}: {}\n{}--> {}\n{} |\n{} | {}\n{} | {}{}",
+                self.severity_name(),
+                self.message,
+                gutter,
+                self.location(),
+                gutter,
+                number,
+                text.trim_end(),
+                gutter,
+                " ".repeat(self.span.0),
+                carets)
+    }
+
+    fn severity_name(&self) -> &'static str {
+        match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+
+    // `file:line:column`, with the column 1-based like an editor's
+    fn location(&self) -> String {
+        let file = self.file.as_ref().map_or("<source>", |file| file.as_str());
+        match self.line {
+            0 => String::from(file),
+            line => format!("{}:{}:{}", file, line, self.span.0 + 1),
+        }
+    }
+}
+
+// on one line, like `main.s:3:9: error: undefined symbol 'lop'`
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.location(), self.severity_name(), self.message)
+    }
+}
+
+// in the order they'd be come across reading the source, rather than the order the passes found them in
+pub fn sort(diagnostics: &mut [Diagnostic]) {
+    diagnostics.sort_by(|a, b| (&a.file, a.line, a.span.0).cmp(&(&b.file, b.line, b.span.0)));
+}
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+
+    use super::Source;
+
+    #[test]
+    fn spans_and_rendering() {
+        let text = Rc::new(String::from("\tbne lop ; again"));
+        let source = Source {
+            file: Some(Rc::new(String::from("main.s"))),
+            line: 12,
+            text: text.clone(),
+            code: (1, 8),
+        };
+
+        assert_eq!(source.span(&text[5..8]), (5, 8));
+        assert_eq!(source.span("elsewhere"), (1, 8));
+        assert_eq!(source.find((4, 8), "lop"), Some((5, 8)));
+        assert_eq!(source.find((4, 8), "again"), None);
+
+        let err = source.error((5, 8), String::from("undefined symbol 'lop'"));
+        assert_eq!(err.to_string(), "main.s:12:6: error: undefined symbol 'lop'");
+        assert_eq!(err.render(),
+                   "error: undefined symbol 'lop'\n  --> main.s:12:6\n   |\n12 |  bne lop ; again\n   |      ^^^");
+    }
+}
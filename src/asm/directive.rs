@@ -0,0 +1,301 @@
+use super::expr::Expr;
+use super::token::{self, Token, Tokens};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Data {
+    Expr(Expr),
+    // a string or an .incbin
+    Bytes(Vec<u8>),
+}
+
+// the iNES header fields, as NESASM sets them
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InesField {
+    // .inesprg, in 16k banks
+    Prg,
+    // .ineschr, in 8k banks
+    Chr,
+    // .inesmap
+    Mapper,
+    // .inesmir: the low four bits of flags 6 (mirroring, battery, trainer, four-screen)
+    Mirroring,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Directive {
+    Org(Expr),
+    // .byte, .db and .asciiz
+    Byte(Vec<Data>),
+    // .word and .dw, little endian
+    Word(Vec<Expr>),
+    // big endian words
+    Dbyt(Vec<Expr>),
+    LoBytes(Vec<Expr>),
+    HiBytes(Vec<Expr>),
+    // .res, .ds and .fill: how many bytes, then what to fill them with (zero if it's left out)
+    Reserve(Expr, Option<Expr>),
+    Align(Expr, Option<Expr>),
+    // .incbin and .include are resolved while the source is read, since they need to know which file they're in
+    Incbin {
+        path: String,
+        offset: Option<Expr>,
+        len: Option<Expr>,
+    },
+    Include(String),
+    // `name = value`
+    Equate(String, Expr),
+    // `.assert cond, error|warning[, "message"]`, checked once every label's known
+    Assert {
+        cond: Expr,
+        fatal: bool,
+        msg: Option<String>,
+    },
+    Ines(InesField, Expr),
+    // .segment "NAME", or .code, .rodata, .bss and .zeropage for the usual ones
+    Segment(String),
+    // the names, and whether they're in zero page (.importzp)
+    Import(Vec<String>, bool),
+    // symbols, so the reader resolves them like any other reference
+    Export(Vec<Expr>),
+}
+
+impl Directive {
+    pub fn exprs_mut(&mut self) -> Vec<&mut Expr> {
+        match *self {
+            Directive::Org(ref mut expr) |
+            Directive::Equate(_, ref mut expr) |
+            Directive::Assert { cond: ref mut expr, .. } |
+            Directive::Ines(_, ref mut expr) => vec![expr],
+            Directive::Byte(ref mut data) => {
+                data.iter_mut()
+                    .filter_map(|data| match *data {
+                        Data::Expr(ref mut expr) => Some(expr),
+                        Data::Bytes(_) => None,
+                    })
+                    .collect()
+            }
+            Directive::Word(ref mut exprs) |
+            Directive::Dbyt(ref mut exprs) |
+            Directive::LoBytes(ref mut exprs) |
+            Directive::HiBytes(ref mut exprs) => exprs.iter_mut().collect(),
+            Directive::Reserve(ref mut count, ref mut fill) | Directive::Align(ref mut count, ref mut fill) => {
+                let mut exprs = vec![count];
+                exprs.extend(fill.as_mut());
+
+                exprs
+            }
+            Directive::Incbin { ref mut offset, ref mut len, .. } => offset.iter_mut().chain(len.iter_mut()).collect(),
+            Directive::Export(ref mut names) => names.iter_mut().collect(),
+            Directive::Include(_) | Directive::Segment(_) | Directive::Import(..) => Vec::new(),
+        }
+    }
+}
+
+// `name` is the directive with its leading dot, in any case
+pub fn parse(name: &str, text: &str) -> Result<Directive, String> {
+    let tokens = token::tokenize(text)?;
+    let mut args = Args { tokens: Tokens::new(&tokens) };
+
+    let directive = match name.to_lowercase().as_str() {
+        ".org" => Directive::Org(args.expr()?),
+        ".byte" | ".db" => Directive::Byte(args.data()?),
+        ".asciiz" => {
+            let mut data = args.data()?;
+            data.push(Data::Bytes(vec![0]));
+
+            Directive::Byte(data)
+        }
+        ".word" | ".dw" => Directive::Word(args.exprs()?),
+        ".dbyt" => Directive::Dbyt(args.exprs()?),
+        ".lobytes" => Directive::LoBytes(args.exprs()?),
+        ".hibytes" => Directive::HiBytes(args.exprs()?),
+        ".res" | ".ds" | ".fill" => {
+            let count = args.expr()?;
+            Directive::Reserve(count, args.optional()?)
+        }
+        ".align" => {
+            let boundary = args.expr()?;
+            Directive::Align(boundary, args.optional()?)
+        }
+        ".incbin" => {
+            let path = args.string()?;
+            let offset = args.optional()?;
+
+            Directive::Incbin {
+                path: path,
+                offset: offset,
+                len: args.optional()?,
+            }
+        }
+        ".include" => Directive::Include(args.string()?),
+        ".assert" => {
+            let cond = args.expr()?;
+            args.tokens.expect(Token::Comma)?;
+
+            let fatal = match args.tokens.next() {
+                Some(&Token::Ident(ref action)) if action.eq_ignore_ascii_case("error") => true,
+                Some(&Token::Ident(ref action)) if action.eq_ignore_ascii_case("warning") => false,
+                _ => return Err(String::from(".assert needs 'error' or 'warning' after the condition")),
+            };
+
+            let msg = match args.tokens.peek() {
+                Some(&Token::Comma) => {
+                    args.tokens.next();
+                    Some(args.string()?)
+                }
+                _ => None,
+            };
+
+            Directive::Assert {
+                cond: cond,
+                fatal: fatal,
+                msg: msg,
+            }
+        }
+        ".inesprg" => Directive::Ines(InesField::Prg, args.expr()?),
+        ".ineschr" => Directive::Ines(InesField::Chr, args.expr()?),
+        ".inesmap" => Directive::Ines(InesField::Mapper, args.expr()?),
+        ".inesmir" => Directive::Ines(InesField::Mirroring, args.expr()?),
+        ".segment" => Directive::Segment(args.string()?),
+        ".code" => Directive::Segment(String::from("CODE")),
+        ".rodata" => Directive::Segment(String::from("RODATA")),
+        ".bss" => Directive::Segment(String::from("BSS")),
+        ".zeropage" => Directive::Segment(String::from("ZEROPAGE")),
+        ".import" | ".importzp" => Directive::Import(args.names()?, name.eq_ignore_ascii_case(".importzp")),
+        ".export" => Directive::Export(args.names()?.into_iter().map(Expr::Symbol).collect()),
+        _ => return Err(format!("unknown directive '{}'", name)),
+    };
+
+    match args.tokens.peek() {
+        None => Ok(directive),
+        Some(token) => Err(format!("unexpected {} after {}", token.describe(), name)),
+    }
+}
+
+// the bytes a string stands for: each character has to be a single byte
+pub fn string_bytes(text: &str) -> Result<Vec<u8>, String> {
+    text.chars()
+        .map(|c| match c as u32 {
+            code @ 0..=0xff => Ok(code as u8),
+            _ => Err(format!("'{}' doesn't fit in a byte", c)),
+        })
+        .collect()
+}
+
+struct Args<'a> {
+    tokens: Tokens<'a>,
+}
+
+impl<'a> Args<'a> {
+    fn expr(&mut self) -> Result<Expr, String> {
+        Expr::parse(&mut self.tokens)
+    }
+
+    fn exprs(&mut self) -> Result<Vec<Expr>, String> {
+        let mut exprs = vec![self.expr()?];
+
+        while self.tokens.peek() == Some(&Token::Comma) {
+            self.tokens.next();
+            exprs.push(self.expr()?);
+        }
+
+        Ok(exprs)
+    }
+
+    fn names(&mut self) -> Result<Vec<String>, String> {
+        let mut names = Vec::new();
+
+        loop {
+            match self.tokens.next() {
+                Some(&Token::Ident(ref name)) => names.push(name.clone()),
+                Some(token) => return Err(format!("expected a name but found {}", token.describe())),
+                None => return Err(String::from("expected a name")),
+            }
+
+            match self.tokens.peek() {
+                Some(&Token::Comma) => self.tokens.next(),
+                _ => return Ok(names),
+            };
+        }
+    }
+
+    // values and strings, mixed freely
+    fn data(&mut self) -> Result<Vec<Data>, String> {
+        let mut data = Vec::new();
+
+        loop {
+            match self.tokens.peek() {
+                Some(&Token::Str(ref text)) => {
+                    self.tokens.next();
+                    data.push(Data::Bytes(string_bytes(text)?));
+                }
+                _ => data.push(Data::Expr(self.expr()?)),
+            }
+
+            match self.tokens.peek() {
+                Some(&Token::Comma) => self.tokens.next(),
+                _ => return Ok(data),
+            };
+        }
+    }
+
+    // `, expr` if there's more to the line
+    fn optional(&mut self) -> Result<Option<Expr>, String> {
+        match self.tokens.peek() {
+            Some(&Token::Comma) => {
+                self.tokens.next();
+                Ok(Some(self.expr()?))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        match self.tokens.next() {
+            Some(&Token::Str(ref text)) => Ok(text.clone()),
+            Some(token) => Err(format!("expected a string but found {}", token.describe())),
+            None => Err(String::from("expected a string")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse, Data, Directive, InesField};
+    use asm::expr::{BinaryOp, Expr};
+
+    #[test]
+    fn arguments() {
+        assert_eq!(parse(".BYTE", "1, \"ab\", 'c'").unwrap(),
+                   Directive::Byte(vec![Data::Expr(Expr::Number(1)), Data::Bytes(vec![0x61, 0x62]), Data::Expr(Expr::Number(0x63))]));
+        assert_eq!(parse(".asciiz", "\"hi\"").unwrap(),
+                   Directive::Byte(vec![Data::Bytes(vec![0x68, 0x69]), Data::Bytes(vec![0])]));
+        assert_eq!(parse(".res", "4").unwrap(), Directive::Reserve(Expr::Number(4), None));
+        assert_eq!(parse(".fill", "4, $ea").unwrap(), Directive::Reserve(Expr::Number(4), Some(Expr::Number(0xea))));
+        assert_eq!(parse(".incbin", "\"chr.bin\", 16").unwrap(),
+                   Directive::Incbin {
+                       path: String::from("chr.bin"),
+                       offset: Some(Expr::Number(16)),
+                       len: None,
+                   });
+
+        assert!(parse(".word", "").unwrap_err().contains("expected an expression"));
+        assert!(parse(".include", "foo").unwrap_err().contains("expected a string"));
+        assert!(parse(".org", "1 2").unwrap_err().contains("unexpected number 2 after .org"));
+        assert!(parse(".byte", "\"\u{263a}\"").unwrap_err().contains("doesn't fit in a byte"));
+        assert_eq!(parse(".assert", "* < $c000, warning, \"too big\"").unwrap(),
+                   Directive::Assert {
+                       cond: Expr::Binary(BinaryOp::Lt, Box::new(Expr::Pc), Box::new(Expr::Number(0xc000))),
+                       fatal: false,
+                       msg: Some(String::from("too big")),
+                   });
+        assert!(parse(".assert", "1, fatal").unwrap_err().contains("'error' or 'warning'"));
+        assert_eq!(parse(".INESMAP", "4").unwrap(), Directive::Ines(InesField::Mapper, Expr::Number(4)));
+        assert_eq!(parse(".code", "").unwrap(), Directive::Segment(String::from("CODE")));
+        assert_eq!(parse(".segment", "\"VECTORS\"").unwrap(), Directive::Segment(String::from("VECTORS")));
+        assert_eq!(parse(".importzp", "ptr, tmp").unwrap(),
+                   Directive::Import(vec![String::from("ptr"), String::from("tmp")], true));
+        assert!(parse(".export", "main, 1").unwrap_err().contains("expected a name but found number 1"));
+        assert!(parse(".bogus", "").unwrap_err().contains("unknown directive"));
+    }
+}
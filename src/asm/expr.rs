@@ -0,0 +1,333 @@
+use std::collections::HashMap;
+
+use super::token::{Token, Tokens};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+    BitNot,
+    // <value and >value
+    LowByte,
+    HighByte,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BinaryOp {
+    Mul,
+    Div,
+    Mod,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    BitAnd,
+    BitXor,
+    BitOr,
+    And,
+    Or,
+}
+
+impl BinaryOp {
+    fn precedence(&self) -> u8 {
+        match *self {
+            BinaryOp::Or => 1,
+            BinaryOp::And => 2,
+            BinaryOp::BitOr => 3,
+            BinaryOp::BitXor => 4,
+            BinaryOp::BitAnd => 5,
+            BinaryOp::Eq | BinaryOp::Ne => 6,
+            BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => 7,
+            BinaryOp::Shl | BinaryOp::Shr => 8,
+            BinaryOp::Add | BinaryOp::Sub => 9,
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => 10,
+        }
+    }
+
+    fn from_op(op: &str) -> Option<BinaryOp> {
+        let op = match op {
+            "||" => BinaryOp::Or,
+            "&&" => BinaryOp::And,
+            "|" => BinaryOp::BitOr,
+            "^" => BinaryOp::BitXor,
+            "&" => BinaryOp::BitAnd,
+            "=" | "==" => BinaryOp::Eq,
+            "<>" | "!=" => BinaryOp::Ne,
+            "<" => BinaryOp::Lt,
+            "<=" => BinaryOp::Le,
+            ">" => BinaryOp::Gt,
+            ">=" => BinaryOp::Ge,
+            "<<" => BinaryOp::Shl,
+            ">>" => BinaryOp::Shr,
+            "+" => BinaryOp::Add,
+            "-" => BinaryOp::Sub,
+            "*" => BinaryOp::Mul,
+            "/" => BinaryOp::Div,
+            "%" => BinaryOp::Mod,
+            _ => return None,
+        };
+
+        Some(op)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Number(i64),
+    Symbol(String),
+    // `:+`, `:-`... until the reader swaps it for the anonymous label it means
+    Anon(i32),
+    // `*`, the address of the line it's on
+    Pc,
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    // reads an expression off the front of `tokens`, leaving whatever follows it (an index, a closing
+    // parenthesis...) for the caller. unary operators, `<` and `>` included, bind tighter than any binary one, so
+    // `<table+1` is the low byte of table, plus one
+    pub fn parse(tokens: &mut Tokens) -> Result<Expr, String> {
+        Expr::binary(tokens, 0)
+    }
+
+    // `pc` is what `*` stands for
+    pub fn eval(&self, pc: u16, symbols: &HashMap<String, i64>) -> Result<i64, String> {
+        let val = match *self {
+            Expr::Number(val) => val,
+            Expr::Symbol(ref name) => {
+                match symbols.get(name) {
+                    Some(val) => *val,
+                    None => return Err(format!("undefined symbol '{}'", name)),
+                }
+            }
+            Expr::Anon(_) => return Err(String::from("anonymous label reference outside of any source")),
+            Expr::Pc => pc as i64,
+            Expr::Unary(op, ref operand) => {
+                let val = operand.eval(pc, symbols)?;
+
+                match op {
+                    UnaryOp::Neg => val.checked_neg().ok_or_else(overflow)?,
+                    UnaryOp::Not => (val == 0) as i64,
+                    UnaryOp::BitNot => !val,
+                    UnaryOp::LowByte => val & 0xff,
+                    UnaryOp::HighByte => (val >> 8) & 0xff,
+                }
+            }
+            Expr::Binary(BinaryOp::And, ref lhs, ref rhs) => {
+                (lhs.eval(pc, symbols)? != 0 && rhs.eval(pc, symbols)? != 0) as i64
+            }
+            Expr::Binary(BinaryOp::Or, ref lhs, ref rhs) => {
+                (lhs.eval(pc, symbols)? != 0 || rhs.eval(pc, symbols)? != 0) as i64
+            }
+            Expr::Binary(op, ref lhs, ref rhs) => {
+                let lhs = lhs.eval(pc, symbols)?;
+                let rhs = rhs.eval(pc, symbols)?;
+
+                match op {
+                    BinaryOp::Mul => lhs.checked_mul(rhs).ok_or_else(overflow)?,
+                    BinaryOp::Div | BinaryOp::Mod if rhs == 0 => return Err(String::from("division by zero")),
+                    BinaryOp::Div => lhs.checked_div(rhs).ok_or_else(overflow)?,
+                    BinaryOp::Mod => lhs.checked_rem(rhs).ok_or_else(overflow)?,
+                    BinaryOp::Add => lhs.checked_add(rhs).ok_or_else(overflow)?,
+                    BinaryOp::Sub => lhs.checked_sub(rhs).ok_or_else(overflow)?,
+                    BinaryOp::Shl | BinaryOp::Shr if !(0..64).contains(&rhs) => {
+                        return Err(format!("can't shift by {}", rhs))
+                    }
+                    BinaryOp::Shl => lhs << rhs,
+                    BinaryOp::Shr => lhs >> rhs,
+                    BinaryOp::Lt => (lhs < rhs) as i64,
+                    BinaryOp::Le => (lhs <= rhs) as i64,
+                    BinaryOp::Gt => (lhs > rhs) as i64,
+                    BinaryOp::Ge => (lhs >= rhs) as i64,
+                    BinaryOp::Eq => (lhs == rhs) as i64,
+                    BinaryOp::Ne => (lhs != rhs) as i64,
+                    BinaryOp::BitAnd => lhs & rhs,
+                    BinaryOp::BitXor => lhs ^ rhs,
+                    BinaryOp::BitOr => lhs | rhs,
+                    BinaryOp::And | BinaryOp::Or => unreachable!(),
+                }
+            }
+        };
+
+        Ok(val)
+    }
+
+    // the value, if it doesn't depend on any labels or where it's assembled
+    pub fn constant(&self) -> Option<i64> {
+        self.constant_in(&HashMap::new())
+    }
+
+    // the value, if it doesn't depend on where it's assembled or anything that isn't in `symbols`
+    pub fn constant_in(&self, symbols: &HashMap<String, i64>) -> Option<i64> {
+        match self.mentions_pc() {
+            true => None,
+            false => self.eval(0, symbols).ok(),
+        }
+    }
+
+    pub fn mentions_pc(&self) -> bool {
+        match *self {
+            Expr::Number(_) | Expr::Symbol(_) | Expr::Anon(_) => false,
+            Expr::Pc => true,
+            Expr::Unary(_, ref operand) => operand.mentions_pc(),
+            Expr::Binary(_, ref lhs, ref rhs) => lhs.mentions_pc() || rhs.mentions_pc(),
+        }
+    }
+
+    // every symbol the expression uses
+    pub fn symbols(&self) -> Vec<&str> {
+        match *self {
+            Expr::Symbol(ref name) => vec![name.as_str()],
+            Expr::Number(_) | Expr::Anon(_) | Expr::Pc => Vec::new(),
+            Expr::Unary(_, ref operand) => operand.symbols(),
+            Expr::Binary(_, ref lhs, ref rhs) => {
+                let mut symbols = lhs.symbols();
+                symbols.extend(rhs.symbols());
+
+                symbols
+            }
+        }
+    }
+
+    // calls `f` on every label reference (symbols and anonymous ones), which it can rewrite in place
+    pub fn resolve<F>(&mut self, f: &mut F) -> Result<(), String>
+        where F: FnMut(&mut Expr) -> Result<(), String>
+    {
+        match *self {
+            Expr::Symbol(_) | Expr::Anon(_) => f(self),
+            Expr::Number(_) | Expr::Pc => Ok(()),
+            Expr::Unary(_, ref mut operand) => operand.resolve(f),
+            Expr::Binary(_, ref mut lhs, ref mut rhs) => {
+                lhs.resolve(f)?;
+                rhs.resolve(f)
+            }
+        }
+    }
+
+    // precedence climbing: only binds operators that are at least as tight as `min_precedence`
+    fn binary(tokens: &mut Tokens, min_precedence: u8) -> Result<Expr, String> {
+        let mut lhs = Expr::unary(tokens)?;
+
+        loop {
+            let op = match tokens.peek() {
+                Some(&Token::Op(op)) => BinaryOp::from_op(op),
+                _ => None,
+            };
+
+            let op = match op {
+                Some(op) if op.precedence() >= min_precedence => op,
+                _ => break,
+            };
+
+            tokens.next();
+
+            let rhs = Expr::binary(tokens, op.precedence() + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+
+        Ok(lhs)
+    }
+
+    fn unary(tokens: &mut Tokens) -> Result<Expr, String> {
+        let op = match tokens.peek() {
+            Some(&Token::Op("-")) => UnaryOp::Neg,
+            Some(&Token::Op("!")) => UnaryOp::Not,
+            Some(&Token::Op("~")) => UnaryOp::BitNot,
+            Some(&Token::Op("<")) => UnaryOp::LowByte,
+            Some(&Token::Op(">")) => UnaryOp::HighByte,
+            Some(&Token::Op("+")) => {
+                tokens.next();
+                return Expr::unary(tokens);
+            }
+            _ => return Expr::primary(tokens),
+        };
+
+        tokens.next();
+
+        Ok(Expr::Unary(op, Box::new(Expr::unary(tokens)?)))
+    }
+
+    fn primary(tokens: &mut Tokens) -> Result<Expr, String> {
+        match tokens.next() {
+            Some(&Token::Number(val)) => Ok(Expr::Number(val)),
+            Some(&Token::Ident(ref name)) => Ok(Expr::Symbol(name.clone())),
+            Some(&Token::Anon(n)) => Ok(Expr::Anon(n)),
+            // in operand position `*` can't be multiplication
+            Some(&Token::Op("*")) => Ok(Expr::Pc),
+            Some(&Token::LParen) => {
+                let expr = Expr::parse(tokens)?;
+                tokens.expect(Token::RParen)?;
+
+                Ok(expr)
+            }
+            Some(token) => Err(format!("expected an expression but found {}", token.describe())),
+            None => Err(String::from("expected an expression")),
+        }
+    }
+}
+
+fn overflow() -> String {
+    String::from("the value is too big to work out")
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::Expr;
+    use asm::token::{self, Tokens};
+
+    fn eval(text: &str) -> Result<i64, String> {
+        let tokens = token::tokenize(text)?;
+        let mut cursor = Tokens::new(&tokens);
+
+        let expr = Expr::parse(&mut cursor)?;
+        if !cursor.at_end() {
+            return Err(String::from("trailing tokens"));
+        }
+
+        let mut symbols = HashMap::new();
+        symbols.insert(String::from("table"), 0x12f0);
+
+        expr.eval(0x6000, &symbols)
+    }
+
+    #[test]
+    fn operators_and_precedence() {
+        assert_eq!(eval("1 + 2 * 3"), Ok(7));
+        assert_eq!(eval("(1 + 2) * 3"), Ok(9));
+        assert_eq!(eval("$10 | %0001 << 1"), Ok(0x12));
+        assert_eq!(eval("7 % 4 ^ 1"), Ok(2));
+        assert_eq!(eval("$f0 & 15"), Ok(0));
+        assert_eq!(eval("table >> 8"), Ok(0x12));
+        assert_eq!(eval("-1 + ~0"), Ok(-2));
+        assert_eq!(eval("1 < 2 = 1 && 3 <> 4 && !0"), Ok(1));
+        assert_eq!(eval("'a' - 'A'"), Ok(32));
+        assert_eq!(eval("1 / 0"), Err(String::from("division by zero")));
+        assert_eq!(eval("$7fffffffffffffff + 1"), Err(String::from("the value is too big to work out")));
+        assert_eq!(eval("$100000000 * $100000000"), Err(String::from("the value is too big to work out")));
+        assert_eq!(eval("1 << 64"), Err(String::from("can't shift by 64")));
+        assert_eq!(eval("table >> -1"), Err(String::from("can't shift by -1")));
+        assert_eq!(eval("missing + 1"), Err(String::from("undefined symbol 'missing'")));
+    }
+
+    #[test]
+    fn bytes_and_pc() {
+        assert_eq!(eval("<table"), Ok(0xf0));
+        assert_eq!(eval(">table"), Ok(0x12));
+        assert_eq!(eval("<table+16"), Ok(0x100));
+        assert_eq!(eval("<(table+16)"), Ok(0x00));
+        assert_eq!(eval("* + 3"), Ok(0x6003));
+        assert_eq!(eval("* * 2"), Ok(0xc000));
+
+        assert!(eval("1 +").unwrap_err().contains("expected an expression"));
+        assert!(eval("(1 + 2").unwrap_err().contains("expected ')'"));
+    }
+}
@@ -0,0 +1,646 @@
+use std::collections::HashMap;
+
+use super::object::{Object, Value, ZERO_PAGE_SEGMENT};
+use super::parse_number;
+
+// a stretch of the address space that segments are put in, one after another
+#[derive(Clone, Debug, PartialEq)]
+pub struct MemoryArea {
+    pub name: String,
+    pub start: u16,
+    pub size: usize,
+    // if it's given, the area's written out whole, with this wherever nothing went; otherwise only as far as its
+    // last byte
+    pub fill: Option<u8>,
+    // areas in different banks can share addresses. the output has them in bank order
+    pub bank: u8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SegmentKind {
+    // `ro` or `rw`: what's assembled into it goes in the output
+    Data,
+    // `bss` and `zp` only reserve space, so they can't hold anything but .res
+    Bss,
+    ZeroPage,
+}
+
+// which memory area a segment goes in, and how
+#[derive(Clone, Debug, PartialEq)]
+pub struct SegmentRule {
+    pub name: String,
+    pub load: String,
+    pub kind: SegmentKind,
+    // somewhere further on in the area than the segments before it would leave it (for vectors, say)
+    pub start: Option<u16>,
+    pub align: usize,
+}
+
+// what the linker puts where, from a file like:
+//
+//     MEMORY {
+//         ZP:  start = $0000, size = $0100;
+//         RAM: start = $0200, size = $0600;
+//         ROM: start = $8000, size = $8000, fill = $ff;
+//     }
+//
+//     SEGMENTS {
+//         ZEROPAGE: load = ZP;
+//         BSS:      load = RAM;
+//         CODE:     load = ROM;
+//         RODATA:   load = ROM;
+//         VECTORS:  load = ROM, start = $fffa;
+//     }
+//
+// segments go in their areas in the order they're listed, and each object's part of a segment in the order the
+// objects are given. `#` starts a comment
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Config {
+    pub areas: Vec<MemoryArea>,
+    pub segments: Vec<SegmentRule>,
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let tokens = tokenize(text)?;
+        let mut tokens = tokens.iter().peekable();
+        let mut config = Config::default();
+
+        while let Some(&(line, ref block)) = tokens.next() {
+            if block != "MEMORY" && block != "SEGMENTS" {
+                return Err(format!("line {}: there's no {} block (just MEMORY and SEGMENTS)", line, block));
+            }
+
+            expect(&mut tokens, "{")?;
+
+            loop {
+                let (line, name) = match tokens.next() {
+                    Some(&(_, ref token)) if token == "}" => break,
+                    Some(&(line, ref name)) => (line, name.clone()),
+                    None => return Err(format!("missing }} after {}", block)),
+                };
+
+                expect(&mut tokens, ":")?;
+                let attrs = attributes(&mut tokens)?;
+                let at = |err: String| format!("line {}: {}", line, err);
+
+                match block.as_str() {
+                    "MEMORY" => config.areas.push(memory_area(name, attrs).map_err(at)?),
+                    _ => config.segments.push(segment_rule(name, attrs).map_err(at)?),
+                }
+            }
+        }
+
+        for (i, area) in config.areas.iter().enumerate() {
+            if config.areas[..i].iter().any(|other| other.name == area.name) {
+                return Err(format!("there's more than one memory area called {}", area.name));
+            }
+        }
+
+        for (i, rule) in config.segments.iter().enumerate() {
+            if config.segments[..i].iter().any(|other| other.name == rule.name) {
+                return Err(format!("segment {} is listed more than once", rule.name));
+            }
+
+            if !config.areas.iter().any(|area| area.name == rule.load) {
+                return Err(format!("segment {} is loaded into {}, but there's no such memory area", rule.name, rule.load));
+            }
+        }
+
+        Ok(config)
+    }
+
+    fn rule(&self, segment: &str) -> Option<&SegmentRule> {
+        self.segments.iter().find(|rule| rule.name == segment)
+    }
+}
+
+type Tokens<'a> = ::std::iter::Peekable<::std::slice::Iter<'a, (usize, String)>>;
+
+// words and punctuation, with the line each is on
+fn tokenize(text: &str) -> Result<Vec<(usize, String)>, String> {
+    let mut tokens = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("");
+        let mut word = String::new();
+
+        for c in line.chars().chain(Some(' ')) {
+            match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '$' | '%' => word.push(c),
+                _ => {
+                    if !word.is_empty() {
+                        tokens.push((i + 1, word.clone()));
+                        word.clear();
+                    }
+
+                    match c {
+                        '{' | '}' | ':' | '=' | ',' | ';' => tokens.push((i + 1, c.to_string())),
+                        _ if c.is_whitespace() => {}
+                        _ => return Err(format!("line {}: unexpected '{}'", i + 1, c)),
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn expect(tokens: &mut Tokens, expected: &str) -> Result<(), String> {
+    match tokens.next() {
+        Some(&(_, ref token)) if token == expected => Ok(()),
+        Some(&(line, ref token)) => Err(format!("line {}: expected '{}' but found '{}'", line, expected, token)),
+        None => Err(format!("expected '{}' at the end", expected)),
+    }
+}
+
+// `name = value, ...;`
+fn attributes(tokens: &mut Tokens) -> Result<Vec<(String, String)>, String> {
+    let mut attrs = Vec::new();
+
+    loop {
+        let name = match tokens.next() {
+            Some(&(_, ref name)) => name.clone(),
+            None => return Err(String::from("expected an attribute at the end")),
+        };
+
+        expect(tokens, "=")?;
+
+        match tokens.next() {
+            Some(&(_, ref value)) => attrs.push((name.to_lowercase(), value.clone())),
+            None => return Err(format!("{} needs a value", name)),
+        }
+
+        match tokens.next() {
+            Some(&(_, ref token)) if token == "," => {}
+            Some(&(_, ref token)) if token == ";" => return Ok(attrs),
+            Some(&(line, ref token)) => return Err(format!("line {}: expected ',' or ';' but found '{}'", line, token)),
+            None => return Err(String::from("expected ';' at the end")),
+        }
+    }
+}
+
+fn number(name: &str, value: &str, max: i64) -> Result<i64, String> {
+    match parse_number(value) {
+        Some(number) if (0..=max).contains(&number) => Ok(number),
+        Some(number) => Err(format!("{} = {} is out of range (it takes 0 to {})", name, number, max)),
+        None => Err(format!("{} = {} isn't a number", name, value)),
+    }
+}
+
+fn memory_area(name: String, attrs: Vec<(String, String)>) -> Result<MemoryArea, String> {
+    let (mut start, mut size, mut fill, mut bank) = (None, None, None, 0);
+
+    for (attr, value) in attrs {
+        match attr.as_str() {
+            "start" => start = Some(number(&attr, &value, 0xffff)? as u16),
+            "size" => size = Some(number(&attr, &value, 0x10000)? as usize),
+            "fill" => fill = Some(number(&attr, &value, 0xff)? as u8),
+            "bank" => bank = number(&attr, &value, 0xff)? as u8,
+            _ => return Err(format!("memory areas don't have a {}", attr)),
+        }
+    }
+
+    let (start, size) = match (start, size) {
+        (Some(start), Some(size)) => (start, size),
+        _ => return Err(format!("memory area {} needs a start and a size", name)),
+    };
+
+    if start as usize + size > 0x10000 {
+        return Err(format!("memory area {} runs past $ffff", name));
+    }
+
+    Ok(MemoryArea {
+        name: name,
+        start: start,
+        size: size,
+        fill: fill,
+        bank: bank,
+    })
+}
+
+fn segment_rule(name: String, attrs: Vec<(String, String)>) -> Result<SegmentRule, String> {
+    let mut rule = SegmentRule {
+        kind: match name.as_str() {
+            ZERO_PAGE_SEGMENT => SegmentKind::ZeroPage,
+            "BSS" => SegmentKind::Bss,
+            _ => SegmentKind::Data,
+        },
+        name: name,
+        load: String::new(),
+        start: None,
+        align: 1,
+    };
+
+    for (attr, value) in attrs {
+        match attr.as_str() {
+            "load" => rule.load = value,
+            "type" => {
+                rule.kind = match value.to_lowercase().as_str() {
+                    "ro" | "rw" => SegmentKind::Data,
+                    "bss" => SegmentKind::Bss,
+                    "zp" => SegmentKind::ZeroPage,
+                    _ => return Err(format!("segment {} can't be of type {} (it takes ro, rw, bss or zp)", rule.name, value)),
+                }
+            }
+            "start" => rule.start = Some(number(&attr, &value, 0xffff)? as u16),
+            "align" => rule.align = number(&attr, &value, 0x10000)?.max(1) as usize,
+            _ => return Err(format!("segments don't have a {}", attr)),
+        }
+    }
+
+    if rule.load.is_empty() {
+        return Err(format!("segment {} needs to say which memory area to load it into", rule.name));
+    }
+
+    Ok(rule)
+}
+
+// a memory area as it goes in the output
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    pub area: String,
+    pub bank: u8,
+    pub start: u16,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Linked {
+    // in bank order, then the order they're in the config. areas with only bss or zero page segments in them and
+    // no fill aren't here
+    pub images: Vec<Image>,
+    // where each segment went, and how big it is
+    pub segments: Vec<(String, u16, usize)>,
+    // everything an object exported
+    pub exports: HashMap<String, i64>,
+}
+
+impl Linked {
+    // the images one after the other
+    pub fn bytes(&self) -> Vec<u8> {
+        self.images.iter().flat_map(|image| image.bytes.iter().cloned()).collect()
+    }
+}
+
+// places every object's segments as `config` says, then fills in what couldn't be worked out before. everything
+// that's wrong comes back at once
+pub fn link(objects: &[Object], config: &Config) -> Result<Linked, Vec<String>> {
+    let mut errors = Vec::new();
+
+    for object in objects.iter() {
+        for segment in object.segments.iter().filter(|segment| !segment.data.is_empty()) {
+            if config.rule(&segment.name).is_none() {
+                errors.push(format!("{}: segment {} isn't in the linker config", object.name, segment.name));
+            }
+        }
+    }
+
+    for (i, area) in config.areas.iter().enumerate() {
+        let (start, end) = (area.start as usize, area.start as usize + area.size);
+
+        for other in config.areas[i + 1..].iter().filter(|other| other.bank == area.bank) {
+            if start < other.start as usize + other.size && (other.start as usize) < end {
+                errors.push(format!("memory areas {} and {} overlap in bank {}", area.name, other.name, area.bank));
+            }
+        }
+    }
+
+    // where each object's part of each of its segments starts
+    let mut bases: Vec<Vec<Option<usize>>> = objects.iter().map(|object| vec![None; object.segments.len()]).collect();
+    let mut segments = Vec::new();
+    let mut used = vec![0; config.areas.len()];
+
+    for (a, area) in config.areas.iter().enumerate() {
+        let end = area.start as usize + area.size;
+        let mut addr = area.start as usize;
+        let mut overflowed = false;
+
+        for rule in config.segments.iter().filter(|rule| rule.load == area.name) {
+            if let Some(start) = rule.start {
+                let start = start as usize;
+                if start < addr {
+                    errors.push(format!("segment {} is meant to start at ${:04x}, but what's before it in {} runs to \
+                                         ${:04x}",
+                                        rule.name,
+                                        start,
+                                        area.name,
+                                        addr));
+                }
+
+                addr = addr.max(start);
+            }
+
+            addr = align(addr, rule.align);
+            let first = addr;
+
+            for (o, object) in objects.iter().enumerate() {
+                for (s, segment) in object.segments.iter().enumerate().filter(|&(_, segment)| segment.name == rule.name) {
+                    addr = align(addr, segment.align);
+                    bases[o][s] = Some(addr);
+                    addr += segment.data.len();
+
+                    if rule.kind != SegmentKind::Data &&
+                       (!segment.fixups.is_empty() || segment.data.iter().any(|&byte| byte != 0)) {
+                        errors.push(format!("{}: segment {} only reserves space, so it can't hold anything but .res",
+                                            object.name,
+                                            rule.name));
+                    }
+                }
+            }
+
+            if addr > end && !overflowed {
+                overflowed = true;
+                errors.push(format!("segment {} overflows {} by {} byte(s)", rule.name, area.name, addr - end));
+            }
+
+            if rule.kind == SegmentKind::ZeroPage && addr > 0x100 {
+                errors.push(format!("segment {} is zero page, but runs to ${:04x}", rule.name, addr));
+            }
+
+            if rule.kind == SegmentKind::Data && addr > first {
+                used[a] = addr;
+            }
+
+            segments.push((rule.name.clone(), first as u16, addr - first));
+        }
+    }
+
+    let (locals, exports) = symbols(objects, &bases, &mut errors);
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let mut images: Vec<Image> = Vec::new();
+
+    for (a, area) in config.areas.iter().enumerate() {
+        let mut bytes = vec![area.fill.unwrap_or(0); area.size];
+
+        for (o, object) in objects.iter().enumerate() {
+            let mut scope = exports.clone();
+            scope.extend(locals[o].iter().map(|(name, &value)| (name.clone(), value)));
+
+            for (s, segment) in object.segments.iter().enumerate() {
+                let base = match (bases[o][s], config.rule(&segment.name)) {
+                    (Some(base), Some(rule)) if rule.load == area.name && rule.kind == SegmentKind::Data => base,
+                    _ => continue,
+                };
+
+                let offset = base - area.start as usize;
+                bytes[offset..offset + segment.data.len()].copy_from_slice(&segment.data);
+
+                for fixup in segment.fixups.iter() {
+                    let pc = (base + fixup.pc) as u16;
+
+                    match fixup.value.eval(pc, &scope).and_then(|value| fixup.field.encode(value, pc)) {
+                        Ok(value) => {
+                            let at = offset + fixup.offset;
+                            bytes[at..at + value.len()].copy_from_slice(&value);
+                        }
+                        Err(err) => errors.push(format!("{}:{}: {}", fixup.file, fixup.line, err)),
+                    }
+                }
+            }
+        }
+
+        let len = match area.fill {
+            Some(_) => area.size,
+            None => used[a].saturating_sub(area.start as usize),
+        };
+
+        if area.fill.is_some() || len > 0 {
+            bytes.truncate(len);
+            images.push(Image {
+                area: area.name.clone(),
+                bank: area.bank,
+                start: area.start,
+                bytes: bytes,
+            });
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    images.sort_by_key(|image| image.bank);
+
+    Ok(Linked {
+        images: images,
+        segments: segments,
+        exports: exports,
+    })
+}
+
+// each object's own symbols, and what they all export. equates can use labels, imports and each other in any
+// order, so they're worked out over as many passes as it takes for no more to come out
+fn symbols(objects: &[Object], bases: &[Vec<Option<usize>>], errors: &mut Vec<String>)
+           -> (Vec<HashMap<String, i64>>, HashMap<String, i64>) {
+    let mut locals: Vec<HashMap<String, i64>> = Vec::new();
+    let mut exporters: HashMap<&str, &str> = HashMap::new();
+
+    for (o, object) in objects.iter().enumerate() {
+        let mut symbols = HashMap::new();
+
+        for symbol in object.symbols.iter() {
+            if symbol.exported {
+                if let Some(other) = exporters.insert(&symbol.name, &object.name) {
+                    errors.push(format!("'{}' is exported by both {} and {}", symbol.name, other, object.name));
+                }
+            }
+
+            if let Value::Label(segment, offset) = symbol.value {
+                if let Some(base) = bases[o][segment] {
+                    symbols.insert(symbol.name.clone(), (base + offset) as i64);
+                }
+            }
+        }
+
+        locals.push(symbols);
+    }
+
+    let mut exports = HashMap::new();
+
+    loop {
+        for (o, object) in objects.iter().enumerate() {
+            for symbol in object.symbols.iter().filter(|symbol| symbol.exported) {
+                if let Some(&value) = locals[o].get(&symbol.name) {
+                    exports.insert(symbol.name.clone(), value);
+                }
+            }
+        }
+
+        let mut progress = false;
+        let mut failures = Vec::new();
+
+        for (o, object) in objects.iter().enumerate() {
+            let mut scope = exports.clone();
+            scope.extend(locals[o].iter().map(|(name, &value)| (name.clone(), value)));
+
+            for symbol in object.symbols.iter().filter(|symbol| !locals[o].contains_key(&symbol.name)) {
+                if let Value::Equate(ref value, segment, offset) = symbol.value {
+                    let pc = (bases[o][segment].unwrap_or(0) + offset) as u16;
+
+                    match value.eval(pc, &scope) {
+                        Ok(value) => {
+                            scope.insert(symbol.name.clone(), value);
+                            progress = true;
+                        }
+                        Err(err) => failures.push(format!("{}: can't work out '{}': {}", object.name, symbol.name, err)),
+                    }
+                }
+            }
+
+            let known: Vec<String> = object.symbols.iter().map(|symbol| symbol.name.clone()).collect();
+            for name in known {
+                if let Some(&value) = scope.get(&name) {
+                    locals[o].insert(name, value);
+                }
+            }
+        }
+
+        if !progress {
+            errors.extend(failures);
+            break;
+        }
+    }
+
+    for object in objects.iter() {
+        for import in object.imports.iter().filter(|import| !exports.contains_key(*import)) {
+            errors.push(format!("{}: '{}' is imported, but nothing exports it", object.name, import));
+        }
+    }
+
+    (locals, exports)
+}
+
+fn align(addr: usize, boundary: usize) -> usize {
+    addr.div_ceil(boundary) * boundary
+}
+
+#[cfg(test)]
+mod test {
+    use super::{link, Config, MemoryArea, SegmentKind};
+    use asm::{Object, Parser};
+
+    const CONFIG: &'static str = "
+        MEMORY {
+            ZP:  start = $0000, size = $0100;
+            RAM: start = $0200, size = $0600;   # no fill, so it isn't written out
+            ROM: start = $c000, size = $4000, fill = $ff;
+        }
+
+        SEGMENTS {
+            ZEROPAGE: load = ZP;
+            BSS:      load = RAM;
+            CODE:     load = ROM;
+            RODATA:   load = ROM;
+            VECTORS:  load = ROM, start = $fffa;
+        }
+    ";
+
+    fn object(source: &str) -> Object {
+        Parser::new(0).assemble_object(source).unwrap()
+    }
+
+    #[test]
+    fn config() {
+        let config = Config::parse(CONFIG).unwrap();
+
+        assert_eq!(config.areas[2],
+                   MemoryArea {
+                       name: String::from("ROM"),
+                       start: 0xc000,
+                       size: 0x4000,
+                       fill: Some(0xff),
+                       bank: 0,
+                   });
+        assert_eq!(config.segments[0].kind, SegmentKind::ZeroPage);
+        assert_eq!(config.segments[4].start, Some(0xfffa));
+
+        let err = |text| Config::parse(text).unwrap_err();
+        assert_eq!(err("MEMORY {\n ROM: start = $c000;\n}"), "line 2: memory area ROM needs a start and a size");
+        assert_eq!(err("SEGMENTS { CODE: load = ROM; }"),
+                   "segment CODE is loaded into ROM, but there's no such memory area");
+        assert!(err("MEMORY { ROM: start = $c000, size = $8000; }").contains("runs past $ffff"));
+        assert!(err("MEMORY { ROM: start = $c000 size = 1; }").contains("expected ',' or ';' but found 'size'"));
+    }
+
+    #[test]
+    fn linking() {
+        let main = object("
+                    .import putc
+                    .importzp ptr
+                    .export reset
+            reset:  ldx #0
+            :       lda message,x
+                    beq done
+                    sta ptr
+                    jsr putc
+                    inx
+                    bne :-
+            done:   jmp done
+                    .rodata
+            message: .byte \"hi\", 0
+                    .segment \"VECTORS\"
+                    .word 0, reset, 0
+        ");
+
+        let io = object("
+                    .export putc, ptr
+                    .zeropage
+            ptr:    .res 2
+                    .bss
+            buffer: .res 16
+                    .code
+            putc:   sta buffer
+                    rts
+        ");
+
+        // objects survive being written out and read back
+        assert_eq!(Object::load(&main.save()).unwrap(), main);
+
+        let linked = link(&[main.clone(), io.clone()], &Config::parse(CONFIG).unwrap()).unwrap();
+        let rom = linked.bytes();
+
+        assert_eq!(linked.images.len(), 1);
+        assert_eq!(rom.len(), 0x4000);
+        assert_eq!(&rom[..25],
+                   &[0xa2, 0x00,
+                     0xbd, 0x16, 0xc0,
+                     0xf0, 0x08,
+                     0x85, 0x00,
+                     0x20, 0x12, 0xc0,
+                     0xe8,
+                     0xd0, 0xf3,
+                     0x4c, 0x0f, 0xc0,
+                     // io's part of CODE follows main's, and RODATA follows that
+                     0x8d, 0x00, 0x02,
+                     0x60,
+                     0x68, 0x69, 0x00]);
+        assert_eq!(rom[25], 0xff);
+        assert_eq!(&rom[0x3ffa..], &[0x00, 0x00, 0x00, 0xc0, 0x00, 0x00]);
+
+        assert_eq!(linked.exports["putc"], 0xc012);
+        assert_eq!(linked.segments[1], (String::from("BSS"), 0x0200, 16));
+
+        let errors = |objects: Vec<Object>, config: &str| link(&objects, &Config::parse(config).unwrap()).unwrap_err();
+
+        assert_eq!(errors(vec![main.clone()], CONFIG),
+                   vec!["<source>: 'putc' is imported, but nothing exports it",
+                        "<source>: 'ptr' is imported, but nothing exports it"]);
+        assert_eq!(errors(vec![io.clone(), io.clone()], CONFIG),
+                   vec!["'ptr' is exported by both <source> and <source>", "'putc' is exported by both <source> and <source>"]);
+        assert!(errors(vec![main.clone(), io.clone()], &CONFIG.replace("size = $4000", "size = $10"))
+            .contains(&String::from("segment CODE overflows ROM by 6 byte(s)")));
+        assert!(errors(vec![main.clone(), io.clone()], &CONFIG.replace("VECTORS:  load = ROM, start = $fffa;", ""))
+            .contains(&String::from("<source>: segment VECTORS isn't in the linker config")));
+
+        // a zero page import that turns out not to be
+        let far = object(".export putc, ptr\n.bss\nptr: .res 2\n.code\nputc: rts");
+        assert_eq!(errors(vec![main, far], CONFIG),
+                   vec!["<source>:8: $200 is too wide for zero page addressing, which takes $0 to $ff"]);
+    }
+}
@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+
+use cpu::addr::AddrMode;
+use cpu::instr::resolver;
+use cpu::{Cpu, ProcessorStatusRegister};
+
+use super::diagnostic::Source;
+use super::directive::Directive;
+use super::{hex, Line, Placement};
+
+// where instructions are run to time them, well away from zero page and the stack
+const PROBE_ADDR: u16 = 0x0200;
+
+// data lines longer than this many bytes are cut short, so a big .res doesn't swamp the listing
+const MAX_LISTED_BYTES: usize = 16;
+const BYTES_PER_ROW: usize = 4;
+
+// what an instruction costs, going by what `cpu::instr` charges for it
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Timing {
+    pub cycles: u8,
+    // extra when indexing crosses into the next page
+    pub page_cross: u8,
+    // for branches: extra when taken, and on top of that when the target's on another page
+    pub taken: u8,
+    pub taken_page_cross: u8,
+}
+
+impl Timing {
+    // the base count alone can't be trusted for anything that varies, so those get a note
+    pub fn note(&self) -> Option<String> {
+        match (self.page_cross, self.taken, self.taken_page_cross) {
+            (0, 0, 0) => None,
+            (0, taken, 0) => Some(format!("+{} if taken", taken)),
+            (0, taken, cross) => Some(format!("+{} if taken, +{} more across a page", taken, cross)),
+            (cross, _, _) => Some(format!("+{} if a page is crossed", cross)),
+        }
+    }
+}
+
+// times `opcode` by running it on scratch cpus set up to take each path that costs something different
+pub fn timing(opcode: u8, mode: &AddrMode) -> Timing {
+    if *mode == AddrMode::Relative {
+        // every branch tests one flag, so it's taken with them all clear or all set
+        let near = [probe(opcode, 0x02, 0, 0x00), probe(opcode, 0x02, 0, 0xff)];
+        let far = [probe(opcode, 0x80, 0, 0x00), probe(opcode, 0x80, 0, 0xff)];
+
+        let base = near[0].min(near[1]);
+        let taken = near[0].max(near[1]);
+
+        return Timing {
+            cycles: base,
+            page_cross: 0,
+            taken: taken - base,
+            taken_page_cross: far[0].max(far[1]).saturating_sub(taken),
+        };
+    }
+
+    let base = probe(opcode, 0xff, 0, 0x00);
+
+    Timing {
+        cycles: base,
+        page_cross: probe(opcode, 0xff, 1, 0x00).saturating_sub(base),
+        taken: 0,
+        taken_page_cross: 0,
+    }
+}
+
+// runs `opcode` with `operand` for its operand bytes, x and y set to `index` and the status register to `status`,
+// and returns the cycles it says it took. every address an operand can lead to is $03ff, one short of a page, so
+// an index of 1 always crosses
+fn probe(opcode: u8, operand: u8, index: u8, status: u8) -> u8 {
+    let mut cpu = Cpu::new();
+
+    // $03ff is the operand itself for absolute modes, and what zero page pointers at $ff point to
+    cpu.memory.write_at(&0x0000, &[0x03]);
+    cpu.memory.write_at(&0x00ff, &[0xff, 0x03]);
+    cpu.memory.write_at(&PROBE_ADDR, &[opcode, operand, 0x03]);
+
+    cpu.reg_pc = PROBE_ADDR + 1;
+    cpu.reg_x = index as i8;
+    cpu.reg_y = index as i8;
+    cpu.reg_status = ProcessorStatusRegister::from(status);
+
+    match resolver::resolve(opcode) {
+        Some(instr) => instr(&mut cpu).get_num_cycles(),
+        None => 0,
+    }
+}
+
+// the source as it was read, a line at a time (includes where they're included), with the address, bytes and
+// cycle count of whatever each line assembled to, then every symbol. a line that expands to several (a macro or
+// a .repeat) gets a row for each, and a row for each further 4 bytes of data
+pub fn render(sources: &[Source], lines: &[Line], placements: &[Placement], encoded: &[Vec<u8>],
+              symbols: &HashMap<String, i64>)
+              -> String {
+    let mut by_source: HashMap<*const String, Vec<usize>> = HashMap::new();
+    for (i, line) in lines.iter().enumerate() {
+        by_source.entry(&*line.source.text).or_default().push(i);
+    }
+
+    let mut timings = HashMap::new();
+    let mut text = String::from(" line  addr  bytes        cyc  source\n");
+    let mut file = None;
+
+    for source in sources.iter() {
+        if source.file != file {
+            file = source.file.clone();
+            text.push_str(&format!("\n{}:\n", file.as_ref().map_or("<source>", |file| file.as_str())));
+        }
+
+        let number = source.line.to_string();
+        let indices = by_source.get(&(&*source.text as *const String)).cloned().unwrap_or_default();
+
+        if indices.is_empty() {
+            row(&mut text, &number, "", "", "", &source.text, None);
+            continue;
+        }
+
+        for (n, &i) in indices.iter().enumerate() {
+            let line = &lines[i];
+            let (addr, _) = placements[i];
+
+            // the first row quotes the source, and any more show what it expanded to
+            let (number, quoted) = match n {
+                0 => (number.as_str(), source.text.to_string()),
+                _ => ("", format!("    {} {}", line.instr, line.rest.as_ref().map_or("", |rest| rest.as_str()))),
+            };
+
+            if let Some(Directive::Equate(ref name, _)) = line.directive {
+                let value = symbols.get(name).map_or(String::new(), |&value| format!("= {}", hex(value)));
+                row(&mut text, number, "", &value, "", &quoted, None);
+                continue;
+            }
+
+            let bytes = &encoded[i];
+
+            // an address means something for a line that put bytes or a label there, or moved there
+            let is_org = match line.directive {
+                Some(Directive::Org(_)) => true,
+                _ => false,
+            };
+            let addr_text = match line.label.is_some() || !bytes.is_empty() || is_org {
+                true => format!("{:04x}", addr),
+                false => String::new(),
+            };
+
+            let (cycles, note) = match (&line.directive, bytes.first()) {
+                (&None, Some(&opcode)) => {
+                    let timing = *timings.entry(opcode).or_insert_with(|| timing_of(opcode));
+                    (timing.cycles.to_string(), timing.note())
+                }
+                _ => (String::new(), None),
+            };
+
+            let mut rows = bytes.chunks(BYTES_PER_ROW).take(MAX_LISTED_BYTES / BYTES_PER_ROW);
+            let first = rows.next().map_or(String::new(), hex_bytes);
+            row(&mut text, number, &addr_text, &first, &cycles, &quoted, note);
+
+            for (k, bytes) in rows.enumerate() {
+                let addr = addr.wrapping_add(((k + 1) * BYTES_PER_ROW) as u16);
+                row(&mut text, "", &format!("{:04x}", addr), &hex_bytes(bytes), "", "", None);
+            }
+
+            if bytes.len() > MAX_LISTED_BYTES {
+                row(&mut text, "", "", &format!("+{} more", bytes.len() - MAX_LISTED_BYTES), "", "", None);
+            }
+        }
+    }
+
+    // anonymous labels only have the numbers the reader gave them, so they'd just be noise
+    let mut names: Vec<&String> = symbols.keys().filter(|name| !name.starts_with(':')).collect();
+    names.sort();
+
+    let width = names.iter().map(|name| name.len()).max().unwrap_or(0);
+    text.push_str("\nsymbols:\n");
+
+    for name in names {
+        let value = symbols[name];
+        let value = match value {
+            0..=0xffff => format!("${:04x}", value),
+            _ => hex(value),
+        };
+
+        text.push_str(&format!("  {:width$}  {}\n", name, value, width = width));
+    }
+
+    text
+}
+
+fn row(text: &mut String, number: &str, addr: &str, bytes: &str, cycles: &str, source: &str, note: Option<String>) {
+    let mut row = format!("{:>5}  {:4}  {:11}  {:3}  {}", number, addr, bytes, cycles, source);
+    if let Some(note) = note {
+        row = format!("{:60}  [{}]", row.trim_end(), note);
+    }
+
+    text.push_str(row.trim_end());
+    text.push('\n');
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<_>>().join(" ")
+}
+
+fn timing_of(opcode: u8) -> Timing {
+    match resolver::OPCODES.iter().find(|&&(op, _, _)| op == opcode) {
+        Some(&(_, _, ref mode)) => timing(opcode, mode),
+        None => Timing::default(),
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::Timing;
+    use asm::Parser;
+    use cpu::addr::AddrMode;
+
+    #[test]
+    fn timing() {
+        let lda_abs_x = super::timing(0xbd, &AddrMode::AbsoluteX);
+        assert_eq!(lda_abs_x.cycles, 4);
+        assert_eq!(lda_abs_x.note(), Some(String::from("+1 if a page is crossed")));
+
+        assert_eq!(super::timing(0xa9, &AddrMode::Immediate).note(), None);
+        assert_eq!(super::timing(0xf0, &AddrMode::Relative),
+                   Timing {
+                       cycles: 2,
+                       page_cross: 0,
+                       taken: 1,
+                       taken_page_cross: 1,
+                   });
+    }
+
+    #[test]
+    fn listing() {
+        let mut parser = Parser::new(0x0600);
+        parser.assemble(".macro twice op\n    op\n    op\n.endmacro\ncount = 3\nstart:  ldx #count\n\
+                         :       lda $1234,X\n        twice inx\n        bne :-\n        .byte 1, 2, 3, 4, 5\n\
+                         .res 20")
+            .unwrap();
+
+        let listing = parser.listing();
+        let rows: Vec<&str> = listing.lines().collect();
+
+        assert_eq!(rows[0], " line  addr  bytes        cyc  source");
+        assert_eq!(rows[1], "    1                          .macro twice op");
+        assert_eq!(rows[5], "    5        = $3              count = 3");
+        assert_eq!(rows[6], "    6  0600  a2 03        2    start:  ldx #count");
+        assert_eq!(rows[7],
+                   "    7  0602  bd 34 12     4    :       lda $1234,X            [+1 if a page is crossed]");
+
+        // a macro gets a row for each line it expands to, and long data carries on over more rows
+        assert_eq!(rows[8], "    8  0605  e8           2            twice inx");
+        assert_eq!(rows[9], "       0606  e8           2        inx");
+        assert_eq!(rows[11], "   10  0609  01 02 03 04               .byte 1, 2, 3, 4, 5");
+        assert_eq!(rows[12], "       060d  05");
+        assert_eq!(rows[17], "             +4 more");
+
+        assert_eq!(&rows[18..], &["", "symbols:", "  count  $0003", "  start  $0600"]);
+    }
+}
@@ -1,135 +1,981 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::mem;
+use std::path::Path;
+use std::rc::Rc;
+
+pub mod cli;
+mod debuginfo;
+mod diagnostic;
+mod directive;
+mod expr;
+pub mod link;
+mod listing;
+mod object;
+mod operand;
+mod output;
+mod reader;
+mod token;
+
 use cpu::addr::AddrMode;
+use cpu::instr::resolver;
+
+pub use self::diagnostic::Diagnostic;
+use self::diagnostic::{Source, Span};
+use self::directive::{Data, Directive};
+pub use self::output::{Chunk, Format, InesHeader};
+pub use self::expr::Expr;
+pub use self::object::Object;
+use self::object::{Field, Fixup, Segment, Segments, Symbol, Value};
+use self::reader::Reader;
 
-extern crate regex;
+// label values only ever shrink from one layout pass to the next (see `assemble`), so this is just a backstop
+const MAX_LAYOUT_PASSES: usize = 16;
 
-lazy_static! {
-    static ref INDIRECT_X_REGEX: regex::Regex = regex::Regex::new(r"\(\$(.*?),X\)").unwrap();
-    static ref INDIRECT_Y_REGEX: regex::Regex = regex::Regex::new(r"\(\$(.*?)\),Y").unwrap();
-    static ref ABSOLUTE_AND_ZERO_PAGE_REGEX: regex::Regex = regex::Regex::new(r"(?m)\$(.*?),?($|X|Y)").unwrap();
+// where a line was laid out, and how many bytes it takes up there
+type Placement = (u16, u16);
+
+enum Piece<'a> {
+    Bytes(Vec<u8>),
+    Value(Field, &'a Expr),
 }
 
 pub struct Line {
+    // where the line was read from; every line out of a macro or .repeat counts as being on the line that used it
+    source: Source,
+    instr_span: Span,
+    // the mnemonic's if there's no operand
+    operand_span: Span,
+    label: Option<String>,
+    // the mnemonic or directive (`=` for an equate), empty for a line that's only a label
     instr: String,
     rest: Option<String>,
     addr_mode: AddrMode,
-    value: Option<u16>,
+    value: Option<Expr>,
+    forced_width: bool,
+    directive: Option<Directive>,
+    // the .proc or .scope the line's in, like `outer::inner` (empty at the top level)
+    scope: Rc<String>,
+}
+
+impl Line {
+    fn label_only(source: &Source, label: Option<String>) -> Line {
+        Line {
+            source: source.clone(),
+            instr_span: source.code,
+            operand_span: source.code,
+            label: label,
+            instr: String::new(),
+            rest: None,
+            addr_mode: AddrMode::Implicit,
+            value: None,
+            forced_width: false,
+            directive: None,
+            scope: Rc::new(String::new()),
+        }
+    }
+
+    fn exprs_mut(&mut self) -> Vec<&mut Expr> {
+        let mut exprs: Vec<&mut Expr> = self.value.iter_mut().collect();
+        if let Some(ref mut directive) = self.directive {
+            exprs.extend(directive.exprs_mut());
+        }
+
+        exprs
+    }
+
+    // the label or equate the line defines, if any
+    fn defines(&self) -> Option<&String> {
+        match self.directive {
+            Some(Directive::Equate(ref name, _)) => Some(name),
+            _ => self.label.as_ref(),
+        }
+    }
+
+    // where `name`, a label as the reader renamed it, is written within `within`, or all of `within` if it's not
+    // there as written (it came out of a macro, say)
+    fn span_of(&self, name: &str, within: Span) -> Span {
+        let written = match name.find('@') {
+            Some(at) => &name[at..],
+            None => name.rsplit("::").next().unwrap_or(name),
+        };
+
+        self.source.find(within, written).unwrap_or(within)
+    }
+
+    // the mnemonic or directive and its operand
+    fn span(&self) -> Span {
+        (self.instr_span.0, self.operand_span.1)
+    }
+
+    fn error(&self, span: Span, msg: String) -> Diagnostic {
+        self.source.error(span, msg)
+    }
 }
 
 #[derive(Default)]
-pub struct Parser {}
+pub struct Parser {
+    // where the first byte of output goes, unless the source starts with an .org
+    pub origin: u16,
+    // every label and equate from the last `assemble`. for an object, labels are offsets into their segments, and
+    // equates that depend on them are left out
+    pub symbols: HashMap<String, i64>,
+    // the errors and warnings from the last `assemble`
+    pub diagnostics: Vec<Diagnostic>,
+    // what the last `assemble` produced, for the writers in `output`
+    pub chunks: Vec<Chunk>,
+    pub ines: InesHeader,
+    // what the last `assemble` read and where it put it, for `listing`
+    sources: Vec<Source>,
+    lines: Vec<Line>,
+    placements: Vec<Placement>,
+    encoded: Vec<Vec<u8>>,
+}
+
+// number literals as they appear in source: $hex, %binary or plain decimal
+pub fn parse_number(text: &str) -> Option<i64> {
+    let (digits, radix) = match text.chars().next() {
+        Some('$') => (&text[1..], 16),
+        Some('%') => (&text[1..], 2),
+        _ => (text, 10),
+    };
+
+    if digits.is_empty() {
+        return None;
+    }
+
+    i64::from_str_radix(digits, radix).ok()
+}
 
 impl Parser {
+    pub fn new(origin: u16) -> Self {
+        Parser {
+            origin: origin,
+            symbols: HashMap::new(),
+            diagnostics: Vec::new(),
+            chunks: Vec::new(),
+            ines: InesHeader::default(),
+            sources: Vec::new(),
+            lines: Vec::new(),
+            placements: Vec::new(),
+            encoded: Vec::new(),
+        }
+    }
+
+    // .include and .incbin paths are relative to the working directory. a line with a mistake in it is skipped
+    // and assembly carries on, so everything that's wrong comes back at once; on failure, the warnings come back
+    // with the errors
+    pub fn assemble(&mut self, input: &str) -> Result<Vec<u8>, Vec<Diagnostic>> {
+        let (lines, errors) = self.read(input, None);
+        self.assemble_lines(lines, errors)
+    }
+
+    // .include and .incbin paths are relative to the file they're in
+    pub fn assemble_file(&mut self, path: &str) -> Result<Vec<u8>, Vec<Diagnostic>> {
+        let (lines, errors) = self.read_file(path)?;
+        self.assemble_lines(lines, errors)
+    }
+
+    // like `assemble`, but into an object for `link::link` to place with others. each .segment is laid out from
+    // zero, and whatever depends on where they end up, or on an .import, is left for the linker to fill in
+    pub fn assemble_object(&mut self, input: &str) -> Result<Object, Vec<Diagnostic>> {
+        let (lines, errors) = self.read(input, None);
+        self.assemble_object_lines(String::from("<source>"), lines, errors)
+    }
+
+    pub fn assemble_object_file(&mut self, path: &str) -> Result<Object, Vec<Diagnostic>> {
+        let (lines, errors) = self.read_file(path)?;
+        self.assemble_object_lines(String::from(path), lines, errors)
+    }
+
+    // the last successful `assemble` as a listing: every line of source with the address, bytes and cycle count
+    // of what it assembled to, then the symbol table
+    pub fn listing(&self) -> String {
+        listing::render(&self.sources, &self.lines, &self.placements, &self.encoded, &self.symbols)
+    }
+
+    // the last successful `assemble_file`'s source map, for the debugger to show where it is in the source
+    pub fn source_map(&self) -> String {
+        debuginfo::source_map(&self.lines, &self.placements, &self.encoded)
+    }
+
+    // the last successful `assemble`'s symbols, in the `name = value` form the debugger reads
+    pub fn symbol_file(&self) -> String {
+        debuginfo::symbols(&self.symbols)
+    }
+
+    fn read(&mut self, input: &str, file: Option<&Path>) -> (Vec<Line>, Vec<Diagnostic>) {
+        let mut reader = Reader::new();
+        reader.read(input, file, 0);
+        self.sources = mem::take(&mut reader.sources);
 
-    #[allow(unused_variables)]
-    pub fn assemble(&mut self, input: &str) -> Vec<u8> {
-        unimplemented!()
+        reader.finish()
     }
 
-    pub fn into_lines(&mut self, input: &str) -> Box<Vec<Line>> {
-        let result = input.split('\n')
-            .into_iter()
-            .map(|line| line.trim())
-            .filter(|line| line.len() != 0)
-            .map(|line| {
-                let mut splitter = line.splitn(2, ' ');
-                let instr = splitter.next().unwrap();
+    fn read_file(&mut self, path: &str) -> Result<(Vec<Line>, Vec<Diagnostic>), Vec<Diagnostic>> {
+        match fs::read_to_string(path) {
+            Ok(input) => Ok(self.read(&input, Some(Path::new(path)))),
+            Err(err) => self.fail(vec![Diagnostic::about_file(path, err.to_string())]),
+        }
+    }
+
+    fn fail<T>(&mut self, mut diagnostics: Vec<Diagnostic>) -> Result<T, Vec<Diagnostic>> {
+        diagnostic::sort(&mut diagnostics);
+        self.diagnostics = diagnostics.clone();
+        self.lines.clear();
+        self.placements.clear();
+        self.encoded.clear();
+        Err(diagnostics)
+    }
+
+    // lays the program out until every label has settled. forward references aren't known on the first pass, so
+    // anything using one is assumed to need an absolute operand; each later pass redoes the layout with the labels
+    // from the one before, shrinking operands that turn out to fit in zero page. shrinking only ever moves labels
+    // down, so this can't flip-flop.
+    //
+    // `known` is what's known before the first pass, and `segments` which segment each line's in, for an object
+    fn settle(&self, lines: &[Line], segments: Option<&Segments>, known: HashMap<String, i64>)
+              -> Result<(HashMap<String, i64>, Vec<Placement>), Diagnostic> {
+        let mut symbols = known;
+        let mut placements: Vec<Placement> = Vec::new();
+        let mut previous = (HashMap::new(), Vec::new());
+
+        for _ in 0..MAX_LAYOUT_PASSES {
+            let (pass_symbols, pass_placements) = self.layout(lines, &symbols, segments);
+            if pass_placements == placements && pass_symbols == symbols {
+                return Ok((symbols, placements));
+            }
+
+            previous = (mem::replace(&mut symbols, pass_symbols), mem::replace(&mut placements, pass_placements));
+        }
+
+        // blame the first line that was still moving
+        let (ref previous_symbols, ref previous_placements) = previous;
+        let moving = lines.iter()
+            .enumerate()
+            .find(|&(i, line)| {
+                previous_placements.get(i) != placements.get(i) ||
+                line.defines().is_some_and(|name| previous_symbols.get(name) != symbols.get(name))
+            })
+            .map_or(&lines[0], |(_, line)| line);
+
+        let msg = "the layout never settled (does an .org, .res or .align depend on a label after it?)";
+        Err(moving.error(moving.span(), String::from(msg)))
+    }
+
+    // lays the program out, then encodes it. once the first byte is out, an .org further on pads up to its
+    // address; before that, it just moves `origin`
+    //
+    // `diagnostics` are whatever reading the source turned up; the lines they're about have been left out
+    fn assemble_lines(&mut self, lines: Vec<Line>, mut diagnostics: Vec<Diagnostic>) -> Result<Vec<u8>, Vec<Diagnostic>> {
+        let (symbols, placements) = match self.settle(&lines, None, HashMap::new()) {
+            Ok(settled) => settled,
+            Err(err) => {
+                diagnostics.push(err);
+                return self.fail(diagnostics);
+            }
+        };
+
+        let mut chunks: Vec<Chunk> = Vec::new();
+        let mut ines = InesHeader::default();
+        let mut pc = self.origin;
+        // whether the last line ended at $ffff, so anything after it without an .org would wrap round to $0000
+        let mut at_end = false;
+        let mut defined = HashSet::new();
+        let mut encoded_lines = vec![Vec::new(); lines.len()];
+
+        for (i, (line, &(addr, size))) in lines.iter().zip(placements.iter()).enumerate() {
+            if let Some(name) = line.defines() {
+                if !defined.insert(name) {
+                    let span = line.span_of(name, line.source.code);
+                    diagnostics.push(line.error(span, format!("'{}' is already defined", name)));
+                }
+            }
+
+            let operand_error = |err: String| line.error(line.operand_span, err);
+
+            let encoded = match line.directive {
+                Some(Directive::Org(ref target)) => {
+                    match target.eval(pc, &symbols) {
+                        Ok(target) if !(0..=0xffff).contains(&target) => {
+                            diagnostics.push(operand_error(format!(".org {} isn't an address", hex(target))))
+                        }
+                        Ok(_) => {
+                            pc = addr;
+                            at_end = false;
+                        }
+                        Err(err) => diagnostics.push(operand_error(err)),
+                    }
+
+                    continue;
+                }
+                Some(Directive::Equate(_, ref value)) => {
+                    if let Err(err) = value.eval(addr, &symbols) {
+                        diagnostics.push(operand_error(err));
+                    }
+
+                    continue;
+                }
+                Some(Directive::Ines(field, ref value)) => {
+                    if let Err(err) = value.eval(addr, &symbols).and_then(|value| ines.set(field, value)) {
+                        diagnostics.push(operand_error(err));
+                    }
+
+                    continue;
+                }
+                Some(Directive::Assert { ref cond, fatal, ref msg }) => {
+                    diagnostics.extend(Parser::check(line, cond, fatal, msg, addr, &symbols));
+                    continue;
+                }
+                Some(Directive::Segment(_)) | Some(Directive::Import(..)) => {
+                    let msg = "segments and imports are for object files, which the linker puts together";
+                    diagnostics.push(line.error(line.span(), String::from(msg)));
 
-                let rest = splitter.next();
-                let addr_mode_results = Parser::get_instr_addr_mode(rest);
+                    continue;
+                }
+                _ if line.instr.is_empty() => continue,
+                _ => Parser::encode(line, addr, &symbols),
+            };
+
+            if size > 0 && (at_end || addr as usize + size as usize > 0x10000) {
+                diagnostics.push(line.error(line.span(), String::from("runs past $ffff")));
+                continue;
+            }
 
-                Line {
-                    instr: String::from(instr),
-                    rest: rest.map(|rest| String::from(rest)),
-                    addr_mode: addr_mode_results.0,
-                    value: addr_mode_results.1,
+            pc = addr.wrapping_add(size);
+            at_end = at_end || addr as usize + size as usize == 0x10000;
+
+            let encoded = match encoded {
+                Ok(encoded) => encoded,
+                Err(err) => {
+                    diagnostics.push(err);
+                    continue;
                 }
+            };
+
+            if encoded.is_empty() {
+                continue;
+            }
+
+            encoded_lines[i] = encoded.clone();
+
+            if let Err(overlap) = Parser::place(&mut chunks, addr, encoded) {
+                diagnostics.push(line.error(line.span(), format!("${:04x} has already been assembled", overlap)));
+            }
+        }
+
+        if diagnostics.iter().any(Diagnostic::is_error) {
+            return self.fail(diagnostics);
+        }
+
+        let (origin, bytes) = match chunks.is_empty() {
+            true => (self.origin, Vec::new()),
+            false => output::raw(&chunks, 0),
+        };
+
+        self.origin = origin;
+        self.chunks = chunks;
+        self.ines = ines;
+        self.symbols = symbols;
+        diagnostic::sort(&mut diagnostics);
+        self.diagnostics = diagnostics;
+        self.lines = lines;
+        self.placements = placements;
+        self.encoded = encoded_lines;
+
+        Ok(bytes)
+    }
+
+    // like `assemble_lines`, but into an object. a value that can't be worked out without knowing where a segment
+    // goes, or what an import is, is left as a fixup in place of its bytes
+    fn assemble_object_lines(&mut self, name: String, lines: Vec<Line>, mut diagnostics: Vec<Diagnostic>)
+                             -> Result<Object, Vec<Diagnostic>> {
+        let segments = Segments::of(&lines);
+
+        // imports count as far away, unless they're .importzp
+        let mut imports: Vec<String> = Vec::new();
+        let mut known = HashMap::new();
+        for line in lines.iter() {
+            if let Some(Directive::Import(ref names, zero_page)) = line.directive {
+                for name in names.iter().filter(|name| !imports.contains(name)).collect::<Vec<_>>() {
+                    known.insert(name.clone(), segments.provisional_import(imports.len(), zero_page));
+                    imports.push(name.clone());
+                }
+            }
+        }
+
+        let (symbols, placements) = match self.settle(&lines, Some(&segments), known) {
+            Ok(settled) => settled,
+            Err(err) => {
+                diagnostics.push(err);
+                return self.fail(diagnostics);
+            }
+        };
+
+        let relocatable = Parser::relocatable(&lines, &imports);
+        let is_relocatable = |expr: &Expr| expr.mentions_pc() || expr.symbols().iter().any(|&name| relocatable.contains(name));
+
+        let mut object = Object {
+            name: name,
+            segments: segments.names.iter().cloned().map(Segment::new).collect(),
+            symbols: Vec::new(),
+            imports: imports.clone(),
+        };
+
+        let mut exports = Vec::new();
+        let mut defined = HashSet::new();
+        let mut encoded_lines = vec![Vec::new(); lines.len()];
+
+        for (i, (line, &(addr, _))) in lines.iter().zip(placements.iter()).enumerate() {
+            let index = segments.of_line[i];
+
+            if let Some(name) = line.defines() {
+                let span = line.span_of(name, line.source.code);
+                if imports.contains(name) {
+                    diagnostics.push(line.error(span, format!("'{}' is imported, so it can't be defined here", name)));
+                } else if !defined.insert(name) {
+                    diagnostics.push(line.error(span, format!("'{}' is already defined", name)));
+                }
+            }
+
+            if let Some(ref label) = line.label {
+                object.symbols.push(Symbol {
+                    name: label.clone(),
+                    value: Value::Label(index, addr as usize),
+                    exported: false,
+                });
+            }
+
+            let operand_error = |err: String| line.error(line.operand_span, err);
+
+            match line.directive {
+                Some(Directive::Org(_)) => {
+                    let msg = ".org can't be used in an object file (the linker config says where segments go)";
+                    diagnostics.push(line.error(line.span(), String::from(msg)));
+
+                    continue;
+                }
+                Some(Directive::Ines(..)) => {
+                    let msg = "iNES header fields only work when assembling straight to an image";
+                    diagnostics.push(line.error(line.span(), String::from(msg)));
+
+                    continue;
+                }
+                Some(Directive::Equate(ref name, ref value)) => {
+                    let value = match is_relocatable(value) {
+                        true => value.clone(),
+                        false => {
+                            match value.eval(addr, &symbols) {
+                                Ok(value) => Expr::Number(value),
+                                Err(err) => {
+                                    diagnostics.push(operand_error(err));
+                                    continue;
+                                }
+                            }
+                        }
+                    };
+
+                    object.symbols.push(Symbol {
+                        name: name.clone(),
+                        value: Value::Equate(value, index, addr as usize),
+                        exported: false,
+                    });
+
+                    continue;
+                }
+                Some(Directive::Assert { ref cond, fatal, ref msg }) => {
+                    match is_relocatable(cond) {
+                        true => {
+                            let msg = ".assert can only check constants in an object file";
+                            diagnostics.push(operand_error(String::from(msg)));
+                        }
+                        false => diagnostics.extend(Parser::check(line, cond, fatal, msg, addr, &symbols)),
+                    }
+
+                    continue;
+                }
+                Some(Directive::Export(ref names)) => {
+                    exports.extend(names.iter().flat_map(|name| name.symbols()).map(|name| (name, line)));
+                    continue;
+                }
+                // the linker puts the segment somewhere that lines this up as well
+                Some(Directive::Align(ref boundary, _)) => {
+                    if let Ok(boundary @ 1..=0x10000) = boundary.eval(addr, &symbols) {
+                        let segment = &mut object.segments[index];
+                        segment.align = lcm(segment.align, boundary as usize);
+                    }
+                }
+                _ if line.instr.is_empty() => continue,
+                _ => {}
+            }
+
+            let pieces = match Parser::pieces(line, addr, &symbols) {
+                Ok(pieces) => pieces,
+                Err(err) => {
+                    diagnostics.push(err);
+                    continue;
+                }
+            };
+
+            let segment = &mut object.segments[index];
+            let start = segment.data.len();
+
+            for piece in pieces {
+                let (field, value) = match piece {
+                    Piece::Bytes(bytes) => {
+                        segment.data.extend(bytes);
+                        continue;
+                    }
+                    Piece::Value(field, value) => (field, value),
+                };
+
+                // a branch is relative to where it ends up, whatever it's to
+                if is_relocatable(value) || field == Field::Operand(AddrMode::Relative) {
+                    segment.fixups.push(Fixup {
+                        offset: segment.data.len(),
+                        pc: addr as usize,
+                        field: field.clone(),
+                        value: value.clone(),
+                        file: line.source.file.as_ref().map_or(String::from("<source>"), |file| file.to_string()),
+                        line: line.source.line,
+                    });
+
+                    let len = segment.data.len() + field.size();
+                    segment.data.resize(len, 0);
+                    continue;
+                }
+
+                match value.eval(addr, &symbols).and_then(|value| field.encode(value, addr)) {
+                    Ok(bytes) => segment.data.extend(bytes),
+                    Err(err) => diagnostics.push(operand_error(err)),
+                }
+            }
+
+            encoded_lines[i] = segment.data[start..].to_vec();
+        }
+
+        for (name, line) in exports {
+            match object.symbols.iter_mut().find(|symbol| symbol.name == name) {
+                Some(symbol) => symbol.exported = true,
+                None => {
+                    let span = line.span_of(name, line.operand_span);
+                    diagnostics.push(line.error(span, format!("'{}' is imported, so it can't be exported", name)));
+                }
+            }
+        }
+
+        if diagnostics.iter().any(Diagnostic::is_error) {
+            return self.fail(diagnostics);
+        }
+
+        // for the listing, labels are where they are in their segments
+        self.symbols = object.symbols
+            .iter()
+            .filter_map(|symbol| match symbol.value {
+                Value::Label(_, offset) => Some((symbol.name.clone(), offset as i64)),
+                Value::Equate(Expr::Number(value), ..) => Some((symbol.name.clone(), value)),
+                Value::Equate(..) => None,
             })
             .collect();
 
-        Box::new(result)
+        self.chunks = Vec::new();
+        self.ines = InesHeader::default();
+        diagnostic::sort(&mut diagnostics);
+        self.diagnostics = diagnostics;
+        self.lines = lines;
+        self.placements = placements;
+        self.encoded = encoded_lines;
+
+        Ok(object)
     }
 
-    fn to_addr_mode_with_value(addr_mode: AddrMode, value_str: &str) -> (AddrMode, Option<u16>) {
-        (addr_mode, Some(u16::from_str_radix(value_str, 16).unwrap()))
+    // the symbols that aren't known until the program's linked: labels, imports, and equates that use them or `*`
+    fn relocatable(lines: &[Line], imports: &[String]) -> HashSet<String> {
+        let mut relocatable: HashSet<String> = imports.iter().cloned().collect();
+        relocatable.extend(lines.iter().filter_map(|line| line.label.clone()));
+
+        loop {
+            let before = relocatable.len();
+
+            for line in lines.iter() {
+                if let Some(Directive::Equate(ref name, ref value)) = line.directive {
+                    if value.mentions_pc() || value.symbols().iter().any(|&name| relocatable.contains(name)) {
+                        relocatable.insert(name.clone());
+                    }
+                }
+            }
+
+            if relocatable.len() == before {
+                return relocatable;
+            }
+        }
     }
 
-    fn get_first_capture(captures: Option<regex::Captures>) -> &str {
-        captures.unwrap().get(1).unwrap().as_str()
+    // adds `bytes` at `addr`, keeping the chunks in address order: next to a chunk it runs on from or into is the
+    // same chunk, and a gap starts a new one. an .org can go back to anywhere that hasn't been assembled yet, so
+    // the only error is landing on bytes that have, and that's the first address they share
+    fn place(chunks: &mut Vec<Chunk>, addr: u16, bytes: Vec<u8>) -> Result<(), u16> {
+        let (start, end) = (addr as usize, addr as usize + bytes.len());
+        let i = chunks.iter().position(|&(chunk_start, _)| chunk_start as usize > start).unwrap_or(chunks.len());
+
+        if let Some(&(prev_start, ref prev)) = i.checked_sub(1).map(|prev| &chunks[prev]) {
+            if start < prev_start as usize + prev.len() {
+                return Err(addr);
+            }
+        }
+
+        if let Some(&(next_start, _)) = chunks.get(i) {
+            if end > next_start as usize {
+                return Err(next_start);
+            }
+        }
+
+        let i = match i.checked_sub(1) {
+            Some(prev) if chunks[prev].0 as usize + chunks[prev].1.len() == start => {
+                chunks[prev].1.extend(bytes);
+                prev
+            }
+            _ => {
+                chunks.insert(i, (addr, bytes));
+                i
+            }
+        };
+
+        match chunks.get(i + 1) {
+            Some(&(next_start, _)) if next_start as usize == end => {
+                let (_, next) = chunks.remove(i + 1);
+                chunks[i].1.extend(next);
+            }
+            _ => {}
+        }
+
+        Ok(())
     }
 
-    pub fn get_instr_addr_mode(rest_or: Option<&str>) -> (AddrMode, Option<u16>) {
-        // implicit
-        if rest_or.is_none() {
-            return (AddrMode::Implicit, None);
+    // an .assert that fails, as an error or a warning
+    fn check(line: &Line, cond: &Expr, fatal: bool, msg: &Option<String>, addr: u16, symbols: &HashMap<String, i64>)
+             -> Option<Diagnostic> {
+        let msg = msg.clone().unwrap_or_else(|| String::from("assertion failed"));
+
+        match cond.eval(addr, symbols) {
+            Ok(0) if fatal => Some(line.error(line.span(), msg)),
+            Ok(0) => Some(line.source.warning(line.span(), msg)),
+            Ok(_) => None,
+            Err(err) => Some(line.error(line.operand_span, err)),
         }
+    }
+
+    // one pass over the program: where each label and line lands and how big each line is, given what's known of
+    // the labels so far. values that can't be worked out yet count as zero, and lines that are wrong as empty;
+    // encoding reports them properly
+    fn layout(&self, lines: &[Line], known: &HashMap<String, i64>, segments: Option<&Segments>)
+              -> (HashMap<String, i64>, Vec<Placement>) {
+        // labels from earlier in this pass win over what the last pass thought
+        let mut symbols = known.clone();
+        let mut placements = Vec::with_capacity(lines.len());
+
+        // where each segment's got to. without segments, it's all one, from `origin`
+        let mut addrs = match segments {
+            Some(segments) => vec![0; segments.names.len()],
+            None => vec![self.origin],
+        };
+
+        for (i, line) in lines.iter().enumerate() {
+            let segment = segments.map_or(0, |segments| segments.of_line[i]);
+            let mut addr = addrs[segment];
 
-        let rest = rest_or.unwrap();
+            if let Some(Directive::Org(ref target)) = line.directive {
+                if let Ok(target) = target.eval(addr, &symbols) {
+                    addr = target as u16;
+                }
+            }
+
+            if let Some(ref label) = line.label {
+                let value = segments.map_or(addr as i64, |segments| segments.provisional(segment, addr));
+                symbols.insert(label.clone(), value);
+            }
 
-        let mut chars = rest.chars();
-        let first_char = chars.nth(0).unwrap_or('\0');
-        let last_char = chars.last().unwrap_or('\0');
+            if let Some(Directive::Equate(ref name, ref value)) = line.directive {
+                if let Ok(value) = value.eval(addr, &symbols) {
+                    symbols.insert(name.clone(), value);
+                }
+            }
 
-        // immediate
-        if first_char == '#' {
-            return Parser::to_addr_mode_with_value(AddrMode::Immediate, &rest[2..]);
+            let size = match (&line.directive, line.instr.is_empty()) {
+                (&Some(ref directive), _) => Parser::directive_size(directive, addr, &symbols).unwrap_or(0),
+                (&None, true) => 0,
+                (&None, false) => {
+                    // anything that can't be worked out yet (a forward reference, say) is assumed to be wide
+                    let value = line.value.as_ref().and_then(|value| value.eval(addr, &symbols).ok());
+                    Parser::select(line, value).map_or(0, |(_, mode)| Parser::size_of(&mode))
+                }
+            };
+
+            placements.push((addr, size));
+            addrs[segment] = addr.wrapping_add(size);
         }
 
-        // indirect
-        if first_char == '(' {
-            if last_char == 'Y' {
-                let value_str = Parser::get_first_capture(INDIRECT_Y_REGEX.captures(rest));
-                return Parser::to_addr_mode_with_value(AddrMode::IndirectY, &value_str);
+        (symbols, placements)
+    }
+
+    // the opcode and addressing mode for a line. the operand's written form only says which family of modes it's
+    // in (zero page or absolute, either indexed the same way); which one gets used depends on the value, if it's
+    // known yet, and what the instruction supports
+    fn select(line: &Line, value: Option<i64>) -> Result<(u8, AddrMode), Diagnostic> {
+        let mnemonic = line.instr.to_lowercase();
+
+        let modes = resolver::modes_of(&mnemonic);
+        if modes.is_empty() {
+            let msg = match similar_mnemonic(&mnemonic) {
+                Some(similar) => format!("unknown mnemonic '{}' (did you mean '{}'?)", line.instr, similar),
+                None => format!("unknown mnemonic '{}'", line.instr),
+            };
+
+            return Err(line.error(line.instr_span, msg));
+        }
+
+        let fits_zero_page = value.is_some_and(|value| (0..=0xff).contains(&value)) || line.forced_width;
+        // when the instruction has neither, the value picks which one the error's about
+        let pick = |zero_page: AddrMode, absolute: AddrMode| match (fits_zero_page && modes.contains(&zero_page), modes.contains(&absolute)) {
+            (true, _) => zero_page,
+            (false, false) if fits_zero_page => zero_page,
+            (false, _) => absolute,
+        };
+
+        let mode = match line.addr_mode {
+            // an `a:` or `z:` prefix already decided
+            ref mode if line.forced_width => mode.clone(),
+            AddrMode::ZeroPage | AddrMode::Absolute => pick(AddrMode::ZeroPage, AddrMode::Absolute),
+            AddrMode::ZeroPageX | AddrMode::AbsoluteX => pick(AddrMode::ZeroPageX, AddrMode::AbsoluteX),
+            AddrMode::ZeroPageY | AddrMode::AbsoluteY => pick(AddrMode::ZeroPageY, AddrMode::AbsoluteY),
+            ref mode => mode.clone(),
+        };
+
+        match resolver::opcode_for(&mnemonic, &mode) {
+            Some(opcode) => Ok((opcode, mode)),
+            None => Err(line.error(line.span(), format!("'{}' has no {} addressing mode", mnemonic, mode_name(&mode)))),
+        }
+    }
+
+    fn size_of(mode: &AddrMode) -> u16 {
+        match *mode {
+            AddrMode::Implicit | AddrMode::Accumulator => 1,
+            AddrMode::Absolute | AddrMode::AbsoluteX | AddrMode::AbsoluteY | AddrMode::Indirect => 3,
+            _ => 2,
+        }
+    }
+
+    // how much space a directive takes up at `addr`
+    fn directive_size(directive: &Directive, addr: u16, symbols: &HashMap<String, i64>) -> Result<u16, String> {
+        let size = match *directive {
+            Directive::Byte(ref data) => {
+                data.iter().fold(0, |size, data| match *data {
+                    Data::Expr(_) => size + 1,
+                    Data::Bytes(ref bytes) => size + bytes.len(),
+                })
+            }
+            Directive::Word(ref words) | Directive::Dbyt(ref words) => words.len() * 2,
+            Directive::LoBytes(ref values) | Directive::HiBytes(ref values) => values.len(),
+            Directive::Reserve(ref count, _) => {
+                let count = count.eval(addr, symbols)?;
+                if !(0..=0xffff).contains(&count) {
+                    return Err(format!("can't reserve {} bytes", count));
+                }
+
+                count as usize
             }
+            Directive::Align(ref boundary, _) => {
+                let boundary = boundary.eval(addr, symbols)?;
+                if !(1..=0x10000).contains(&boundary) {
+                    return Err(format!("can't align to {} bytes", boundary));
+                }
 
-            let value_str = Parser::get_first_capture(INDIRECT_X_REGEX.captures(rest));
-            return Parser::to_addr_mode_with_value(AddrMode::IndirectX, &value_str);
+                ((boundary - addr as i64 % boundary) % boundary) as usize
+            }
+            Directive::Org(_) |
+            Directive::Equate(..) |
+            Directive::Assert { .. } |
+            Directive::Ines(..) |
+            Directive::Segment(_) |
+            Directive::Import(..) |
+            Directive::Export(_) => 0,
+            Directive::Incbin { .. } | Directive::Include(_) => unreachable!("resolved by the reader"),
+        };
+
+        if size > 0xffff {
+            return Err(format!("{} bytes won't fit in memory", size));
         }
 
-        let (addr, addr_reg) = ABSOLUTE_AND_ZERO_PAGE_REGEX.captures(rest)
-            .map(|captures| {
-                let addr_reg = captures.get(2);
-                let addr = captures.get(1)
-                    .map(|a| u16::from_str_radix(&a.as_str(), 16).unwrap())
-                    .unwrap();
+        Ok(size as u16)
+    }
 
-                (addr, addr_reg)
-            })
-            .unwrap();
+    // what a line assembles to, as bytes that are known already and values that go in fields. `symbols` only have
+    // to be good enough to pick the addressing mode and work out sizes; the values are left to the caller
+    fn pieces<'a>(line: &'a Line, addr: u16, symbols: &HashMap<String, i64>) -> Result<Vec<Piece<'a>>, Diagnostic> {
+        let err = |err: String| line.error(line.operand_span, err);
+        let mut pieces = Vec::new();
 
-        let is_zero_page = addr <= 0xff;
+        let directive = match line.directive {
+            Some(ref directive) => directive,
+            None => {
+                let value = line.value.as_ref().and_then(|value| value.eval(addr, symbols).ok());
+                let (opcode, mode) = Parser::select(line, value)?;
+                pieces.push(Piece::Bytes(vec![opcode]));
 
-        // absolute / zero page
-        return match addr_reg.unwrap().as_str() {
-            "X" => {
-                return match is_zero_page {
-                    true => (AddrMode::ZeroPageX, Some(addr)),
-                    _ => (AddrMode::AbsoluteX, Some(addr)),
+                if let Some(ref value) = line.value {
+                    if Parser::size_of(&mode) > 1 {
+                        pieces.push(Piece::Value(Field::Operand(mode), value));
+                    }
+                }
+
+                return Ok(pieces);
+            }
+        };
+
+        match *directive {
+            Directive::Byte(ref data) => {
+                for data in data.iter() {
+                    pieces.push(match *data {
+                        Data::Expr(ref expr) => Piece::Value(Field::Byte, expr),
+                        Data::Bytes(ref bytes) => Piece::Bytes(bytes.clone()),
+                    });
+                }
+            }
+            Directive::Word(ref values) => pieces.extend(values.iter().map(|value| Piece::Value(Field::Word, value))),
+            Directive::Dbyt(ref values) => pieces.extend(values.iter().map(|value| Piece::Value(Field::BigWord, value))),
+            Directive::LoBytes(ref values) => pieces.extend(values.iter().map(|value| Piece::Value(Field::LowByte, value))),
+            Directive::HiBytes(ref values) => pieces.extend(values.iter().map(|value| Piece::Value(Field::HighByte, value))),
+            Directive::Reserve(_, ref fill) | Directive::Align(_, ref fill) => {
+                let fill = match *fill {
+                    Some(ref fill) => Field::Byte.encode(fill.eval(addr, symbols).map_err(&err)?, addr).map_err(&err)?[0],
+                    None => 0,
                 };
+
+                let size = Parser::directive_size(directive, addr, symbols).map_err(&err)?;
+                pieces.push(Piece::Bytes(vec![fill; size as usize]));
             }
-            "Y" => (AddrMode::AbsoluteY, Some(addr)),
-            "" => {
-                match is_zero_page {
-                    true => (AddrMode::ZeroPage, Some(addr)),
-                    _ => (AddrMode::Absolute, Some(addr)),
+            _ => {}
+        }
+
+        Ok(pieces)
+    }
+
+    fn encode(line: &Line, addr: u16, symbols: &HashMap<String, i64>) -> Result<Vec<u8>, Diagnostic> {
+        let err = |err: String| line.error(line.operand_span, err);
+        let mut bytes = Vec::new();
+
+        for piece in Parser::pieces(line, addr, symbols)? {
+            match piece {
+                Piece::Bytes(known) => bytes.extend(known),
+                Piece::Value(field, value) => {
+                    let value = value.eval(addr, symbols).map_err(&err)?;
+                    bytes.extend(field.encode(value, addr).map_err(&err)?);
                 }
             }
-            _ => (AddrMode::Unknown, None),
-        };
+        }
+
+        Ok(bytes)
+    }
+
+    pub fn into_lines(&mut self, input: &str) -> Result<Box<Vec<Line>>, Vec<Diagnostic>> {
+        let mut reader = Reader::new();
+        reader.read(input, None, 0);
+
+        match reader.finish() {
+            (lines, ref errors) if errors.is_empty() => Ok(Box::new(lines)),
+            (_, errors) => self.fail(errors),
+        }
     }
 }
 
+fn lcm(a: usize, b: usize) -> usize {
+    let (mut x, mut y) = (a, b);
+    while y != 0 {
+        let rem = x % y;
+        x = y;
+        y = rem;
+    }
+
+    a / x * b
+}
+
+// for error messages, which would otherwise show negative values in two's complement
+fn hex(val: i64) -> String {
+    match val < 0 {
+        true => format!("-${:x}", -val),
+        false => format!("${:x}", val),
+    }
+}
+
+// how error messages spell addressing modes, close to how they're written
+fn mode_name(mode: &AddrMode) -> String {
+    let name = match *mode {
+        AddrMode::Implicit => "implied",
+        AddrMode::Accumulator => "accumulator",
+        AddrMode::Immediate => "immediate",
+        AddrMode::Relative => "relative",
+        AddrMode::ZeroPage => "zero page",
+        AddrMode::ZeroPageX => "zero page,X",
+        AddrMode::ZeroPageY => "zero page,Y",
+        AddrMode::Absolute => "absolute",
+        AddrMode::AbsoluteX => "absolute,X",
+        AddrMode::AbsoluteY => "absolute,Y",
+        AddrMode::Indirect => "indirect",
+        AddrMode::IndirectX => "(indirect,X)",
+        AddrMode::IndirectY => "(indirect),Y",
+        ref mode => return format!("{:?}", mode),
+    };
+
+    String::from(name)
+}
+
+// a real mnemonic that `mnemonic` is probably a typo of: one letter wrong, or two swapped
+fn similar_mnemonic(mnemonic: &str) -> Option<&'static str> {
+    let typed: Vec<char> = mnemonic.chars().collect();
+
+    resolver::OPCODES.iter().map(|&(_, name, _)| name).find(|name| {
+        let name: Vec<char> = name.chars().collect();
+        if name.len() != typed.len() {
+            return false;
+        }
+
+        let diffs: Vec<usize> = (0..name.len()).filter(|&i| name[i] != typed[i]).collect();
+        match diffs.len() {
+            1 => true,
+            2 => diffs[1] == diffs[0] + 1 && name[diffs[0]] == typed[diffs[1]] && name[diffs[1]] == typed[diffs[0]],
+            _ => false,
+        }
+    })
+}
+
 #[cfg(test)]
 mod test {
     use super::Parser;
     use super::AddrMode;
+    use super::{Diagnostic, Format, InesHeader};
+    use super::{Field, Value};
     use super::Line;
+    use super::Expr;
+    use cpu::Cpu;
+    use cpu::instr::resolver;
+    use std::env;
+    use std::fs;
+    use std::rc::Rc;
     use std::time;
 
+    // the first error, as `line N: message`
+    fn first_error<T>(result: Result<T, Vec<Diagnostic>>) -> String {
+        let errors = result.err().expect("expected an error");
+        let err = errors.iter().find(|err| err.is_error()).unwrap();
+
+        format!("line {}: {}", err.line, err.message)
+    }
+
     fn assert_line(line: &Line, instr: &str, rest: Option<&str>, addr_mode: AddrMode, value: Option<u16>) {
         assert_eq!(&line.instr, instr);
         assert_eq!(line.rest.as_ref().map(|r| r.as_str()), rest);
         assert_eq!(line.addr_mode, addr_mode);
-        assert_eq!(line.value, value);
+        assert_eq!(line.value, value.map(|value| Expr::Number(value as i64)));
     }
 
     #[test]
@@ -143,7 +989,7 @@ mod test {
             beq $0f
             bit
             lda ($1000),Y
-        ");
+        ").unwrap();
 
         assert_line(&lines[0],
                     "lda",
@@ -158,7 +1004,7 @@ mod test {
         assert_line(&lines[2],
                     "beq",
                     Some("$0f"),
-                    AddrMode::ZeroPage,
+                    AddrMode::Relative,
                     Some(0x0f));
         assert_line(&lines[3], "bit", None, AddrMode::Implicit, None);
         assert_line(&lines[4],
@@ -170,10 +1016,598 @@ mod test {
         println!("into_lines | elapsed: {:?}", now.elapsed().as_secs());
     }
 
+    #[test]
+    fn parse_number() {
+        assert_eq!(super::parse_number("$beef"), Some(0xbeef));
+        assert_eq!(super::parse_number("%1010"), Some(0b1010));
+        assert_eq!(super::parse_number("42"), Some(42));
+        assert_eq!(super::parse_number("$"), None);
+        assert_eq!(super::parse_number("%12"), None);
+    }
+
     #[test]
     fn get_instr_addr_mode() {
-        let imm = Parser::get_instr_addr_mode(Some("#$0011"));
-        assert_eq!(imm.0, AddrMode::Immediate);
-        assert_eq!(imm.1, Some(0x0011));
+        let imm = super::operand::parse("lda", "#$0011").unwrap();
+        assert_eq!(imm.addr_mode, AddrMode::Immediate);
+        assert_eq!(imm.value, Some(Expr::Number(0x0011)));
+    }
+
+    #[test]
+    fn operand_forms() {
+        let bytes = Parser::new(0x10)
+            .assemble("
+                LSR A
+                ROL
+                LDA z:ptr       ; ptr isn't known yet, but z: says it'll fit
+                STA a:$10,X
+                LDX $10,Y
+                JMP ($1234)
+                LDA #';'        ; not a comment
+            ptr:
+            ")
+            .unwrap();
+
+        assert_eq!(bytes,
+                   vec![0x4a, 0x2a, 0xa5, 0x1e, 0x9d, 0x10, 0x00, 0xb6, 0x10, 0x6c, 0x34, 0x12, 0xa9, 0x3b]);
+
+        assert!(first_error(Parser::new(0).assemble("lda ($12),Z")).starts_with("line 1: only Y"));
+    }
+
+    #[test]
+    fn assemble_with_labels() {
+        let mut parser = Parser::new(0x6000);
+        let bytes = parser.assemble("
+            start:  ldx #0          ; count up to 5
+            loop:   inx
+                    stx counter
+                    cpx #5
+                    bne loop
+                    jsr done
+                    jmp (vector)
+            done:   asl
+                    lda table,X
+                    ldx table,Y
+                    ldx $10,Y
+                    rts
+            counter:
+            vector:
+            table:
+        ").unwrap();
+
+        assert_eq!(bytes,
+                   vec![0xa2, 0x00,
+                        0xe8,
+                        0x8e, 0x1a, 0x60,
+                        0xe0, 0x05,
+                        0xd0, 0xf8,
+                        0x20, 0x10, 0x60,
+                        0x6c, 0x1a, 0x60,
+                        0x0a,
+                        0xbd, 0x1a, 0x60,
+                        0xbe, 0x1a, 0x60,
+                        0xb6, 0x10,
+                        0x60]);
+
+        assert_eq!(parser.symbols["loop"], 0x6002);
+        assert_eq!(parser.symbols["table"], 0x601a);
+    }
+
+    #[test]
+    fn forward_references_shrink_to_zero_page() {
+        // `data` ends up at $0e, but that isn't known until after the first pass
+        let bytes = Parser::new(0).assemble("
+                lda data
+                sta data,X
+                lda (data),Y
+                jmp end
+            end:
+                brk
+                nop
+                nop
+                nop
+                nop
+            data:
+        ").unwrap();
+
+        assert_eq!(bytes, vec![0xa5, 0x0e, 0x95, 0x0e, 0xb1, 0x0e, 0x4c, 0x09, 0x00, 0x00, 0xea, 0xea, 0xea, 0xea]);
+    }
+
+    #[test]
+    fn assemble_errors() {
+        assert!(first_error(Parser::new(0).assemble("foo #1")).contains("unknown mnemonic 'foo'"));
+        assert!(first_error(Parser::new(0).assemble("lda missing")).contains("undefined symbol 'missing'"));
+        assert!(first_error(Parser::new(0).assemble("a:\na: nop")).contains("'a' is already defined"));
+        assert!(first_error(Parser::new(0).assemble("lda #$100")).contains("too wide for immediate addressing"));
+        assert_eq!(first_error(Parser::new(0).assemble("stx $1234,X")), "line 1: 'stx' has no absolute,X addressing mode");
+        assert_eq!(first_error(Parser::new(0).assemble("nop\nbeq $90")),
+                   "line 2: branch to $0090 is 141 bytes away, but a branch can only reach 127 bytes forward (use jmp?)");
+    }
+
+    #[test]
+    fn diagnostics() {
+        let errors = Parser::new(0)
+            .assemble("start:  lda #$100\n        ldz #1\n        bne nowhere\n        .if\n        .endif\n\
+                       .macro m\n\tlda #256\n.endmacro\n\tm ; expands to a mistake\n        jmp start")
+            .unwrap_err();
+
+        // every mistake comes back, not just the first, in the order they're in the source
+        assert_eq!(errors.iter().map(|err| err.to_string()).collect::<Vec<_>>(),
+                   vec!["<source>:1:13: error: $100 is too wide for immediate addressing, which takes -$80 to $ff",
+                        "<source>:2:9: error: unknown mnemonic 'ldz' (did you mean 'lda'?)",
+                        "<source>:3:13: error: undefined symbol 'nowhere'",
+                        "<source>:4:9: error: expected an expression",
+                        "<source>:9:2: error: $100 is too wide for immediate addressing, which takes -$80 to $ff"]);
+
+        assert_eq!(errors[2].render(),
+                   "error: undefined symbol 'nowhere'\n --> <source>:3:13\n  |\n3 |         bne nowhere\n  |             ^^^^^^^");
+        assert_eq!(errors[4].render(),
+                   "error: $100 is too wide for immediate addressing, which takes -$80 to $ff\n --> <source>:9:2\n  |\n\
+                    9 |  m ; expands to a mistake\n  |  ^");
+    }
+
+    #[test]
+    fn expressions() {
+        let mut parser = Parser::new(0x6000);
+        let bytes = parser.assemble("
+                    lda #<table
+                    sta ptr
+                    lda #>table
+                    sta ptr+1
+                    lda #-1
+                    ldx #(table - * + 2) / 2 == 5
+                    lda (<ptr),Y
+                    bne *+2
+                    jmp (table+2)
+            table:
+            ptr:
+        ").unwrap();
+
+        assert_eq!(bytes,
+                   vec![0xa9, 0x15,
+                        0x8d, 0x15, 0x60,
+                        0xa9, 0x60,
+                        0x8d, 0x16, 0x60,
+                        0xa9, 0xff,
+                        0xa2, 0x01,
+                        0xb1, 0x15,
+                        0xd0, 0x00,
+                        0x6c, 0x17, 0x60]);
+    }
+
+    #[test]
+    fn range_checks() {
+        let err = |source| first_error(Parser::new(0).assemble(source));
+
+        assert_eq!(err("lda #$ff+1"), "line 1: $100 is too wide for immediate addressing, which takes -$80 to $ff");
+        assert_eq!(err("lda #-129"), "line 1: -$81 is too wide for immediate addressing, which takes -$80 to $ff");
+        assert_eq!(err("lda $ffff+1"), "line 1: $10000 is too wide for absolute addressing, which takes $0 to $ffff");
+        assert_eq!(err("lda 0-1"), "line 1: -$1 is too wide for absolute addressing, which takes $0 to $ffff");
+        assert_eq!(err("lda z:$100"), "line 1: $100 is too wide for zero page addressing, which takes $0 to $ff");
+        assert_eq!(err("lda ($100,X)"), "line 1: $100 is too wide for (indirect,X) addressing, which takes $0 to $ff");
+        assert_eq!(err("lda #1/0"), "line 1: division by zero");
+        assert_eq!(err("beq -2"), "line 1: branch target -$2 isn't an address");
+
+        // high and low bytes always fit
+        assert!(Parser::new(0).assemble("lda #>$12345
+lda #<-1").is_ok());
+    }
+
+    #[test]
+    fn data_directives() {
+        let bytes = Parser::new(0x6000).assemble("
+                    .byte 1, \"ab\", -1   ; mixed
+                    .db 'c'
+                    .word $1234, table
+                    .dw -2
+                    .dbyt $1234
+                    .asciiz \"hi;\"
+                    .lobytes table, $1234
+                    .hibytes table, $1234
+            table:  .res 2
+                    .ds 1, $ea
+                    .fill 2, 'x'
+                    .align 4, $ff
+            end:
+        ").unwrap();
+
+        assert_eq!(bytes,
+                   vec![0x01, 0x61, 0x62, 0xff,
+                        0x63,
+                        0x34, 0x12, 0x15, 0x60,
+                        0xfe, 0xff,
+                        0x12, 0x34,
+                        0x68, 0x69, 0x3b, 0x00,
+                        0x15, 0x34,
+                        0x60, 0x12,
+                        0x00, 0x00,
+                        0xea,
+                        0x78, 0x78,
+                        0xff, 0xff]);
+    }
+
+    #[test]
+    fn rom_image() {
+        let mut parser = Parser::new(0);
+        let bytes = parser.assemble("
+                    .org $fff0
+            reset:  sei
+                    jmp reset
+            nmi:    rti
+                    .org $fffa
+                    .word nmi, reset, reset
+        ").unwrap();
+
+        assert_eq!(parser.origin, 0xfff0);
+        assert_eq!(bytes.len(), 0x10);
+        assert_eq!(&bytes[..5], &[0x78, 0x4c, 0xf0, 0xff, 0x40]);
+        assert_eq!(&bytes[5..10], &[0; 5]);
+        assert_eq!(&bytes[10..], &[0xf4, 0xff, 0xf0, 0xff, 0xf0, 0xff]);
+        assert_eq!(parser.chunks,
+                   vec![(0xfff0, vec![0x78, 0x4c, 0xf0, 0xff, 0x40]), (0xfffa, vec![0xf4, 0xff, 0xf0, 0xff, 0xf0, 0xff])]);
+
+        // going back to somewhere that hasn't been assembled slots in before, and joins up with what it runs into
+        let mut parser = Parser::new(0);
+        assert_eq!(parser.assemble(".org $100\nnop\n.org $50\nnop\n.org $ff\nnop").unwrap()[..2], [0xea, 0x00]);
+        assert_eq!(parser.chunks, vec![(0x50, vec![0xea]), (0xff, vec![0xea, 0xea])]);
+
+        let mut parser = Parser::new(0);
+        parser.assemble(".inesprg 1\n.ineschr 1\n.inesmap 1\n.inesmir 1\n.org $c000\nrts").unwrap();
+
+        assert_eq!(parser.ines,
+                   InesHeader {
+                       prg_banks: Some(1),
+                       chr_banks: 1,
+                       mapper: 1,
+                       mirroring: 1,
+                   });
+        assert_eq!(&Format::Ines.write(&parser.chunks, &parser.ines, &[], 0xff).unwrap()[..8],
+                   &[0x4e, 0x45, 0x53, 0x1a, 1, 1, 0x11, 0x00]);
+        assert_eq!(first_error(Parser::new(0).assemble(".inesmir 16")),
+                   "line 1: 16 is out of range for .inesmir (it takes 0 to 15)");
+    }
+
+    #[test]
+    fn include_and_incbin() {
+        let dir = env::temp_dir().join("sixty-five-oh-too-asm-include");
+        fs::create_dir_all(dir.join("lib")).unwrap();
+
+        fs::write(dir.join("main.s"), "start: .include \"lib/util.s\"\n.incbin \"lib/data.bin\", 1, 2\n").unwrap();
+        fs::write(dir.join("lib/util.s"), "nop\nclear: lda #0\n.incbin \"data.bin\"\n").unwrap();
+        fs::write(dir.join("lib/data.bin"), [1, 2, 3, 4]).unwrap();
+        fs::write(dir.join("loop.s"), ".include \"loop.s\"\n").unwrap();
+        fs::write(dir.join("bad.s"), "nop\n.incbin \"lib/data.bin\", 3, 2\n").unwrap();
+
+        let path = |name: &str| dir.join(name).display().to_string();
+
+        let mut parser = Parser::new(0x6000);
+        assert_eq!(parser.assemble_file(&path("main.s")).unwrap(),
+                   vec![0xea, 0xa9, 0x00, 0x01, 0x02, 0x03, 0x04, 0x02, 0x03]);
+        assert_eq!((parser.symbols["start"], parser.symbols["clear"]), (0x6000, 0x6001));
+
+        assert!(first_error(Parser::new(0).assemble_file(&path("loop.s"))).contains("nested more than 16 deep"));
+        assert!(Parser::new(0).assemble_file(&path("bad.s")).unwrap_err()[0].to_string().starts_with(&format!("{}:2:", path("bad.s"))));
+        assert_eq!(Parser::new(0).assemble_file(&path("none.s")).unwrap_err()[0].file, Some(Rc::new(path("none.s"))));
+        assert!(first_error(Parser::new(0).assemble(".include \"missing.s\"")).starts_with("line 1: missing.s: "));
+    }
+
+    #[test]
+    fn directive_errors() {
+        let err = |source| first_error(Parser::new(0).assemble(source));
+
+        assert_eq!(err(".byte 256"), "line 1: $100 doesn't fit in a byte");
+        assert_eq!(err(".word $10000"), "line 1: $10000 doesn't fit in 16 bits");
+        assert_eq!(err(".res -1"), "line 1: can't reserve -1 bytes");
+        assert_eq!(err(".align 0"), "line 1: can't align to 0 bytes");
+        assert_eq!(err(".res count"), "line 1: undefined symbol 'count'");
+        assert_eq!(err(".org $10\nnop\n.org $10\nnop"), "line 4: $0010 has already been assembled");
+        assert_eq!(err(".org $10\nnop\n.org $0e\nlda $1234"), "line 4: $0010 has already been assembled");
+        assert_eq!(err(".org $fffe\nnop\nlda $1234"), "line 3: runs past $ffff");
+        assert_eq!(err(".org $fffe\n.word 1\nlabel:\nnop"), "line 4: runs past $ffff");
+        assert_eq!(err(".org $ff00\n.res $101"), "line 2: runs past $ffff");
+        assert_eq!(Parser::new(0).assemble(".org $fffa\n.word 1, 2, 3\nend:").map(|bytes| bytes.len()), Ok(6));
+        assert_eq!(err(".org -1"), "line 1: .org -$1 isn't an address");
+        assert_eq!(err(".bogus"), "line 1: unknown directive '.bogus'");
+
+        // a forward reference in a .res keeps moving the label it refers to
+        assert!(err(".res end + 1\nend:").contains("never settled"));
+    }
+
+    #[test]
+    fn macros() {
+        let mut parser = Parser::new(0x6000);
+        let bytes = parser.assemble("
+            PPUADDR = $2006
+            ptr = $10
+
+            .macro inc16 addr
+                    inc addr
+                    bne skip
+                    inc addr+1
+            skip:
+            .endmacro
+
+            .macro ppu_addr value
+                    lda #>(value)
+                    sta PPUADDR
+                    lda #<(value)
+                    sta PPUADDR
+            .endmacro
+
+            start:  inc16 ptr
+                    inc16 ptr+2
+                    ppu_addr $2000 + 32 * 2
+        ").unwrap();
+
+        assert_eq!(bytes,
+                   vec![0xe6, 0x10, 0xd0, 0x02, 0xe6, 0x11,
+                        0xe6, 0x12, 0xd0, 0x02, 0xe6, 0x13,
+                        0xa9, 0x20, 0x8d, 0x06, 0x20, 0xa9, 0x40, 0x8d, 0x06, 0x20]);
+
+        assert_eq!(parser.symbols["PPUADDR"], 0x2006);
+        assert_eq!((parser.symbols["start"], parser.symbols["skip__1"], parser.symbols["skip__2"]), (0x6000, 0x6006, 0x600c));
+    }
+
+    #[test]
+    fn cheap_locals_around_macros() {
+        // the label inside inc16 mustn't take @l away from main
+        let bytes = Parser::new(0x6000).assemble("
+            .macro inc16 addr
+                    inc addr
+                    bne skip
+                    inc addr+1
+            skip:
+            .endmacro
+
+            main:   nop
+            @l:     nop
+                    inc16 $10
+                    bne @l
+        ").unwrap();
+
+        assert_eq!(bytes, vec![0xea, 0xea, 0xe6, 0x10, 0xd0, 0x02, 0xe6, 0x11, 0xd0, 0xf7]);
+    }
+
+    #[test]
+    fn conditional_assembly() {
+        let source = |defines: &str| format!("
+            {}
+            .ifdef PAL
+                    .byte 50
+            .elseif REGION = 2
+                    .byte 2
+            .else
+                    .byte 60
+              .ifndef PAL
+                    .byte 1
+              .endif
+              .if 0
+                    bogus instruction
+              .endif
+            .endif
+        ", defines);
+
+        assert_eq!(Parser::new(0).assemble(&source("PAL = 1")).unwrap(), vec![50]);
+        assert_eq!(Parser::new(0).assemble(&source("REGION = 1 + 1")).unwrap(), vec![2]);
+        assert_eq!(Parser::new(0).assemble(&source("REGION = 0")).unwrap(), vec![60, 1]);
+    }
+
+    #[test]
+    fn repeat_and_assert() {
+        let mut parser = Parser::new(0);
+        let bytes = parser.assemble("
+            COLUMNS = 3
+            .repeat COLUMNS, i
+              .repeat 2, j
+                    .byte i * 16 + j
+              .endrepeat
+            .endrepeat
+            .assert * = 6, error, \"table's the wrong size\"
+            .assert * < 4, warning, \"table's getting big\"
+        ").unwrap();
+
+        assert_eq!(bytes, vec![0x00, 0x01, 0x10, 0x11, 0x20, 0x21]);
+        assert_eq!(parser.diagnostics.iter().map(|warning| warning.to_string()).collect::<Vec<_>>(),
+                   vec!["<source>:9:13: warning: table's getting big"]);
+
+        assert_eq!(first_error(Parser::new(0).assemble("nop\n.assert end = 0, error\nend:")), "line 2: assertion failed");
+    }
+
+    #[test]
+    fn preprocessor_errors() {
+        let err = |source| first_error(Parser::new(0).assemble(source));
+
+        assert_eq!(err(".if 1\nnop"), "line 1: missing .endif");
+        assert_eq!(err(".macro m\nnop"), "line 1: missing .endmacro");
+        assert_eq!(err(".endif"), "line 1: .endif without .if");
+        assert_eq!(err(".if 1\n.else\n.else\n.endif"), "line 3: .else after .else");
+        assert_eq!(err(".repeat 2\n.endmacro"), "line 1: .endmacro ends the wrong block");
+        assert_eq!(err(".if later\n.endif\nlater = 1"), "line 1: undefined symbol 'later' (.if can only use equates defined above it)");
+        assert_eq!(err(".repeat *\n.endrepeat"), "line 1: .repeat can't use '*'");
+        assert_eq!(err(".macro m a\nnop\n.endmacro\nm 1, 2"), "line 4: expected at most 1 arguments but got 2");
+        assert_eq!(err(".macro m\nm\n.endmacro\nm"), "line 4: macros are nested more than 64 deep");
+        assert_eq!(err("x = 1\nx = 2"), "line 2: 'x' is already defined");
+    }
+
+    #[test]
+    fn scopes() {
+        let mut parser = Parser::new(0x6000);
+        let bytes = parser.assemble("
+            count = 1
+
+            .proc clear
+                    count = 2
+                    ldx #count
+            loop:   dex
+                    bne loop
+                    jmp done
+              .scope inner
+                    count = 3
+                    lda #count + ::count
+                    jmp clear::loop
+              .endscope
+            done:   rts
+            .endproc
+
+                    jsr clear
+                    lda #clear::inner::count
+                    jmp loop
+            loop:   nop
+        ").unwrap();
+
+        assert_eq!(bytes,
+                   vec![0xa2, 0x02,
+                        0xca,
+                        0xd0, 0xfd,
+                        0x4c, 0x0d, 0x60,
+                        0xa9, 0x04,
+                        0x4c, 0x02, 0x60,
+                        0x60,
+                        0x20, 0x00, 0x60,
+                        0xa9, 0x03,
+                        0x4c, 0x16, 0x60,
+                        0xea]);
+
+        assert_eq!((parser.symbols["clear"], parser.symbols["clear::loop"], parser.symbols["loop"]), (0x6000, 0x6002, 0x6016));
+    }
+
+    #[test]
+    fn cheap_local_and_anonymous_labels() {
+        let bytes = Parser::new(0x6000).assemble("
+            first:  ldx #2
+            @loop:  dex
+                    bne @loop
+                    beq :+
+            :       jmp :-
+            :       jmp :--
+            second: jmp @loop
+            @loop:  bne :++
+                    bne :+
+            :       nop
+            :
+        ").unwrap();
+
+        assert_eq!(bytes,
+                   vec![0xa2, 0x02,
+                        0xca,
+                        0xd0, 0xfd,
+                        0xf0, 0x00,
+                        0x4c, 0x07, 0x60,
+                        0x4c, 0x07, 0x60,
+                        0x4c, 0x10, 0x60,
+                        0xd0, 0x03,
+                        0xd0, 0x00,
+                        0xea]);
+
+        let err = |source| first_error(Parser::new(0).assemble(source));
+
+        assert_eq!(err("jmp :-"), "line 1: there's no anonymous label that far back");
+        assert_eq!(err(":\njmp :++\n:"), "line 2: there's no anonymous label that far ahead");
+        assert_eq!(err(".proc p\nnop"), "line 1: missing .endproc");
+        assert_eq!(err(".scope\n.endproc"), "line 2: .endproc ends the wrong block");
+        assert_eq!(err(".proc p\nx: nop\n.endproc\njmp x"), "line 4: undefined symbol 'x'");
+    }
+
+    // some of these go by other names in the disassembly
+    const UNDOCUMENTED: &'static [&'static str] = &["aax", "dcp", "dop", "isc", "lax", "rla", "rra", "slo", "sre", "top"];
+
+    #[test]
+    fn every_official_opcode_round_trips() {
+        // every (mnemonic, mode) assembles to the opcode the cpu decodes back into the same instruction
+        let mut mismatches = Vec::new();
+
+        for &(opcode, mnemonic, ref mode) in resolver::OPCODES {
+            if resolver::opcode_for(mnemonic, mode) != Some(opcode) || UNDOCUMENTED.contains(&mnemonic) {
+                continue;
+            }
+
+            let operand = match *mode {
+                AddrMode::Implicit => "",
+                AddrMode::Accumulator => "",
+                AddrMode::Immediate => "#$12",
+                AddrMode::Relative => "$6010",
+                AddrMode::ZeroPage => "$12",
+                AddrMode::ZeroPageX => "$12,X",
+                AddrMode::ZeroPageY => "$12,Y",
+                AddrMode::Absolute => "$1234",
+                AddrMode::AbsoluteX => "$1234,X",
+                AddrMode::AbsoluteY => "$1234,Y",
+                AddrMode::Indirect => "($1234)",
+                AddrMode::IndirectX => "($12,X)",
+                AddrMode::IndirectY => "($12),Y",
+                _ => panic!("unexpected mode {:?}", mode),
+            };
+
+            let source = format!("{} {}", mnemonic, operand);
+            let bytes = Parser::new(0x6000).assemble(&source).unwrap();
+
+            assert_eq!(bytes[0], opcode, "{}", source);
+
+            let mut cpu = Cpu::new();
+            cpu.load_program(0x6000, &bytes);
+
+            let line = ::debug::disasm::disassemble(&cpu, 0x6000);
+            if line.bytes != bytes || !line.text.starts_with(mnemonic) {
+                mismatches.push(format!("{} -> {:02x?} -> {}", source, bytes, line.text));
+            }
+        }
+
+        assert!(mismatches.is_empty(), "{:#?}", mismatches);
+    }
+
+    #[test]
+    fn objects() {
+        let object = Parser::new(0).assemble_object("
+                    .importzp ptr
+                    .import far
+            COUNT = 3
+            END = buffer + COUNT
+                    .zeropage
+            buffer: .res COUNT
+                    .code
+            start:  lda #COUNT
+                    lda buffer
+                    lda far,x
+                    sta (ptr),y
+                    bne start
+                    .word END
+        ").unwrap();
+
+        assert_eq!(object.imports, vec![String::from("ptr"), String::from("far")]);
+
+        // segments are in the order they're used, after CODE
+        let (code, zero_page) = (&object.segments[0], &object.segments[1]);
+        assert_eq!((code.name.as_str(), zero_page.name.as_str()), ("CODE", "ZEROPAGE"));
+        assert_eq!(zero_page.data, vec![0, 0, 0]);
+
+        // constants are filled in, and anything to do with labels or imports is left to the linker, zero page if the
+        // assembler can tell it will be
+        assert_eq!(code.data,
+                   vec![0xa9, 0x03, 0xa5, 0x00, 0xbd, 0x00, 0x00, 0x91, 0x00, 0xd0, 0x00, 0x00, 0x00]);
+
+        let fixups: Vec<(usize, &Field, &Expr)> =
+            code.fixups.iter().map(|fixup| (fixup.offset, &fixup.field, &fixup.value)).collect();
+        assert_eq!(fixups,
+                   vec![(3, &Field::Operand(AddrMode::ZeroPage), &Expr::Symbol(String::from("buffer"))),
+                        (5, &Field::Operand(AddrMode::AbsoluteX), &Expr::Symbol(String::from("far"))),
+                        (8, &Field::Operand(AddrMode::IndirectY), &Expr::Symbol(String::from("ptr"))),
+                        (10, &Field::Operand(AddrMode::Relative), &Expr::Symbol(String::from("start"))),
+                        (11, &Field::Word, &Expr::Symbol(String::from("END")))]);
+        assert_eq!(code.fixups[3].pc, 9);
+
+        let symbol = |name| object.symbols.iter().find(|symbol| symbol.name == name).map(|symbol| &symbol.value);
+        assert_eq!(symbol("COUNT"), Some(&Value::Equate(Expr::Number(3), 0, 0)));
+        assert_eq!(symbol("start"), Some(&Value::Label(0, 0)));
+        assert_eq!(symbol("buffer"), Some(&Value::Label(1, 0)));
+
+        // what only makes sense once it's placed can't be used, and what only makes sense for objects needs one
+        let err = |source| first_error(Parser::new(0).assemble_object(source));
+        assert_eq!(err(".org $c000"),
+                   "line 1: .org can't be used in an object file (the linker config says where segments go)");
+        assert_eq!(err("start: rts\n.assert start < $100, error"),
+                   "line 2: .assert can only check constants in an object file");
+        assert_eq!(err(".import putc\n.export putc"), "line 2: 'putc' is imported, so it can't be exported");
+        assert_eq!(err(".import putc\nputc: rts"), "line 2: 'putc' is imported, so it can't be defined here");
+        assert_eq!(first_error(Parser::new(0).assemble(".segment \"CODE\"")),
+                   "line 1: segments and imports are for object files, which the linker puts together");
     }
 }
@@ -0,0 +1,662 @@
+extern crate byteorder;
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::{Cursor, Read, Write};
+
+use self::byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use cpu::addr::AddrMode;
+
+use super::directive::Directive;
+use super::expr::{BinaryOp, Expr, UnaryOp};
+use super::{hex, mode_name, Line, Parser};
+
+const MAGIC: &'static [u8] = b"65OB";
+pub const OBJECT_VERSION: u8 = 1;
+
+// where lines go until a .segment says otherwise
+pub const DEFAULT_SEGMENT: &'static str = "CODE";
+// the one segment the assembler knows is in zero page, so operands using its labels can be
+pub const ZERO_PAGE_SEGMENT: &'static str = "ZEROPAGE";
+
+// nothing bigger fits in the address space, so a longer segment means the file's damaged
+const MAX_SEGMENT_LEN: usize = 0x10000;
+
+const VALUE_LABEL: u8 = 0;
+const VALUE_EQUATE: u8 = 1;
+
+const EXPR_NUMBER: u8 = 0;
+const EXPR_SYMBOL: u8 = 1;
+const EXPR_PC: u8 = 2;
+const EXPR_UNARY: u8 = 3;
+const EXPR_BINARY: u8 = 4;
+
+// fields are stored as their index in here, after the operand modes
+const FIELDS: [Field; 5] = [Field::Byte, Field::Word, Field::BigWord, Field::LowByte, Field::HighByte];
+
+// the modes an operand can have, stored as their index in here
+const OPERAND_MODES: [AddrMode; 11] = [AddrMode::Immediate,
+                                       AddrMode::Relative,
+                                       AddrMode::ZeroPage,
+                                       AddrMode::ZeroPageX,
+                                       AddrMode::ZeroPageY,
+                                       AddrMode::Absolute,
+                                       AddrMode::AbsoluteX,
+                                       AddrMode::AbsoluteY,
+                                       AddrMode::Indirect,
+                                       AddrMode::IndirectX,
+                                       AddrMode::IndirectY];
+
+const UNARY_OPS: [UnaryOp; 5] = [UnaryOp::Neg, UnaryOp::Not, UnaryOp::BitNot, UnaryOp::LowByte, UnaryOp::HighByte];
+
+const BINARY_OPS: [BinaryOp; 18] = [BinaryOp::Mul,
+                                    BinaryOp::Div,
+                                    BinaryOp::Mod,
+                                    BinaryOp::Add,
+                                    BinaryOp::Sub,
+                                    BinaryOp::Shl,
+                                    BinaryOp::Shr,
+                                    BinaryOp::Lt,
+                                    BinaryOp::Le,
+                                    BinaryOp::Gt,
+                                    BinaryOp::Ge,
+                                    BinaryOp::Eq,
+                                    BinaryOp::Ne,
+                                    BinaryOp::BitAnd,
+                                    BinaryOp::BitXor,
+                                    BinaryOp::BitOr,
+                                    BinaryOp::And,
+                                    BinaryOp::Or];
+
+// where a value goes in the output, which decides how it's written and what it has to fit in
+#[derive(Clone, Debug, PartialEq)]
+pub enum Field {
+    // an instruction's operand, in whatever form its addressing mode takes
+    Operand(AddrMode),
+    // .byte
+    Byte,
+    // .word, little endian
+    Word,
+    // .dbyt, big endian
+    BigWord,
+    // .lobytes and .hibytes, which take anything
+    LowByte,
+    HighByte,
+}
+
+impl Field {
+    pub fn size(&self) -> usize {
+        match *self {
+            Field::Operand(ref mode) => Parser::size_of(mode) as usize - 1,
+            Field::Byte | Field::LowByte | Field::HighByte => 1,
+            Field::Word | Field::BigWord => 2,
+        }
+    }
+
+    // `value` as it's written in the field, on a line at `pc`
+    pub fn encode(&self, value: i64, pc: u16) -> Result<Vec<u8>, String> {
+        let word = || match (-0x8000..=0xffff).contains(&value) {
+            true => Ok(value as u16),
+            false => Err(format!("{} doesn't fit in 16 bits", hex(value))),
+        };
+
+        let bytes = match *self {
+            Field::Operand(ref mode) => return operand(mode, value, pc),
+            Field::Byte => {
+                match (-0x80..=0xff).contains(&value) {
+                    true => vec![value as u8],
+                    false => return Err(format!("{} doesn't fit in a byte", hex(value))),
+                }
+            }
+            Field::Word => {
+                let word = word()?;
+                vec![word as u8, (word >> 8) as u8]
+            }
+            Field::BigWord => {
+                let word = word()?;
+                vec![(word >> 8) as u8, word as u8]
+            }
+            Field::LowByte => vec![value as u8],
+            Field::HighByte => vec![(value >> 8) as u8],
+        };
+
+        Ok(bytes)
+    }
+}
+
+fn operand(mode: &AddrMode, value: i64, pc: u16) -> Result<Vec<u8>, String> {
+    // what the operand has to fit in
+    let too_wide = |min: i64, max: i64| {
+        Err(format!("{} is too wide for {} addressing, which takes {} to {}",
+                    hex(value),
+                    mode_name(mode),
+                    hex(min),
+                    hex(max)))
+    };
+
+    let bytes = match *mode {
+        AddrMode::Implicit | AddrMode::Accumulator => Vec::new(),
+        AddrMode::Relative => {
+            if !(0..=0xffff).contains(&value) {
+                return Err(format!("branch target {} isn't an address", hex(value)));
+            }
+
+            let offset = value - (pc as i64 + 2);
+            if !(-128..=127).contains(&offset) {
+                let reach = if offset < 0 { "128 bytes back" } else { "127 bytes forward" };
+                return Err(format!("branch to ${:04x} is {} bytes away, but a branch can only reach {} (use jmp?)",
+                                   value,
+                                   offset.abs(),
+                                   reach));
+            }
+
+            vec![offset as i8 as u8]
+        }
+        // negative immediates are written as their two's complement, so `lda #-1` is `lda #$ff`
+        AddrMode::Immediate => {
+            if !(-0x80..=0xff).contains(&value) {
+                return too_wide(-0x80, 0xff);
+            }
+
+            vec![value as u8]
+        }
+        _ if Parser::size_of(mode) == 3 => {
+            if !(0..=0xffff).contains(&value) {
+                return too_wide(0, 0xffff);
+            }
+
+            vec![value as u8, (value >> 8) as u8]
+        }
+        _ => {
+            if !(0..=0xff).contains(&value) {
+                return too_wide(0, 0xff);
+            }
+
+            vec![value as u8]
+        }
+    };
+
+    Ok(bytes)
+}
+
+// which segment each of a program's lines is in, for assembling it into an object
+pub struct Segments {
+    // in the order they're first used, after CODE
+    pub names: Vec<String>,
+    pub of_line: Vec<usize>,
+}
+
+impl Segments {
+    pub fn of(lines: &[Line]) -> Segments {
+        let mut names = vec![String::from(DEFAULT_SEGMENT)];
+        let mut current = 0;
+
+        let of_line = lines.iter()
+            .map(|line| {
+                if let Some(Directive::Segment(ref name)) = line.directive {
+                    current = match names.iter().position(|existing| existing == name) {
+                        Some(index) => index,
+                        None => {
+                            names.push(name.clone());
+                            names.len() - 1
+                        }
+                    };
+                }
+
+                current
+            })
+            .collect();
+
+        Segments {
+            names: names,
+            of_line: of_line,
+        }
+    }
+
+    // what a label `offset` bytes into a segment stands for until it's linked. zero page labels are just their
+    // offset, so operands using them can be zero page; anything else gets a 64k of its own, well clear of zero page
+    // and of every other segment, so a size worked out from labels in two segments is too big to go unnoticed
+    pub fn provisional(&self, segment: usize, offset: u16) -> i64 {
+        match self.names[segment] == ZERO_PAGE_SEGMENT {
+            true => offset as i64,
+            false => 0x10000 * (segment as i64 + 1) + offset as i64,
+        }
+    }
+
+    // the same for the `index`th import, which is only assumed to be in zero page if it came from .importzp
+    pub fn provisional_import(&self, index: usize, zero_page: bool) -> i64 {
+        match zero_page {
+            true => 0,
+            false => 0x10000 * (self.names.len() + 1 + index) as i64,
+        }
+    }
+}
+
+// a program assembled without knowing where it'll go, for the linker to place
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Object {
+    // the source it came from, for the linker's messages
+    pub name: String,
+    pub segments: Vec<Segment>,
+    // every label and equate, since the fixups can use any of them
+    pub symbols: Vec<Symbol>,
+    // what it expects other objects to export
+    pub imports: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Segment {
+    pub name: String,
+    // what the start of this object's part of the segment has to be a multiple of, for its .aligns
+    pub align: usize,
+    // with zeroes wherever a fixup goes
+    pub data: Vec<u8>,
+    pub fixups: Vec<Fixup>,
+}
+
+impl Segment {
+    pub fn new(name: String) -> Segment {
+        Segment {
+            name: name,
+            align: 1,
+            data: Vec::new(),
+            fixups: Vec::new(),
+        }
+    }
+}
+
+// a value that can't be filled in until the segment's been placed
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fixup {
+    // where it goes in the segment, and where the line it's on starts (for `*` and branches)
+    pub offset: usize,
+    pub pc: usize,
+    pub field: Field,
+    pub value: Expr,
+    // where it was written, for the linker's messages
+    pub file: String,
+    pub line: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    // a segment, and how far into it
+    Label(usize, usize),
+    // an equate, with the segment and offset of the line it's on for `*`
+    Equate(Expr, usize, usize),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub value: Value,
+    pub exported: bool,
+}
+
+#[derive(Debug)]
+pub enum ObjectError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    Truncated,
+    Corrupt(&'static str),
+    Io(io::Error),
+}
+
+impl fmt::Display for ObjectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ObjectError::BadMagic => write!(f, "not an object file (bad magic)"),
+            ObjectError::UnsupportedVersion(version) => {
+                write!(f, "unsupported object version {} (this build reads up to {})", version, OBJECT_VERSION)
+            }
+            ObjectError::Truncated => write!(f, "object file is truncated"),
+            ObjectError::Corrupt(what) => write!(f, "corrupt {} in object file", what),
+            ObjectError::Io(ref err) => write!(f, "i/o error: {}", err),
+        }
+    }
+}
+
+impl error::Error for ObjectError {}
+
+impl From<io::Error> for ObjectError {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::UnexpectedEof => ObjectError::Truncated,
+            _ => ObjectError::Io(err),
+        }
+    }
+}
+
+impl Object {
+    pub fn save(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+
+        // writing to a vec can't fail
+        self.write(&mut out).unwrap();
+
+        out
+    }
+
+    pub fn write<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        out.write_u8(OBJECT_VERSION)?;
+        write_str(out, &self.name)?;
+
+        out.write_u32::<LittleEndian>(self.segments.len() as u32)?;
+        for segment in self.segments.iter() {
+            write_str(out, &segment.name)?;
+            out.write_u32::<LittleEndian>(segment.align as u32)?;
+            out.write_u32::<LittleEndian>(segment.data.len() as u32)?;
+            out.write_all(&segment.data)?;
+
+            out.write_u32::<LittleEndian>(segment.fixups.len() as u32)?;
+            for fixup in segment.fixups.iter() {
+                out.write_u32::<LittleEndian>(fixup.offset as u32)?;
+                out.write_u32::<LittleEndian>(fixup.pc as u32)?;
+                write_field(out, &fixup.field)?;
+                write_expr(out, &fixup.value)?;
+                write_str(out, &fixup.file)?;
+                out.write_u32::<LittleEndian>(fixup.line as u32)?;
+            }
+        }
+
+        out.write_u32::<LittleEndian>(self.symbols.len() as u32)?;
+        for symbol in self.symbols.iter() {
+            write_str(out, &symbol.name)?;
+            out.write_u8(symbol.exported as u8)?;
+
+            match symbol.value {
+                Value::Label(segment, offset) => {
+                    out.write_u8(VALUE_LABEL)?;
+                    out.write_u32::<LittleEndian>(segment as u32)?;
+                    out.write_u32::<LittleEndian>(offset as u32)?;
+                }
+                Value::Equate(ref value, segment, offset) => {
+                    out.write_u8(VALUE_EQUATE)?;
+                    write_expr(out, value)?;
+                    out.write_u32::<LittleEndian>(segment as u32)?;
+                    out.write_u32::<LittleEndian>(offset as u32)?;
+                }
+            }
+        }
+
+        out.write_u32::<LittleEndian>(self.imports.len() as u32)?;
+        for import in self.imports.iter() {
+            write_str(out, import)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn load(bytes: &[u8]) -> Result<Object, ObjectError> {
+        Object::read(&mut Cursor::new(bytes))
+    }
+
+    pub fn read<R: Read>(input: &mut R) -> Result<Object, ObjectError> {
+        let mut magic = [0; 4];
+        input.read_exact(&mut magic)?;
+
+        if &magic[..] != MAGIC {
+            return Err(ObjectError::BadMagic);
+        }
+
+        match input.read_u8()? {
+            1..=OBJECT_VERSION => {}
+            version => return Err(ObjectError::UnsupportedVersion(version)),
+        }
+
+        let name = read_str(input)?;
+
+        let mut segments = Vec::new();
+        for _ in 0..input.read_u32::<LittleEndian>()? {
+            let mut segment = Segment::new(read_str(input)?);
+
+            segment.align = match input.read_u32::<LittleEndian>()? as usize {
+                align @ 1..=MAX_SEGMENT_LEN => align,
+                _ => return Err(ObjectError::Corrupt("segment alignment")),
+            };
+
+            segment.data = match input.read_u32::<LittleEndian>()? as usize {
+                len if len <= MAX_SEGMENT_LEN => read_bytes(input, len)?,
+                _ => return Err(ObjectError::Corrupt("segment size")),
+            };
+
+            for _ in 0..input.read_u32::<LittleEndian>()? {
+                let fixup = Fixup {
+                    offset: input.read_u32::<LittleEndian>()? as usize,
+                    pc: input.read_u32::<LittleEndian>()? as usize,
+                    field: read_field(input)?,
+                    value: read_expr(input)?,
+                    file: read_str(input)?,
+                    line: input.read_u32::<LittleEndian>()? as usize,
+                };
+
+                // the linker writes it straight into the segment's bytes
+                if fixup.pc > fixup.offset || fixup.offset + fixup.field.size() > segment.data.len() {
+                    return Err(ObjectError::Corrupt("fixup"));
+                }
+
+                segment.fixups.push(fixup);
+            }
+
+            segments.push(segment);
+        }
+
+        let mut symbols = Vec::new();
+        for _ in 0..input.read_u32::<LittleEndian>()? {
+            let name = read_str(input)?;
+            let exported = input.read_u8()? != 0;
+
+            let value = match input.read_u8()? {
+                VALUE_LABEL => {
+                    Value::Label(input.read_u32::<LittleEndian>()? as usize,
+                                 input.read_u32::<LittleEndian>()? as usize)
+                }
+                VALUE_EQUATE => {
+                    Value::Equate(read_expr(input)?,
+                                  input.read_u32::<LittleEndian>()? as usize,
+                                  input.read_u32::<LittleEndian>()? as usize)
+                }
+                _ => return Err(ObjectError::Corrupt("symbol")),
+            };
+
+            let (segment, offset) = match value {
+                Value::Label(segment, offset) | Value::Equate(_, segment, offset) => (segment, offset),
+            };
+
+            match segments.get(segment) {
+                Some(segment) if offset <= segment.data.len() => {}
+                _ => return Err(ObjectError::Corrupt("symbol")),
+            }
+
+            symbols.push(Symbol {
+                name: name,
+                value: value,
+                exported: exported,
+            });
+        }
+
+        let mut imports = Vec::new();
+        for _ in 0..input.read_u32::<LittleEndian>()? {
+            imports.push(read_str(input)?);
+        }
+
+        Ok(Object {
+            name: name,
+            segments: segments,
+            symbols: symbols,
+            imports: imports,
+        })
+    }
+}
+
+fn write_str<W: Write>(out: &mut W, text: &str) -> io::Result<()> {
+    out.write_u32::<LittleEndian>(text.len() as u32)?;
+    out.write_all(text.as_bytes())
+}
+
+fn read_str<R: Read>(input: &mut R) -> Result<String, ObjectError> {
+    let len = input.read_u32::<LittleEndian>()? as usize;
+
+    String::from_utf8(read_bytes(input, len)?).map_err(|_| ObjectError::Corrupt("name"))
+}
+
+// a damaged length could be anything, so the bytes are only taken as they turn up rather than allocated up front
+fn read_bytes<R: Read>(input: &mut R, len: usize) -> Result<Vec<u8>, ObjectError> {
+    let mut bytes = Vec::new();
+    input.by_ref().take(len as u64).read_to_end(&mut bytes)?;
+
+    match bytes.len() == len {
+        true => Ok(bytes),
+        false => Err(ObjectError::Truncated),
+    }
+}
+
+// operands as the index of their mode, and everything else after those
+fn write_field<W: Write>(out: &mut W, field: &Field) -> io::Result<()> {
+    let index = match *field {
+        Field::Operand(ref mode) => OPERAND_MODES.iter().position(|known| known == mode),
+        ref field => FIELDS.iter().position(|known| known == field).map(|index| OPERAND_MODES.len() + index),
+    };
+
+    // only modes with an operand ever get a fixup
+    out.write_u8(index.expect("a field with nothing to fix up") as u8)
+}
+
+fn read_field<R: Read>(input: &mut R) -> Result<Field, ObjectError> {
+    let index = input.read_u8()? as usize;
+
+    match OPERAND_MODES.get(index) {
+        Some(mode) => Ok(Field::Operand(mode.clone())),
+        None => FIELDS.get(index - OPERAND_MODES.len()).cloned().ok_or(ObjectError::Corrupt("field")),
+    }
+}
+
+fn write_expr<W: Write>(out: &mut W, expr: &Expr) -> io::Result<()> {
+    match *expr {
+        Expr::Number(value) => {
+            out.write_u8(EXPR_NUMBER)?;
+            out.write_i64::<LittleEndian>(value)
+        }
+        Expr::Symbol(ref name) => {
+            out.write_u8(EXPR_SYMBOL)?;
+            write_str(out, name)
+        }
+        Expr::Pc => out.write_u8(EXPR_PC),
+        Expr::Unary(op, ref operand) => {
+            out.write_u8(EXPR_UNARY)?;
+            out.write_u8(UNARY_OPS.iter().position(|&known| known == op).unwrap() as u8)?;
+            write_expr(out, operand)
+        }
+        Expr::Binary(op, ref lhs, ref rhs) => {
+            out.write_u8(EXPR_BINARY)?;
+            out.write_u8(BINARY_OPS.iter().position(|&known| known == op).unwrap() as u8)?;
+            write_expr(out, lhs)?;
+            write_expr(out, rhs)
+        }
+        // the reader swaps these for labels
+        Expr::Anon(_) => unreachable!("anonymous label reference in an object"),
+    }
+}
+
+fn read_expr<R: Read>(input: &mut R) -> Result<Expr, ObjectError> {
+    let expr = match input.read_u8()? {
+        EXPR_NUMBER => Expr::Number(input.read_i64::<LittleEndian>()?),
+        EXPR_SYMBOL => Expr::Symbol(read_str(input)?),
+        EXPR_PC => Expr::Pc,
+        EXPR_UNARY => {
+            let op = *UNARY_OPS.get(input.read_u8()? as usize).ok_or(ObjectError::Corrupt("operator"))?;
+            Expr::Unary(op, Box::new(read_expr(input)?))
+        }
+        EXPR_BINARY => {
+            let op = *BINARY_OPS.get(input.read_u8()? as usize).ok_or(ObjectError::Corrupt("operator"))?;
+            let lhs = read_expr(input)?;
+
+            Expr::Binary(op, Box::new(lhs), Box::new(read_expr(input)?))
+        }
+        _ => return Err(ObjectError::Corrupt("expression")),
+    };
+
+    Ok(expr)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Field, Fixup, Object, ObjectError, Segment, Symbol, Value};
+    use asm::expr::Expr;
+    use cpu::addr::AddrMode;
+
+    fn corrupt(object: &Object) -> Option<&'static str> {
+        match Object::load(&object.save()) {
+            Err(ObjectError::Corrupt(what)) => Some(what),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn fields() {
+        assert_eq!(Field::Word.encode(0x1234, 0).unwrap(), vec![0x34, 0x12]);
+        assert_eq!(Field::BigWord.encode(-1, 0).unwrap(), vec![0xff, 0xff]);
+        assert_eq!(Field::HighByte.encode(0x12345, 0).unwrap(), vec![0x23]);
+        assert_eq!(Field::Operand(AddrMode::Relative).encode(0xc000, 0xc010).unwrap(), vec![0xee]);
+        assert_eq!(Field::Operand(AddrMode::Absolute).size(), 2);
+
+        assert_eq!(Field::Byte.encode(0x100, 0).unwrap_err(), "$100 doesn't fit in a byte");
+        assert_eq!(Field::Operand(AddrMode::ZeroPageX).encode(0x100, 0).unwrap_err(),
+                   "$100 is too wide for zero page,X addressing, which takes $0 to $ff");
+    }
+
+    #[test]
+    fn bad_objects() {
+        let bytes = Object::default().save();
+
+        assert_eq!(Object::load(&bytes).unwrap(), Object::default());
+        assert!(match Object::load(b"65SS") {
+            Err(ObjectError::BadMagic) => true,
+            _ => false,
+        });
+        assert!(match Object::load(&bytes[..bytes.len() - 1]) {
+            Err(ObjectError::Truncated) => true,
+            _ => false,
+        });
+
+        // a name claiming to be 4gb long is just truncated, not allocated
+        assert!(match Object::load(b"65OB\x01\xff\xff\xff\xff") {
+            Err(ObjectError::Truncated) => true,
+            _ => false,
+        });
+
+        let mut segment = Segment::new(String::from("CODE"));
+        segment.data = vec![0; 3];
+
+        let mut object = Object::default();
+        object.segments.push(segment);
+        object.symbols.push(Symbol {
+            name: String::from("start"),
+            value: Value::Label(1, 0),
+            exported: false,
+        });
+        assert_eq!(corrupt(&object), Some("symbol"));
+
+        object.symbols[0].value = Value::Label(0, 3);
+        assert_eq!(corrupt(&object), None);
+
+        object.segments[0].fixups.push(Fixup {
+            offset: 2,
+            pc: 0,
+            field: Field::Word,
+            value: Expr::Number(0),
+            file: String::new(),
+            line: 1,
+        });
+        assert_eq!(corrupt(&object), Some("fixup"));
+
+        object.segments[0].fixups[0].offset = 1;
+        assert_eq!(corrupt(&object), None);
+
+        object.segments[0].align = 0;
+        assert_eq!(corrupt(&object), Some("segment alignment"));
+    }
+}
@@ -0,0 +1,276 @@
+use cpu::addr::AddrMode;
+use cpu::instr::resolver;
+
+use super::expr::Expr;
+use super::token::{self, Token, Tokens};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Operand {
+    pub addr_mode: AddrMode,
+    pub value: Option<Expr>,
+    // an `a:` or `z:` prefix pinned the width, so the assembler mustn't pick zero page or absolute itself
+    pub forced_width: bool,
+}
+
+impl Operand {
+    fn new(addr_mode: AddrMode, value: Option<Expr>) -> Self {
+        Operand {
+            addr_mode: addr_mode,
+            value: value,
+            forced_width: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Index {
+    None,
+    X,
+    Y,
+}
+
+// works out the addressing mode from how the operand's written:
+//
+//   (nothing) or A     implied or accumulator
+//   #value             immediate
+//   value              zero page, absolute or a branch target
+//   value,X / value,Y  zero page or absolute, indexed
+//   (value)            indirect
+//   (value,X)          indexed indirect
+//   (value),Y          indirect indexed
+//
+// every value is an expression (see `Expr`). a parenthesised one is only indirect when nothing but an index
+// follows the closing parenthesis and the instruction has that mode, so `lda (base+1)*2` is still absolute.
+//
+// any of the plain or indexed forms can be prefixed with `z:` or `a:` to force zero page or absolute. until label
+// values are known, zero page is only picked for constants that fit in a byte
+pub fn parse(mnemonic: &str, text: &str) -> Result<Operand, String> {
+    let tokens = token::tokenize(text)?;
+    let modes = resolver::modes_of(&mnemonic.to_lowercase());
+
+    let mut parser = OperandParser { tokens: Tokens::new(&tokens) };
+
+    let operand = parser.operand(&modes)?;
+
+    match parser.tokens.peek() {
+        None => Ok(operand),
+        Some(token) => Err(format!("unexpected {} after operand", token.describe())),
+    }
+}
+
+struct OperandParser<'a> {
+    tokens: Tokens<'a>,
+}
+
+impl<'a> OperandParser<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.peek()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&'a Token> {
+        self.tokens.peek_at(offset)
+    }
+
+    fn next(&mut self) -> Option<&'a Token> {
+        self.tokens.next()
+    }
+
+    fn is_register(token: Option<&Token>, register: &str) -> bool {
+        match token {
+            Some(&Token::Ident(ref name)) => name.eq_ignore_ascii_case(register),
+            _ => false,
+        }
+    }
+
+    fn operand(&mut self, modes: &[AddrMode]) -> Result<Operand, String> {
+        match self.peek() {
+            None if modes.contains(&AddrMode::Accumulator) => Ok(Operand::new(AddrMode::Accumulator, None)),
+            None => Ok(Operand::new(AddrMode::Implicit, None)),
+            Some(_) if OperandParser::is_register(self.peek(), "a") && self.peek_at(1).is_none() => {
+                self.next();
+                Ok(Operand::new(AddrMode::Accumulator, None))
+            }
+            Some(&Token::Hash) => {
+                self.next();
+                Ok(Operand::new(AddrMode::Immediate, Some(self.value()?)))
+            }
+            Some(&Token::LParen) => {
+                let start = self.tokens.pos;
+
+                match self.indirect(modes)? {
+                    Some(operand) => Ok(operand),
+                    None => {
+                        self.tokens.pos = start;
+                        self.direct(modes)
+                    }
+                }
+            }
+            Some(_) => self.direct(modes),
+        }
+    }
+
+    // None if the parentheses turn out to just be grouping part of an expression
+    fn indirect(&mut self, modes: &[AddrMode]) -> Result<Option<Operand>, String> {
+        self.tokens.expect(Token::LParen)?;
+        let value = self.value()?;
+
+        match self.next() {
+            Some(&Token::Comma) => {
+                if !OperandParser::is_register(self.next(), "x") {
+                    return Err(String::from("only X can index inside the parentheses"));
+                }
+
+                self.tokens.expect(Token::RParen)?;
+                Ok(Some(Operand::new(AddrMode::IndirectX, Some(value))))
+            }
+            Some(&Token::RParen) => {
+                match (self.peek(), self.peek_at(1)) {
+                    (Some(&Token::Comma), index) if !OperandParser::is_register(index, "x") => {
+                        self.next();
+                        if !OperandParser::is_register(self.next(), "y") {
+                            return Err(String::from("only Y can index after the parentheses"));
+                        }
+
+                        Ok(Some(Operand::new(AddrMode::IndirectY, Some(value))))
+                    }
+                    (None, _) if modes.contains(&AddrMode::Indirect) => Ok(Some(Operand::new(AddrMode::Indirect, Some(value)))),
+                    _ => Ok(None),
+                }
+            }
+            Some(token) => Err(format!("expected ',' or ')' but found {}", token.describe())),
+            None => Err(String::from("expected ')'")),
+        }
+    }
+
+    fn direct(&mut self, modes: &[AddrMode]) -> Result<Operand, String> {
+        let forced = match (self.peek(), self.peek_at(1)) {
+            (Some(&Token::Ident(ref prefix)), Some(&Token::Colon)) => {
+                let zero_page = match prefix.to_lowercase().as_str() {
+                    "z" => true,
+                    "a" => false,
+                    _ => return Err(format!("unknown width prefix '{}:' (expected z: or a:)", prefix)),
+                };
+
+                self.tokens.pos += 2;
+                Some(zero_page)
+            }
+            _ => None,
+        };
+
+        let value = self.value()?;
+
+        let index = match self.peek() {
+            Some(&Token::Comma) => {
+                self.next();
+
+                match self.next() {
+                    token if OperandParser::is_register(token, "x") => Index::X,
+                    token if OperandParser::is_register(token, "y") => Index::Y,
+                    _ => return Err(String::from("expected X or Y after ','")),
+                }
+            }
+            _ => Index::None,
+        };
+
+        if modes.contains(&AddrMode::Relative) {
+            if index != Index::None || forced.is_some() {
+                return Err(String::from("branches only take a target address"));
+            }
+
+            return Ok(Operand::new(AddrMode::Relative, Some(value)));
+        }
+
+        let zero_page = match forced {
+            Some(zero_page) => zero_page,
+            None => value.constant().is_some_and(|val| (0..=0xff).contains(&val)),
+        };
+
+        let addr_mode = match (index, zero_page) {
+            (Index::None, true) => AddrMode::ZeroPage,
+            (Index::None, false) => AddrMode::Absolute,
+            (Index::X, true) => AddrMode::ZeroPageX,
+            (Index::X, false) => AddrMode::AbsoluteX,
+            // only ldx and stx have zero page,Y; everything else has to use the absolute form
+            (Index::Y, true) if modes.contains(&AddrMode::ZeroPageY) || forced.is_some() => AddrMode::ZeroPageY,
+            (Index::Y, _) => AddrMode::AbsoluteY,
+        };
+
+        Ok(Operand {
+            addr_mode: addr_mode,
+            value: Some(value),
+            forced_width: forced.is_some(),
+        })
+    }
+
+    fn value(&mut self) -> Result<Expr, String> {
+        Expr::parse(&mut self.tokens)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse;
+    use asm::expr::{BinaryOp, Expr, UnaryOp};
+    use cpu::addr::AddrMode;
+
+    fn mode(mnemonic: &str, text: &str) -> AddrMode {
+        parse(mnemonic, text).unwrap().addr_mode
+    }
+
+    #[test]
+    fn every_operand_form() {
+        assert_eq!(mode("nop", ""), AddrMode::Implicit);
+        assert_eq!(mode("asl", ""), AddrMode::Accumulator);
+        assert_eq!(mode("ROL", "A"), AddrMode::Accumulator);
+        assert_eq!(mode("lda", "#'A'"), AddrMode::Immediate);
+        assert_eq!(mode("lda", "$12"), AddrMode::ZeroPage);
+        assert_eq!(mode("lda", "$0012"), AddrMode::ZeroPage);
+        assert_eq!(mode("lda", "300"), AddrMode::Absolute);
+        assert_eq!(mode("lda", "$12,x"), AddrMode::ZeroPageX);
+        assert_eq!(mode("lda", "%10010,Y"), AddrMode::AbsoluteY);
+        assert_eq!(mode("ldx", "$12, Y"), AddrMode::ZeroPageY);
+        assert_eq!(mode("ldx", "label,Y"), AddrMode::AbsoluteY);
+        assert_eq!(mode("JMP", "($1234)"), AddrMode::Indirect);
+        assert_eq!(mode("lda", "( $12 , X )"), AddrMode::IndirectX);
+        assert_eq!(mode("lda", "($12),Y"), AddrMode::IndirectY);
+        assert_eq!(mode("beq", "$0f"), AddrMode::Relative);
+        assert_eq!(mode("bne", "loop"), AddrMode::Relative);
+
+        let forced = parse("lda", "a:$12,X").unwrap();
+        assert_eq!((forced.addr_mode, forced.forced_width), (AddrMode::AbsoluteX, true));
+        assert_eq!(mode("lda", "z:label"), AddrMode::ZeroPage);
+
+        assert_eq!(parse("lda", "#'A'").unwrap().value, Some(Expr::Number(0x41)));
+        assert_eq!(parse("lda", "(ptr),Y").unwrap().value, Some(Expr::Symbol(String::from("ptr"))));
+    }
+
+    #[test]
+    fn expressions() {
+        assert_eq!(parse("lda", "#<table").unwrap().value,
+                   Some(Expr::Unary(UnaryOp::LowByte, Box::new(Expr::Symbol(String::from("table"))))));
+        assert_eq!(mode("sta", "ptr+1"), AddrMode::Absolute);
+        assert_eq!(mode("sta", "$10+1"), AddrMode::ZeroPage);
+        assert_eq!(mode("lda", "$80*2"), AddrMode::Absolute);
+        assert_eq!(mode("lda", "(ptr+1),y"), AddrMode::IndirectY);
+        assert_eq!(mode("jmp", "(vectors+2)"), AddrMode::Indirect);
+
+        // parentheses that only group
+        assert_eq!(mode("lda", "(1+2)*3"), AddrMode::ZeroPage);
+        assert_eq!(mode("lda", "(base),x"), AddrMode::AbsoluteX);
+        assert_eq!(mode("lda", "($10)"), AddrMode::ZeroPage);
+        assert_eq!(parse("jmp", "(a)+1").unwrap().value,
+                   Some(Expr::Binary(BinaryOp::Add, Box::new(Expr::Symbol(String::from("a"))), Box::new(Expr::Number(1)))));
+    }
+
+    #[test]
+    fn errors() {
+        assert!(parse("lda", "($12),Z").unwrap_err().contains("only Y"));
+        assert!(parse("lda", "($12,Y)").unwrap_err().contains("only X"));
+        assert!(parse("lda", "$12,Z").unwrap_err().contains("expected X or Y"));
+        assert!(parse("lda", "#$12 $34").unwrap_err().contains("unexpected number"));
+        assert!(parse("lda", "q:$12").unwrap_err().contains("width prefix"));
+        assert!(parse("beq", "$12,X").unwrap_err().contains("branches"));
+        assert!(parse("lda", "#").unwrap_err().contains("expected an expression"));
+        assert!(parse("lda", "#1 +").unwrap_err().contains("expected an expression"));
+    }
+}
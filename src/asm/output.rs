@@ -0,0 +1,294 @@
+use super::directive::InesField;
+
+const INES_MAGIC: &'static [u8] = b"NES\x1a";
+const PRG_BANK_SIZE: usize = 0x4000;
+const CHR_BANK_SIZE: usize = 0x2000;
+
+// how many bytes go in each Intel HEX or S-record data record
+const RECORD_LEN: usize = 16;
+
+// a run of assembled bytes and the address of the first. an assembly's chunks are in address order, and there's a
+// new one wherever an .org leaves a gap
+pub type Chunk = (u16, Vec<u8>);
+
+// what the source said about the iNES header, with .inesprg, .ineschr, .inesmap and .inesmir
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InesHeader {
+    // left to the writer if the source doesn't say
+    pub prg_banks: Option<u8>,
+    pub chr_banks: u8,
+    pub mapper: u8,
+    pub mirroring: u8,
+}
+
+impl InesHeader {
+    pub fn set(&mut self, field: InesField, value: i64) -> Result<(), String> {
+        let (min, max) = match field {
+            InesField::Prg => (1, 0xff),
+            InesField::Mirroring => (0, 0x0f),
+            InesField::Chr | InesField::Mapper => (0, 0xff),
+        };
+
+        if value < min || value > max {
+            return Err(format!("{} is out of range for {} (it takes {} to {})", value, field_name(field), min, max));
+        }
+
+        let value = value as u8;
+        match field {
+            InesField::Prg => self.prg_banks = Some(value),
+            InesField::Chr => self.chr_banks = value,
+            InesField::Mapper => self.mapper = value,
+            InesField::Mirroring => self.mirroring = value,
+        }
+
+        Ok(())
+    }
+}
+
+fn field_name(field: InesField) -> &'static str {
+    match field {
+        InesField::Prg => ".inesprg",
+        InesField::Chr => ".ineschr",
+        InesField::Mapper => ".inesmap",
+        InesField::Mirroring => ".inesmir",
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    // the bytes from the lowest address assembled to the highest, gaps filled in
+    Raw,
+    Ines,
+    IntelHex,
+    SRecord,
+    // a Commodore program file: a raw image after its two-byte load address
+    Prg,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Format> {
+        let format = match name.to_lowercase().as_str() {
+            "raw" | "bin" => Format::Raw,
+            "ines" | "nes" => Format::Ines,
+            "hex" | "ihex" => Format::IntelHex,
+            "srec" | "s19" => Format::SRecord,
+            "prg" => Format::Prg,
+            _ => return None,
+        };
+
+        Some(format)
+    }
+
+    // what files in the format are usually called
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            Format::Raw => "bin",
+            Format::Ines => "nes",
+            Format::IntelHex => "hex",
+            Format::SRecord => "s19",
+            Format::Prg => "prg",
+        }
+    }
+
+    // `fill` goes wherever nothing was assembled, and `chr` is the chr rom for an iNES image
+    pub fn write(&self, chunks: &[Chunk], header: &InesHeader, chr: &[u8], fill: u8) -> Result<Vec<u8>, String> {
+        match *self {
+            Format::Raw => Ok(raw(chunks, fill).1),
+            Format::Ines => ines(chunks, header, chr, fill),
+            Format::IntelHex => Ok(intel_hex(chunks).into_bytes()),
+            Format::SRecord => Ok(srecord(chunks).into_bytes()),
+            Format::Prg => Ok(prg(chunks, fill)),
+        }
+    }
+}
+
+// the chunks laid out from the first one's address, with `fill` in the gaps, and that address
+pub fn raw(chunks: &[Chunk], fill: u8) -> (u16, Vec<u8>) {
+    let start = chunks.first().map_or(0, |&(addr, _)| addr);
+    let mut bytes = Vec::new();
+
+    for &(addr, ref data) in chunks.iter() {
+        bytes.resize((addr - start) as usize, fill);
+        bytes.extend(data);
+    }
+
+    (start, bytes)
+}
+
+// prg rom is what's assembled into the top of the address space, where the vectors are: $c000 up for one bank,
+// $8000 up for two or more. any banks beyond the last two can't be addressed without a mapper, so they're left
+// filled. chr rom comes from outside the source, since the cpu can't see it
+pub fn ines(chunks: &[Chunk], header: &InesHeader, chr: &[u8], fill: u8) -> Result<Vec<u8>, String> {
+    let lowest = chunks.first().map_or(0xffff, |&(addr, _)| addr);
+    let prg_banks = header.prg_banks.unwrap_or(if lowest >= 0xc000 { 1 } else { 2 }) as usize;
+
+    let window = prg_banks.min(2) * PRG_BANK_SIZE;
+    let window_start = 0x10000 - window;
+    let mut prg = vec![fill; prg_banks * PRG_BANK_SIZE];
+    let skipped = prg.len() - window;
+
+    for &(addr, ref data) in chunks.iter() {
+        let addr = addr as usize;
+        if addr < window_start {
+            return Err(format!("${:04x} is outside prg rom, which starts at ${:04x} with {} bank(s)",
+                               addr,
+                               window_start,
+                               prg_banks));
+        }
+
+        if addr + data.len() > 0x10000 {
+            return Err(format!("the chunk at ${:04x} runs past $ffff", addr));
+        }
+
+        let offset = skipped + addr - window_start;
+        prg[offset..offset + data.len()].copy_from_slice(data);
+    }
+
+    let chr_len = header.chr_banks as usize * CHR_BANK_SIZE;
+    if chr.len() > chr_len {
+        return Err(format!("the chr rom is {} bytes, but .ineschr {} only has room for {}",
+                           chr.len(),
+                           header.chr_banks,
+                           chr_len));
+    }
+
+    let mut bytes = INES_MAGIC.to_vec();
+    bytes.extend(&[prg_banks as u8,
+                   header.chr_banks,
+                   (header.mapper << 4) | header.mirroring,
+                   header.mapper & 0xf0]);
+    bytes.resize(16, 0);
+
+    bytes.extend(prg);
+    bytes.extend(chr);
+    bytes.resize(bytes.len() + chr_len - chr.len(), fill);
+
+    Ok(bytes)
+}
+
+// data records of up to 16 bytes, then an end of file record
+pub fn intel_hex(chunks: &[Chunk]) -> String {
+    let mut text = String::new();
+
+    for &(addr, ref data) in chunks.iter() {
+        for (i, record) in data.chunks(RECORD_LEN).enumerate() {
+            let addr = addr.wrapping_add((i * RECORD_LEN) as u16);
+
+            let mut fields = vec![record.len() as u8, (addr >> 8) as u8, addr as u8, 0x00];
+            fields.extend(record);
+
+            text.push_str(&intel_hex_record(&fields));
+        }
+    }
+
+    text.push_str(&intel_hex_record(&[0x00, 0x00, 0x00, 0x01]));
+    text
+}
+
+// `:`, the fields in hex, then the two's complement of their sum
+fn intel_hex_record(fields: &[u8]) -> String {
+    let sum = fields.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+    format!(":{}{:02X}\n", hex_bytes(fields), sum.wrapping_neg())
+}
+
+// a header, S1 data records of up to 16 bytes, an S5 count of them, and an S9 giving the first chunk's address as
+// where to start
+pub fn srecord(chunks: &[Chunk]) -> String {
+    let mut text = srecord_record(0, &[0x00, 0x00]);
+    let mut count = 0u16;
+
+    for &(addr, ref data) in chunks.iter() {
+        for (i, record) in data.chunks(RECORD_LEN).enumerate() {
+            let addr = addr.wrapping_add((i * RECORD_LEN) as u16);
+
+            let mut fields = vec![(addr >> 8) as u8, addr as u8];
+            fields.extend(record);
+
+            text.push_str(&srecord_record(1, &fields));
+            count = count.wrapping_add(1);
+        }
+    }
+
+    let start = chunks.first().map_or(0, |&(addr, _)| addr);
+    text.push_str(&srecord_record(5, &[(count >> 8) as u8, count as u8]));
+    text.push_str(&srecord_record(9, &[(start >> 8) as u8, start as u8]));
+
+    text
+}
+
+// `S`, the type, then in hex: how many bytes follow, the fields, and the ones' complement of the sum of both
+fn srecord_record(kind: u8, fields: &[u8]) -> String {
+    let len = fields.len() as u8 + 1;
+    let sum = fields.iter().fold(len, |sum, &byte| sum.wrapping_add(byte));
+
+    format!("S{}{:02X}{}{:02X}\n", kind, len, hex_bytes(fields), !sum)
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02X}", byte)).collect()
+}
+
+pub fn prg(chunks: &[Chunk], fill: u8) -> Vec<u8> {
+    let (start, image) = raw(chunks, fill);
+
+    let mut bytes = vec![start as u8, (start >> 8) as u8];
+    bytes.extend(image);
+
+    bytes
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Chunk, Format, InesHeader};
+    use asm::directive::InesField;
+
+    fn chunks() -> Vec<Chunk> {
+        vec![(0x0100, vec![0x01, 0x02]), (0x0104, vec![0x03])]
+    }
+
+    #[test]
+    fn flat_formats() {
+        assert_eq!(Format::Raw.write(&chunks(), &InesHeader::default(), &[], 0xff).unwrap(),
+                   vec![0x01, 0x02, 0xff, 0xff, 0x03]);
+        assert_eq!(Format::Prg.write(&chunks(), &InesHeader::default(), &[], 0).unwrap(),
+                   vec![0x00, 0x01, 0x01, 0x02, 0x00, 0x00, 0x03]);
+        assert_eq!(Format::from_name("S19"), Some(Format::SRecord));
+        assert_eq!(Format::from_name("elf"), None);
+    }
+
+    #[test]
+    fn record_formats() {
+        assert_eq!(super::intel_hex(&chunks()), ":020100000102FA\n:0101040003F7\n:00000001FF\n");
+        assert_eq!(super::srecord(&chunks()),
+                   "S0030000FC\nS10501000102F6\nS104010403F3\nS5030002FA\nS9030100FB\n");
+
+        // long chunks are split into records of 16 bytes
+        let long = vec![(0xc000, vec![0xea; 17])];
+        assert_eq!(super::intel_hex(&long).lines().count(), 3);
+        assert!(super::intel_hex(&long).lines().nth(1).unwrap().starts_with(":01C01000EA"));
+    }
+
+    #[test]
+    fn ines() {
+        let mut header = InesHeader::default();
+        header.set(InesField::Chr, 1).unwrap();
+        header.set(InesField::Mapper, 0x12).unwrap();
+        header.set(InesField::Mirroring, 1).unwrap();
+        assert!(header.set(InesField::Prg, 0).unwrap_err().contains("out of range for .inesprg"));
+
+        let chunks = vec![(0xc000, vec![0xa9, 0x01]), (0xfffc, vec![0x00, 0xc0])];
+        let image = super::ines(&chunks, &header, &[0x55], 0xff).unwrap();
+
+        assert_eq!(&image[..8], &[0x4e, 0x45, 0x53, 0x1a, 1, 1, 0x21, 0x10]);
+        assert_eq!(image.len(), 16 + 0x4000 + 0x2000);
+        assert_eq!(&image[16..19], &[0xa9, 0x01, 0xff]);
+        assert_eq!(&image[16 + 0x3ffc..16 + 0x4000], &[0x00, 0xc0, 0xff, 0xff]);
+        assert_eq!(&image[16 + 0x4000..16 + 0x4002], &[0x55, 0xff]);
+
+        // two banks go at $8000, and nothing can go lower
+        header.set(InesField::Prg, 2).unwrap();
+        assert_eq!(super::ines(&chunks, &header, &[], 0).unwrap()[16 + 0x4000], 0xa9);
+        assert!(super::ines(&[(0x6000, vec![0])], &header, &[], 0).unwrap_err().contains("outside prg rom"));
+        assert!(super::ines(&chunks, &header, &[0; 0x2001], 0).unwrap_err().contains("only has room for 8192"));
+    }
+}
@@ -0,0 +1,998 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use cpu::addr::AddrMode;
+
+use super::diagnostic::{Diagnostic, Source};
+use super::directive::{self, Data, Directive};
+use super::expr::Expr;
+use super::operand;
+use super::token::{self, Tokens};
+use super::Line;
+
+extern crate regex;
+
+// deep enough for any real project, shallow enough to catch a file including itself
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+// likewise for a macro that invokes itself
+const MAX_EXPANSION_DEPTH: usize = 64;
+
+lazy_static! {
+    static ref SYMBOL_REGEX: regex::Regex = regex::Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$").unwrap();
+    static ref LABEL_REGEX: regex::Regex = regex::Regex::new(r"^@?[A-Za-z_][A-Za-z0-9_]*$").unwrap();
+    static ref EQUATE_REGEX: regex::Regex = regex::Regex::new(r"^([A-Za-z_][A-Za-z0-9_]*)\s*=((?:[^=].*)?)$").unwrap();
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<String>,
+    // labels defined in the body, which get renamed for each expansion so it can be used more than once
+    labels: Vec<String>,
+}
+
+enum Block {
+    Macro { name: String, params: Vec<String> },
+    Repeat { count: i64, var: Option<String> },
+}
+
+// the body of a .macro or .repeat, collected until its end
+struct Capture {
+    block: Block,
+    // how many blocks inside this one haven't ended yet
+    nesting: usize,
+    body: Vec<String>,
+    at: Source,
+}
+
+struct Scope {
+    name: String,
+    // .proc rather than .scope
+    is_proc: bool,
+    at: Source,
+}
+
+struct Cond {
+    // whether the branch we're in is being assembled
+    active: bool,
+    // whether any branch of this .if has been assembled already
+    done: bool,
+    // whether the .if itself is somewhere that's being assembled
+    parent: bool,
+    seen_else: bool,
+    at: Source,
+}
+
+// turns source into `Line`s: strips comments, follows .include and .incbin, expands macros and .repeat, and skips
+// whatever conditional assembly leaves out.
+//
+// all of that happens before any labels have addresses, so .if and .repeat can only use equates that are defined
+// above them, and .ifdef only knows about what it's already seen.
+//
+// labels are renamed as they're read so every one is unique:
+//
+// - inside `.proc name` or `.scope name`, they're qualified with the scope, like `name::loop`. references look in
+//   the innermost scope first and work outwards, unless they start with `::`, which means the top level
+// - cheap locals like `@loop` belong to the last ordinary label, and become `that_label@loop`
+// - anonymous labels (a lone `:`) are numbered `:0`, `:1`... and `:-`/`:+` become the one they point at
+pub struct Reader {
+    pub lines: Vec<Line>,
+    // everything wrong with the lines read so far
+    pub errors: Vec<Diagnostic>,
+    // every line of source read, in the order it was read, for listings
+    pub sources: Vec<Source>,
+    macros: HashMap<String, Rc<Macro>>,
+    constants: HashMap<String, i64>,
+    defined: HashSet<String>,
+    conds: Vec<Cond>,
+    capture: Option<Capture>,
+    expansions: usize,
+    expansion_depth: usize,
+    scopes: Vec<Scope>,
+    // the scopes joined with `::`
+    scope: Rc<String>,
+    unnamed_scopes: usize,
+    // what cheap locals belong to
+    last_global: String,
+    anonymous: usize,
+    // the furthest anonymous label referred to so far, which had better exist by the end
+    furthest_anonymous: Option<(usize, Source)>,
+}
+
+impl Reader {
+    pub fn new() -> Self {
+        Reader {
+            lines: Vec::new(),
+            sources: Vec::new(),
+            errors: Vec::new(),
+            macros: HashMap::new(),
+            constants: HashMap::new(),
+            defined: HashSet::new(),
+            conds: Vec::new(),
+            capture: None,
+            expansions: 0,
+            expansion_depth: 0,
+            scopes: Vec::new(),
+            scope: Rc::new(String::new()),
+            unnamed_scopes: 0,
+            last_global: String::new(),
+            anonymous: 0,
+            furthest_anonymous: None,
+        }
+    }
+
+    // `file` is where `input` came from, if anywhere, for error messages and to find what it includes. a line
+    // that's wrong is noted in `errors` and skipped, so one run can report everything
+    pub fn read(&mut self, input: &str, file: Option<&Path>, depth: usize) {
+        let name = file.map(|file| Rc::new(file.display().to_string()));
+
+        for (i, line) in input.split('\n').enumerate() {
+            let text = Rc::new(String::from(line));
+            let code = strip_comment(&text).trim();
+
+            let mut at = Source {
+                file: name.clone(),
+                line: i + 1,
+                text: text.clone(),
+                code: (0, 0),
+            };
+            at.code = at.span(code);
+            self.sources.push(at.clone());
+
+            if let Err(err) = self.line(code, file, &at, depth) {
+                self.errors.push(err);
+            }
+        }
+    }
+
+    // the lines read, once every block's been closed, and everything that was wrong with them
+    pub fn finish(mut self) -> (Vec<Line>, Vec<Diagnostic>) {
+        if let Some(capture) = self.capture.take() {
+            let end = match capture.block {
+                Block::Macro { .. } => ".endmacro",
+                Block::Repeat { .. } => ".endrepeat",
+            };
+
+            self.errors.push(capture.at.error(capture.at.code, format!("missing {}", end)));
+        }
+
+        if let Some(cond) = self.conds.last() {
+            self.errors.push(cond.at.error(cond.at.code, String::from("missing .endif")));
+        }
+
+        if let Some(scope) = self.scopes.last() {
+            let end = if scope.is_proc { ".endproc" } else { ".endscope" };
+            self.errors.push(scope.at.error(scope.at.code, format!("missing {}", end)));
+        }
+
+        if let Some((index, ref at)) = self.furthest_anonymous {
+            if index >= self.anonymous {
+                self.errors.push(at.error(at.code, String::from("there's no anonymous label that far ahead")));
+            }
+        }
+
+        // every label's known now, so references can be matched up with the scope they mean
+        let mut lines = self.lines;
+
+        let mut defined = HashSet::new();
+        for line in lines.iter() {
+            defined.extend(line.label.iter().cloned());
+            match line.directive {
+                Some(Directive::Equate(ref name, _)) => {
+                    defined.insert(name.clone());
+                }
+                // imports are always at the top level, wherever they're declared
+                Some(Directive::Import(ref names, _)) => defined.extend(names.iter().cloned()),
+                _ => {}
+            }
+        }
+
+        let mut undefined = Vec::new();
+        for (i, line) in lines.iter_mut().enumerate() {
+            let scope = line.scope.clone();
+
+            for expr in line.exprs_mut() {
+                let _ = expr.resolve(&mut |expr| {
+                    if let Expr::Symbol(ref mut name) = *expr {
+                        *name = qualify(name, &scope, |name| defined.contains(name));
+                        if !defined.contains(name) {
+                            undefined.push((i, name.clone()));
+                        }
+                    }
+
+                    Ok(())
+                });
+            }
+        }
+
+        // each reference gets its own error, pointing at where it's written. like any other line with a mistake, the
+        // line's left out, except that an equate's kept as zero so what uses it isn't reported as well
+        for (i, name) in undefined {
+            let line = &mut lines[i];
+            let span = line.span_of(&name, line.operand_span);
+            self.errors.push(line.error(span, format!("undefined symbol '{}'", name)));
+
+            match line.directive {
+                Some(Directive::Equate(_, ref mut value)) => *value = Expr::Number(0),
+                _ => {
+                    line.instr.clear();
+                    line.value = None;
+                    line.directive = None;
+                }
+            }
+        }
+
+        (lines, self.errors)
+    }
+
+    fn active(&self) -> bool {
+        self.conds.last().is_none_or(|cond| cond.active)
+    }
+
+    fn line(&mut self, text: &str, file: Option<&Path>, at: &Source, depth: usize) -> Result<(), Diagnostic> {
+        if text.is_empty() {
+            return Ok(());
+        }
+
+        let (label, body) = split_label(text);
+        let (instr, rest) = match body.find(char::is_whitespace) {
+            Some(end) => (&body[..end], body[end..].trim()),
+            None => (body, ""),
+        };
+
+        // most mistakes are in the arguments, so those get underlined, or the keyword if there aren't any
+        let span = at.span(if rest.is_empty() { body } else { rest });
+        let err = |err: String| at.error(span, err);
+        let keyword = instr.to_lowercase();
+
+        if self.capture.is_some() {
+            return self.capture_line(text, &keyword, file, depth);
+        }
+
+        match keyword.as_str() {
+            ".if" | ".ifdef" | ".ifndef" => {
+                let parent = self.active();
+                let condition = match parent {
+                    true => self.condition(&keyword, rest),
+                    false => Ok(false),
+                };
+
+                // a condition that can't be worked out leaves the block out, but it's still a block
+                let active = condition == Ok(true);
+                self.conds.push(Cond {
+                    active: active,
+                    done: active,
+                    parent: parent,
+                    seen_else: false,
+                    at: at.clone(),
+                });
+
+                return condition.map(|_| ()).map_err(&err);
+            }
+            ".elseif" | ".else" => {
+                let evaluate = match self.conds.last() {
+                    Some(cond) if cond.seen_else => return Err(err(format!("{} after .else", keyword))),
+                    Some(cond) => cond.parent && !cond.done,
+                    None => return Err(err(format!("{} without .if", keyword))),
+                };
+
+                let condition = match evaluate && keyword == ".elseif" {
+                    true => self.condition(".if", rest),
+                    false => Ok(evaluate),
+                };
+
+                let active = condition == Ok(true);
+                let cond = self.conds.last_mut().unwrap();
+
+                cond.active = active;
+                cond.done = cond.done || active;
+                cond.seen_else = keyword == ".else";
+
+                return condition.map(|_| ()).map_err(&err);
+            }
+            ".endif" => {
+                return match self.conds.pop() {
+                    Some(_) => Ok(()),
+                    None => Err(err(String::from(".endif without .if"))),
+                };
+            }
+            _ => {}
+        }
+
+        if !self.active() {
+            return Ok(());
+        }
+
+        match keyword.as_str() {
+            // a block with a bad start is still collected up to its end, so its body isn't read as ordinary lines.
+            // it just never gets used
+            ".macro" => {
+                let (name, params, result) = match macro_args(rest) {
+                    Ok((name, params)) => (name, params, Ok(())),
+                    Err(msg) => (String::new(), Vec::new(), Err(err(msg))),
+                };
+
+                self.start_capture(Block::Macro {
+                                       name: name,
+                                       params: params,
+                                   },
+                                   at);
+                return result;
+            }
+            ".repeat" | ".rept" => {
+                let (count, var, result) = match self.repeat_args(rest) {
+                    Ok((count, var)) => (count, var, Ok(())),
+                    Err(msg) => (0, None, Err(err(msg))),
+                };
+
+                self.start_capture(Block::Repeat {
+                                       count: count,
+                                       var: var,
+                                   },
+                                   at);
+                return result;
+            }
+            ".endmacro" | ".endmac" | ".endrepeat" | ".endrep" => return Err(err(format!("{} without a start", keyword))),
+            ".proc" => {
+                if !SYMBOL_REGEX.is_match(rest) {
+                    return Err(err(String::from(".proc needs a name")));
+                }
+
+                let label = self.define(rest);
+                self.push_label(label, at);
+                self.enter_scope(String::from(rest), true, at);
+
+                return Ok(());
+            }
+            ".scope" => {
+                let name = match rest {
+                    "" => {
+                        self.unnamed_scopes += 1;
+                        format!("__scope{}", self.unnamed_scopes)
+                    }
+                    name if SYMBOL_REGEX.is_match(name) => String::from(name),
+                    _ => return Err(err(format!("'{}' can't be a scope name", rest))),
+                };
+
+                self.enter_scope(name, false, at);
+                return Ok(());
+            }
+            ".endproc" | ".endscope" => return self.leave_scope(&keyword).map_err(&err),
+            _ => {}
+        }
+
+        if let Some(captures) = EQUATE_REGEX.captures(text) {
+            let name = self.qualified(&captures[1]);
+            let value = parse_expr(&captures[2]).map_err(&err)?;
+
+            let mut constant = value.clone();
+            self.qualify_constants(&mut constant);
+            if let Some(val) = constant.constant_in(&self.constants) {
+                self.constants.insert(name.clone(), val);
+            }
+            self.defined.insert(name.clone());
+
+            let mut line = Line::label_only(at, None);
+            line.instr = String::from("=");
+            line.directive = Some(Directive::Equate(name, value));
+            self.localize(&mut line, at).map_err(|err| at.error(at.span(&captures[2]), err))?;
+            self.lines.push(line);
+
+            return Ok(());
+        }
+
+        let label = label.map(|label| self.define(&label));
+
+        if let Some(mac) = self.macros.get(instr).cloned() {
+            if let Some(label) = label {
+                self.push_label(label, at);
+            }
+
+            return self.expand(&mac, rest, file, at, depth);
+        }
+
+        // a line that's wrong still defines its label, so whatever refers to it doesn't get reported as well
+        let result = self.instruction(label.clone(), instr, rest, file, at, depth);
+        if let (&Err(_), Some(label)) = (&result, label) {
+            self.push_label(label, at);
+        }
+
+        result
+    }
+
+    fn push_label(&mut self, label: String, at: &Source) {
+        let mut line = Line::label_only(at, Some(label));
+        line.scope = self.scope.clone();
+
+        self.lines.push(line);
+    }
+
+    fn condition(&self, keyword: &str, rest: &str) -> Result<bool, String> {
+        if keyword != ".if" {
+            let name = rest.trim();
+            if !SYMBOL_REGEX.is_match(name) {
+                return Err(format!("{} needs a name", keyword));
+            }
+
+            let name = qualify(name, &self.scope, |name| self.defined.contains(name));
+            return Ok(self.defined.contains(&name) == (keyword == ".ifdef"));
+        }
+
+        Ok(self.constant(rest, ".if")? != 0)
+    }
+
+    fn constant(&self, text: &str, what: &str) -> Result<i64, String> {
+        let mut expr = parse_expr(text)?;
+        if expr.mentions_pc() {
+            return Err(format!("{} can't use '*'", what));
+        }
+
+        self.qualify_constants(&mut expr);
+        expr.eval(0, &self.constants).map_err(|err| format!("{} ({} can only use equates defined above it)", err, what))
+    }
+
+    fn qualify_constants(&self, expr: &mut Expr) {
+        let (scope, constants) = (&self.scope, &self.constants);
+
+        let _ = expr.resolve(&mut |expr| {
+            if let Expr::Symbol(ref mut name) = *expr {
+                *name = qualify(name, scope, |name| constants.contains_key(name));
+            }
+
+            Ok(())
+        });
+    }
+
+    // what a label defined here is really called
+    fn define(&mut self, label: &str) -> String {
+        let name = match label {
+            ":" => {
+                self.anonymous += 1;
+                format!(":{}", self.anonymous - 1)
+            }
+            _ if label.starts_with('@') => format!("{}{}", self.last_global, label),
+            _ => {
+                self.last_global = self.qualified(label);
+                self.last_global.clone()
+            }
+        };
+
+        self.defined.insert(name.clone());
+        name
+    }
+
+    fn qualified(&self, name: &str) -> String {
+        match self.scope.is_empty() {
+            true => String::from(name),
+            false => format!("{}::{}", self.scope, name),
+        }
+    }
+
+    // swaps cheap locals and anonymous references in a line for the labels they mean, and notes what scope it's in
+    fn localize(&mut self, line: &mut Line, at: &Source) -> Result<(), String> {
+        let global = &self.last_global;
+        let anonymous = self.anonymous as i64;
+        let mut furthest = None;
+
+        for expr in line.exprs_mut() {
+            expr.resolve(&mut |expr| {
+                    let name = match *expr {
+                        Expr::Symbol(ref name) if name.starts_with('@') => format!("{}{}", global, name),
+                        Expr::Anon(n) => {
+                            // `:-` is the last one defined and `:+` the next, so the count is off by one going forward
+                            let index = if n < 0 { anonymous + n as i64 } else { anonymous + n as i64 - 1 };
+                            if index < 0 {
+                                return Err(String::from("there's no anonymous label that far back"));
+                            }
+
+                            if n > 0 {
+                                furthest = Some(furthest.map_or(index, |furthest: i64| furthest.max(index)));
+                            }
+
+                            format!(":{}", index)
+                        }
+                        _ => return Ok(()),
+                    };
+
+                    *expr = Expr::Symbol(name);
+                    Ok(())
+                })?;
+        }
+
+        if let Some(index) = furthest {
+            let index = index as usize;
+            if self.furthest_anonymous.as_ref().is_none_or(|&(furthest, _)| index > furthest) {
+                self.furthest_anonymous = Some((index, at.clone()));
+            }
+        }
+
+        line.scope = self.scope.clone();
+        Ok(())
+    }
+
+    fn enter_scope(&mut self, name: String, is_proc: bool, at: &Source) {
+        self.scopes.push(Scope {
+            name: name,
+            is_proc: is_proc,
+            at: at.clone(),
+        });
+        self.scope = Rc::new(self.scopes.iter().map(|scope| scope.name.as_str()).collect::<Vec<_>>().join("::"));
+    }
+
+    fn leave_scope(&mut self, keyword: &str) -> Result<(), String> {
+        match self.scopes.pop() {
+            Some(ref scope) if scope.is_proc == (keyword == ".endproc") => {}
+            Some(_) => return Err(format!("{} ends the wrong block", keyword)),
+            None => return Err(format!("{} without a start", keyword)),
+        }
+
+        self.scope = Rc::new(self.scopes.iter().map(|scope| scope.name.as_str()).collect::<Vec<_>>().join("::"));
+        Ok(())
+    }
+
+    // `.repeat count[, var]`
+    fn repeat_args(&self, rest: &str) -> Result<(i64, Option<String>), String> {
+        let args = split_args(rest);
+        if args.is_empty() || args.len() > 2 {
+            return Err(String::from(".repeat takes a count and, optionally, a variable name"));
+        }
+
+        let count = self.constant(&args[0], ".repeat")?;
+        if count < 0 {
+            return Err(format!("can't repeat {} times", count));
+        }
+
+        let var = match args.get(1) {
+            Some(var) if SYMBOL_REGEX.is_match(var) => Some(var.clone()),
+            Some(var) => return Err(format!("'{}' can't be a variable name", var)),
+            None => None,
+        };
+
+        Ok((count, var))
+    }
+
+    fn start_capture(&mut self, block: Block, at: &Source) {
+        self.capture = Some(Capture {
+            block: block,
+            nesting: 0,
+            body: Vec::new(),
+            at: at.clone(),
+        });
+    }
+
+    fn capture_line(&mut self, text: &str, keyword: &str, file: Option<&Path>, depth: usize) -> Result<(), Diagnostic> {
+        let ends = {
+            let capture = self.capture.as_mut().unwrap();
+
+            let ends = match keyword {
+                ".macro" | ".repeat" | ".rept" => {
+                    capture.nesting += 1;
+                    false
+                }
+                ".endmacro" | ".endmac" | ".endrepeat" | ".endrep" if capture.nesting > 0 => {
+                    capture.nesting -= 1;
+                    false
+                }
+                ".endmacro" | ".endmac" | ".endrepeat" | ".endrep" => true,
+                _ => false,
+            };
+
+            if !ends {
+                capture.body.push(String::from(text));
+                return Ok(());
+            }
+
+            match capture.block {
+                Block::Macro { .. } => keyword.starts_with(".endmac"),
+                Block::Repeat { .. } => keyword.starts_with(".endrep"),
+            }
+        };
+
+        let capture = self.capture.take().unwrap();
+        if !ends {
+            return Err(capture.at.error(capture.at.code, format!("{} ends the wrong block", keyword)));
+        }
+
+        match capture.block {
+            Block::Macro { ref name, .. } if name.is_empty() => Ok(()),
+            Block::Macro { name, params } => {
+                let labels = capture.body
+                    .iter()
+                    .filter_map(|line| split_label(line).0)
+                    .filter(|label| SYMBOL_REGEX.is_match(label))
+                    .collect();
+
+                self.macros.insert(name,
+                                   Rc::new(Macro {
+                                       params: params,
+                                       body: capture.body,
+                                       labels: labels,
+                                   }));
+
+                Ok(())
+            }
+            Block::Repeat { count, var } => {
+                for i in 0..count {
+                    let mut names = HashMap::new();
+                    if let Some(ref var) = var {
+                        names.insert(var.clone(), i.to_string());
+                    }
+
+                    self.feed(&capture.body, &names, file, &capture.at, depth)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    fn expand(&mut self, mac: &Macro, rest: &str, file: Option<&Path>, at: &Source, depth: usize)
+              -> Result<(), Diagnostic> {
+        let args = split_args(rest);
+        if args.len() > mac.params.len() {
+            let msg = format!("expected at most {} arguments but got {}", mac.params.len(), args.len());
+            return Err(at.error(at.span(rest), msg));
+        }
+
+        self.expansions += 1;
+
+        // parameters that weren't passed expand to nothing
+        let mut names: HashMap<String, String> = mac.params
+            .iter()
+            .enumerate()
+            .map(|(i, param)| (param.clone(), args.get(i).cloned().unwrap_or_default()))
+            .collect();
+
+        for label in mac.labels.iter() {
+            names.insert(label.clone(), format!("{}__{}", label, self.expansions));
+        }
+
+        // the macro's own labels can own cheap locals inside it, but the caller's go on belonging to the caller
+        let owner = self.last_global.clone();
+        let result = self.feed(&mac.body, &names, file, at, depth);
+        self.last_global = owner;
+
+        result
+    }
+
+    // reads lines from an expansion, with `names` substituted in; they all count as being on the line at `at`
+    fn feed(&mut self, body: &[String], names: &HashMap<String, String>, file: Option<&Path>,
+            at: &Source, depth: usize)
+            -> Result<(), Diagnostic> {
+        if self.expansion_depth == MAX_EXPANSION_DEPTH {
+            return Err(at.error(at.code, format!("macros are nested more than {} deep", MAX_EXPANSION_DEPTH)));
+        }
+
+        self.expansion_depth += 1;
+
+        for line in body.iter() {
+            if let Err(err) = self.line(&substitute(line, names), file, at, depth) {
+                self.errors.push(err);
+            }
+        }
+
+        self.expansion_depth -= 1;
+
+        Ok(())
+    }
+
+    // an instruction or a directive that isn't about how the source is read
+    fn instruction(&mut self, label: Option<String>, instr: &str, rest: &str, file: Option<&Path>,
+                   at: &Source, depth: usize)
+                   -> Result<(), Diagnostic> {
+        let instr_span = at.span(instr);
+        let operand_span = match rest.is_empty() {
+            true => instr_span,
+            false => at.span(rest),
+        };
+        let err = |err: String| at.error(operand_span, err);
+
+        let directive = match instr.starts_with('.') {
+            true => Some(directive::parse(instr, rest).map_err(&err)?),
+            false => None,
+        };
+
+        let directive = match directive {
+            Some(Directive::Include(path)) => {
+                if depth == MAX_INCLUDE_DEPTH {
+                    return Err(err(format!("includes are nested more than {} deep", MAX_INCLUDE_DEPTH)));
+                }
+
+                let path = relative_to(file, &path);
+                let text = fs::read_to_string(&path).map_err(|e| err(format!("{}: {}", path.display(), e)))?;
+
+                // a label on the .include marks where the included source starts
+                if let Some(label) = label {
+                    self.push_label(label, at);
+                }
+
+                self.read(&text, Some(&path), depth + 1);
+                return Ok(());
+            }
+            Some(Directive::Incbin { path, offset, len }) => {
+                let path = relative_to(file, &path);
+                let data = fs::read(&path).map_err(|e| err(format!("{}: {}", path.display(), e)))?;
+
+                let data = incbin_slice(data, offset, len).map_err(|e| err(format!("{}: {}", path.display(), e)))?;
+                Some(Directive::Byte(vec![Data::Bytes(data)]))
+            }
+            Some(Directive::Import(names, zero_page)) => {
+                self.defined.extend(names.iter().cloned());
+                Some(Directive::Import(names, zero_page))
+            }
+            directive => directive,
+        };
+
+        let operand = match instr.is_empty() || directive.is_some() {
+            true => operand::Operand {
+                addr_mode: AddrMode::Implicit,
+                value: None,
+                forced_width: false,
+            },
+            false => operand::parse(instr, rest).map_err(&err)?,
+        };
+
+        let mut line = Line {
+            source: at.clone(),
+            instr_span: instr_span,
+            operand_span: operand_span,
+            label: label,
+            instr: String::from(instr),
+            rest: match rest.is_empty() {
+                true => None,
+                false => Some(String::from(rest)),
+            },
+            addr_mode: operand.addr_mode,
+            value: operand.value,
+            forced_width: operand.forced_width,
+            directive: directive,
+            scope: self.scope.clone(),
+        };
+
+        self.localize(&mut line, at).map_err(&err)?;
+        self.lines.push(line);
+
+        Ok(())
+    }
+}
+
+// `.macro name [param, ...]`
+fn macro_args(rest: &str) -> Result<(String, Vec<String>), String> {
+    let mut words = rest.split(|c: char| c == ',' || c.is_whitespace()).filter(|word| !word.is_empty());
+    let name = match words.next() {
+        Some(name) if SYMBOL_REGEX.is_match(name) => String::from(name),
+        _ => return Err(String::from(".macro needs a name")),
+    };
+
+    let params: Vec<String> = words.map(String::from).collect();
+    if let Some(param) = params.iter().find(|param| !SYMBOL_REGEX.is_match(param)) {
+        return Err(format!("'{}' can't be a parameter name", param));
+    }
+
+    Ok((name, params))
+}
+
+// a leading `name:` or `@name:` labels the line, and a lone `:` is an anonymous label
+fn split_label(line: &str) -> (Option<String>, &str) {
+    if line == ":" || line.starts_with(": ") || line.starts_with(":\t") {
+        return (Some(String::from(":")), line[1..].trim());
+    }
+
+    match line.find(':') {
+        Some(end) if LABEL_REGEX.is_match(&line[..end]) && !line[end + 1..].starts_with(':') => {
+            (Some(String::from(&line[..end])), line[end + 1..].trim())
+        }
+        _ => (None, line),
+    }
+}
+
+// which label `name` means from inside `scope`: the innermost scope that has one by that name, or the top level
+fn qualify<F>(name: &str, scope: &str, defined: F) -> String
+    where F: Fn(&str) -> bool
+{
+    // already qualified by `define` or `localize`
+    if name.contains('@') || (name.starts_with(':') && !name.starts_with("::")) {
+        return String::from(name);
+    }
+
+    if let Some(name) = name.strip_prefix("::") {
+        return String::from(name);
+    }
+
+    let mut scope = scope;
+    loop {
+        let candidate = match scope.is_empty() {
+            true => String::from(name),
+            false => format!("{}::{}", scope, name),
+        };
+
+        if scope.is_empty() || defined(&candidate) {
+            return candidate;
+        }
+
+        scope = match scope.rfind("::") {
+            Some(end) => &scope[..end],
+            None => "",
+        };
+    }
+}
+
+fn parse_expr(text: &str) -> Result<Expr, String> {
+    let tokens = token::tokenize(text)?;
+    let mut cursor = Tokens::new(&tokens);
+
+    let expr = Expr::parse(&mut cursor)?;
+    match cursor.peek() {
+        None => Ok(expr),
+        Some(token) => Err(format!("unexpected {} after the expression", token.describe())),
+    }
+}
+
+// macro arguments: split on the commas that aren't inside parentheses, strings or character constants
+fn split_args(text: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut start = 0;
+    let mut parens = 0;
+    let mut quote = None;
+    let mut escaped = false;
+
+    for (i, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quote.is_some() => escaped = true,
+            '\'' | '"' if quote.is_none() => quote = Some(c),
+            c if quote == Some(c) => quote = None,
+            _ if quote.is_some() => {}
+            '(' => parens += 1,
+            ')' => parens -= 1,
+            ',' if parens == 0 => {
+                args.push(String::from(text[start..i].trim()));
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+
+    let last = text[start..].trim();
+    if !last.is_empty() || !args.is_empty() {
+        args.push(String::from(last));
+    }
+
+    args
+}
+
+// swaps every identifier in `names` for what it maps to, leaving strings, character constants, numbers and
+// directives alone
+fn substitute(line: &str, names: &HashMap<String, String>) -> String {
+    if names.is_empty() {
+        return String::from(line);
+    }
+
+    let chars: Vec<char> = line.chars().collect();
+    let mut out = String::with_capacity(line.len());
+    let mut pos = 0;
+    let mut quote = None;
+
+    while pos < chars.len() {
+        let c = chars[pos];
+
+        match quote {
+            Some(q) => {
+                out.push(c);
+                if c == '\\' && pos + 1 < chars.len() {
+                    out.push(chars[pos + 1]);
+                    pos += 1;
+                } else if c == q {
+                    quote = None;
+                }
+
+                pos += 1;
+            }
+            None if c == '\'' || c == '"' => {
+                quote = Some(c);
+                out.push(c);
+                pos += 1;
+            }
+            // a number or directive name runs on through anything that looks like an identifier
+            None if c == '$' || c == '.' || c.is_ascii_digit() => {
+                out.push(c);
+                pos += 1;
+
+                while pos < chars.len() && (chars[pos].is_alphanumeric() || chars[pos] == '_') {
+                    out.push(chars[pos]);
+                    pos += 1;
+                }
+            }
+            None if c.is_alphabetic() || c == '_' => {
+                let start = pos;
+                while pos < chars.len() && (chars[pos].is_alphanumeric() || chars[pos] == '_') {
+                    pos += 1;
+                }
+
+                let word: String = chars[start..pos].iter().collect();
+                match names.get(&word) {
+                    Some(replacement) => out.push_str(replacement),
+                    None => out.push_str(&word),
+                }
+            }
+            None => {
+                out.push(c);
+                pos += 1;
+            }
+        }
+    }
+
+    out
+}
+
+fn relative_to(file: Option<&Path>, path: &str) -> PathBuf {
+    match file.and_then(Path::parent) {
+        Some(dir) => dir.join(path),
+        None => PathBuf::from(path),
+    }
+}
+
+// the part of an .incbin'd file that was asked for. the offset and length have to be constants, since the file's
+// read before any labels exist
+fn incbin_slice(data: Vec<u8>, offset: Option<Expr>, len: Option<Expr>) -> Result<Vec<u8>, String> {
+    let constant = |expr: Option<Expr>, default: usize| match expr {
+        Some(expr) => {
+            match expr.constant() {
+                Some(val) if val >= 0 => Ok(val as usize),
+                Some(val) => Err(format!("{} can't be negative", val)),
+                None => Err(String::from(".incbin's offset and length have to be constants")),
+            }
+        }
+        None => Ok(default),
+    };
+
+    let offset = constant(offset, 0)?;
+    let len = constant(len, data.len().saturating_sub(offset))?;
+
+    match offset.checked_add(len) {
+        Some(end) if end <= data.len() => Ok(data[offset..end].to_vec()),
+        _ => Err(format!("only has {} bytes, so {} at offset {} won't fit", data.len(), len, offset)),
+    }
+}
+
+// everything before a `;` that isn't inside a character constant or string
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quote.is_some() => escaped = true,
+            '\'' | '"' if quote.is_none() => quote = Some(c),
+            c if quote == Some(c) => quote = None,
+            ';' if quote.is_none() => return &line[..i],
+            _ => {}
+        }
+    }
+
+    line
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::{split_args, substitute};
+
+    #[test]
+    fn arguments_and_substitution() {
+        assert_eq!(split_args("a, (b, c), ',', \"x,y\""), vec!["a", "(b, c)", "','", "\"x,y\""]);
+        assert_eq!(split_args(""), Vec::<String>::new());
+        assert_eq!(split_args("a,"), vec!["a", ""]);
+
+        let mut names = HashMap::new();
+        names.insert(String::from("val"), String::from("$10"));
+        names.insert(String::from("byte"), String::from("oops"));
+
+        assert_eq!(substitute("lda #<val+val", &names), "lda #<$10+$10");
+        assert_eq!(substitute(".byte val, 'v', \"val\", $val, value", &names), ".byte $10, 'v', \"val\", $val, value");
+    }
+}
@@ -0,0 +1,308 @@
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token {
+    Number(i64),
+    Ident(String),
+    Str(String),
+    Hash,
+    LParen,
+    RParen,
+    Comma,
+    Colon,
+    // `:+`, `:++`, `:-`... an anonymous label that many ahead (positive) or back (negative)
+    Anon(i32),
+    // operators, longest match first: << >> <= >= == != <> && || then single characters
+    Op(&'static str),
+}
+
+const OPERATORS: &'static [&'static str] = &["<<", ">>", "<=", ">=", "==", "!=", "<>", "&&", "||", "+", "-", "*", "/",
+                                             "%", "&", "|", "^", "<", ">", "=", "~", "!"];
+
+impl Token {
+    pub fn describe(&self) -> String {
+        match *self {
+            Token::Number(val) => format!("number {}", val),
+            Token::Ident(ref name) => format!("'{}'", name),
+            Token::Str(ref text) => format!("string \"{}\"", text),
+            Token::Hash => String::from("'#'"),
+            Token::LParen => String::from("'('"),
+            Token::RParen => String::from("')'"),
+            Token::Comma => String::from("','"),
+            Token::Colon => String::from("':'"),
+            Token::Anon(n) => format!("':{}'", if n > 0 { "+" } else { "-" }.repeat(n.unsigned_abs() as usize)),
+            Token::Op(op) => format!("'{}'", op),
+        }
+    }
+}
+
+// a token and the column (0-based, in chars) it starts at
+#[derive(Clone, Debug, PartialEq)]
+pub struct Spanned {
+    pub token: Token,
+    pub col: usize,
+}
+
+// splits an operand into tokens. identifiers can be cheap locals (`@loop`) or qualified with scopes (`a::b`,
+// `::global`). numbers are decimal, $hex, %binary or a character constant like 'a'; strings are
+// in double quotes. both take \n, \r, \t, \0, \\, \' and \" escapes
+pub fn tokenize(text: &str) -> Result<Vec<Spanned>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    while pos < chars.len() {
+        let start = pos;
+        let c = chars[pos];
+        pos += 1;
+
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '#' => Token::Hash,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            ',' => Token::Comma,
+            // `a:+1` is a width prefix, not an anonymous label
+            ':' if chars.get(pos).is_some_and(|c| *c == '+' || *c == '-') && !follows_ident(&tokens) => {
+                let sign = chars[pos];
+                let count = take_while(&chars, &mut pos, |c| c == sign).len() as i32;
+
+                Token::Anon(if sign == '+' { count } else { -count })
+            }
+            ':' if chars.get(pos) == Some(&':') => {
+                pos -= 1;
+                Token::Ident(identifier(&chars, &mut pos))
+            }
+            ':' => Token::Colon,
+            '$' => {
+                let digits = take_while(&chars, &mut pos, |c| c.is_ascii_hexdigit());
+
+                Token::Number(number(&digits, 16, c)?)
+            }
+            // %1010 is binary, but `x % 2` is modulo
+            '%' if chars.get(pos).is_some_and(|c| *c == '0' || *c == '1') && !follows_operand(&tokens) => {
+                let radix = 2;
+                let digits = take_while(&chars, &mut pos, |c| c.is_digit(radix));
+
+                Token::Number(number(&digits, radix, c)?)
+            }
+            c if c.is_ascii_digit() => {
+                pos -= 1;
+                let digits = take_while(&chars, &mut pos, |c| c.is_ascii_digit());
+
+                Token::Number(number(&digits, 10, c)?)
+            }
+            '\'' => {
+                let val = match chars.get(pos) {
+                    Some(&'\\') => {
+                        pos += 1;
+                        escape(chars.get(pos).cloned())?
+                    }
+                    Some(&c) if c != '\'' => c,
+                    _ => return Err(String::from("empty character constant")),
+                };
+
+                if chars.get(pos + 1) != Some(&'\'') {
+                    return Err(String::from("unterminated character constant"));
+                }
+
+                pos += 2;
+                Token::Number(val as i64)
+            }
+            '"' => {
+                let mut text = String::new();
+
+                loop {
+                    match chars.get(pos) {
+                        Some(&'"') => break,
+                        Some(&'\\') => {
+                            pos += 1;
+                            match chars.get(pos) {
+                                Some(&c) => text.push(escape(Some(c))?),
+                                None => return Err(String::from("unterminated string")),
+                            }
+                        }
+                        Some(&c) => text.push(c),
+                        None => return Err(String::from("unterminated string")),
+                    }
+
+                    pos += 1;
+                }
+
+                pos += 1;
+                Token::Str(text)
+            }
+            c if c.is_alphabetic() || c == '_' || c == '@' => {
+                pos -= 1;
+                Token::Ident(identifier(&chars, &mut pos))
+            }
+            c => {
+                let rest: String = chars[start..].iter().take(2).collect();
+
+                match OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+                    Some(op) => {
+                        pos = start + op.len();
+                        Token::Op(op)
+                    }
+                    None => return Err(format!("unexpected '{}'", c)),
+                }
+            }
+        };
+
+        tokens.push(Spanned {
+            token: token,
+            col: start,
+        });
+    }
+
+    Ok(tokens)
+}
+
+// a name, made up of `::` separated parts
+fn identifier(chars: &[char], pos: &mut usize) -> String {
+    let mut name = String::new();
+
+    loop {
+        if chars.get(*pos) == Some(&':') && chars.get(*pos + 1) == Some(&':') {
+            name.push_str("::");
+            *pos += 2;
+        }
+
+        if chars.get(*pos) == Some(&'@') {
+            name.push('@');
+            *pos += 1;
+        }
+
+        name.push_str(&take_while(chars, pos, |c| c.is_alphanumeric() || c == '_'));
+
+        let more = chars.get(*pos) == Some(&':') && chars.get(*pos + 1) == Some(&':') &&
+                   chars.get(*pos + 2).is_some_and(|c| c.is_alphabetic() || *c == '_');
+        if !more {
+            return name;
+        }
+    }
+}
+
+fn follows_ident(tokens: &[Spanned]) -> bool {
+    match tokens.last().map(|spanned| &spanned.token) {
+        Some(&Token::Ident(_)) => true,
+        _ => false,
+    }
+}
+
+// whether the last token ends an operand, in which case what comes next is a binary operator
+fn follows_operand(tokens: &[Spanned]) -> bool {
+    match tokens.last().map(|spanned| &spanned.token) {
+        Some(&Token::Number(_)) | Some(&Token::Ident(_)) | Some(&Token::RParen) => true,
+        _ => false,
+    }
+}
+
+// a cursor over a line's tokens for the parsers built on them
+pub struct Tokens<'a> {
+    tokens: &'a [Spanned],
+    pub pos: usize,
+}
+
+impl<'a> Tokens<'a> {
+    pub fn new(tokens: &'a [Spanned]) -> Self {
+        Tokens {
+            tokens: tokens,
+            pos: 0,
+        }
+    }
+
+    pub fn peek(&self) -> Option<&'a Token> {
+        self.peek_at(0)
+    }
+
+    pub fn peek_at(&self, offset: usize) -> Option<&'a Token> {
+        self.tokens.get(self.pos + offset).map(|spanned| &spanned.token)
+    }
+
+    pub fn next(&mut self) -> Option<&'a Token> {
+        let token = self.peek();
+        self.pos += 1;
+
+        token
+    }
+
+    pub fn expect(&mut self, expected: Token) -> Result<(), String> {
+        match self.next() {
+            Some(token) if *token == expected => Ok(()),
+            Some(token) => Err(format!("expected {} but found {}", expected.describe(), token.describe())),
+            None => Err(format!("expected {}", expected.describe())),
+        }
+    }
+
+    pub fn at_end(&self) -> bool {
+        self.pos >= self.tokens.len()
+    }
+}
+
+fn take_while<F>(chars: &[char], pos: &mut usize, pred: F) -> String
+    where F: Fn(char) -> bool
+{
+    let start = *pos;
+    while *pos < chars.len() && pred(chars[*pos]) {
+        *pos += 1;
+    }
+
+    chars[start..*pos].iter().collect()
+}
+
+fn number(digits: &str, radix: u32, prefix: char) -> Result<i64, String> {
+    if digits.is_empty() {
+        return Err(format!("expected digits after '{}'", prefix));
+    }
+
+    i64::from_str_radix(digits, radix).map_err(|_| format!("'{}' is too big", digits))
+}
+
+fn escape(c: Option<char>) -> Result<char, String> {
+    match c {
+        Some('n') => Ok('\n'),
+        Some('r') => Ok('\r'),
+        Some('t') => Ok('\t'),
+        Some('0') => Ok('\0'),
+        Some('\\') => Ok('\\'),
+        Some('\'') => Ok('\''),
+        Some('"') => Ok('"'),
+        Some(c) => Err(format!("unknown escape '\\{}'", c)),
+        None => Err(String::from("unterminated character constant")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{tokenize, Token};
+
+    fn tokens(text: &str) -> Vec<Token> {
+        tokenize(text).unwrap().into_iter().map(|spanned| spanned.token).collect()
+    }
+
+    #[test]
+    fn literals_and_punctuation() {
+        assert_eq!(tokens("($10),y"),
+                   vec![Token::LParen, Token::Number(0x10), Token::RParen, Token::Comma, Token::Ident(String::from("y"))]);
+        assert_eq!(tokens("#%1010 'a' '\\n' '\\'' 42"),
+                   vec![Token::Hash, Token::Number(0b1010), Token::Number(97), Token::Number(10), Token::Number(39),
+                        Token::Number(42)]);
+        assert_eq!(tokens("a:$1234"), vec![Token::Ident(String::from("a")), Token::Colon, Token::Number(0x1234)]);
+        assert_eq!(tokenize("  lda").unwrap()[0].col, 2);
+
+        assert!(tokenize("$").unwrap_err().contains("expected digits"));
+        assert!(tokenize("'ab'").unwrap_err().contains("unterminated"));
+        assert_eq!(tokens("\"hi, \\\"you\\\"\" 1"), vec![Token::Str(String::from("hi, \"you\"")), Token::Number(1)]);
+        assert!(tokenize("\"abc").unwrap_err().contains("unterminated string"));
+        assert!(tokenize("lda 1?").unwrap_err().contains("unexpected '?'"));
+
+        assert_eq!(tokens("@loop ::reset a::b::c a:+1"),
+                   vec![Token::Ident(String::from("@loop")), Token::Ident(String::from("::reset")),
+                        Token::Ident(String::from("a::b::c")), Token::Ident(String::from("a")), Token::Colon,
+                        Token::Op("+"), Token::Number(1)]);
+        assert_eq!(tokens(":++ :-"), vec![Token::Anon(2), Token::Anon(-1)]);
+
+        assert_eq!(tokens("x%%10<<2<=>"),
+                   vec![Token::Ident(String::from("x")), Token::Op("%"), Token::Number(2), Token::Op("<<"),
+                        Token::Number(2), Token::Op("<="), Token::Op(">")]);
+    }
+}
@@ -25,6 +25,19 @@ pub enum AddrMode {
     Indirect,
     IndirectX,
     IndirectY,
+
+    // 65c816 only
+    AbsoluteLong,
+    AbsoluteLongX,
+    AbsoluteIndirectX,
+    AbsoluteIndirectLong,
+    DirectIndirect,
+    DirectIndirectLong,
+    DirectIndirectLongY,
+    StackRelative,
+    StackRelativeIndirectY,
+    RelativeLong,
+    BlockMove,
 }
 
 #[derive(Clone)]
@@ -177,7 +190,7 @@ pub fn abs_y(cpu: &mut Cpu) -> AddrResult {
     let offset = cpu.reg_y;
 
     let addr = partial_addr.overflowing_add(offset as u8 as u16).0 as u16;
-    let resolved_value = cpu.memory.read_u8_at(&addr);
+    let resolved_value = cpu.memory.peek_u8_at(&addr);
 
     AddrResult {
         value: addr,
@@ -0,0 +1,337 @@
+use std::cell::Cell;
+
+pub type BreakpointId = usize;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    Access,
+}
+
+impl WatchKind {
+    fn matches(&self, access: WatchKind) -> bool {
+        *self == WatchKind::Access || *self == access
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopReason {
+    // pc ran off the end of memory
+    Halted,
+    Breakpoint { id: BreakpointId, pc: u16 },
+    // `kind` is the access that actually happened, never `Access`
+    Watchpoint { id: BreakpointId, addr: u16, value: u8, kind: WatchKind },
+}
+
+#[derive(Clone, Debug)]
+pub struct Breakpoint {
+    pub id: BreakpointId,
+    pub start: u16,
+    pub end: u16,
+    pub enabled: bool,
+    pub hits: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct Watchpoint {
+    pub id: BreakpointId,
+    pub start: u16,
+    pub end: u16,
+    pub kind: WatchKind,
+    // only fire when this value is read or written
+    pub value: Option<u8>,
+    pub enabled: bool,
+
+    // bumped from inside reads, which only get `&self`
+    hits: Cell<u64>,
+}
+
+impl Watchpoint {
+    pub fn hits(&self) -> u64 {
+        self.hits.get()
+    }
+}
+
+// execution breakpoints on pc addresses or ranges (both ends inclusive)
+#[derive(Clone, Debug, Default)]
+pub struct Breakpoints {
+    points: Vec<Breakpoint>,
+    next_id: BreakpointId,
+    active: bool,
+}
+
+impl Breakpoints {
+    pub fn add(&mut self, start: u16, end: u16) -> BreakpointId {
+        self.next_id += 1;
+
+        self.points.push(Breakpoint {
+            id: self.next_id,
+            start: start,
+            end: end,
+            enabled: true,
+            hits: 0,
+        });
+        self.update_active();
+
+        self.next_id
+    }
+
+    pub fn remove(&mut self, id: BreakpointId) -> bool {
+        let len = self.points.len();
+        self.points.retain(|point| point.id != id);
+        self.update_active();
+
+        self.points.len() != len
+    }
+
+    pub fn set_enabled(&mut self, id: BreakpointId, enabled: bool) -> bool {
+        let found = match self.points.iter_mut().find(|point| point.id == id) {
+            Some(point) => {
+                point.enabled = enabled;
+                true
+            }
+            None => false,
+        };
+        self.update_active();
+
+        found
+    }
+
+    pub fn get(&self, id: BreakpointId) -> Option<&Breakpoint> {
+        self.points.iter().find(|point| point.id == id)
+    }
+
+    pub fn iter(&self) -> ::std::slice::Iter<Breakpoint> {
+        self.points.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+        self.update_active();
+    }
+
+    // false when nothing is enabled, so the hot path can skip the search entirely
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn hit(&mut self, pc: u16) -> Option<BreakpointId> {
+        let point = self.points.iter_mut().find(|point| point.enabled && point.start <= pc && pc <= point.end)?;
+        point.hits += 1;
+
+        Some(point.id)
+    }
+
+    fn update_active(&mut self) {
+        self.active = self.points.iter().any(|point| point.enabled);
+    }
+}
+
+// read/write/access watchpoints on memory ranges (both ends inclusive), checked by `MemoryMap` on every access
+#[derive(Clone, Debug, Default)]
+pub struct Watchpoints {
+    points: Vec<Watchpoint>,
+    next_id: BreakpointId,
+    active: bool,
+
+    // the first watchpoint to fire since the last `take_hit`
+    hit: Cell<Option<StopReason>>,
+}
+
+impl Watchpoints {
+    pub fn add(&mut self, start: u16, end: u16, kind: WatchKind, value: Option<u8>) -> BreakpointId {
+        self.next_id += 1;
+
+        self.points.push(Watchpoint {
+            id: self.next_id,
+            start: start,
+            end: end,
+            kind: kind,
+            value: value,
+            enabled: true,
+            hits: Cell::new(0),
+        });
+        self.update_active();
+
+        self.next_id
+    }
+
+    pub fn remove(&mut self, id: BreakpointId) -> bool {
+        let len = self.points.len();
+        self.points.retain(|point| point.id != id);
+        self.update_active();
+
+        self.points.len() != len
+    }
+
+    pub fn set_enabled(&mut self, id: BreakpointId, enabled: bool) -> bool {
+        let found = match self.points.iter_mut().find(|point| point.id == id) {
+            Some(point) => {
+                point.enabled = enabled;
+                true
+            }
+            None => false,
+        };
+        self.update_active();
+
+        found
+    }
+
+    pub fn get(&self, id: BreakpointId) -> Option<&Watchpoint> {
+        self.points.iter().find(|point| point.id == id)
+    }
+
+    pub fn iter(&self) -> ::std::slice::Iter<Watchpoint> {
+        self.points.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+        self.update_active();
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn check(&self, addr: u16, value: u8, access: WatchKind) {
+        for point in &self.points {
+            let matches = point.enabled && point.start <= addr && addr <= point.end && point.kind.matches(access) &&
+                          point.value.map_or(true, |filter| filter == value);
+
+            if !matches {
+                continue;
+            }
+
+            point.hits.set(point.hits.get() + 1);
+
+            if self.hit.get().is_none() {
+                self.hit.set(Some(StopReason::Watchpoint {
+                    id: point.id,
+                    addr: addr,
+                    value: value,
+                    kind: access,
+                }));
+            }
+        }
+    }
+
+    pub fn take_hit(&self) -> Option<StopReason> {
+        self.hit.take()
+    }
+
+    fn update_active(&mut self) {
+        self.active = self.points.iter().any(|point| point.enabled);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{StopReason, WatchKind};
+    use cpu::Cpu;
+
+    fn program() -> Cpu {
+        let mut cpu = Cpu::new();
+        cpu.trace = false;
+
+        cpu.load_program(0x6000,
+                         &[// ldx #$00
+                           0xa2, 0x00,
+                           // inx
+                           0xe8,
+                           // stx $20
+                           0x86, 0x20,
+                           // cpx #$03
+                           0xe0, 0x03,
+                           // bne $6002
+                           0xd0, 0xf9,
+                           // lda $20
+                           0xa5, 0x20]);
+
+        cpu
+    }
+
+    #[test]
+    fn breakpoint() {
+        let mut cpu = program();
+        let id = cpu.breakpoints.add(0x6003, 0x6003);
+
+        assert_eq!(cpu.run(), StopReason::Breakpoint { id: id, pc: 0x6003 });
+        assert_eq!(cpu.reg_x, 1);
+
+        // resuming steps over the breakpoint we're sitting on
+        assert_eq!(cpu.resume(), StopReason::Breakpoint { id: id, pc: 0x6003 });
+        assert_eq!(cpu.reg_x, 2);
+        assert_eq!(cpu.breakpoints.get(id).unwrap().hits, 2);
+
+        cpu.breakpoints.set_enabled(id, false);
+        assert_eq!(cpu.resume(), StopReason::Halted);
+        assert_eq!(cpu.reg_acc, 3);
+    }
+
+    #[test]
+    fn breakpoint_range() {
+        let mut cpu = program();
+        let id = cpu.breakpoints.add(0x6009, 0x60ff);
+
+        assert_eq!(cpu.run(), StopReason::Breakpoint { id: id, pc: 0x6009 });
+        assert_eq!(cpu.reg_x, 3);
+    }
+
+    #[test]
+    fn write_watchpoint_with_value() {
+        let mut cpu = program();
+        let id = cpu.memory.watchpoints.add(0x0020, 0x0020, WatchKind::Write, Some(0x02));
+
+        assert_eq!(cpu.run(),
+                   StopReason::Watchpoint {
+                       id: id,
+                       addr: 0x0020,
+                       value: 0x02,
+                       kind: WatchKind::Write,
+                   });
+
+        // stops after the instruction that made the access
+        assert_eq!(cpu.reg_pc, 0x6005);
+        assert_eq!(cpu.memory.watchpoints.get(id).unwrap().hits(), 1);
+    }
+
+    #[test]
+    fn read_watchpoint() {
+        let mut cpu = program();
+        let id = cpu.memory.watchpoints.add(0x0000, 0x00ff, WatchKind::Read, None);
+
+        assert_eq!(cpu.run(),
+                   StopReason::Watchpoint {
+                       id: id,
+                       addr: 0x0020,
+                       value: 0x03,
+                       kind: WatchKind::Read,
+                   });
+        assert_eq!(cpu.reg_acc, 3);
+
+        // peeking doesn't count as an access
+        cpu.memory.peek_u8_at(&0x0020);
+        assert_eq!(cpu.memory.watchpoints.get(id).unwrap().hits(), 1);
+    }
+
+    #[test]
+    fn indexed_store_isnt_a_read() {
+        let mut cpu = Cpu::new();
+        cpu.trace = false;
+
+        // ldy #$01 / sta $02ff,Y / lda $0300
+        cpu.load_program(0x6000, &[0xa0, 0x01, 0x99, 0xff, 0x02, 0xad, 0x00, 0x03]);
+        let id = cpu.memory.watchpoints.add(0x0300, 0x0300, WatchKind::Read, None);
+
+        assert_eq!(cpu.run(),
+                   StopReason::Watchpoint {
+                       id: id,
+                       addr: 0x0300,
+                       value: 0x00,
+                       kind: WatchKind::Read,
+                   });
+        assert_eq!(cpu.reg_pc, 0x6008);
+    }
+}
@@ -0,0 +1,172 @@
+use std::sync::Arc;
+
+use util;
+
+use super::mem::Mapper;
+use super::mos6510::{LORAM, HIRAM, CHAREN};
+
+const BASIC_START_ADDR: u16 = 0xa000;
+const BASIC_END_ADDR: u16 = 0xbfff;
+const IO_START_ADDR: u16 = 0xd000;
+const IO_END_ADDR: u16 = 0xdfff;
+const KERNAL_START_ADDR: u16 = 0xe000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BankConfig {
+    pub basic: bool,
+    pub kernal: bool,
+    pub char_rom: bool,
+    pub io: bool,
+}
+
+impl BankConfig {
+    // decodes loram/hiram/charen the way the c64 pla does (assuming no cartridge is pulling game/exrom)
+    pub fn from_lines(lines: u8) -> Self {
+        let loram = util::test_bit_set(lines, LORAM);
+        let hiram = util::test_bit_set(lines, HIRAM);
+        let charen = util::test_bit_set(lines, CHAREN);
+
+        let any_rom = loram || hiram;
+
+        BankConfig {
+            basic: loram && hiram,
+            kernal: hiram,
+            char_rom: any_rom && !charen,
+            io: any_rom && charen,
+        }
+    }
+}
+
+// a c64 memory map: basic, kernal and char rom overlay ram depending on what the 6510 port says.
+// the i/o area is backed by plain registers until real chips are attached
+// the roms never change, so forks share them
+#[derive(Clone)]
+pub struct C64Mapper {
+    pub basic: Arc<Vec<u8>>,
+    pub kernal: Arc<Vec<u8>>,
+    pub char_rom: Arc<Vec<u8>>,
+    pub io: Vec<u8>,
+
+    config: BankConfig,
+}
+
+impl C64Mapper {
+    pub fn new(basic: Vec<u8>, kernal: Vec<u8>, char_rom: Vec<u8>) -> Self {
+        C64Mapper {
+            basic: Arc::new(basic),
+            kernal: Arc::new(kernal),
+            char_rom: Arc::new(char_rom),
+            io: vec![0; (IO_END_ADDR - IO_START_ADDR) as usize + 1],
+
+            config: BankConfig::from_lines(0xff),
+        }
+    }
+
+    pub fn config(&self) -> BankConfig {
+        self.config
+    }
+
+    fn rom_at(rom: &[u8], offset: u16) -> Option<u8> {
+        rom.get(offset as usize).cloned()
+    }
+}
+
+impl Mapper for C64Mapper {
+    fn read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            BASIC_START_ADDR..=BASIC_END_ADDR if self.config.basic => C64Mapper::rom_at(&self.basic, addr - BASIC_START_ADDR),
+            IO_START_ADDR..=IO_END_ADDR if self.config.io => Some(self.io[(addr - IO_START_ADDR) as usize]),
+            IO_START_ADDR..=IO_END_ADDR if self.config.char_rom => C64Mapper::rom_at(&self.char_rom, addr - IO_START_ADDR),
+            KERNAL_START_ADDR..=0xffff if self.config.kernal => C64Mapper::rom_at(&self.kernal, addr - KERNAL_START_ADDR),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, addr: u16, val: u8) -> bool {
+        // writes to rom fall through to the ram underneath; only i/o swallows them
+        match addr {
+            IO_START_ADDR..=IO_END_ADDR if self.config.io => {
+                self.io[(addr - IO_START_ADDR) as usize] = val;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn bank_changed(&mut self, lines: u8) {
+        self.config = BankConfig::from_lines(lines);
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        self.io.clone()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> bool {
+        if state.len() != self.io.len() {
+            return false;
+        }
+
+        self.io.copy_from_slice(state);
+        true
+    }
+
+    fn box_clone(&self) -> Box<Mapper> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BankConfig, C64Mapper};
+    use cpu::Cpu;
+
+    fn c64() -> Cpu {
+        let mut cpu = Cpu::new_6510();
+        cpu.memory.set_mapper(Box::new(C64Mapper::new(vec![0xba; 0x2000], vec![0xea; 0x2000], vec![0xc4; 0x1000])));
+
+        cpu
+    }
+
+    #[test]
+    fn bank_config() {
+        assert_eq!(BankConfig::from_lines(0x37),
+                   BankConfig { basic: true, kernal: true, char_rom: false, io: true });
+        assert_eq!(BankConfig::from_lines(0x33),
+                   BankConfig { basic: true, kernal: true, char_rom: true, io: false });
+        assert_eq!(BankConfig::from_lines(0x34),
+                   BankConfig { basic: false, kernal: false, char_rom: false, io: false });
+    }
+
+    #[test]
+    fn roms_visible_out_of_reset() {
+        let cpu = c64();
+
+        assert_eq!(cpu.memory.read_u8_at(&0xa000), 0xba);
+        assert_eq!(cpu.memory.read_u8_at(&0xe123), 0xea);
+    }
+
+    #[test]
+    fn port_switches_banks() {
+        let mut cpu = c64();
+
+        // writes under rom land in ram
+        cpu.memory.write_at(&0xa000, &[0x42]);
+        assert_eq!(cpu.memory.read_u8_at(&0xa000), 0xba);
+
+        // all ram
+        cpu.memory.write_at(&0x0000, &[0x07]);
+        cpu.memory.write_at(&0x0001, &[0x34]);
+        assert_eq!(cpu.memory.read_u8_at(&0xa000), 0x42);
+
+        // char rom in, basic/kernal in
+        cpu.memory.write_at(&0x0001, &[0x33]);
+        assert_eq!(cpu.memory.read_u8_at(&0xd000), 0xc4);
+        assert_eq!(cpu.memory.read_u8_at(&0xa000), 0xba);
+
+        // i/o in
+        cpu.memory.write_at(&0x0001, &[0x37]);
+        cpu.memory.write_at(&0xd020, &[0x0e]);
+        assert_eq!(cpu.memory.read_u8_at(&0xd020), 0x0e);
+        assert_eq!(cpu.memory.mem[0xd020], 0x00);
+    }
+}
@@ -0,0 +1,199 @@
+// page 1 can only hold this many return addresses, so anything deeper is the stack pointer wrapping around
+pub const MAX_DEPTH: usize = 128;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrameKind {
+    Subroutine,
+    Brk,
+    Irq,
+    Nmi,
+}
+
+impl FrameKind {
+    pub fn to_u8(&self) -> u8 {
+        match *self {
+            FrameKind::Subroutine => 0,
+            FrameKind::Brk => 1,
+            FrameKind::Irq => 2,
+            FrameKind::Nmi => 3,
+        }
+    }
+
+    pub fn from_u8(val: u8) -> Option<FrameKind> {
+        match val {
+            0 => Some(FrameKind::Subroutine),
+            1 => Some(FrameKind::Brk),
+            2 => Some(FrameKind::Irq),
+            3 => Some(FrameKind::Nmi),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frame {
+    pub kind: FrameKind,
+    // the jsr or brk that made the call, or the instruction an interrupt arrived before
+    pub caller: u16,
+    pub target: u16,
+    // sp before the return address was pushed
+    pub sp: u8,
+}
+
+// a shadow of the calls on the hardware stack, so tools can show a backtrace without decoding page 1.
+// frames come off when the stack pointer says their return address is gone rather than by pairing every rts
+// with a jsr, which keeps it honest about code that plays games with the stack:
+//
+// - an rts through an address the code pushed itself (the rts jump table trick) leaves sp below the top frame,
+//   so nothing pops and the jump stays inside the current frame
+// - dropping a return address with pla/pla, or resetting the stack with txs, pops every frame that went with it
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CallStack {
+    frames: Vec<Frame>,
+}
+
+impl CallStack {
+    pub fn push(&mut self, frame: Frame) {
+        if self.frames.len() == MAX_DEPTH {
+            self.frames.remove(0);
+        }
+
+        self.frames.push(frame);
+    }
+
+    // pops every frame whose return address is no longer on the stack, given where sp is now
+    pub fn unwind(&mut self, sp: u8) -> usize {
+        let depth = self.frames.len();
+        self.frames.retain(|frame| frame.sp > sp);
+
+        depth - self.frames.len()
+    }
+
+    // outermost first
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub fn top(&self) -> Option<&Frame> {
+        self.frames.last()
+    }
+
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::FrameKind;
+    use cpu::Cpu;
+
+    fn run(program: &[u8], instructions: usize) -> Cpu {
+        let mut cpu = Cpu::new();
+        cpu.trace = false;
+
+        cpu.load_program(0x6000, program);
+        cpu.reset();
+
+        while cpu.instructions < instructions as u64 {
+            cpu.step();
+        }
+
+        cpu
+    }
+
+    #[test]
+    fn jsr_and_rts() {
+        let program = &[// jsr $6006
+                        0x20, 0x06, 0x60,
+                        // jmp $ffff
+                        0x4c, 0xff, 0xff,
+                        // jsr $600a
+                        0x20, 0x0a, 0x60,
+                        // rts
+                        0x60,
+                        // rts
+                        0x60];
+
+        let cpu = run(program, 2);
+        let frames = cpu.call_stack.frames();
+
+        assert_eq!(frames.len(), 2);
+        assert_eq!((frames[0].caller, frames[0].target, frames[0].sp), (0x6000, 0x6006, 0xfd));
+        assert_eq!((frames[1].caller, frames[1].target, frames[1].sp), (0x6006, 0x600a, 0xfb));
+
+        let cpu = run(program, 3);
+        assert_eq!(cpu.call_stack.depth(), 1);
+
+        let cpu = run(program, 4);
+        assert_eq!(cpu.call_stack.depth(), 0);
+        assert_eq!(cpu.reg_pc, 0x6003);
+    }
+
+    #[test]
+    fn rts_trick_and_dropped_frames() {
+        let program = &[// jsr $6004
+                        0x20, 0x04, 0x60,
+                        // brk
+                        0x00,
+                        // lda #$60 / pha / lda #$0b / pha
+                        0xa9, 0x60, 0x48, 0xa9, 0x0b, 0x48,
+                        // rts, which jumps to $600c
+                        0x60,
+                        // (padding)
+                        0xea,
+                        // pla / pla, dropping the return address
+                        0x68, 0x68,
+                        // nop
+                        0xea];
+
+        // the jump table rts stays inside the subroutine
+        let cpu = run(program, 6);
+        assert_eq!(cpu.reg_pc, 0x600c);
+        assert_eq!(cpu.call_stack.depth(), 1);
+
+        // throwing away the return address doesn't pop anything until something looks at the stack...
+        let mut cpu = run(program, 8);
+        assert_eq!(cpu.call_stack.depth(), 1);
+
+        // ...like a txs resetting it
+        cpu.reg_x = 0xff_u8 as i8;
+        cpu.memory.write_at(&0x600e, &[0x9a]);
+
+        while cpu.instructions < 9 {
+            cpu.step();
+        }
+
+        assert_eq!(cpu.call_stack.depth(), 0);
+    }
+
+    #[test]
+    fn interrupts() {
+        let mut cpu = run(&[0xea, 0xea, 0xea], 1);
+
+        // nmi handler at $7000 is an rti
+        cpu.memory.write_at(&0xfffa, &[0x00, 0x70]);
+        cpu.memory.write_at(&0x7000, &[0x40]);
+
+        cpu.set_nmi(true);
+        while cpu.call_stack.depth() == 0 {
+            cpu.step();
+        }
+
+        let frame = *cpu.call_stack.top().unwrap();
+        assert_eq!(frame.kind, FrameKind::Nmi);
+        assert_eq!((frame.caller, frame.target), (0x6001, 0x7000));
+
+        // the rti itself
+        while cpu.instructions < 2 {
+            cpu.step();
+        }
+
+        assert_eq!(cpu.reg_pc, 0x6001);
+        assert_eq!(cpu.call_stack.depth(), 0);
+    }
+}
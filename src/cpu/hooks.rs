@@ -0,0 +1,252 @@
+use std::cell::RefCell;
+
+use super::Cpu;
+
+pub type HookId = usize;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Access {
+    OpcodeFetch,
+    OperandRead,
+    DataRead,
+    DataWrite,
+    StackPush,
+    StackPop,
+    // irq, nmi or brk: `addr` is the vector taken and `value` the status that was pushed
+    Interrupt,
+    // `addr` is the reset vector and `value` the status register
+    Reset,
+}
+
+pub const ALL_ACCESSES: &'static [Access] = &[Access::OpcodeFetch,
+                                              Access::OperandRead,
+                                              Access::DataRead,
+                                              Access::DataWrite,
+                                              Access::StackPush,
+                                              Access::StackPop,
+                                              Access::Interrupt,
+                                              Access::Reset];
+
+impl Access {
+    fn bit(&self) -> u8 {
+        1 << (*self as u8)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Event {
+    pub access: Access,
+    pub addr: u16,
+    pub value: u8,
+    // the cycle the instruction (or interrupt) making the access started on
+    pub cycle: u64,
+}
+
+struct Hook {
+    id: HookId,
+    mask: u8,
+    callback: Box<FnMut(&Event) + Send>,
+}
+
+// callbacks for tools (coverage, profilers, loggers...) that want to see every bus access without touching the
+// instructions. `MemoryMap` and the cpu report through `report`, which is a single mask test when nothing's
+// listening for that kind of access.
+//
+// closures can't be cloned, so forks and other clones of a machine start out with no hooks
+#[derive(Default)]
+pub struct Hooks {
+    hooks: RefCell<Vec<Hook>>,
+    next_id: HookId,
+    mask: u8,
+
+    // kept up to date by the cpu while any hooks are registered
+    pub cycle: u64,
+}
+
+impl Clone for Hooks {
+    fn clone(&self) -> Self {
+        Hooks::default()
+    }
+}
+
+impl Hooks {
+    pub fn add<F>(&mut self, accesses: &[Access], callback: F) -> HookId
+        where F: FnMut(&Event) + Send + 'static
+    {
+        self.next_id += 1;
+
+        self.hooks.get_mut().push(Hook {
+            id: self.next_id,
+            mask: accesses.iter().fold(0, |mask, access| mask | access.bit()),
+            callback: Box::new(callback),
+        });
+        self.update_mask();
+
+        self.next_id
+    }
+
+    pub fn remove(&mut self, id: HookId) -> bool {
+        let len = self.hooks.get_mut().len();
+        self.hooks.get_mut().retain(|hook| hook.id != id);
+        self.update_mask();
+
+        self.hooks.get_mut().len() != len
+    }
+
+    pub fn clear(&mut self) {
+        self.hooks.get_mut().clear();
+        self.update_mask();
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.mask != 0
+    }
+
+    #[inline]
+    pub fn report(&self, access: Access, addr: u16, value: u8) {
+        if self.mask & access.bit() != 0 {
+            self.fire(access, addr, value);
+        }
+    }
+
+    fn fire(&self, access: Access, addr: u16, value: u8) {
+        let event = Event {
+            access: access,
+            addr: addr,
+            value: value,
+            cycle: self.cycle,
+        };
+
+        for hook in self.hooks.borrow_mut().iter_mut() {
+            if hook.mask & access.bit() != 0 {
+                (hook.callback)(&event);
+            }
+        }
+    }
+
+    fn update_mask(&mut self) {
+        self.mask = self.hooks.get_mut().iter().fold(0, |mask, hook| mask | hook.mask);
+    }
+}
+
+impl Cpu {
+    // calls `callback` for every access of the given kinds until it's removed
+    pub fn add_hook<F>(&mut self, accesses: &[Access], callback: F) -> HookId
+        where F: FnMut(&Event) + Send + 'static
+    {
+        let id = self.memory.hooks.add(accesses, callback);
+        self.memory.hooks.cycle = self.cycles;
+
+        id
+    }
+
+    pub fn remove_hook(&mut self, id: HookId) -> bool {
+        self.memory.hooks.remove(id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use super::{Access, Event, ALL_ACCESSES};
+    use cpu::Cpu;
+
+    fn record(cpu: &mut Cpu, accesses: &[Access]) -> Arc<Mutex<Vec<Event>>> {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+
+        cpu.add_hook(accesses, move |event| sink.lock().unwrap().push(*event));
+
+        events
+    }
+
+    fn summary(events: &Arc<Mutex<Vec<Event>>>) -> Vec<(Access, u16, u8)> {
+        events.lock().unwrap().iter().map(|event| (event.access, event.addr, event.value)).collect()
+    }
+
+    #[test]
+    fn every_kind_of_access() {
+        let mut cpu = Cpu::new();
+        cpu.trace = false;
+
+        // lda $20 / pha / sta $21 / sta $0300,Y / pla
+        cpu.load_program(0x6000, &[0xa5, 0x20, 0x48, 0x85, 0x21, 0x99, 0x00, 0x03, 0x68]);
+        cpu.memory.write_at(&0x0020, &[0x42]);
+
+        // nmi handler at $7000
+        cpu.memory.write_at(&0xfffa, &[0x00, 0x70]);
+
+        let events = record(&mut cpu, ALL_ACCESSES);
+        cpu.reset();
+
+        while cpu.instructions < 4 {
+            cpu.step();
+        }
+
+        let cycle_of_pla = cpu.cycles;
+        while cpu.instructions < 5 {
+            cpu.step();
+        }
+
+        cpu.set_nmi(true);
+        while cpu.reg_pc != 0x7000 {
+            cpu.step();
+        }
+
+        assert_eq!(summary(&events),
+                   vec![(Access::Reset, 0xfffc, 0x20),
+                        (Access::DataRead, 0xfffc, 0x00),
+                        (Access::DataRead, 0xfffd, 0x60),
+                        (Access::OpcodeFetch, 0x6000, 0xa5),
+                        (Access::OperandRead, 0x6001, 0x20),
+                        (Access::DataRead, 0x0020, 0x42),
+                        (Access::OpcodeFetch, 0x6002, 0x48),
+                        (Access::StackPush, 0x01fd, 0x42),
+                        (Access::OpcodeFetch, 0x6003, 0x85),
+                        (Access::OperandRead, 0x6004, 0x21),
+                        (Access::DataWrite, 0x0021, 0x42),
+                        // an indexed store only writes its target
+                        (Access::OpcodeFetch, 0x6005, 0x99),
+                        (Access::OperandRead, 0x6006, 0x00),
+                        (Access::OperandRead, 0x6007, 0x03),
+                        (Access::DataWrite, 0x0300, 0x42),
+                        (Access::OpcodeFetch, 0x6008, 0x68),
+                        (Access::StackPop, 0x01fd, 0x42),
+                        (Access::StackPush, 0x01fd, 0x60),
+                        (Access::StackPush, 0x01fc, 0x09),
+                        (Access::StackPush, 0x01fb, 0x20),
+                        (Access::Interrupt, 0xfffa, 0x20),
+                        (Access::DataRead, 0xfffa, 0x00),
+                        (Access::DataRead, 0xfffb, 0x70)]);
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.iter().find(|event| event.access == Access::StackPop).unwrap().cycle, cycle_of_pla);
+    }
+
+    #[test]
+    fn filtering_and_removal() {
+        let mut cpu = Cpu::new();
+        cpu.trace = false;
+
+        // sta $10 / sta $11
+        cpu.load_program(0x6000, &[0x85, 0x10, 0x85, 0x11]);
+        cpu.reset();
+
+        let writes = record(&mut cpu, &[Access::DataWrite]);
+        let id = cpu.add_hook(&[Access::OpcodeFetch], |_| panic!("removed hooks shouldn't fire"));
+        assert!(cpu.remove_hook(id));
+        assert!(!cpu.remove_hook(id));
+
+        // clones don't bring hooks along
+        let mut fork = cpu.fork();
+        assert!(!fork.memory.hooks.is_active());
+        fork.step();
+
+        while cpu.instructions < 2 {
+            cpu.step();
+        }
+
+        assert_eq!(summary(&writes), vec![(Access::DataWrite, 0x0010, 0x00), (Access::DataWrite, 0x0011, 0x00)]);
+    }
+}
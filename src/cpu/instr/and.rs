@@ -42,9 +42,9 @@ pub fn abs_y(cpu: &mut Cpu) -> Box<InstrResult> {
 }
 
 pub fn ind_x(cpu: &mut Cpu) -> Box<InstrResult> {
-    let res = addr::abs_x(cpu);
+    let res = addr::ind_x(cpu);
 
-    and(cpu, res, 3, 4)
+    and(cpu, res, 2, 6)
 }
 
 pub fn ind_y(cpu: &mut Cpu) -> Box<InstrResult> {
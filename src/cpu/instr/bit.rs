@@ -13,7 +13,7 @@ pub fn zero_page(cpu: &mut Cpu) -> Box<InstrResult> {
 }
 
 pub fn abs(cpu: &mut Cpu) -> Box<InstrResult> {
-    let addr_result = addr::zero_page(cpu);
+    let addr_result = addr::abs(cpu);
 
     bit(addr_result, 3, 4)
 }
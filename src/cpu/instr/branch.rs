@@ -48,12 +48,13 @@ fn branch(instr_name: &'static str, cpu: &mut Cpu, should_branch: bool, bytes: u
     let addr_result = addr::rel(cpu);
     let mut final_cycles = cycles;
 
+    // a taken branch costs one more, and one more again if it lands on another page
     if should_branch {
         final_cycles += 1;
-    }
 
-    if addr_result.crosses_boundary.unwrap_or(false) {
-        final_cycles += 2;
+        if addr_result.crosses_boundary.unwrap_or(false) {
+            final_cycles += 1;
+        }
     }
 
     let next_pc = match should_branch {
@@ -120,6 +121,27 @@ mod test {
         assert_eq!(cpu.reg_pc, 0x2);
     }
 
+    #[test]
+    fn cycles() {
+        let mut cpu = Cpu::new();
+
+        // taken, on the same page
+        cpu.reg_pc = 0x0201;
+        cpu.memory.mem[0x0201] = 0x10;
+        cpu.reg_status.carry = false;
+        assert_eq!(super::bcc(&mut cpu).get_num_cycles(), 3);
+
+        // taken, back onto the page before
+        cpu.reg_pc = 0x0201;
+        cpu.memory.mem[0x0201] = 0x80;
+        assert_eq!(super::bcc(&mut cpu).get_num_cycles(), 4);
+
+        // not taken, however far it would have gone
+        cpu.reg_pc = 0x0201;
+        cpu.reg_status.carry = true;
+        assert_eq!(super::bcc(&mut cpu).get_num_cycles(), 2);
+    }
+
     #[test]
     fn bcs() {}
 
@@ -2,6 +2,8 @@ use super::Cpu;
 use super::InstrResult;
 use cpu;
 use super::addr;
+use cpu::callstack::{Frame, FrameKind};
+use cpu::hooks::Access;
 
 use std::fmt;
 
@@ -25,15 +27,24 @@ impl InstrResult for BrkResult {
         let pc_lo = (pc & 0x00ff) as u8;
 
         let status: u8 = cpu.reg_status.clone().into();
+        let sp = cpu.reg_sp;
 
         cpu.push_u8(pc_hi);
         cpu.push_u8(pc_lo);
         cpu.push_u8(status);
+        cpu.memory.hooks.report(Access::Interrupt, cpu::IRQ_BRK_VECTOR_ADDR[0], status);
 
         let irq_vec = cpu.memory.read_u16_at(&cpu::IRQ_BRK_VECTOR_ADDR[0]);
         cpu.reg_pc = irq_vec;
 
         cpu.reg_status.brk = true;
+
+        cpu.call_stack.push(Frame {
+            kind: FrameKind::Brk,
+            caller: pc.wrapping_sub(1),
+            target: irq_vec,
+            sp: sp,
+        });
     }
 
     fn get_num_cycles(&self) -> u8 {
@@ -24,5 +24,5 @@ pub fn abs(cpu: &mut Cpu) -> Box<InstrResult> {
 fn cpy(cpu: &mut Cpu, addr_result: AddrResult, bytes: u8, cycles: u8) -> Box<InstrResult> {
     let reg_val = cpu.reg_y as i8;
 
-    super::compare("cpx", addr_result, reg_val, bytes, cycles)
+    super::compare("cpy", addr_result, reg_val, bytes, cycles)
 }
\ No newline at end of file
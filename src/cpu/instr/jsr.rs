@@ -1,5 +1,6 @@
 use super::Cpu;
 use super::super::addr;
+use super::super::callstack::{Frame, FrameKind};
 use super::InstrResult;
 
 use std::fmt;
@@ -23,6 +24,7 @@ struct JsrInstrResult {
 impl InstrResult for JsrInstrResult {
     fn run(&self, cpu: &mut Cpu) {
         let rts_addr = cpu.reg_pc - 0x01;
+        let sp = cpu.reg_sp;
 
         let pc_hi = ((rts_addr & 0xff00) >> 8) as u8;
         let pc_lo = (rts_addr & 0x00ff) as u8;
@@ -31,6 +33,13 @@ impl InstrResult for JsrInstrResult {
         cpu.push_u8(pc_lo);
 
         cpu.reg_pc = self.addr_result.value;
+
+        cpu.call_stack.push(Frame {
+            kind: FrameKind::Subroutine,
+            caller: rts_addr.wrapping_sub(2),
+            target: cpu.reg_pc,
+            sp: sp,
+        });
     }
 
     fn get_num_cycles(&self) -> u8 {
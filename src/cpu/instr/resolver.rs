@@ -1,239 +1,363 @@
 use super::Cpu;
 use super::InstrResult;
+use cpu::addr::AddrMode;
 
-pub fn resolve(opcode: u8) -> Option<fn(&mut Cpu) -> Box<InstrResult>> {
-    match opcode {
-        0x69 => Some(super::adc::imm),
-        0x65 => Some(super::adc::zero_page),
-        0x75 => Some(super::adc::zero_page_x),
-        0x6d => Some(super::adc::abs),
-        0x7d => Some(super::adc::abs_x),
-        0x79 => Some(super::adc::abs_y),
-        0x61 => Some(super::adc::ind_x),
-        0x71 => Some(super::adc::ind_y),
-        0x29 => Some(super::and::imm),
-        0x25 => Some(super::and::zero_page),
-        0x35 => Some(super::and::zero_page_x),
-        0x2d => Some(super::and::abs),
-        0x3d => Some(super::and::abs_x),
-        0x39 => Some(super::and::abs_y),
-        0x21 => Some(super::and::ind_x),
-        0x31 => Some(super::and::ind_y),
-        0x0a => Some(super::asl::acc),
-        0x06 => Some(super::asl::zero_page),
-        0x16 => Some(super::asl::zero_page_x),
-        0x0e => Some(super::asl::abs),
-        0x1e => Some(super::asl::abs_x),
-        0x90 => Some(super::branch::bcc),
-        0xb0 => Some(super::branch::bcs),
-        0xf0 => Some(super::branch::beq),
-        0x24 => Some(super::bit::zero_page),
-        0x2c => Some(super::bit::abs),
-        0x30 => Some(super::branch::bmi),
-        0xd0 => Some(super::branch::bne),
-        0x10 => Some(super::branch::bpl),
-        0x00 => Some(super::brk::brk),
-        0x50 => Some(super::branch::bvc),
-        0x70 => Some(super::branch::bvs),
-        0x18 => Some(super::clear::clc),
-        0xd8 => Some(super::clear::cld),
-        0x58 => Some(super::clear::cli),
-        0xb8 => Some(super::clear::clv),
-        0xc9 => Some(super::compare::cmp::imm),
-        0xc5 => Some(super::compare::cmp::zero_page),
-        0xd5 => Some(super::compare::cmp::zero_page_x),
-        0xcd => Some(super::compare::cmp::abs),
-        0xdd => Some(super::compare::cmp::abs_x),
-        0xd9 => Some(super::compare::cmp::abs_y),
-        0xc1 => Some(super::compare::cmp::ind_y),
-        0xd1 => Some(super::compare::cmp::ind_x),
-        0xe0 => Some(super::compare::cpx::imm),
-        0xe4 => Some(super::compare::cpx::zero_page),
-        0xec => Some(super::compare::cpx::abs),
-        0xc0 => Some(super::compare::cpy::imm),
-        0xc4 => Some(super::compare::cpy::zero_page),
-        0xcc => Some(super::compare::cpy::abs),
-        0xc6 => Some(super::dec::zero_page),
-        0xd6 => Some(super::dec::zero_page_x),
-        0xce => Some(super::dec::abs),
-        0xde => Some(super::dec::abs_x),
-        0xca => Some(super::dec::dex),
-        0x88 => Some(super::dec::dey),
-        0x49 => Some(super::or::eor::imm),
-        0x45 => Some(super::or::eor::zero_page),
-        0x55 => Some(super::or::eor::zero_page_x),
-        0x4d => Some(super::or::eor::abs),
-        0x5d => Some(super::or::eor::abs_x),
-        0x59 => Some(super::or::eor::abs_y),
-        0x41 => Some(super::or::eor::ind_x),
-        0x51 => Some(super::or::eor::ind_y),
-        0xe6 => Some(super::inc::zero_page),
-        0xf6 => Some(super::inc::zero_page_x),
-        0xee => Some(super::inc::abs),
-        0xfe => Some(super::inc::abs_x),
-        0xe8 => Some(super::inc::inx),
-        0xc8 => Some(super::inc::iny),
-        0x4c => Some(super::jmp::abs),
-        0x6c => Some(super::jmp::ind),
-        0x20 => Some(super::jsr::jsr),
-        0xa9 => Some(super::load::lda::imm),
-        0xa5 => Some(super::load::lda::zero_page),
-        0xb5 => Some(super::load::lda::zero_page_x),
-        0xad => Some(super::load::lda::abs),
-        0xbd => Some(super::load::lda::abs_x),
-        0xb9 => Some(super::load::lda::abs_y),
-        0xa1 => Some(super::load::lda::ind_x),
-        0xb1 => Some(super::load::lda::ind_y),
-        0xa2 => Some(super::load::ldx::imm),
-        0xa6 => Some(super::load::ldx::zero_page),
-        0xb6 => Some(super::load::ldx::zero_page_y),
-        0xae => Some(super::load::ldx::abs),
-        0xbe => Some(super::load::ldx::abs_y),
-        0xa0 => Some(super::load::ldy::imm),
-        0xa4 => Some(super::load::ldy::zero_page),
-        0xb4 => Some(super::load::ldy::zero_page_x),
-        0xac => Some(super::load::ldy::abs),
-        0xbc => Some(super::load::ldy::abs_x),
-        0x4a => Some(super::lsr::acc),
-        0x46 => Some(super::lsr::zero_page),
-        0x56 => Some(super::lsr::zero_page_x),
-        0x4e => Some(super::lsr::abs),
-        0x5e => Some(super::lsr::abs_x),
-        0xea => Some(super::nop::imp),
-        0x1a => Some(super::nop::imp),
-        0x3a => Some(super::nop::imp),
-        0x5a => Some(super::nop::imp),
-        0x7a => Some(super::nop::imp),
-        0xda => Some(super::nop::imp),
-        0xfa => Some(super::nop::imp),
-        0x09 => Some(super::or::ora::imm),
-        0x05 => Some(super::or::ora::zero_page),
-        0x15 => Some(super::or::ora::zero_page_x),
-        0x0d => Some(super::or::ora::abs),
-        0x1d => Some(super::or::ora::abs_x),
-        0x19 => Some(super::or::ora::abs_y),
-        0x01 => Some(super::or::ora::ind_x),
-        0x11 => Some(super::or::ora::ind_y),
-        0x48 => Some(super::push::pha),
-        0x08 => Some(super::push::php),
-        0x68 => Some(super::pull::pla),
-        0x28 => Some(super::pull::plp),
-        0x2a => Some(super::rotate::rol::acc),
-        0x26 => Some(super::rotate::rol::zero_page),
-        0x36 => Some(super::rotate::rol::zero_page_x),
-        0x2e => Some(super::rotate::rol::abs),
-        0x3e => Some(super::rotate::rol::abs_x),
-        0x6a => Some(super::rotate::ror::acc),
-        0x66 => Some(super::rotate::ror::zero_page),
-        0x76 => Some(super::rotate::ror::zero_page_x),
-        0x6e => Some(super::rotate::ror::abs),
-        0x7e => Some(super::rotate::ror::abs_x),
-        0x40 => Some(super::ret::rti),
-        0x60 => Some(super::ret::rts),
-        0xe9 |
-        0xeb => Some(super::numeric::sbc::imm),
-        0xe5 => Some(super::numeric::sbc::zero_page),
-        0xf5 => Some(super::numeric::sbc::zero_page_x),
-        0xed => Some(super::numeric::sbc::abs),
-        0xfd => Some(super::numeric::sbc::abs_x),
-        0xf9 => Some(super::numeric::sbc::abs_y),
-        0xe1 => Some(super::numeric::sbc::ind_x),
-        0xf1 => Some(super::numeric::sbc::ind_y),
-        0x38 => Some(super::set::sec),
-        0xf8 => Some(super::set::sed),
-        0x78 => Some(super::set::sei),
-        0x85 => Some(super::store::sta::zero_page),
-        0x95 => Some(super::store::sta::zero_page_x),
-        0x8d => Some(super::store::sta::abs),
-        0x9d => Some(super::store::sta::abs_x),
-        0x99 => Some(super::store::sta::abs_y),
-        0x81 => Some(super::store::sta::ind_x),
-        0x91 => Some(super::store::sta::ind_y),
-        0x86 => Some(super::store::stx::zero_page),
-        0x96 => Some(super::store::stx::zero_page_y),
-        0x8e => Some(super::store::stx::abs),
-        0x84 => Some(super::store::sty::zero_page),
-        0x94 => Some(super::store::sty::zero_page_x),
-        0x8c => Some(super::store::sty::abs),
-        0xaa => Some(super::transfer::tay),
-        0xa8 => Some(super::transfer::tay),
-        0xba => Some(super::transfer::tsx),
-        0x8a => Some(super::transfer::txa),
-        0x9a => Some(super::transfer::txs),
-        0x98 => Some(super::transfer::tya),
-        0x87 => Some(super::secret::aax::zero_page),
-        0x97 => Some(super::secret::aax::zero_page_y),
-        0x8f => Some(super::secret::aax::abs),
-        0x83 => Some(super::secret::aax::ind_x),
-        0xc7 => Some(super::secret::dcp::zero_page),
-        0xd7 => Some(super::secret::dcp::zero_page_x),
-        0xcf => Some(super::secret::dcp::abs),
-        0xdf => Some(super::secret::dcp::abs_x),
-        0xdb => Some(super::secret::dcp::abs_y),
-        0xc3 => Some(super::secret::dcp::ind_x),
-        0xd3 => Some(super::secret::dcp::ind_y),
-        0x04 | 
-        0x44 | 
-        0x64 => Some(super::secret::dop::zero_page),
-        0x14 | 
-        0x34 | 
-        0x54 | 
-        0x74 | 
-        0xd4 | 
-        0xf4 => Some(super::secret::dop::zero_page_x),
-        0x80 | 
-        0x82 | 
-        0x89 | 
-        0xc2 | 
-        0xe2 => Some(super::secret::dop::imm),
-        0xa7 => Some(super::secret::lax::zero_page),
-        0xb7 => Some(super::secret::lax::zero_page_y),
-        0xaf => Some(super::secret::lax::abs),
-        0xbf => Some(super::secret::lax::abs_y),
-        0xa3 => Some(super::secret::lax::ind_x),
-        0xb3 => Some(super::secret::lax::ind_y),
-        0xe7 => Some(super::secret::isc::zero_page),
-        0xf7 => Some(super::secret::isc::zero_page_x),
-        0xef => Some(super::secret::isc::abs),
-        0xff => Some(super::secret::isc::abs_x),
-        0xfb => Some(super::secret::isc::abs_y),
-        0xe3 => Some(super::secret::isc::ind_x),
-        0xf3 => Some(super::secret::isc::ind_y),
-        0x27 => Some(super::secret::rla::zero_page),
-        0x37 => Some(super::secret::rla::zero_page_x),
-        0x2f => Some(super::secret::rla::abs),
-        0x3f => Some(super::secret::rla::abs_x),
-        0x3b => Some(super::secret::rla::abs_y),
-        0x23 => Some(super::secret::rla::ind_x),
-        0x33 => Some(super::secret::rla::ind_y),
-        0x07 => Some(super::secret::slo::zero_page),
-        0x17 => Some(super::secret::slo::zero_page_x),
-        0x0f => Some(super::secret::slo::abs),
-        0x1f => Some(super::secret::slo::abs_x),
-        0x1b => Some(super::secret::slo::abs_y),
-        0x03 => Some(super::secret::slo::ind_x),
-        0x13 => Some(super::secret::slo::ind_y),
-        0x67 => Some(super::secret::rra::zero_page),
-        0x77 => Some(super::secret::rra::zero_page_x),
-        0x6f => Some(super::secret::rra::abs),
-        0x7f => Some(super::secret::rra::abs_x),
-        0x7b => Some(super::secret::rra::abs_y),
-        0x63 => Some(super::secret::rra::ind_x),
-        0x73 => Some(super::secret::rra::ind_y),
-        0x47 => Some(super::secret::sre::zero_page),
-        0x57 => Some(super::secret::sre::zero_page_x),
-        0x4f => Some(super::secret::sre::abs),
-        0x5f => Some(super::secret::sre::abs_x),
-        0x5b => Some(super::secret::sre::abs_y),
-        0x43 => Some(super::secret::sre::ind_x),
-        0x53 => Some(super::secret::sre::ind_y),
-        0x0c => Some(super::secret::top::abs),
-        0x1c | 
-        0x3c |
-        0x5c |
-        0x7c |
-        0xdc |
-        0xfc => Some(super::secret::top::abs_x),
-        _ => None,
+// every opcode the cpu knows: its mnemonic, addressing mode and implementation. the assembler encodes from the
+// same table the cpu decodes with, so the two can't disagree
+macro_rules! opcodes {
+    ($($opcode:expr => $mnemonic:ident, $mode:ident, $instr:path;)*) => {
+        pub fn resolve(opcode: u8) -> Option<fn(&mut Cpu) -> Box<InstrResult>> {
+            match opcode {
+                $($opcode => Some($instr),)*
+                _ => None,
+            }
+        }
+
+        // official opcodes come before undocumented ones that share a mnemonic and mode, so the first match is
+        // the one to assemble
+        pub const OPCODES: &'static [(u8, &'static str, AddrMode)] = &[$(($opcode, stringify!($mnemonic), AddrMode::$mode)),*];
+    }
+}
+
+opcodes! {
+    0x69 => adc, Immediate, super::adc::imm;
+    0x65 => adc, ZeroPage, super::adc::zero_page;
+    0x75 => adc, ZeroPageX, super::adc::zero_page_x;
+    0x6d => adc, Absolute, super::adc::abs;
+    0x7d => adc, AbsoluteX, super::adc::abs_x;
+    0x79 => adc, AbsoluteY, super::adc::abs_y;
+    0x61 => adc, IndirectX, super::adc::ind_x;
+    0x71 => adc, IndirectY, super::adc::ind_y;
+    0x29 => and, Immediate, super::and::imm;
+    0x25 => and, ZeroPage, super::and::zero_page;
+    0x35 => and, ZeroPageX, super::and::zero_page_x;
+    0x2d => and, Absolute, super::and::abs;
+    0x3d => and, AbsoluteX, super::and::abs_x;
+    0x39 => and, AbsoluteY, super::and::abs_y;
+    0x21 => and, IndirectX, super::and::ind_x;
+    0x31 => and, IndirectY, super::and::ind_y;
+    0x0a => asl, Accumulator, super::asl::acc;
+    0x06 => asl, ZeroPage, super::asl::zero_page;
+    0x16 => asl, ZeroPageX, super::asl::zero_page_x;
+    0x0e => asl, Absolute, super::asl::abs;
+    0x1e => asl, AbsoluteX, super::asl::abs_x;
+    0x90 => bcc, Relative, super::branch::bcc;
+    0xb0 => bcs, Relative, super::branch::bcs;
+    0xf0 => beq, Relative, super::branch::beq;
+    0x24 => bit, ZeroPage, super::bit::zero_page;
+    0x2c => bit, Absolute, super::bit::abs;
+    0x30 => bmi, Relative, super::branch::bmi;
+    0xd0 => bne, Relative, super::branch::bne;
+    0x10 => bpl, Relative, super::branch::bpl;
+    0x00 => brk, Implicit, super::brk::brk;
+    0x50 => bvc, Relative, super::branch::bvc;
+    0x70 => bvs, Relative, super::branch::bvs;
+    0x18 => clc, Implicit, super::clear::clc;
+    0xd8 => cld, Implicit, super::clear::cld;
+    0x58 => cli, Implicit, super::clear::cli;
+    0xb8 => clv, Implicit, super::clear::clv;
+    0xc9 => cmp, Immediate, super::compare::cmp::imm;
+    0xc5 => cmp, ZeroPage, super::compare::cmp::zero_page;
+    0xd5 => cmp, ZeroPageX, super::compare::cmp::zero_page_x;
+    0xcd => cmp, Absolute, super::compare::cmp::abs;
+    0xdd => cmp, AbsoluteX, super::compare::cmp::abs_x;
+    0xd9 => cmp, AbsoluteY, super::compare::cmp::abs_y;
+    0xc1 => cmp, IndirectX, super::compare::cmp::ind_x;
+    0xd1 => cmp, IndirectY, super::compare::cmp::ind_y;
+    0xe0 => cpx, Immediate, super::compare::cpx::imm;
+    0xe4 => cpx, ZeroPage, super::compare::cpx::zero_page;
+    0xec => cpx, Absolute, super::compare::cpx::abs;
+    0xc0 => cpy, Immediate, super::compare::cpy::imm;
+    0xc4 => cpy, ZeroPage, super::compare::cpy::zero_page;
+    0xcc => cpy, Absolute, super::compare::cpy::abs;
+    0xc6 => dec, ZeroPage, super::dec::zero_page;
+    0xd6 => dec, ZeroPageX, super::dec::zero_page_x;
+    0xce => dec, Absolute, super::dec::abs;
+    0xde => dec, AbsoluteX, super::dec::abs_x;
+    0xca => dex, Implicit, super::dec::dex;
+    0x88 => dey, Implicit, super::dec::dey;
+    0x49 => eor, Immediate, super::or::eor::imm;
+    0x45 => eor, ZeroPage, super::or::eor::zero_page;
+    0x55 => eor, ZeroPageX, super::or::eor::zero_page_x;
+    0x4d => eor, Absolute, super::or::eor::abs;
+    0x5d => eor, AbsoluteX, super::or::eor::abs_x;
+    0x59 => eor, AbsoluteY, super::or::eor::abs_y;
+    0x41 => eor, IndirectX, super::or::eor::ind_x;
+    0x51 => eor, IndirectY, super::or::eor::ind_y;
+    0xe6 => inc, ZeroPage, super::inc::zero_page;
+    0xf6 => inc, ZeroPageX, super::inc::zero_page_x;
+    0xee => inc, Absolute, super::inc::abs;
+    0xfe => inc, AbsoluteX, super::inc::abs_x;
+    0xe8 => inx, Implicit, super::inc::inx;
+    0xc8 => iny, Implicit, super::inc::iny;
+    0x4c => jmp, Absolute, super::jmp::abs;
+    0x6c => jmp, Indirect, super::jmp::ind;
+    0x20 => jsr, Absolute, super::jsr::jsr;
+    0xa9 => lda, Immediate, super::load::lda::imm;
+    0xa5 => lda, ZeroPage, super::load::lda::zero_page;
+    0xb5 => lda, ZeroPageX, super::load::lda::zero_page_x;
+    0xad => lda, Absolute, super::load::lda::abs;
+    0xbd => lda, AbsoluteX, super::load::lda::abs_x;
+    0xb9 => lda, AbsoluteY, super::load::lda::abs_y;
+    0xa1 => lda, IndirectX, super::load::lda::ind_x;
+    0xb1 => lda, IndirectY, super::load::lda::ind_y;
+    0xa2 => ldx, Immediate, super::load::ldx::imm;
+    0xa6 => ldx, ZeroPage, super::load::ldx::zero_page;
+    0xb6 => ldx, ZeroPageY, super::load::ldx::zero_page_y;
+    0xae => ldx, Absolute, super::load::ldx::abs;
+    0xbe => ldx, AbsoluteY, super::load::ldx::abs_y;
+    0xa0 => ldy, Immediate, super::load::ldy::imm;
+    0xa4 => ldy, ZeroPage, super::load::ldy::zero_page;
+    0xb4 => ldy, ZeroPageX, super::load::ldy::zero_page_x;
+    0xac => ldy, Absolute, super::load::ldy::abs;
+    0xbc => ldy, AbsoluteX, super::load::ldy::abs_x;
+    0x4a => lsr, Accumulator, super::lsr::acc;
+    0x46 => lsr, ZeroPage, super::lsr::zero_page;
+    0x56 => lsr, ZeroPageX, super::lsr::zero_page_x;
+    0x4e => lsr, Absolute, super::lsr::abs;
+    0x5e => lsr, AbsoluteX, super::lsr::abs_x;
+    0xea => nop, Implicit, super::nop::imp;
+    0x1a => nop, Implicit, super::nop::imp;
+    0x3a => nop, Implicit, super::nop::imp;
+    0x5a => nop, Implicit, super::nop::imp;
+    0x7a => nop, Implicit, super::nop::imp;
+    0xda => nop, Implicit, super::nop::imp;
+    0xfa => nop, Implicit, super::nop::imp;
+    0x09 => ora, Immediate, super::or::ora::imm;
+    0x05 => ora, ZeroPage, super::or::ora::zero_page;
+    0x15 => ora, ZeroPageX, super::or::ora::zero_page_x;
+    0x0d => ora, Absolute, super::or::ora::abs;
+    0x1d => ora, AbsoluteX, super::or::ora::abs_x;
+    0x19 => ora, AbsoluteY, super::or::ora::abs_y;
+    0x01 => ora, IndirectX, super::or::ora::ind_x;
+    0x11 => ora, IndirectY, super::or::ora::ind_y;
+    0x48 => pha, Implicit, super::push::pha;
+    0x08 => php, Implicit, super::push::php;
+    0x68 => pla, Implicit, super::pull::pla;
+    0x28 => plp, Implicit, super::pull::plp;
+    0x2a => rol, Accumulator, super::rotate::rol::acc;
+    0x26 => rol, ZeroPage, super::rotate::rol::zero_page;
+    0x36 => rol, ZeroPageX, super::rotate::rol::zero_page_x;
+    0x2e => rol, Absolute, super::rotate::rol::abs;
+    0x3e => rol, AbsoluteX, super::rotate::rol::abs_x;
+    0x6a => ror, Accumulator, super::rotate::ror::acc;
+    0x66 => ror, ZeroPage, super::rotate::ror::zero_page;
+    0x76 => ror, ZeroPageX, super::rotate::ror::zero_page_x;
+    0x6e => ror, Absolute, super::rotate::ror::abs;
+    0x7e => ror, AbsoluteX, super::rotate::ror::abs_x;
+    0x40 => rti, Implicit, super::ret::rti;
+    0x60 => rts, Implicit, super::ret::rts;
+    0xe9 => sbc, Immediate, super::numeric::sbc::imm;
+    0xeb => sbc, Immediate, super::numeric::sbc::imm;
+    0xe5 => sbc, ZeroPage, super::numeric::sbc::zero_page;
+    0xf5 => sbc, ZeroPageX, super::numeric::sbc::zero_page_x;
+    0xed => sbc, Absolute, super::numeric::sbc::abs;
+    0xfd => sbc, AbsoluteX, super::numeric::sbc::abs_x;
+    0xf9 => sbc, AbsoluteY, super::numeric::sbc::abs_y;
+    0xe1 => sbc, IndirectX, super::numeric::sbc::ind_x;
+    0xf1 => sbc, IndirectY, super::numeric::sbc::ind_y;
+    0x38 => sec, Implicit, super::set::sec;
+    0xf8 => sed, Implicit, super::set::sed;
+    0x78 => sei, Implicit, super::set::sei;
+    0x85 => sta, ZeroPage, super::store::sta::zero_page;
+    0x95 => sta, ZeroPageX, super::store::sta::zero_page_x;
+    0x8d => sta, Absolute, super::store::sta::abs;
+    0x9d => sta, AbsoluteX, super::store::sta::abs_x;
+    0x99 => sta, AbsoluteY, super::store::sta::abs_y;
+    0x81 => sta, IndirectX, super::store::sta::ind_x;
+    0x91 => sta, IndirectY, super::store::sta::ind_y;
+    0x86 => stx, ZeroPage, super::store::stx::zero_page;
+    0x96 => stx, ZeroPageY, super::store::stx::zero_page_y;
+    0x8e => stx, Absolute, super::store::stx::abs;
+    0x84 => sty, ZeroPage, super::store::sty::zero_page;
+    0x94 => sty, ZeroPageX, super::store::sty::zero_page_x;
+    0x8c => sty, Absolute, super::store::sty::abs;
+    0xaa => tax, Implicit, super::transfer::tax;
+    0xa8 => tay, Implicit, super::transfer::tay;
+    0xba => tsx, Implicit, super::transfer::tsx;
+    0x8a => txa, Implicit, super::transfer::txa;
+    0x9a => txs, Implicit, super::transfer::txs;
+    0x98 => tya, Implicit, super::transfer::tya;
+    0x87 => aax, ZeroPage, super::secret::aax::zero_page;
+    0x97 => aax, ZeroPageY, super::secret::aax::zero_page_y;
+    0x8f => aax, Absolute, super::secret::aax::abs;
+    0x83 => aax, IndirectX, super::secret::aax::ind_x;
+    0xc7 => dcp, ZeroPage, super::secret::dcp::zero_page;
+    0xd7 => dcp, ZeroPageX, super::secret::dcp::zero_page_x;
+    0xcf => dcp, Absolute, super::secret::dcp::abs;
+    0xdf => dcp, AbsoluteX, super::secret::dcp::abs_x;
+    0xdb => dcp, AbsoluteY, super::secret::dcp::abs_y;
+    0xc3 => dcp, IndirectX, super::secret::dcp::ind_x;
+    0xd3 => dcp, IndirectY, super::secret::dcp::ind_y;
+    0x04 => dop, ZeroPage, super::secret::dop::zero_page;
+    0x44 => dop, ZeroPage, super::secret::dop::zero_page;
+    0x64 => dop, ZeroPage, super::secret::dop::zero_page;
+    0x14 => dop, ZeroPageX, super::secret::dop::zero_page_x;
+    0x34 => dop, ZeroPageX, super::secret::dop::zero_page_x;
+    0x54 => dop, ZeroPageX, super::secret::dop::zero_page_x;
+    0x74 => dop, ZeroPageX, super::secret::dop::zero_page_x;
+    0xd4 => dop, ZeroPageX, super::secret::dop::zero_page_x;
+    0xf4 => dop, ZeroPageX, super::secret::dop::zero_page_x;
+    0x80 => dop, Immediate, super::secret::dop::imm;
+    0x82 => dop, Immediate, super::secret::dop::imm;
+    0x89 => dop, Immediate, super::secret::dop::imm;
+    0xc2 => dop, Immediate, super::secret::dop::imm;
+    0xe2 => dop, Immediate, super::secret::dop::imm;
+    0xa7 => lax, ZeroPage, super::secret::lax::zero_page;
+    0xb7 => lax, ZeroPageY, super::secret::lax::zero_page_y;
+    0xaf => lax, Absolute, super::secret::lax::abs;
+    0xbf => lax, AbsoluteY, super::secret::lax::abs_y;
+    0xa3 => lax, IndirectX, super::secret::lax::ind_x;
+    0xb3 => lax, IndirectY, super::secret::lax::ind_y;
+    0xe7 => isc, ZeroPage, super::secret::isc::zero_page;
+    0xf7 => isc, ZeroPageX, super::secret::isc::zero_page_x;
+    0xef => isc, Absolute, super::secret::isc::abs;
+    0xff => isc, AbsoluteX, super::secret::isc::abs_x;
+    0xfb => isc, AbsoluteY, super::secret::isc::abs_y;
+    0xe3 => isc, IndirectX, super::secret::isc::ind_x;
+    0xf3 => isc, IndirectY, super::secret::isc::ind_y;
+    0x27 => rla, ZeroPage, super::secret::rla::zero_page;
+    0x37 => rla, ZeroPageX, super::secret::rla::zero_page_x;
+    0x2f => rla, Absolute, super::secret::rla::abs;
+    0x3f => rla, AbsoluteX, super::secret::rla::abs_x;
+    0x3b => rla, AbsoluteY, super::secret::rla::abs_y;
+    0x23 => rla, IndirectX, super::secret::rla::ind_x;
+    0x33 => rla, IndirectY, super::secret::rla::ind_y;
+    0x07 => slo, ZeroPage, super::secret::slo::zero_page;
+    0x17 => slo, ZeroPageX, super::secret::slo::zero_page_x;
+    0x0f => slo, Absolute, super::secret::slo::abs;
+    0x1f => slo, AbsoluteX, super::secret::slo::abs_x;
+    0x1b => slo, AbsoluteY, super::secret::slo::abs_y;
+    0x03 => slo, IndirectX, super::secret::slo::ind_x;
+    0x13 => slo, IndirectY, super::secret::slo::ind_y;
+    0x67 => rra, ZeroPage, super::secret::rra::zero_page;
+    0x77 => rra, ZeroPageX, super::secret::rra::zero_page_x;
+    0x6f => rra, Absolute, super::secret::rra::abs;
+    0x7f => rra, AbsoluteX, super::secret::rra::abs_x;
+    0x7b => rra, AbsoluteY, super::secret::rra::abs_y;
+    0x63 => rra, IndirectX, super::secret::rra::ind_x;
+    0x73 => rra, IndirectY, super::secret::rra::ind_y;
+    0x47 => sre, ZeroPage, super::secret::sre::zero_page;
+    0x57 => sre, ZeroPageX, super::secret::sre::zero_page_x;
+    0x4f => sre, Absolute, super::secret::sre::abs;
+    0x5f => sre, AbsoluteX, super::secret::sre::abs_x;
+    0x5b => sre, AbsoluteY, super::secret::sre::abs_y;
+    0x43 => sre, IndirectX, super::secret::sre::ind_x;
+    0x53 => sre, IndirectY, super::secret::sre::ind_y;
+    0x0c => top, Absolute, super::secret::top::abs;
+    0x1c => top, AbsoluteX, super::secret::top::abs_x;
+    0x3c => top, AbsoluteX, super::secret::top::abs_x;
+    0x5c => top, AbsoluteX, super::secret::top::abs_x;
+    0x7c => top, AbsoluteX, super::secret::top::abs_x;
+    0xdc => top, AbsoluteX, super::secret::top::abs_x;
+    0xfc => top, AbsoluteX, super::secret::top::abs_x;
+}
+
+// the opcode for `mnemonic` (lowercase) in `mode`, if there is one
+pub fn opcode_for(mnemonic: &str, mode: &AddrMode) -> Option<u8> {
+    OPCODES.iter().find(|&(_, name, op_mode)| *name == mnemonic && op_mode == mode).map(|&(opcode, _, _)| opcode)
+}
+
+// every addressing mode `mnemonic` can be assembled with
+pub fn modes_of(mnemonic: &str) -> Vec<AddrMode> {
+    OPCODES.iter().filter(|&&(_, name, _)| name == mnemonic).map(|(_, _, mode)| mode.clone()).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use cpu::Cpu;
+
+    // runs `program` from $6000 for `count` instructions and returns what the last one took
+    fn run(cpu: &mut Cpu, program: &[u8], count: u64) -> u64 {
+        cpu.trace = false;
+        cpu.load_program(0x6000, program);
+        cpu.reset();
+
+        let mut cycles = 0;
+        while cpu.instructions < count {
+            let before = cpu.cycles;
+            cpu.step();
+            cycles = cpu.cycles - before;
+        }
+
+        cycles
+    }
+
+    #[test]
+    fn tax() {
+        let mut cpu = Cpu::new();
+
+        // lda #$42 / tax
+        run(&mut cpu, &[0xa9, 0x42, 0xaa], 2);
+
+        assert_eq!(cpu.reg_x, 0x42);
+        assert_eq!(cpu.reg_y, 0x00);
+    }
+
+    #[test]
+    fn cmp_ind_x() {
+        let mut cpu = Cpu::new();
+        cpu.memory.write_at(&0x0012, &[0x00, 0x03]);
+        cpu.memory.write_at(&0x0300, &[0x42]);
+
+        // ldx #$02 / lda #$42 / cmp ($10,X)
+        let cycles = run(&mut cpu, &[0xa2, 0x02, 0xa9, 0x42, 0xc1, 0x10], 3);
+
+        assert!(cpu.reg_status.zero);
+        assert_eq!(cpu.reg_pc, 0x6006);
+        assert_eq!(cycles, 6);
+    }
+
+    #[test]
+    fn cmp_ind_y() {
+        let mut cpu = Cpu::new();
+        cpu.memory.write_at(&0x0010, &[0x00, 0x03]);
+        cpu.memory.write_at(&0x0301, &[0x42]);
+
+        // ldy #$01 / lda #$42 / cmp ($10),Y
+        let cycles = run(&mut cpu, &[0xa0, 0x01, 0xa9, 0x42, 0xd1, 0x10], 3);
+
+        assert!(cpu.reg_status.zero);
+        assert_eq!(cpu.reg_pc, 0x6006);
+        assert_eq!(cycles, 5);
+    }
+
+    #[test]
+    fn and_ind_x() {
+        let mut cpu = Cpu::new();
+        cpu.memory.write_at(&0x0012, &[0x00, 0x03]);
+        cpu.memory.write_at(&0x0300, &[0x0f]);
+
+        // ldx #$02 / lda #$ff / and ($10,X)
+        let cycles = run(&mut cpu, &[0xa2, 0x02, 0xa9, 0xff, 0x21, 0x10], 3);
+
+        assert_eq!(cpu.reg_acc, 0x0f);
+        assert_eq!(cpu.reg_pc, 0x6006);
+        assert_eq!(cycles, 6);
+    }
+
+    #[test]
+    fn bit_abs() {
+        let mut cpu = Cpu::new();
+        cpu.memory.write_at(&0x0300, &[0xc1]);
+
+        // lda #$01 / bit $0300
+        let cycles = run(&mut cpu, &[0xa9, 0x01, 0x2c, 0x00, 0x03], 2);
+
+        assert!(!cpu.reg_status.zero);
+        assert!(cpu.reg_status.overflow);
+        assert!(cpu.reg_status.negative);
+        assert_eq!(cpu.reg_pc, 0x6005);
+        assert_eq!(cycles, 4);
+    }
+
+    #[test]
+    fn cpy_names_itself() {
+        let mut cpu = Cpu::new();
+        cpu.memory.write_at(&0x6001, &[0x05]);
+        cpu.reg_pc = 0x6001;
+
+        let instr = super::resolve(0xc0).unwrap()(&mut cpu);
+        assert_eq!(format!("{:?}", instr), "cpy #$5");
     }
 }
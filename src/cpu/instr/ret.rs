@@ -42,6 +42,12 @@ impl InstrResult for ReturnInstrResult {
                 let status = cpu::ProcessorStatusRegister::from(cpu.pop_u8().unwrap());
 
                 cpu.reg_status = status;
+
+                // unlike rts, the pushed address is where execution resumes
+                let addr_lo = cpu.pop_u8().unwrap();
+                let addr_hi = cpu.pop_u8().unwrap();
+
+                cpu.reg_pc = byteorder::LittleEndian::read_u16(&[addr_lo, addr_hi]);
             }
             ReturnFrom::Subroutine => {
                 let addr_lo = cpu.pop_u8().unwrap();
@@ -50,6 +56,8 @@ impl InstrResult for ReturnInstrResult {
                 cpu.reg_pc = byteorder::LittleEndian::read_u16(&[addr_lo, addr_hi]) + 1;
             }
         }
+
+        cpu.call_stack.unwind(cpu.reg_sp);
     }
 
     fn get_num_cycles(&self) -> u8 {
@@ -64,3 +72,40 @@ impl fmt::Debug for ReturnInstrResult {
                super::debug_fmt(self.instr_name, &addr::implicit()))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use cpu::Cpu;
+
+    #[test]
+    fn rti() {
+        let mut cpu = Cpu::new();
+        cpu.trace = false;
+
+        // nop / nop, with an nmi handler that's just rti
+        cpu.load_program(0x6000, &[0xea, 0xea]);
+        cpu.memory.write_at(&0x7000, &[0x40]);
+        cpu.memory.write_at(&0xfffa, &[0x00, 0x70]);
+        cpu.reset();
+
+        cpu.reg_status.carry = true;
+        cpu.step();
+
+        let sp = cpu.reg_sp;
+        cpu.set_nmi(true);
+        while cpu.reg_pc != 0x7000 {
+            cpu.step();
+        }
+
+        cpu.reg_status.carry = false;
+        while cpu.reg_pc == 0x7000 {
+            cpu.step();
+        }
+
+        // back where the interrupt came in, with the status it had then
+        assert_eq!(cpu.reg_pc, 0x6001);
+        assert_eq!(cpu.reg_sp, sp);
+        assert!(cpu.reg_status.carry);
+        assert_eq!(cpu.call_stack.depth(), 0);
+    }
+}
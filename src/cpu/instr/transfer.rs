@@ -82,6 +82,11 @@ impl InstrResult for TransferInstruction {
             }
             &TransferLocation::Memory(ref address) => cpu.memory.write_at(address, &[value as u8]),
         };
+
+        // txs can throw away any number of frames at once
+        if let TransferLocation::Register(cpu::Register::SP) = self.to {
+            cpu.call_stack.unwind(cpu.reg_sp);
+        }
     }
 
     fn get_num_cycles(&self) -> u8 {
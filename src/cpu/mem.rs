@@ -1,25 +1,206 @@
 extern crate byteorder;
 
+use std::fmt;
+use std::ops::{Index, IndexMut};
+use std::sync::Arc;
+
 use self::byteorder::{LittleEndian, ByteOrder};
+use super::breakpoint::{WatchKind, Watchpoints};
+use super::hooks::{Access, Hooks};
+use super::mos6510::IoPort;
+
+pub const PAGE_SIZE: usize = 0x100;
+const NUM_PAGES: usize = 0x100;
+
+const BANK_SIZE: usize = 0x10000;
+const NUM_BANKS: usize = 0x100;
+
+// common interface for anything a core can be wired up to.
+// addresses are 24 bits wide; 16-bit buses simply ignore the bank byte
+pub trait Bus {
+    fn read_u8(&mut self, addr: u32) -> u8;
+    fn write_u8(&mut self, addr: u32, val: u8);
+}
+
+// lets a machine overlay roms and i/o on top of ram, and follow bank switches made through the 6510 port.
+// mappers have to be cloneable and sendable so a forked cpu can take its own copy to another thread
+pub trait Mapper: Send {
+    fn read(&self, addr: u16) -> Option<u8>;
+
+    // returns true if the write was consumed and shouldn't fall through to ram
+    fn write(&mut self, addr: u16, val: u8) -> bool;
+
+    fn bank_changed(&mut self, lines: u8);
+
+    // opaque device state for save states; roms aren't included since the machine supplies them
+    fn save_state(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    // returns false if the state doesn't belong to this mapper
+    fn load_state(&mut self, state: &[u8]) -> bool {
+        state.is_empty()
+    }
+
+    fn box_clone(&self) -> Box<Mapper>;
+}
+
+impl Clone for Box<Mapper> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+// 64k of ram split into pages. clones share every page until one side writes to it, at which point only
+// that page is copied
+#[derive(Clone)]
+pub struct PagedMemory {
+    pages: Vec<Arc<Vec<u8>>>,
+}
+
+impl Default for PagedMemory {
+    fn default() -> Self {
+        let zeroed = Arc::new(vec![0; PAGE_SIZE]);
+
+        PagedMemory { pages: (0..NUM_PAGES).map(|_| zeroed.clone()).collect() }
+    }
+}
+
+impl PagedMemory {
+    pub fn len(&self) -> usize {
+        self.pages.len() * PAGE_SIZE
+    }
+
+    pub fn num_pages(&self) -> usize {
+        self.pages.len()
+    }
+
+    pub fn page(&self, index: usize) -> &[u8] {
+        &self.pages[index]
+    }
+
+    pub fn page_mut(&mut self, index: usize) -> &mut [u8] {
+        let page: &mut Vec<u8> = Arc::make_mut(&mut self.pages[index]);
+
+        page
+    }
 
+    pub fn shares_page_with(&self, other: &PagedMemory, index: usize) -> bool {
+        Arc::ptr_eq(&self.pages[index], &other.pages[index])
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.pages.iter().flat_map(|page| page.iter().cloned()).collect()
+    }
+}
+
+impl Index<usize> for PagedMemory {
+    type Output = u8;
+
+    fn index(&self, addr: usize) -> &u8 {
+        &self.pages[addr / PAGE_SIZE][addr % PAGE_SIZE]
+    }
+}
+
+impl IndexMut<usize> for PagedMemory {
+    fn index_mut(&mut self, addr: usize) -> &mut u8 {
+        &mut self.page_mut(addr / PAGE_SIZE)[addr % PAGE_SIZE]
+    }
+}
+
+impl PartialEq for PagedMemory {
+    fn eq(&self, other: &PagedMemory) -> bool {
+        (0..self.num_pages()).all(|i| self.shares_page_with(other, i) || self.page(i) == other.page(i))
+    }
+}
+
+impl fmt::Debug for PagedMemory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let used = self.pages.iter().filter(|page| page.iter().any(|b| *b != 0)).count();
+
+        write!(f, "PagedMemory {{ pages: {}, used: {} }}", self.num_pages(), used)
+    }
+}
+
+#[derive(Clone)]
 pub struct MemoryMap {
-    pub mem: Vec<u8>,
+    pub mem: PagedMemory,
+    pub port: Option<IoPort>,
+    pub mapper: Option<Box<Mapper>>,
+    pub watchpoints: Watchpoints,
+    pub hooks: Hooks,
 }
 
 impl Default for MemoryMap {
     fn default() -> Self {
-        MemoryMap { mem: vec![0;0xffff + 1] }
+        MemoryMap {
+            mem: PagedMemory::default(),
+            port: None,
+            mapper: None,
+            watchpoints: Watchpoints::default(),
+            hooks: Hooks::default(),
+        }
     }
 }
 
 impl MemoryMap {
+    pub fn set_mapper(&mut self, mut mapper: Box<Mapper>) {
+        mapper.bank_changed(self.port_lines());
+
+        self.mapper = Some(mapper);
+    }
+
+    // the lines a mapper sees; with no port attached everything floats high
+    pub fn port_lines(&self) -> u8 {
+        match self.port {
+            Some(ref port) => port.lines(),
+            None => 0xff,
+        }
+    }
+
     pub fn read_u16_at(&self, addr: &u16) -> u16 {
-        let buf = &[self.mem[*addr as usize], self.mem[(*addr + 1) as usize]];
+        let buf = &[self.read_u8_at(addr), self.read_u8_at(&addr.wrapping_add(1))];
+
+        LittleEndian::read_u16(buf)
+    }
+
+    pub fn peek_u16_at(&self, addr: &u16) -> u16 {
+        let buf = &[self.peek_u8_at(addr), self.peek_u8_at(&addr.wrapping_add(1))];
 
         LittleEndian::read_u16(buf)
     }
 
     pub fn read_u8_at(&self, addr: &u16) -> u8 {
+        self.read_u8_as(addr, Access::DataRead)
+    }
+
+    // a read that hooks see as `access`
+    pub fn read_u8_as(&self, addr: &u16, access: Access) -> u8 {
+        let val = self.peek_u8_at(addr);
+
+        if self.watchpoints.is_active() {
+            self.watchpoints.check(*addr, val, WatchKind::Read);
+        }
+
+        self.hooks.report(access, *addr, val);
+
+        val
+    }
+
+    // reads without tripping watchpoints; for instruction fetches and debuggers poking around
+    pub fn peek_u8_at(&self, addr: &u16) -> u8 {
+        if *addr <= 0x0001 {
+            if let Some(ref port) = self.port {
+                return port.read(*addr);
+            }
+        }
+
+        if let Some(ref mapper) = self.mapper {
+            if let Some(val) = mapper.read(*addr) {
+                return val;
+            }
+        }
+
         self.mem[*addr as usize]
     }
 
@@ -38,24 +219,149 @@ impl MemoryMap {
     pub fn write_at(&mut self, start_addr: &u16, bytes: &[u8]) {
         let mut i = 0;
         for byte in bytes {
-            self.mem[(start_addr + i) as usize] = *byte;
+            self.write_u8_at(&start_addr.wrapping_add(i), *byte);
 
             i += 1;
         }
     }
 
+    pub fn write_u8_at(&mut self, addr: &u16, val: u8) {
+        self.write_u8_as(addr, val, Access::DataWrite)
+    }
+
+    // a write that hooks see as `access`
+    pub fn write_u8_as(&mut self, addr: &u16, val: u8, access: Access) {
+        if self.watchpoints.is_active() {
+            self.watchpoints.check(*addr, val, WatchKind::Write);
+        }
+
+        self.hooks.report(access, *addr, val);
+
+        if *addr <= 0x0001 && self.port.is_some() {
+            return self.write_port(addr, val);
+        }
+
+        let consumed = match self.mapper {
+            Some(ref mut mapper) => mapper.write(*addr, val),
+            None => false,
+        };
+
+        if !consumed {
+            self.mem[*addr as usize] = val;
+        }
+    }
+
+    fn write_port(&mut self, addr: &u16, val: u8) {
+        let old_lines = self.port_lines();
+
+        if let Some(ref mut port) = self.port {
+            port.write(*addr, val);
+        }
+
+        // the 6510 still drives the bus during a port write, so the ram underneath sees it too
+        self.mem[*addr as usize] = val;
+
+        let new_lines = self.port_lines();
+        if old_lines != new_lines {
+            if let Some(ref mut mapper) = self.mapper {
+                mapper.bank_changed(new_lines);
+            }
+        }
+    }
+
     pub fn crosses_page_boundary(addr_one: &u16, addr_two: &u16) -> bool {
         (addr_one & 0xff00) >> 8 != (addr_two & 0xff00) >> 8
     }
 }
 
+impl Bus for MemoryMap {
+    fn read_u8(&mut self, addr: u32) -> u8 {
+        self.read_u8_at(&(addr as u16))
+    }
+
+    fn write_u8(&mut self, addr: u32, val: u8) {
+        self.write_at(&(addr as u16), &[val]);
+    }
+}
+
+// full 16MB address space for the 65c816; banks are only allocated once written to
+pub struct LongMemoryMap {
+    banks: Vec<Option<Box<[u8]>>>,
+}
+
+impl Default for LongMemoryMap {
+    fn default() -> Self {
+        LongMemoryMap { banks: (0..NUM_BANKS).map(|_| None).collect() }
+    }
+}
+
+impl LongMemoryMap {
+    pub fn write_at(&mut self, start_addr: u32, bytes: &[u8]) {
+        for (i, byte) in bytes.iter().enumerate() {
+            self.write_u8(start_addr.wrapping_add(i as u32), *byte);
+        }
+    }
+
+    pub fn crosses_bank_boundary(addr_one: u32, addr_two: u32) -> bool {
+        (addr_one & 0xff_0000) != (addr_two & 0xff_0000)
+    }
+}
+
+impl Bus for LongMemoryMap {
+    fn read_u8(&mut self, addr: u32) -> u8 {
+        let bank = ((addr >> 16) & 0xff) as usize;
+
+        match self.banks[bank] {
+            Some(ref mem) => mem[(addr & 0xffff) as usize],
+            None => 0,
+        }
+    }
+
+    fn write_u8(&mut self, addr: u32, val: u8) {
+        let bank = ((addr >> 16) & 0xff) as usize;
+
+        let mem = self.banks[bank].get_or_insert_with(|| vec![0; BANK_SIZE].into_boxed_slice());
+        mem[(addr & 0xffff) as usize] = val;
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use super::MemoryMap;
+    use super::{Bus, LongMemoryMap, MemoryMap, PagedMemory};
 
     #[test]
     pub fn crosses_page_boundary() {
         assert!(MemoryMap::crosses_page_boundary(&0x01FF, &0x0200));
         assert!(!MemoryMap::crosses_page_boundary(&0x01FF, &0x01FE));
     }
+
+    #[test]
+    pub fn paged_memory_copies_on_write() {
+        let mut parent = PagedMemory::default();
+        parent[0x1234] = 0x42;
+
+        let mut child = parent.clone();
+        assert!(child.shares_page_with(&parent, 0x12));
+
+        child[0x1235] = 0x43;
+
+        assert!(!child.shares_page_with(&parent, 0x12));
+        assert!(child.shares_page_with(&parent, 0x13));
+        assert_eq!(parent[0x1235], 0x00);
+        assert_eq!(child[0x1234], 0x42);
+    }
+
+    #[test]
+    pub fn long_memory_map() {
+        let mut mem = LongMemoryMap::default();
+
+        mem.write_at(0x7e_fffe, &[0x01, 0x02, 0x03]);
+
+        assert_eq!(mem.read_u8(0x7e_fffe), 0x01);
+        assert_eq!(mem.read_u8(0x7e_ffff), 0x02);
+        assert_eq!(mem.read_u8(0x7f_0000), 0x03);
+        assert_eq!(mem.read_u8(0x00_0000), 0x00);
+
+        assert!(LongMemoryMap::crosses_bank_boundary(0x7e_ffff, 0x7f_0000));
+    }
 }
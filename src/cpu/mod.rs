@@ -1,21 +1,45 @@
 extern crate byteorder;
 
 pub mod mem;
+pub mod breakpoint;
+pub mod callstack;
+pub mod hooks;
 pub mod addr;
 pub mod instr;
+pub mod mos6510;
+pub mod c64;
+pub mod pins;
+pub mod snapshot;
+pub mod rewind;
+pub mod w65c816;
 
 mod status_reg;
 
 pub use self::status_reg::ProcessorStatusRegister;
 
 use util;
+use self::breakpoint::{Breakpoints, StopReason};
+use self::callstack::CallStack;
+use self::hooks::Access;
 use self::instr::resolver;
 
-const NMI_VECTOR_ADDR: &'static [u16] = &[0xfffa, 0xfffb];
+pub const NMI_VECTOR_ADDR: &'static [u16] = &[0xfffa, 0xfffb];
 const RESET_VECTOR_ADDR: &'static [u16] = &[0xfffc, 0xfffd];
-pub const IRQ_BRK_VECTOR_ADDR: &'static [u16] = &[0xffe, 0xffff];
+pub const IRQ_BRK_VECTOR_ADDR: &'static [u16] = &[0xfffe, 0xffff];
 const STACK_POINTER_START_ADDR: u16 = 0x0100;
 
+// run control shared by every core in the crate
+pub trait Processor {
+    fn reset(&mut self);
+    fn step(&mut self) -> bool;
+
+    fn run(&mut self) {
+        self.reset();
+
+        while self.step() {}
+    }
+}
+
 pub enum Register {
     A,
     X,
@@ -23,6 +47,8 @@ pub enum Register {
     SP,
 }
 
+// cloning is cheap: ram pages and roms are shared until written to (see `fork`)
+#[derive(Clone)]
 pub struct Cpu {
     pub reg_acc: i8,
     pub reg_x: i8,
@@ -34,6 +60,27 @@ pub struct Cpu {
     pub memory: mem::MemoryMap,
 
     pub pending_cycles: Option<u8>,
+    pub cycles: u64,
+    pub instructions: u64,
+
+    pub rdy_line: bool,
+    pub stall_cycles: u64,
+    pub so_line: bool,
+    pub irq_line: bool,
+    pub nmi_line: bool,
+    pub nmi_pending: bool,
+
+    // print every instruction as it's executed
+    pub trace: bool,
+
+    // calls and interrupts in progress, for backtraces
+    pub call_stack: CallStack,
+
+    pub breakpoints: Breakpoints,
+    // why the last step wants execution to stop, if it does
+    pub stop_reason: Option<StopReason>,
+    // the breakpoint we last stopped on, so resuming executes it instead of stopping again
+    resume_pc: Option<u16>,
 }
 
 impl Default for Cpu {
@@ -49,6 +96,23 @@ impl Default for Cpu {
             memory: mem::MemoryMap::default(),
 
             pending_cycles: None,
+            cycles: 0,
+            instructions: 0,
+
+            rdy_line: true,
+            stall_cycles: 0,
+            so_line: true,
+            irq_line: false,
+            nmi_line: false,
+            nmi_pending: false,
+
+            trace: true,
+
+            call_stack: CallStack::default(),
+
+            breakpoints: Breakpoints::default(),
+            stop_reason: None,
+            resume_pc: None,
         }
     }
 }
@@ -58,6 +122,12 @@ impl Cpu {
         Cpu::default()
     }
 
+    // a copy of the machine that shares memory with this one until either side writes to it, so it's cheap to
+    // spin up thousands of what-ifs and hand each to its own thread
+    pub fn fork(&self) -> Self {
+        self.clone()
+    }
+
     pub fn load_program(&mut self, start_addr: u16, bytes: &[u8]) {
         let start_hi = ((start_addr & 0xff00) >> 8) as u8;
         let start_lo = (start_addr & 0x00ff) as u8;
@@ -73,21 +143,35 @@ impl Cpu {
     }
 
     pub fn reset(&mut self) {
+        if self.memory.hooks.is_active() {
+            self.memory.hooks.cycle = self.cycles;
+            self.memory.hooks.report(Access::Reset, RESET_VECTOR_ADDR[0], self.reg_status.clone().into());
+        }
+
         let address = self.memory.read_u16_at(&RESET_VECTOR_ADDR[0]);
 
         self.reg_pc = address;
+        self.call_stack.clear();
     }
 
     pub fn read_u8(&mut self) -> u8 {
-        let val = self.memory.read_u8_at(&self.reg_pc);
-        self.reg_pc += 0x1;
-
-        val
+        self.fetch(Access::OperandRead)
     }
 
     pub fn read_u16(&mut self) -> u16 {
-        let val = self.memory.read_u16_at(&self.reg_pc);
-        self.reg_pc += 0x2;
+        let lo = self.fetch(Access::OperandRead);
+        let hi = self.fetch(Access::OperandRead);
+
+        util::to_u16(&[lo, hi])
+    }
+
+    // instruction stream reads don't trip watchpoints, but hooks still get to see them
+    fn fetch(&mut self, access: Access) -> u8 {
+        let pc = self.reg_pc;
+        let val = self.memory.peek_u8_at(&pc);
+        self.reg_pc += 0x1;
+
+        self.memory.hooks.report(access, pc, val);
 
         val
     }
@@ -104,7 +188,7 @@ impl Cpu {
 
     pub fn push_u8(&mut self, val: u8) {
         let addr = self.get_real_sp_addr();
-        self.memory.mem[addr as usize] = val;
+        self.memory.write_u8_as(&addr, val, Access::StackPush);
 
         self.reg_sp -= 1;
     }
@@ -117,23 +201,37 @@ impl Cpu {
             return None;
         }
 
-        let val = self.memory.mem[addr as usize];
+        let val = self.memory.read_u8_as(&addr, Access::StackPop);
         Some(val)
     }
 
-    pub fn run(&mut self) {
+    pub fn run(&mut self) -> StopReason {
         println!("starting execution...");
 
         self.reset();
+        let reason = self.resume();
+
+        println!("finished execution!");
+
+        reason
+    }
+
+    // keeps going from wherever the cpu is until it halts or a breakpoint or watchpoint fires
+    pub fn resume(&mut self) -> StopReason {
+        self.stop_reason = None;
 
         'main: loop {
             match self.step() {
                 true => {}
                 false => break 'main,
             }
+
+            if let Some(reason) = self.stop_reason.take() {
+                return reason;
+            }
         }
 
-        println!("finished execution!");
+        StopReason::Halted
     }
 
     pub fn step(&mut self) -> bool {
@@ -155,6 +253,34 @@ impl Cpu {
             return true;
         }
 
+        // a pulled-down rdy or an in-flight dma keeps the cpu off the bus
+        if self.steal_cycle() {
+            return true;
+        }
+
+        if self.memory.hooks.is_active() {
+            self.memory.hooks.cycle = self.cycles;
+        }
+
+        if self.service_interrupts() {
+            self.check_watchpoints();
+            return true;
+        }
+
+        if self.breakpoints.is_active() && self.resume_pc != Some(self.reg_pc) {
+            if let Some(id) = self.breakpoints.hit(self.reg_pc) {
+                self.resume_pc = Some(self.reg_pc);
+                self.stop_reason = Some(StopReason::Breakpoint { id: id, pc: self.reg_pc });
+
+                return true;
+            }
+        }
+
+        self.resume_pc = None;
+
+        // anything that poked memory between steps isn't this instruction's doing
+        self.memory.watchpoints.take_hit();
+
         match self.next_instr() {
             None => false,
             Some(opcode) => {
@@ -163,18 +289,25 @@ impl Cpu {
                     Some(instr) => {
                         let instr_result = (instr)(self);
 
-                        let instr_str = format!("{0:<4x}\t{1:<2x}\t{2:?}", start_pc, opcode, instr_result);
-                        println!("{0:<35}\tA:{1:02x}, X:{2:02x}, Y:{3:02x}, P:{4:02?}, SP:{5:02x}",
-                                 instr_str,
-                                 self.reg_acc,
-                                 self.reg_x,
-                                 self.reg_y,
-                                 self.reg_status,
-                                 self.reg_sp);
+                        if self.trace {
+                            let instr_str = format!("{0:<4x}\t{1:<2x}\t{2:?}", start_pc, opcode, instr_result);
+                            println!("{0:<35}\tA:{1:02x}, X:{2:02x}, Y:{3:02x}, P:{4:02?}, SP:{5:02x}",
+                                     instr_str,
+                                     self.reg_acc,
+                                     self.reg_x,
+                                     self.reg_y,
+                                     self.reg_status,
+                                     self.reg_sp);
+                        }
 
                         (*instr_result).run(self);
+                        self.instructions += 1;
+                        self.check_watchpoints();
 
-                        let cycles = self.pending_cycles.unwrap_or(0) + instr_result.get_num_cycles();
+                        let instr_cycles = instr_result.get_num_cycles();
+                        self.tick(instr_cycles as u64);
+
+                        let cycles = self.pending_cycles.unwrap_or(0) + instr_cycles;
                         self.pending_cycles = Some(cycles);
 
                         true
@@ -184,17 +317,51 @@ impl Cpu {
         }
     }
 
+    // lets the next step execute the instruction at pc even if there's a breakpoint on it
+    pub fn skip_breakpoint(&mut self) {
+        self.resume_pc = Some(self.reg_pc);
+    }
+
+    fn check_watchpoints(&mut self) {
+        if let Some(reason) = self.memory.watchpoints.take_hit() {
+            self.stop_reason = Some(reason);
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.cycles += cycles;
+
+        if let Some(ref mut port) = self.memory.port {
+            port.tick(cycles);
+        }
+    }
+
     fn next_instr(&mut self) -> Option<u8> {
         match self.reg_pc < 0xffff {
-            true => Some(self.read_u8()),
+            true => Some(self.fetch(Access::OpcodeFetch)),
             false => None,
         }
     }
 }
 
+impl Processor for Cpu {
+    fn reset(&mut self) {
+        Cpu::reset(self)
+    }
+
+    fn step(&mut self) -> bool {
+        Cpu::step(self)
+    }
+
+    fn run(&mut self) {
+        Cpu::run(self);
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::io::Cursor;
+    use std::thread;
     use super::byteorder::{LittleEndian, ReadBytesExt};
     use super::Cpu;
     use super::super::util;
@@ -234,6 +401,41 @@ mod test {
         assert_eq!(cpu.reg_sp, 0xff);
     }
 
+    #[test]
+    pub fn fork() {
+        let mut cpu = Cpu::new();
+        cpu.trace = false;
+
+        // lda $10 / sta $0200
+        cpu.load_program(0x6000, &[0xa5, 0x10, 0x8d, 0x00, 0x02]);
+        cpu.reset();
+
+        // try every value of $10 in parallel
+        let handles: Vec<_> = (0..4u8)
+            .map(|val| {
+                let mut fork = cpu.fork();
+                fork.memory.write_at(&0x0010, &[val * 3]);
+
+                thread::spawn(move || {
+                    while fork.step() {}
+
+                    fork
+                })
+            })
+            .collect();
+
+        for (val, handle) in handles.into_iter().enumerate() {
+            let fork = handle.join().unwrap();
+
+            assert_eq!(fork.memory.mem[0x0200], val as u8 * 3);
+            assert!(fork.memory.mem.shares_page_with(&cpu.memory.mem, 0x60));
+            assert!(!fork.memory.mem.shares_page_with(&cpu.memory.mem, 0x02));
+        }
+
+        assert_eq!(cpu.memory.mem[0x0200], 0x00);
+        assert_eq!(cpu.reg_pc, 0x6000);
+    }
+
     #[test]
     pub fn load_program() {
         let mut cpu = Cpu::new();
@@ -0,0 +1,184 @@
+extern crate byteorder;
+
+use std::io;
+use std::io::{Read, Write};
+
+use self::byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use util;
+
+use super::Cpu;
+
+// how long an unconnected line keeps the last value driven onto it once it's switched to an input
+pub const FADE_OUT_CYCLES: u64 = 350_000;
+
+// bits 6 and 7 aren't wired to anything on the c64, so they only ever see what the port itself drove
+const FLOATING_BITS: u8 = 0b1100_0000;
+
+// loram, hiram, charen, cassette sense and cassette motor are all pulled high on the board
+const PULLED_UP_BITS: u8 = 0b0011_1111;
+
+pub const LORAM: u8 = 0;
+pub const HIRAM: u8 = 1;
+pub const CHAREN: u8 = 2;
+pub const CASSETTE_WRITE: u8 = 3;
+pub const CASSETTE_SENSE: u8 = 4;
+pub const CASSETTE_MOTOR: u8 = 5;
+
+#[derive(Clone, Debug)]
+pub struct IoPort {
+    // $0000: a set bit makes the matching line an output
+    pub direction: u8,
+    // $0001: the output latch
+    pub data: u8,
+    // levels external hardware drives onto lines configured as inputs (e.g. a pressed cassette button pulls bit 4 low)
+    pub inputs: u8,
+
+    floating: u8,
+    fade_cycles: [u64; 2],
+}
+
+impl Default for IoPort {
+    fn default() -> Self {
+        IoPort {
+            direction: 0,
+            data: 0,
+            inputs: PULLED_UP_BITS,
+
+            floating: 0,
+            fade_cycles: [0; 2],
+        }
+    }
+}
+
+impl IoPort {
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr & 0x0001 {
+            0 => self.direction,
+            _ => self.lines(),
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, val: u8) {
+        match addr & 0x0001 {
+            0 => self.direction = val,
+            _ => self.data = val,
+        }
+
+        // while a floating line is an output it charges up to whatever is being driven
+        for bit in 6..8 {
+            if util::test_bit_set(self.direction, bit) {
+                self.floating = util::set_bit(self.floating, bit, util::test_bit_set(self.data, bit));
+                self.fade_cycles[(bit - 6) as usize] = FADE_OUT_CYCLES;
+            }
+        }
+    }
+
+    // the level on every pin: outputs come from the latch, inputs from the outside world
+    pub fn lines(&self) -> u8 {
+        let inputs = (self.inputs & PULLED_UP_BITS) | (self.floating & FLOATING_BITS);
+
+        (self.data & self.direction) | (inputs & !self.direction)
+    }
+
+    pub fn tick(&mut self, cycles: u64) {
+        for bit in 6..8 {
+            let is_input = !util::test_bit_set(self.direction, bit);
+            let is_charged = util::test_bit_set(self.floating, bit);
+
+            if !is_input || !is_charged {
+                continue;
+            }
+
+            let remaining = &mut self.fade_cycles[(bit - 6) as usize];
+
+            match *remaining <= cycles {
+                true => {
+                    *remaining = 0;
+                    self.floating = util::set_bit(self.floating, bit, false);
+                }
+                false => *remaining -= cycles,
+            }
+        }
+    }
+
+    pub fn save_state<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_u8(self.direction)?;
+        out.write_u8(self.data)?;
+        out.write_u8(self.inputs)?;
+        out.write_u8(self.floating)?;
+        out.write_u64::<LittleEndian>(self.fade_cycles[0])?;
+        out.write_u64::<LittleEndian>(self.fade_cycles[1])
+    }
+
+    pub fn load_state<R: Read>(input: &mut R) -> io::Result<Self> {
+        Ok(IoPort {
+            direction: input.read_u8()?,
+            data: input.read_u8()?,
+            inputs: input.read_u8()?,
+            floating: input.read_u8()?,
+            fade_cycles: [input.read_u64::<LittleEndian>()?, input.read_u64::<LittleEndian>()?],
+        })
+    }
+}
+
+impl Cpu {
+    // a 6510: a 6502 with the on-chip i/o port mapped over $0000/$0001
+    pub fn new_6510() -> Self {
+        let mut cpu = Cpu::new();
+        cpu.memory.port = Some(IoPort::default());
+
+        cpu
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{IoPort, FADE_OUT_CYCLES};
+    use cpu::Cpu;
+
+    #[test]
+    fn direction_register() {
+        let mut port = IoPort::default();
+
+        // everything is an input out of reset, so the pull-ups win
+        assert_eq!(port.read(0x01) & 0x3f, 0x3f);
+
+        port.write(0x00, 0b0010_1111);
+        port.write(0x01, 0b0000_0101);
+
+        assert_eq!(port.read(0x00), 0b0010_1111);
+        assert_eq!(port.read(0x01) & 0x3f, 0b0001_0101);
+    }
+
+    #[test]
+    fn floating_bits_fade_out() {
+        let mut port = IoPort::default();
+
+        port.write(0x00, 0b1100_0000);
+        port.write(0x01, 0b1100_0000);
+
+        // switch bits 6 and 7 back to inputs
+        port.write(0x00, 0b0000_0000);
+        assert_eq!(port.read(0x01) & 0xc0, 0xc0);
+
+        port.tick(FADE_OUT_CYCLES - 1);
+        assert_eq!(port.read(0x01) & 0xc0, 0xc0);
+
+        port.tick(1);
+        assert_eq!(port.read(0x01) & 0xc0, 0x00);
+    }
+
+    #[test]
+    fn cpu_sees_port() {
+        let mut cpu = Cpu::new_6510();
+
+        // lda #$2f / sta $00 / lda #$35 / sta $01
+        cpu.load_program(0x6000, &[0xa9, 0x2f, 0x85, 0x00, 0xa9, 0x35, 0x85, 0x01]);
+        cpu.run();
+
+        let port = cpu.memory.port.as_ref().unwrap();
+        assert_eq!(port.direction, 0x2f);
+        assert_eq!(port.data, 0x35);
+        assert_eq!(cpu.memory.read_u8_at(&0x0001) & 0x07, 0x05);
+    }
+}
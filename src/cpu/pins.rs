@@ -0,0 +1,230 @@
+use super::{Cpu, IRQ_BRK_VECTOR_ADDR, NMI_VECTOR_ADDR};
+use super::callstack::{Frame, FrameKind};
+use super::hooks::Access;
+
+const INTERRUPT_CYCLES: u8 = 7;
+
+// which cycle parity a dma transfer has to start on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DmaAlignment {
+    Any,
+    Even,
+    Odd,
+}
+
+impl Cpu {
+    pub fn set_rdy(&mut self, level: bool) {
+        self.rdy_line = level;
+    }
+
+    // steals exactly `cycles` cycles, starting as soon as the current instruction is off the bus
+    pub fn stall(&mut self, cycles: u64) {
+        self.stall_cycles += cycles;
+    }
+
+    // halts the cpu for a dma transfer. the cpu needs a cycle to notice rdy going low, and the transfer
+    // may need one more to line up with the requested parity (nes oam dma, for one, is 513 or 514 cycles).
+    // returns how many cycles were stolen in total
+    pub fn stall_aligned(&mut self, cycles: u64, alignment: DmaAlignment) -> u64 {
+        let halt_cycle = self.cycles + self.stall_cycles;
+        let first_transfer_cycle = halt_cycle + 1;
+
+        let alignment_cycles = match alignment {
+            DmaAlignment::Any => 0,
+            DmaAlignment::Even => first_transfer_cycle % 2,
+            DmaAlignment::Odd => 1 - (first_transfer_cycle % 2),
+        };
+
+        let total = 1 + alignment_cycles + cycles;
+        self.stall_cycles += total;
+
+        total
+    }
+
+    pub fn is_halted(&self) -> bool {
+        !self.rdy_line || self.stall_cycles > 0
+    }
+
+    // so sets the overflow flag on a falling edge; holding it low does nothing more
+    pub fn set_so(&mut self, level: bool) {
+        if self.so_line && !level {
+            self.reg_status.overflow = true;
+        }
+
+        self.so_line = level;
+    }
+
+    // irq is level triggered: it's serviced for as long as it's asserted and not masked
+    pub fn set_irq(&mut self, asserted: bool) {
+        self.irq_line = asserted;
+    }
+
+    // nmi is edge triggered: only the transition to asserted latches an interrupt
+    pub fn set_nmi(&mut self, asserted: bool) {
+        if !self.nmi_line && asserted {
+            self.nmi_pending = true;
+        }
+
+        self.nmi_line = asserted;
+    }
+
+    pub fn steal_cycle(&mut self) -> bool {
+        if !self.is_halted() {
+            return false;
+        }
+
+        self.stall_cycles = self.stall_cycles.saturating_sub(1);
+        self.tick(1);
+
+        true
+    }
+
+    pub fn service_interrupts(&mut self) -> bool {
+        let (vector_addr, kind) = match (self.nmi_pending, self.irq_line && !self.reg_status.irq_disable) {
+            (true, _) => {
+                self.nmi_pending = false;
+                (NMI_VECTOR_ADDR[0], FrameKind::Nmi)
+            }
+            (false, true) => (IRQ_BRK_VECTOR_ADDR[0], FrameKind::Irq),
+            _ => return false,
+        };
+
+        let pc = self.reg_pc;
+        let pc_hi = ((pc & 0xff00) >> 8) as u8;
+        let pc_lo = (pc & 0x00ff) as u8;
+
+        // hardware interrupts push the status with b clear so handlers can tell them apart from brk
+        let mut status = self.reg_status.clone();
+        status.brk = false;
+
+        let sp = self.reg_sp;
+        self.push_u8(pc_hi);
+        self.push_u8(pc_lo);
+        let status = status.into();
+        self.push_u8(status);
+        self.memory.hooks.report(Access::Interrupt, vector_addr, status);
+
+        self.reg_status.irq_disable = true;
+        self.reg_pc = self.memory.read_u16_at(&vector_addr);
+
+        self.call_stack.push(Frame {
+            kind: kind,
+            caller: pc,
+            target: self.reg_pc,
+            sp: sp,
+        });
+
+        self.tick(INTERRUPT_CYCLES as u64);
+        self.pending_cycles = Some(INTERRUPT_CYCLES);
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DmaAlignment;
+    use cpu::Cpu;
+
+    fn cpu_with_nops() -> Cpu {
+        let mut cpu = Cpu::new();
+        cpu.load_program(0x6000, &[0xea; 0x10]);
+        cpu.reset();
+
+        cpu
+    }
+
+    #[test]
+    fn rdy_halts() {
+        let mut cpu = cpu_with_nops();
+
+        cpu.set_rdy(false);
+        for _ in 0..5 {
+            cpu.step();
+        }
+
+        assert_eq!(cpu.reg_pc, 0x6000);
+        assert_eq!(cpu.cycles, 5);
+
+        cpu.set_rdy(true);
+        cpu.step();
+
+        assert_eq!(cpu.reg_pc, 0x6001);
+        assert_eq!(cpu.cycles, 7);
+    }
+
+    #[test]
+    fn stall() {
+        let mut cpu = cpu_with_nops();
+
+        cpu.stall(3);
+        for _ in 0..3 {
+            cpu.step();
+        }
+
+        assert_eq!(cpu.reg_pc, 0x6000);
+        assert!(!cpu.is_halted());
+
+        cpu.step();
+        assert_eq!(cpu.reg_pc, 0x6001);
+    }
+
+    #[test]
+    fn stall_aligned() {
+        let mut cpu = Cpu::new();
+
+        // halting on cycle 0 means the transfer would start on odd cycle 1
+        assert_eq!(cpu.stall_aligned(512, DmaAlignment::Even), 514);
+
+        cpu.stall_cycles = 0;
+        cpu.cycles = 1;
+        assert_eq!(cpu.stall_aligned(512, DmaAlignment::Even), 513);
+
+        cpu.stall_cycles = 0;
+        assert_eq!(cpu.stall_aligned(4, DmaAlignment::Any), 5);
+    }
+
+    #[test]
+    fn so_falling_edge() {
+        let mut cpu = Cpu::new();
+
+        cpu.set_so(true);
+        assert!(!cpu.reg_status.overflow);
+
+        cpu.set_so(false);
+        assert!(cpu.reg_status.overflow);
+
+        cpu.reg_status.overflow = false;
+        cpu.set_so(false);
+        assert!(!cpu.reg_status.overflow);
+    }
+
+    #[test]
+    fn nmi_is_edge_triggered() {
+        let mut cpu = cpu_with_nops();
+        cpu.memory.write_at(&0xfffa, &[0x00, 0x70]);
+
+        cpu.set_nmi(true);
+        assert!(cpu.service_interrupts());
+        assert_eq!(cpu.reg_pc, 0x7000);
+        assert!(cpu.reg_status.irq_disable);
+
+        // still held, but no new edge
+        assert!(!cpu.service_interrupts());
+    }
+
+    #[test]
+    fn irq_is_masked() {
+        let mut cpu = cpu_with_nops();
+        cpu.memory.write_at(&0xfffe, &[0x00, 0x80]);
+
+        cpu.reg_status.irq_disable = true;
+        cpu.set_irq(true);
+        assert!(!cpu.service_interrupts());
+
+        cpu.reg_status.irq_disable = false;
+        assert!(cpu.service_interrupts());
+        assert_eq!(cpu.reg_sp, 0xfa);
+        assert_eq!(cpu.reg_pc, 0x8000);
+    }
+}
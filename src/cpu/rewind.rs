@@ -0,0 +1,417 @@
+extern crate byteorder;
+
+use std::collections::VecDeque;
+
+use self::byteorder::{LittleEndian, ByteOrder};
+
+use super::Cpu;
+use super::snapshot::SnapshotError;
+
+pub const DEFAULT_KEYFRAME_INTERVAL: usize = 30;
+
+// xor runs shorter than this aren't worth ending a literal for
+const MIN_SKIP_RUN: usize = 8;
+
+// snapshots are taken with every page written out, so a page going from zeroed to used doesn't shift everything
+// after it and blow up the deltas
+enum Frame {
+    // the snapshot diffed against nothing, which squeezes out its zeroed pages
+    Key(Vec<u8>),
+    // xor of the snapshot against the closest older keyframe, with the zero runs squeezed out
+    Delta(Vec<u8>),
+}
+
+struct Entry {
+    cycle: u64,
+    instructions: u64,
+    frame: Frame,
+}
+
+// a ring of machine states captured every `interval` cycles (or whenever the host calls `record`, e.g. once a frame).
+// most entries are deltas against a keyframe so a few seconds of history stay cheap
+pub struct RewindBuffer {
+    pub interval: u64,
+    pub capacity: usize,
+    pub keyframe_interval: usize,
+
+    entries: VecDeque<Entry>,
+    // the newest keyframe's snapshot, in full
+    keyframe: Option<Vec<u8>>,
+    since_keyframe: usize,
+    next_capture_cycle: u64,
+}
+
+impl RewindBuffer {
+    pub fn new(interval: u64, capacity: usize) -> Self {
+        RewindBuffer {
+            interval: interval,
+            capacity: capacity,
+            keyframe_interval: DEFAULT_KEYFRAME_INTERVAL,
+
+            entries: VecDeque::new(),
+            keyframe: None,
+            since_keyframe: 0,
+            next_capture_cycle: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.keyframe = None;
+        self.since_keyframe = 0;
+        self.next_capture_cycle = 0;
+    }
+
+    pub fn oldest_cycle(&self) -> Option<u64> {
+        self.entries.front().map(|entry| entry.cycle)
+    }
+
+    pub fn newest_cycle(&self) -> Option<u64> {
+        self.entries.back().map(|entry| entry.cycle)
+    }
+
+    // bytes held by captured states
+    pub fn memory_usage(&self) -> usize {
+        self.entries
+            .iter()
+            .map(|entry| match entry.frame {
+                Frame::Key(ref bytes) => bytes.len(),
+                Frame::Delta(ref bytes) => bytes.len(),
+            })
+            .sum()
+    }
+
+    // call after every step; captures once `interval` cycles have passed since the last capture
+    pub fn tick(&mut self, cpu: &Cpu) -> bool {
+        if cpu.cycles < self.next_capture_cycle {
+            return false;
+        }
+
+        self.record(cpu);
+
+        true
+    }
+
+    pub fn record(&mut self, cpu: &Cpu) {
+        let snapshot = cpu.save_state_expanded();
+        let group_len = self.group_len();
+
+        let frame = match self.keyframe {
+            Some(ref keyframe) if self.since_keyframe < group_len => Frame::Delta(diff(keyframe, &snapshot)),
+            _ => Frame::Key(diff(&[], &snapshot)),
+        };
+
+        match frame {
+            Frame::Key(_) => {
+                self.keyframe = Some(snapshot);
+                self.since_keyframe = 1;
+            }
+            Frame::Delta(_) => self.since_keyframe += 1,
+        }
+
+        self.entries.push_back(Entry {
+            cycle: cpu.cycles,
+            instructions: cpu.instructions,
+            frame: frame,
+        });
+        self.next_capture_cycle = cpu.cycles + self.interval;
+
+        while self.entries.len() > self.capacity {
+            self.evict_oldest();
+        }
+    }
+
+    // restores the state captured `steps` captures ago (1 being the newest) and forgets everything after it, so
+    // recording can carry on from there. returns the cycle that was restored, or none if there's no history
+    pub fn rewind(&mut self, cpu: &mut Cpu, steps: usize) -> Result<Option<u64>, SnapshotError> {
+        if self.entries.is_empty() || steps == 0 {
+            return Ok(None);
+        }
+
+        let index = self.entries.len().saturating_sub(steps);
+        let snapshot = self.state_at(index);
+
+        cpu.load_state(&snapshot)?;
+
+        self.entries.truncate(index + 1);
+        self.resync_keyframe();
+
+        Ok(Some(self.entries[index].cycle))
+    }
+
+    // rewinds to the newest capture at or before `cycle`
+    pub fn rewind_to_cycle(&mut self, cpu: &mut Cpu, cycle: u64) -> Result<Option<u64>, SnapshotError> {
+        match self.entries.iter().rposition(|entry| entry.cycle <= cycle) {
+            Some(index) => self.rewind(cpu, self.entries.len() - index),
+            None => Ok(None),
+        }
+    }
+
+    // rewinds to the newest capture taken at or before the `instructions`th instruction
+    pub fn rewind_to_instruction(&mut self, cpu: &mut Cpu, instructions: u64) -> Result<Option<u64>, SnapshotError> {
+        match self.entries.iter().rposition(|entry| entry.instructions <= instructions) {
+            Some(index) => self.rewind(cpu, self.entries.len() - index),
+            None => Ok(None),
+        }
+    }
+
+    // a keyframe and its deltas are evicted together, so a group that took up more than half the buffer would
+    // leave next to nothing behind when it went
+    fn group_len(&self) -> usize {
+        self.keyframe_interval.min(self.capacity / 2).max(1)
+    }
+
+    fn state_at(&self, index: usize) -> Vec<u8> {
+        match self.entries[index].frame {
+            Frame::Key(ref key) => patch(&[], key),
+            Frame::Delta(ref delta) => {
+                let keyframe_index = self.keyframe_before(index).expect("delta without a keyframe");
+
+                match self.entries[keyframe_index].frame {
+                    Frame::Key(ref key) => patch(&patch(&[], key), delta),
+                    Frame::Delta(_) => unreachable!(),
+                }
+            }
+        }
+    }
+
+    fn keyframe_before(&self, index: usize) -> Option<usize> {
+        (0..index + 1).rev().find(|i| match self.entries[*i].frame {
+            Frame::Key(_) => true,
+            Frame::Delta(_) => false,
+        })
+    }
+
+    // dropping a keyframe orphans its deltas, so they go with it
+    fn evict_oldest(&mut self) {
+        self.entries.pop_front();
+
+        while let Some(&Entry { frame: Frame::Delta(_), .. }) = self.entries.front() {
+            self.entries.pop_front();
+        }
+
+        if self.entries.is_empty() {
+            self.keyframe = None;
+        }
+    }
+
+    fn resync_keyframe(&mut self) {
+        let last = self.entries.len() - 1;
+
+        match self.keyframe_before(last) {
+            Some(index) => {
+                self.keyframe = match self.entries[index].frame {
+                    Frame::Key(ref key) => Some(patch(&[], key)),
+                    Frame::Delta(_) => unreachable!(),
+                };
+                self.since_keyframe = self.entries.len() - index;
+            }
+            None => {
+                self.keyframe = None;
+                self.since_keyframe = 0;
+            }
+        }
+
+        self.next_capture_cycle = self.entries[last].cycle + self.interval;
+    }
+}
+
+// delta layout: target length, then (skip, literal length, literal bytes) runs of the xor against `base`
+fn diff(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let xor: Vec<u8> = target
+        .iter()
+        .enumerate()
+        .map(|(i, b)| b ^ base.get(i).cloned().unwrap_or(0))
+        .collect();
+
+    let mut out = vec![0; 4];
+    LittleEndian::write_u32(&mut out, target.len() as u32);
+
+    let mut i = 0;
+    while i < xor.len() {
+        let start = i;
+        while i < xor.len() && xor[i] == 0 {
+            i += 1;
+        }
+
+        if i == xor.len() {
+            break;
+        }
+
+        let skip = i - start;
+        let literal_start = i;
+
+        let mut zeros = 0;
+        while i < xor.len() && zeros < MIN_SKIP_RUN {
+            zeros = match xor[i] {
+                0 => zeros + 1,
+                _ => 0,
+            };
+
+            i += 1;
+        }
+
+        let literal_end = i - zeros;
+        i = literal_end;
+
+        push_u32(&mut out, skip as u32);
+        push_u32(&mut out, (literal_end - literal_start) as u32);
+        out.extend_from_slice(&xor[literal_start..literal_end]);
+    }
+
+    out
+}
+
+fn patch(base: &[u8], delta: &[u8]) -> Vec<u8> {
+    let len = LittleEndian::read_u32(&delta[0..4]) as usize;
+
+    let mut out: Vec<u8> = (0..len).map(|i| base.get(i).cloned().unwrap_or(0)).collect();
+
+    let mut pos = 0;
+    let mut i = 4;
+    while i < delta.len() {
+        let skip = LittleEndian::read_u32(&delta[i..i + 4]) as usize;
+        let literal_len = LittleEndian::read_u32(&delta[i + 4..i + 8]) as usize;
+        i += 8;
+
+        pos += skip;
+        for b in &delta[i..i + literal_len] {
+            out[pos] ^= *b;
+            pos += 1;
+        }
+
+        i += literal_len;
+    }
+
+    out
+}
+
+fn push_u32(out: &mut Vec<u8>, val: u32) {
+    let mut buf = [0; 4];
+    LittleEndian::write_u32(&mut buf, val);
+
+    out.extend_from_slice(&buf);
+}
+
+#[cfg(test)]
+mod test {
+    use super::{diff, patch, RewindBuffer};
+    use cpu::Cpu;
+
+    // inc $0200 / jmp $6000
+    fn counter() -> Cpu {
+        let mut cpu = Cpu::new();
+        cpu.trace = false;
+        cpu.load_program(0x6000, &[0xee, 0x00, 0x02, 0x4c, 0x00, 0x60]);
+        cpu.reset();
+
+        cpu
+    }
+
+    #[test]
+    fn diff_and_patch() {
+        let base = vec![1, 2, 3, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 9];
+        let target = vec![1, 2, 7, 4, 0, 0, 0, 0, 0, 0, 0, 0, 5, 0, 9, 10, 11];
+
+        assert_eq!(patch(&base, &diff(&base, &target)), target);
+        assert_eq!(patch(&target, &diff(&target, &base)), base);
+        assert_eq!(diff(&base, &base).len(), 4);
+    }
+
+    #[test]
+    fn rewind() {
+        let mut cpu = counter();
+        let mut rewind = RewindBuffer::new(100, 64);
+        rewind.keyframe_interval = 4;
+
+        while cpu.cycles < 1000 {
+            cpu.step();
+            rewind.tick(&cpu);
+        }
+
+        assert_eq!(rewind.len(), 10);
+
+        let count = cpu.memory.mem[0x0200];
+        let cycle = rewind.rewind(&mut cpu, 3).unwrap().unwrap();
+
+        assert_eq!(cpu.cycles, cycle);
+        assert!(cpu.memory.mem[0x0200] < count);
+        assert_eq!(rewind.len(), 8);
+
+        // re-running from the restored state lands in the same place
+        while cpu.cycles < 1000 {
+            cpu.step();
+        }
+        assert_eq!(cpu.memory.mem[0x0200], count);
+    }
+
+    #[test]
+    fn eviction_keeps_deltas_restorable() {
+        let mut cpu = counter();
+        let mut rewind = RewindBuffer::new(50, 10);
+        rewind.keyframe_interval = 4;
+
+        while cpu.cycles < 2000 {
+            cpu.step();
+            rewind.tick(&cpu);
+        }
+
+        assert!(rewind.len() <= 10);
+
+        let oldest = rewind.oldest_cycle().unwrap();
+        let len = rewind.len();
+        assert_eq!(rewind.rewind(&mut cpu, len).unwrap(), Some(oldest));
+        assert_eq!(cpu.cycles, oldest);
+    }
+
+    #[test]
+    fn eviction_with_the_default_keyframe_interval() {
+        let mut cpu = counter();
+        let mut rewind = RewindBuffer::new(1, 10);
+
+        for _ in 0..200 {
+            cpu.step();
+            rewind.tick(&cpu);
+
+            // evicting a group never takes more than half the history with it
+            if cpu.instructions >= 10 {
+                assert!(rewind.len() >= 5 && rewind.len() <= 10);
+            }
+        }
+
+        let oldest = rewind.oldest_cycle().unwrap();
+        let len = rewind.len();
+        assert_eq!(rewind.rewind(&mut cpu, len).unwrap(), Some(oldest));
+    }
+
+    #[test]
+    fn deltas_are_small() {
+        let mut cpu = counter();
+        let mut rewind = RewindBuffer::new(10, 100);
+
+        rewind.record(&cpu);
+        let keyframe_size = rewind.memory_usage();
+
+        for _ in 0..5 {
+            cpu.step();
+        }
+        rewind.record(&cpu);
+
+        assert!(rewind.memory_usage() - keyframe_size < keyframe_size / 4);
+
+        // a page that's no longer zeroed only costs what changed in it
+        let before = rewind.memory_usage();
+        cpu.memory.mem[0x1000] = 1;
+        rewind.record(&cpu);
+
+        assert!(rewind.memory_usage() - before < 64);
+    }
+}
@@ -0,0 +1,399 @@
+extern crate byteorder;
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::{Cursor, Read, Write};
+
+use self::byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use util;
+
+use super::{Cpu, ProcessorStatusRegister};
+use super::callstack::{CallStack, Frame, FrameKind};
+use super::mem::PagedMemory;
+use super::mos6510::IoPort;
+
+const MAGIC: &'static [u8] = b"65SS";
+pub const SNAPSHOT_VERSION: u8 = 1;
+
+// per-page tags; untouched pages are the common case so they're stored as a single byte
+const PAGE_ZEROED: u8 = 0;
+const PAGE_RAW: u8 = 1;
+
+const PIN_RDY: u8 = 0;
+const PIN_SO: u8 = 1;
+const PIN_IRQ: u8 = 2;
+const PIN_NMI: u8 = 3;
+const PIN_NMI_PENDING: u8 = 4;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    Truncated,
+    BadPageTag(u8),
+    BadFrameKind(u8),
+    // the snapshot has mapper state but the cpu has no mapper, or the mapper rejected it
+    MapperMismatch,
+    Io(io::Error),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SnapshotError::BadMagic => write!(f, "not a snapshot (bad magic)"),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "unsupported snapshot version {} (this build reads up to {})", version, SNAPSHOT_VERSION)
+            }
+            SnapshotError::Truncated => write!(f, "snapshot is truncated"),
+            SnapshotError::BadPageTag(tag) => write!(f, "corrupt memory page tag {:x}", tag),
+            SnapshotError::BadFrameKind(kind) => write!(f, "corrupt call stack frame kind {:x}", kind),
+            SnapshotError::MapperMismatch => write!(f, "snapshot mapper state doesn't match the attached mapper"),
+            SnapshotError::Io(ref err) => write!(f, "i/o error: {}", err),
+        }
+    }
+}
+
+impl error::Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::UnexpectedEof => SnapshotError::Truncated,
+            _ => SnapshotError::Io(err),
+        }
+    }
+}
+
+impl Cpu {
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+
+        // writing to a vec can't fail
+        self.write_state(&mut out).unwrap();
+
+        out
+    }
+
+    // a snapshot with every page written out, zeroed or not, so any two of the same machine line up byte for byte
+    pub fn save_state_expanded(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.write_state_paged(&mut out, false).unwrap();
+
+        out
+    }
+
+    pub fn write_state<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        self.write_state_paged(out, true)
+    }
+
+    fn write_state_paged<W: Write>(&self, out: &mut W, squeeze_zeroed: bool) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        out.write_u8(SNAPSHOT_VERSION)?;
+
+        // registers
+        out.write_u8(self.reg_acc as u8)?;
+        out.write_u8(self.reg_x as u8)?;
+        out.write_u8(self.reg_y as u8)?;
+        out.write_u16::<LittleEndian>(self.reg_pc)?;
+        out.write_u8(self.reg_sp)?;
+        out.write_u8(self.reg_status.clone().into())?;
+
+        // timing
+        out.write_u8(self.pending_cycles.unwrap_or(0))?;
+        out.write_u64::<LittleEndian>(self.cycles)?;
+        out.write_u64::<LittleEndian>(self.instructions)?;
+        out.write_u64::<LittleEndian>(self.stall_cycles)?;
+
+        // pins
+        let mut pins = 0;
+        pins = util::set_bit(pins, PIN_RDY, self.rdy_line);
+        pins = util::set_bit(pins, PIN_SO, self.so_line);
+        pins = util::set_bit(pins, PIN_IRQ, self.irq_line);
+        pins = util::set_bit(pins, PIN_NMI, self.nmi_line);
+        pins = util::set_bit(pins, PIN_NMI_PENDING, self.nmi_pending);
+        out.write_u8(pins)?;
+
+        // ram
+        for index in 0..self.memory.mem.num_pages() {
+            let page = self.memory.mem.page(index);
+
+            match squeeze_zeroed && page.iter().all(|b| *b == 0) {
+                true => out.write_u8(PAGE_ZEROED)?,
+                false => {
+                    out.write_u8(PAGE_RAW)?;
+                    out.write_all(page)?;
+                }
+            }
+        }
+
+        // devices
+        match self.memory.port {
+            Some(ref port) => {
+                out.write_u8(1)?;
+                port.save_state(out)?;
+            }
+            None => out.write_u8(0)?,
+        }
+
+        let mapper_state = match self.memory.mapper {
+            Some(ref mapper) => mapper.save_state(),
+            None => Vec::new(),
+        };
+
+        out.write_u32::<LittleEndian>(mapper_state.len() as u32)?;
+        out.write_all(&mapper_state)?;
+
+        // call stack
+        let frames = self.call_stack.frames();
+        out.write_u8(frames.len() as u8)?;
+
+        for frame in frames {
+            out.write_u8(frame.kind.to_u8())?;
+            out.write_u16::<LittleEndian>(frame.caller)?;
+            out.write_u16::<LittleEndian>(frame.target)?;
+            out.write_u8(frame.sp)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn load_state(&mut self, bytes: &[u8]) -> Result<(), SnapshotError> {
+        self.read_state(&mut Cursor::new(bytes))
+    }
+
+    // nothing on the cpu is touched unless the whole snapshot parses
+    pub fn read_state<R: Read>(&mut self, input: &mut R) -> Result<(), SnapshotError> {
+        let mut magic = [0; 4];
+        input.read_exact(&mut magic)?;
+
+        if &magic[..] != MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+
+        // when the format changes, the versions before it are read here too, with whatever they lack filled in
+        match input.read_u8()? {
+            SNAPSHOT_VERSION => {}
+            version => return Err(SnapshotError::UnsupportedVersion(version)),
+        }
+
+        let reg_acc = input.read_u8()? as i8;
+        let reg_x = input.read_u8()? as i8;
+        let reg_y = input.read_u8()? as i8;
+        let reg_pc = input.read_u16::<LittleEndian>()?;
+        let reg_sp = input.read_u8()?;
+        let reg_status = ProcessorStatusRegister::from(input.read_u8()?);
+
+        let pending_cycles = match input.read_u8()? {
+            0 => None,
+            cycles => Some(cycles),
+        };
+        let cycles = input.read_u64::<LittleEndian>()?;
+        let instructions = input.read_u64::<LittleEndian>()?;
+        let stall_cycles = input.read_u64::<LittleEndian>()?;
+
+        let pins = input.read_u8()?;
+
+        let mut mem = PagedMemory::default();
+        for index in 0..mem.num_pages() {
+            match input.read_u8()? {
+                PAGE_ZEROED => {}
+                PAGE_RAW => input.read_exact(mem.page_mut(index))?,
+                tag => return Err(SnapshotError::BadPageTag(tag)),
+            }
+        }
+
+        let port = match input.read_u8()? {
+            0 => None,
+            _ => Some(IoPort::load_state(input)?),
+        };
+
+        let mapper_state_len = input.read_u32::<LittleEndian>()? as usize;
+        let mut mapper_state = vec![0; mapper_state_len];
+        input.read_exact(&mut mapper_state)?;
+
+        let mut call_stack = CallStack::default();
+        for _ in 0..input.read_u8()? {
+            let kind = input.read_u8()?;
+
+            call_stack.push(Frame {
+                kind: FrameKind::from_u8(kind).ok_or(SnapshotError::BadFrameKind(kind))?,
+                caller: input.read_u16::<LittleEndian>()?,
+                target: input.read_u16::<LittleEndian>()?,
+                sp: input.read_u8()?,
+            });
+        }
+
+        let mapper_accepted = match self.memory.mapper {
+            Some(ref mut mapper) => mapper.load_state(&mapper_state),
+            None => mapper_state.is_empty(),
+        };
+
+        if !mapper_accepted {
+            return Err(SnapshotError::MapperMismatch);
+        }
+
+        self.reg_acc = reg_acc;
+        self.reg_x = reg_x;
+        self.reg_y = reg_y;
+        self.reg_pc = reg_pc;
+        self.reg_sp = reg_sp;
+        self.reg_status = reg_status;
+
+        self.pending_cycles = pending_cycles;
+        self.cycles = cycles;
+        self.instructions = instructions;
+        self.stall_cycles = stall_cycles;
+
+        self.rdy_line = util::test_bit_set(pins, PIN_RDY);
+        self.so_line = util::test_bit_set(pins, PIN_SO);
+        self.irq_line = util::test_bit_set(pins, PIN_IRQ);
+        self.nmi_line = util::test_bit_set(pins, PIN_NMI);
+        self.nmi_pending = util::test_bit_set(pins, PIN_NMI_PENDING);
+
+        self.memory.mem = mem;
+        self.memory.port = port;
+
+        self.call_stack = call_stack;
+
+        // the mapper's banking follows the restored port
+        let lines = self.memory.port_lines();
+        if let Some(ref mut mapper) = self.memory.mapper {
+            mapper.bank_changed(lines);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SnapshotError;
+    use cpu::Cpu;
+    use cpu::c64::C64Mapper;
+
+    fn c64() -> Cpu {
+        let mut cpu = Cpu::new_6510();
+        cpu.memory.set_mapper(Box::new(C64Mapper::new(vec![0xba; 0x2000], vec![0xea; 0x2000], vec![0xc4; 0x1000])));
+
+        cpu
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut cpu = Cpu::new();
+
+        // lda #$42 / sta $0200 / ldx #$07
+        cpu.load_program(0x6000, &[0xa9, 0x42, 0x8d, 0x00, 0x02, 0xa2, 0x07]);
+        cpu.reset();
+        cpu.step();
+        cpu.step();
+        cpu.set_so(false);
+        cpu.stall(3);
+
+        let snapshot = cpu.save_state();
+
+        let mut restored = Cpu::new();
+        restored.load_state(&snapshot).unwrap();
+
+        assert_eq!(restored.reg_acc, 0x42);
+        assert_eq!(restored.reg_pc, cpu.reg_pc);
+        assert_eq!(restored.pending_cycles, cpu.pending_cycles);
+        assert_eq!(restored.cycles, cpu.cycles);
+        assert_eq!(restored.instructions, cpu.instructions);
+        assert_eq!(restored.stall_cycles, 3);
+        assert!(!restored.so_line);
+        assert!(restored.reg_status.overflow);
+        assert!(restored.rdy_line);
+        assert_eq!(restored.memory.mem, cpu.memory.mem);
+
+        // and the restored cpu picks up where the original left off
+        while restored.step() {}
+        assert_eq!(restored.reg_x, 0x07);
+    }
+
+    #[test]
+    fn call_stack_round_trip() {
+        let mut cpu = Cpu::new();
+        cpu.trace = false;
+
+        // jsr $6003 / jsr $6006
+        cpu.load_program(0x6000, &[0x20, 0x03, 0x60, 0x20, 0x06, 0x60]);
+        cpu.reset();
+
+        while cpu.instructions < 2 {
+            cpu.step();
+        }
+
+        let mut restored = Cpu::new();
+        restored.load_state(&cpu.save_state()).unwrap();
+
+        assert_eq!(restored.call_stack.depth(), 2);
+        assert_eq!(restored.call_stack, cpu.call_stack);
+
+        let mut corrupt = cpu.save_state();
+        let len = corrupt.len();
+        corrupt[len - 6] = 9;
+
+        match Cpu::new().load_state(&corrupt) {
+            Err(SnapshotError::BadFrameKind(9)) => {}
+            other => panic!("expected a bad frame kind, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn untouched_pages_are_compact() {
+        let cpu = Cpu::new();
+
+        assert!(cpu.save_state().len() < 0x200);
+    }
+
+    #[test]
+    fn devices_round_trip() {
+        let mut cpu = c64();
+
+        cpu.memory.write_at(&0x0000, &[0x07]);
+        cpu.memory.write_at(&0x0001, &[0x35]);
+        cpu.memory.write_at(&0xd020, &[0x0e]);
+
+        let snapshot = cpu.save_state();
+
+        let mut restored = c64();
+        restored.load_state(&snapshot).unwrap();
+
+        assert_eq!(restored.memory.port_lines() & 0x07, 0x05);
+        assert_eq!(restored.memory.read_u8_at(&0xd020), 0x0e);
+
+        // basic is banked out again, so ram shows through
+        assert_eq!(restored.memory.read_u8_at(&0xa000), 0x00);
+
+        // a plain 6502 has nowhere to put the i/o registers
+        match Cpu::new().load_state(&snapshot) {
+            Err(SnapshotError::MapperMismatch) => {}
+            other => panic!("expected a mapper mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_bad_snapshots() {
+        let mut cpu = Cpu::new();
+        let mut snapshot = cpu.save_state();
+
+        match cpu.load_state(&snapshot[..10]) {
+            Err(SnapshotError::Truncated) => {}
+            other => panic!("expected truncation, got {:?}", other),
+        }
+
+        snapshot[4] = 0x7f;
+        match cpu.load_state(&snapshot) {
+            Err(SnapshotError::UnsupportedVersion(0x7f)) => {}
+            other => panic!("expected a version error, got {:?}", other),
+        }
+
+        snapshot[0] = b'x';
+        match cpu.load_state(&snapshot) {
+            Err(SnapshotError::BadMagic) => {}
+            other => panic!("expected a magic error, got {:?}", other),
+        }
+    }
+}
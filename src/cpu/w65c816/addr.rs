@@ -0,0 +1,383 @@
+use cpu::addr::AddrMode;
+use super::Cpu816;
+
+use std::fmt;
+
+// the 65c816 counterpart to addr::AddrResult; effective addresses are 24 bits wide
+#[derive(Clone)]
+pub struct LongAddrResult {
+    pub value: u32,
+    pub operand: u32,
+    pub crosses_boundary: Option<bool>,
+    pub addr_mode: AddrMode,
+}
+
+impl LongAddrResult {
+    pub fn resolve(&self, cpu: &mut Cpu816, wide: bool) -> u16 {
+        match self.addr_mode {
+            AddrMode::Immediate | AddrMode::Implicit => self.value as u16,
+            AddrMode::Accumulator => cpu.acc(wide),
+            AddrMode::Unknown => panic!("unknown addr mode!"),
+            _ => cpu.read_data(self.value, wide),
+        }
+    }
+
+    pub fn write(&self, cpu: &mut Cpu816, val: u16, wide: bool) {
+        match self.addr_mode {
+            AddrMode::Accumulator => cpu.set_acc(val, wide),
+            AddrMode::Immediate | AddrMode::Implicit | AddrMode::Unknown => panic!("can't write to {:?}!", self.addr_mode),
+            _ => cpu.write_data(self.value, val, wide),
+        }
+    }
+}
+
+impl fmt::Debug for LongAddrResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = self.operand;
+
+        let result = match self.addr_mode {
+            AddrMode::Immediate if op > 0xff => format!("#${:04x}", op),
+            AddrMode::Immediate => format!("#${:02x}", op),
+            AddrMode::Implicit | AddrMode::Accumulator | AddrMode::Unknown => String::from(""),
+            AddrMode::ZeroPage => format!("${:02x}", op),
+            AddrMode::ZeroPageX => format!("${:02x},X", op),
+            AddrMode::ZeroPageY => format!("${:02x},Y", op),
+            AddrMode::DirectIndirect => format!("(${:02x})", op),
+            AddrMode::IndirectX => format!("(${:02x},X)", op),
+            AddrMode::IndirectY => format!("(${:02x}),Y", op),
+            AddrMode::DirectIndirectLong => format!("[${:02x}]", op),
+            AddrMode::DirectIndirectLongY => format!("[${:02x}],Y", op),
+            AddrMode::Absolute => format!("${:04x}", op),
+            AddrMode::AbsoluteX => format!("${:04x},X", op),
+            AddrMode::AbsoluteY => format!("${:04x},Y", op),
+            AddrMode::AbsoluteLong => format!("${:06x}", op),
+            AddrMode::AbsoluteLongX => format!("${:06x},X", op),
+            AddrMode::Indirect => format!("(${:04x})", op),
+            AddrMode::AbsoluteIndirectX => format!("(${:04x},X)", op),
+            AddrMode::AbsoluteIndirectLong => format!("[${:04x}]", op),
+            AddrMode::StackRelative => format!("${:02x},S", op),
+            AddrMode::StackRelativeIndirectY => format!("(${:02x},S),Y", op),
+            AddrMode::Relative | AddrMode::RelativeLong => format!("${:04x}", self.value & 0xffff),
+            AddrMode::BlockMove => format!("${:02x},${:02x}", (op >> 8) & 0xff, op & 0xff),
+        };
+
+        write!(f, "{}", result)
+    }
+}
+
+fn result(value: u32, operand: u32, addr_mode: AddrMode) -> LongAddrResult {
+    LongAddrResult {
+        value: value & 0xff_ffff,
+        operand: operand,
+        crosses_boundary: None,
+        addr_mode: addr_mode,
+    }
+}
+
+fn indexed(base: u32, offset: u16, operand: u32, addr_mode: AddrMode) -> LongAddrResult {
+    let addr = base.wrapping_add(offset as u32) & 0xff_ffff;
+
+    LongAddrResult {
+        value: addr,
+        operand: operand,
+        crosses_boundary: Some((base & 0xff_ff00) != (addr & 0xff_ff00)),
+        addr_mode: addr_mode,
+    }
+}
+
+fn data_bank(cpu: &Cpu816, addr: u16) -> u32 {
+    ((cpu.reg_dbr as u32) << 16) | addr as u32
+}
+
+// in emulation mode a page-aligned direct page behaves exactly like the 6502 zero page
+pub fn direct_addr(cpu: &Cpu816, offset: u16) -> u32 {
+    match cpu.emulation && cpu.reg_dp & 0x00ff == 0 {
+        true => (cpu.reg_dp | (offset & 0x00ff)) as u32,
+        false => cpu.reg_dp.wrapping_add(offset) as u32,
+    }
+}
+
+fn direct_ptr_u16(cpu: &mut Cpu816, offset: u16) -> u16 {
+    let lo_addr = direct_addr(cpu, offset);
+    let hi_addr = direct_addr(cpu, offset.wrapping_add(1));
+
+    let lo = cpu.memory.read_u8(lo_addr) as u16;
+    let hi = cpu.memory.read_u8(hi_addr) as u16;
+
+    (hi << 8) | lo
+}
+
+fn direct_ptr_u24(cpu: &mut Cpu816, offset: u16) -> u32 {
+    let addr = direct_ptr_u16(cpu, offset) as u32;
+    let bank_addr = direct_addr(cpu, offset.wrapping_add(2));
+
+    ((cpu.memory.read_u8(bank_addr) as u32) << 16) | addr
+}
+
+pub fn implicit() -> LongAddrResult {
+    result(0, 0, AddrMode::Implicit)
+}
+
+pub fn acc() -> LongAddrResult {
+    result(0, 0, AddrMode::Accumulator)
+}
+
+pub fn imm(cpu: &mut Cpu816, wide: bool) -> LongAddrResult {
+    let value = match wide {
+        true => cpu.read_u16() as u32,
+        false => cpu.read_u8() as u32,
+    };
+
+    result(value, value, AddrMode::Immediate)
+}
+
+pub fn rel(cpu: &mut Cpu816) -> LongAddrResult {
+    let offset = cpu.read_u8() as i8 as i16 as u16;
+    let target = cpu.reg_pc.wrapping_add(offset);
+
+    LongAddrResult {
+        value: ((cpu.reg_pbr as u32) << 16) | target as u32,
+        operand: offset as u8 as u32,
+        crosses_boundary: Some((cpu.reg_pc & 0xff00) != (target & 0xff00)),
+        addr_mode: AddrMode::Relative,
+    }
+}
+
+pub fn rel_long(cpu: &mut Cpu816) -> LongAddrResult {
+    let offset = cpu.read_u16();
+    let target = cpu.reg_pc.wrapping_add(offset);
+
+    result(((cpu.reg_pbr as u32) << 16) | target as u32, offset as u32, AddrMode::RelativeLong)
+}
+
+pub fn direct(cpu: &mut Cpu816) -> LongAddrResult {
+    let op = cpu.read_u8() as u16;
+
+    result(direct_addr(cpu, op), op as u32, AddrMode::ZeroPage)
+}
+
+pub fn direct_x(cpu: &mut Cpu816) -> LongAddrResult {
+    let op = cpu.read_u8() as u16;
+    let addr = direct_addr(cpu, op.wrapping_add(cpu.reg_x));
+
+    result(addr, op as u32, AddrMode::ZeroPageX)
+}
+
+pub fn direct_y(cpu: &mut Cpu816) -> LongAddrResult {
+    let op = cpu.read_u8() as u16;
+    let addr = direct_addr(cpu, op.wrapping_add(cpu.reg_y));
+
+    result(addr, op as u32, AddrMode::ZeroPageY)
+}
+
+pub fn direct_ind(cpu: &mut Cpu816) -> LongAddrResult {
+    let op = cpu.read_u8() as u16;
+    let ptr = direct_ptr_u16(cpu, op);
+
+    result(data_bank(cpu, ptr), op as u32, AddrMode::DirectIndirect)
+}
+
+pub fn direct_ind_x(cpu: &mut Cpu816) -> LongAddrResult {
+    let op = cpu.read_u8() as u16;
+    let ptr = direct_ptr_u16(cpu, op.wrapping_add(cpu.reg_x));
+
+    result(data_bank(cpu, ptr), op as u32, AddrMode::IndirectX)
+}
+
+pub fn direct_ind_y(cpu: &mut Cpu816) -> LongAddrResult {
+    let op = cpu.read_u8() as u16;
+    let ptr = direct_ptr_u16(cpu, op);
+    let base = data_bank(cpu, ptr);
+
+    indexed(base, cpu.reg_y, op as u32, AddrMode::IndirectY)
+}
+
+pub fn direct_ind_long(cpu: &mut Cpu816) -> LongAddrResult {
+    let op = cpu.read_u8() as u16;
+    let ptr = direct_ptr_u24(cpu, op);
+
+    result(ptr, op as u32, AddrMode::DirectIndirectLong)
+}
+
+pub fn direct_ind_long_y(cpu: &mut Cpu816) -> LongAddrResult {
+    let op = cpu.read_u8() as u16;
+    let ptr = direct_ptr_u24(cpu, op);
+
+    indexed(ptr, cpu.reg_y, op as u32, AddrMode::DirectIndirectLongY)
+}
+
+pub fn abs(cpu: &mut Cpu816) -> LongAddrResult {
+    let op = cpu.read_u16();
+
+    result(data_bank(cpu, op), op as u32, AddrMode::Absolute)
+}
+
+// jmp/jsr take their absolute operand from the program bank rather than the data bank
+pub fn abs_program(cpu: &mut Cpu816) -> LongAddrResult {
+    let op = cpu.read_u16();
+
+    result(((cpu.reg_pbr as u32) << 16) | op as u32, op as u32, AddrMode::Absolute)
+}
+
+pub fn abs_x(cpu: &mut Cpu816) -> LongAddrResult {
+    let op = cpu.read_u16();
+    let base = data_bank(cpu, op);
+
+    indexed(base, cpu.reg_x, op as u32, AddrMode::AbsoluteX)
+}
+
+pub fn abs_y(cpu: &mut Cpu816) -> LongAddrResult {
+    let op = cpu.read_u16();
+    let base = data_bank(cpu, op);
+
+    indexed(base, cpu.reg_y, op as u32, AddrMode::AbsoluteY)
+}
+
+pub fn abs_long(cpu: &mut Cpu816) -> LongAddrResult {
+    let op = cpu.read_u24();
+
+    result(op, op, AddrMode::AbsoluteLong)
+}
+
+pub fn abs_long_x(cpu: &mut Cpu816) -> LongAddrResult {
+    let op = cpu.read_u24();
+
+    indexed(op, cpu.reg_x, op, AddrMode::AbsoluteLongX)
+}
+
+pub fn ind(cpu: &mut Cpu816) -> LongAddrResult {
+    let op = cpu.read_u16();
+
+    let lo = cpu.memory.read_u8(op as u32) as u32;
+    let hi = cpu.memory.read_u8(op.wrapping_add(1) as u32) as u32;
+
+    result(((cpu.reg_pbr as u32) << 16) | (hi << 8) | lo, op as u32, AddrMode::Indirect)
+}
+
+pub fn abs_ind_x(cpu: &mut Cpu816) -> LongAddrResult {
+    let op = cpu.read_u16();
+    let bank = (cpu.reg_pbr as u32) << 16;
+    let ptr = op.wrapping_add(cpu.reg_x);
+
+    let lo = cpu.memory.read_u8(bank | ptr as u32) as u32;
+    let hi = cpu.memory.read_u8(bank | ptr.wrapping_add(1) as u32) as u32;
+
+    result(bank | (hi << 8) | lo, op as u32, AddrMode::AbsoluteIndirectX)
+}
+
+pub fn abs_ind_long(cpu: &mut Cpu816) -> LongAddrResult {
+    let op = cpu.read_u16();
+
+    let lo = cpu.memory.read_u8(op as u32) as u32;
+    let hi = cpu.memory.read_u8(op.wrapping_add(1) as u32) as u32;
+    let bank = cpu.memory.read_u8(op.wrapping_add(2) as u32) as u32;
+
+    result((bank << 16) | (hi << 8) | lo, op as u32, AddrMode::AbsoluteIndirectLong)
+}
+
+pub fn stack_rel(cpu: &mut Cpu816) -> LongAddrResult {
+    let op = cpu.read_u8() as u16;
+
+    result(cpu.reg_sp.wrapping_add(op) as u32, op as u32, AddrMode::StackRelative)
+}
+
+pub fn stack_rel_ind_y(cpu: &mut Cpu816) -> LongAddrResult {
+    let op = cpu.read_u8() as u16;
+    let ptr_addr = cpu.reg_sp.wrapping_add(op);
+
+    let lo = cpu.memory.read_u8(ptr_addr as u32) as u16;
+    let hi = cpu.memory.read_u8(ptr_addr.wrapping_add(1) as u32) as u16;
+    let base = data_bank(cpu, (hi << 8) | lo);
+
+    let mut addr_result = indexed(base, cpu.reg_y, op as u32, AddrMode::StackRelativeIndirectY);
+    addr_result.crosses_boundary = None;
+
+    addr_result
+}
+
+pub fn block_move(cpu: &mut Cpu816) -> LongAddrResult {
+    // operand bytes are encoded destination bank first
+    let dest = cpu.read_u8() as u32;
+    let src = cpu.read_u8() as u32;
+
+    result((dest << 8) | src, (dest << 8) | src, AddrMode::BlockMove)
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::Cpu816;
+
+    fn cpu_at(bytes: &[u8]) -> Cpu816 {
+        let mut cpu = Cpu816::new();
+        cpu.emulation = false;
+        cpu.reg_pbr = 0x01;
+        cpu.reg_pc = 0x8000;
+
+        for (i, byte) in bytes.iter().enumerate() {
+            cpu.memory.write_u8(0x01_8000 + i as u32, *byte);
+        }
+
+        cpu
+    }
+
+    #[test]
+    fn direct() {
+        let mut cpu = cpu_at(&[0x10]);
+        cpu.reg_dp = 0x2001;
+
+        assert_eq!(super::direct(&mut cpu).value, 0x2011);
+    }
+
+    #[test]
+    fn direct_x_emulation_wraps_in_page() {
+        let mut cpu = cpu_at(&[0xf0]);
+        cpu.emulation = true;
+        cpu.reg_dp = 0x0200;
+        cpu.reg_x = 0x20;
+
+        assert_eq!(super::direct_x(&mut cpu).value, 0x0210);
+    }
+
+    #[test]
+    fn abs_long_x() {
+        let mut cpu = cpu_at(&[0xff, 0xff, 0x7e]);
+        cpu.reg_x = 0x0002;
+
+        let result = super::abs_long_x(&mut cpu);
+        assert_eq!(result.value, 0x7f_0001);
+        assert_eq!(result.crosses_boundary, Some(true));
+    }
+
+    #[test]
+    fn direct_ind_long_y() {
+        let mut cpu = cpu_at(&[0x20]);
+        cpu.reg_y = 0x0010;
+        cpu.memory.write_u8(0x20, 0x00);
+        cpu.memory.write_u8(0x21, 0x30);
+        cpu.memory.write_u8(0x22, 0x7e);
+
+        assert_eq!(super::direct_ind_long_y(&mut cpu).value, 0x7e_3010);
+    }
+
+    #[test]
+    fn stack_rel_ind_y() {
+        let mut cpu = cpu_at(&[0x03]);
+        cpu.reg_sp = 0x1ff0;
+        cpu.reg_dbr = 0x02;
+        cpu.reg_y = 0x0004;
+        cpu.memory.write_u8(0x1ff3, 0x00);
+        cpu.memory.write_u8(0x1ff4, 0x40);
+
+        assert_eq!(super::stack_rel_ind_y(&mut cpu).value, 0x02_4004);
+    }
+
+    #[test]
+    fn abs_ind_x_uses_program_bank() {
+        let mut cpu = cpu_at(&[0x00, 0x90]);
+        cpu.reg_x = 0x0002;
+        cpu.memory.write_u8(0x01_9002, 0x34);
+        cpu.memory.write_u8(0x01_9003, 0x12);
+
+        assert_eq!(super::abs_ind_x(&mut cpu).value, 0x01_1234);
+    }
+}
@@ -0,0 +1,969 @@
+use cpu::addr::AddrMode;
+use super::addr;
+use super::addr::LongAddrResult;
+use super::{Cpu816, Interrupt};
+
+use std::fmt;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Op {
+    Adc, And, Asl, Bcc, Bcs, Beq, Bit, Bmi, Bne, Bpl, Bra, Brk, Brl, Bvc, Bvs, Clc,
+    Cld, Cli, Clv, Cmp, Cop, Cpx, Cpy, Dec, Dex, Dey, Eor, Inc, Inx, Iny, Jml, Jmp,
+    Jsl, Jsr, Lda, Ldx, Ldy, Lsr, Mvn, Mvp, Nop, Ora, Pea, Pei, Per, Pha, Phb, Phd,
+    Phk, Php, Phx, Phy, Pla, Plb, Pld, Plp, Plx, Ply, Rep, Rol, Ror, Rti, Rtl, Rts,
+    Sbc, Sec, Sed, Sei, Sep, Sta, Stp, Stx, Sty, Stz, Tax, Tay, Tcd, Tcs, Tdc, Trb,
+    Tsb, Tsc, Tsx, Txa, Txs, Txy, Tya, Tyx, Wai, Wdm, Xba, Xce,
+}
+
+impl Op {
+    pub fn name(&self) -> String {
+        format!("{:?}", self).to_lowercase()
+    }
+
+    fn acc_sized(&self) -> bool {
+        match *self {
+            Op::Ora | Op::And | Op::Eor | Op::Adc | Op::Sbc | Op::Cmp | Op::Bit | Op::Lda | Op::Sta | Op::Stz | Op::Asl | Op::Lsr |
+            Op::Rol | Op::Ror | Op::Inc | Op::Dec | Op::Tsb | Op::Trb => true,
+            _ => false,
+        }
+    }
+
+    fn index_sized(&self) -> bool {
+        match *self {
+            Op::Ldx | Op::Ldy | Op::Stx | Op::Sty | Op::Cpx | Op::Cpy => true,
+            _ => false,
+        }
+    }
+
+    fn is_read(&self) -> bool {
+        match *self {
+            Op::Ora | Op::And | Op::Eor | Op::Adc | Op::Sbc | Op::Cmp | Op::Bit | Op::Lda | Op::Ldx | Op::Ldy | Op::Cpx | Op::Cpy => true,
+            _ => false,
+        }
+    }
+
+    fn is_rmw(&self) -> bool {
+        match *self {
+            Op::Asl | Op::Lsr | Op::Rol | Op::Ror | Op::Inc | Op::Dec | Op::Tsb | Op::Trb => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Operand {
+    Implied,
+    Accumulator,
+    Immediate,
+    Immediate8,
+    Immediate16,
+    Direct,
+    DirectX,
+    DirectY,
+    DirectIndirect,
+    DirectIndirectX,
+    DirectIndirectY,
+    DirectIndirectLong,
+    DirectIndirectLongY,
+    Absolute,
+    AbsoluteProgram,
+    AbsoluteX,
+    AbsoluteY,
+    AbsoluteLong,
+    AbsoluteLongX,
+    Indirect,
+    AbsoluteIndirectX,
+    AbsoluteIndirectLong,
+    StackRelative,
+    StackRelativeIndirectY,
+    Relative,
+    RelativeLong,
+    BlockMove,
+}
+
+pub fn lookup(opcode: u8) -> (Op, Operand, u8) {
+    match opcode {
+        0x00 => (Op::Brk, Operand::Immediate8, 7),
+        0x01 => (Op::Ora, Operand::DirectIndirectX, 6),
+        0x02 => (Op::Cop, Operand::Immediate8, 7),
+        0x03 => (Op::Ora, Operand::StackRelative, 4),
+        0x04 => (Op::Tsb, Operand::Direct, 5),
+        0x05 => (Op::Ora, Operand::Direct, 3),
+        0x06 => (Op::Asl, Operand::Direct, 5),
+        0x07 => (Op::Ora, Operand::DirectIndirectLong, 6),
+        0x08 => (Op::Php, Operand::Implied, 3),
+        0x09 => (Op::Ora, Operand::Immediate, 2),
+        0x0a => (Op::Asl, Operand::Accumulator, 2),
+        0x0b => (Op::Phd, Operand::Implied, 4),
+        0x0c => (Op::Tsb, Operand::Absolute, 6),
+        0x0d => (Op::Ora, Operand::Absolute, 4),
+        0x0e => (Op::Asl, Operand::Absolute, 6),
+        0x0f => (Op::Ora, Operand::AbsoluteLong, 5),
+        0x10 => (Op::Bpl, Operand::Relative, 2),
+        0x11 => (Op::Ora, Operand::DirectIndirectY, 5),
+        0x12 => (Op::Ora, Operand::DirectIndirect, 5),
+        0x13 => (Op::Ora, Operand::StackRelativeIndirectY, 7),
+        0x14 => (Op::Trb, Operand::Direct, 5),
+        0x15 => (Op::Ora, Operand::DirectX, 4),
+        0x16 => (Op::Asl, Operand::DirectX, 6),
+        0x17 => (Op::Ora, Operand::DirectIndirectLongY, 6),
+        0x18 => (Op::Clc, Operand::Implied, 2),
+        0x19 => (Op::Ora, Operand::AbsoluteY, 4),
+        0x1a => (Op::Inc, Operand::Accumulator, 2),
+        0x1b => (Op::Tcs, Operand::Implied, 2),
+        0x1c => (Op::Trb, Operand::Absolute, 6),
+        0x1d => (Op::Ora, Operand::AbsoluteX, 4),
+        0x1e => (Op::Asl, Operand::AbsoluteX, 7),
+        0x1f => (Op::Ora, Operand::AbsoluteLongX, 5),
+        0x20 => (Op::Jsr, Operand::AbsoluteProgram, 6),
+        0x21 => (Op::And, Operand::DirectIndirectX, 6),
+        0x22 => (Op::Jsl, Operand::AbsoluteLong, 8),
+        0x23 => (Op::And, Operand::StackRelative, 4),
+        0x24 => (Op::Bit, Operand::Direct, 3),
+        0x25 => (Op::And, Operand::Direct, 3),
+        0x26 => (Op::Rol, Operand::Direct, 5),
+        0x27 => (Op::And, Operand::DirectIndirectLong, 6),
+        0x28 => (Op::Plp, Operand::Implied, 4),
+        0x29 => (Op::And, Operand::Immediate, 2),
+        0x2a => (Op::Rol, Operand::Accumulator, 2),
+        0x2b => (Op::Pld, Operand::Implied, 5),
+        0x2c => (Op::Bit, Operand::Absolute, 4),
+        0x2d => (Op::And, Operand::Absolute, 4),
+        0x2e => (Op::Rol, Operand::Absolute, 6),
+        0x2f => (Op::And, Operand::AbsoluteLong, 5),
+        0x30 => (Op::Bmi, Operand::Relative, 2),
+        0x31 => (Op::And, Operand::DirectIndirectY, 5),
+        0x32 => (Op::And, Operand::DirectIndirect, 5),
+        0x33 => (Op::And, Operand::StackRelativeIndirectY, 7),
+        0x34 => (Op::Bit, Operand::DirectX, 4),
+        0x35 => (Op::And, Operand::DirectX, 4),
+        0x36 => (Op::Rol, Operand::DirectX, 6),
+        0x37 => (Op::And, Operand::DirectIndirectLongY, 6),
+        0x38 => (Op::Sec, Operand::Implied, 2),
+        0x39 => (Op::And, Operand::AbsoluteY, 4),
+        0x3a => (Op::Dec, Operand::Accumulator, 2),
+        0x3b => (Op::Tsc, Operand::Implied, 2),
+        0x3c => (Op::Bit, Operand::AbsoluteX, 4),
+        0x3d => (Op::And, Operand::AbsoluteX, 4),
+        0x3e => (Op::Rol, Operand::AbsoluteX, 7),
+        0x3f => (Op::And, Operand::AbsoluteLongX, 5),
+        0x40 => (Op::Rti, Operand::Implied, 6),
+        0x41 => (Op::Eor, Operand::DirectIndirectX, 6),
+        0x42 => (Op::Wdm, Operand::Immediate8, 2),
+        0x43 => (Op::Eor, Operand::StackRelative, 4),
+        0x44 => (Op::Mvp, Operand::BlockMove, 7),
+        0x45 => (Op::Eor, Operand::Direct, 3),
+        0x46 => (Op::Lsr, Operand::Direct, 5),
+        0x47 => (Op::Eor, Operand::DirectIndirectLong, 6),
+        0x48 => (Op::Pha, Operand::Implied, 3),
+        0x49 => (Op::Eor, Operand::Immediate, 2),
+        0x4a => (Op::Lsr, Operand::Accumulator, 2),
+        0x4b => (Op::Phk, Operand::Implied, 3),
+        0x4c => (Op::Jmp, Operand::AbsoluteProgram, 3),
+        0x4d => (Op::Eor, Operand::Absolute, 4),
+        0x4e => (Op::Lsr, Operand::Absolute, 6),
+        0x4f => (Op::Eor, Operand::AbsoluteLong, 5),
+        0x50 => (Op::Bvc, Operand::Relative, 2),
+        0x51 => (Op::Eor, Operand::DirectIndirectY, 5),
+        0x52 => (Op::Eor, Operand::DirectIndirect, 5),
+        0x53 => (Op::Eor, Operand::StackRelativeIndirectY, 7),
+        0x54 => (Op::Mvn, Operand::BlockMove, 7),
+        0x55 => (Op::Eor, Operand::DirectX, 4),
+        0x56 => (Op::Lsr, Operand::DirectX, 6),
+        0x57 => (Op::Eor, Operand::DirectIndirectLongY, 6),
+        0x58 => (Op::Cli, Operand::Implied, 2),
+        0x59 => (Op::Eor, Operand::AbsoluteY, 4),
+        0x5a => (Op::Phy, Operand::Implied, 3),
+        0x5b => (Op::Tcd, Operand::Implied, 2),
+        0x5c => (Op::Jml, Operand::AbsoluteLong, 4),
+        0x5d => (Op::Eor, Operand::AbsoluteX, 4),
+        0x5e => (Op::Lsr, Operand::AbsoluteX, 7),
+        0x5f => (Op::Eor, Operand::AbsoluteLongX, 5),
+        0x60 => (Op::Rts, Operand::Implied, 6),
+        0x61 => (Op::Adc, Operand::DirectIndirectX, 6),
+        0x62 => (Op::Per, Operand::RelativeLong, 6),
+        0x63 => (Op::Adc, Operand::StackRelative, 4),
+        0x64 => (Op::Stz, Operand::Direct, 3),
+        0x65 => (Op::Adc, Operand::Direct, 3),
+        0x66 => (Op::Ror, Operand::Direct, 5),
+        0x67 => (Op::Adc, Operand::DirectIndirectLong, 6),
+        0x68 => (Op::Pla, Operand::Implied, 4),
+        0x69 => (Op::Adc, Operand::Immediate, 2),
+        0x6a => (Op::Ror, Operand::Accumulator, 2),
+        0x6b => (Op::Rtl, Operand::Implied, 6),
+        0x6c => (Op::Jmp, Operand::Indirect, 5),
+        0x6d => (Op::Adc, Operand::Absolute, 4),
+        0x6e => (Op::Ror, Operand::Absolute, 6),
+        0x6f => (Op::Adc, Operand::AbsoluteLong, 5),
+        0x70 => (Op::Bvs, Operand::Relative, 2),
+        0x71 => (Op::Adc, Operand::DirectIndirectY, 5),
+        0x72 => (Op::Adc, Operand::DirectIndirect, 5),
+        0x73 => (Op::Adc, Operand::StackRelativeIndirectY, 7),
+        0x74 => (Op::Stz, Operand::DirectX, 4),
+        0x75 => (Op::Adc, Operand::DirectX, 4),
+        0x76 => (Op::Ror, Operand::DirectX, 6),
+        0x77 => (Op::Adc, Operand::DirectIndirectLongY, 6),
+        0x78 => (Op::Sei, Operand::Implied, 2),
+        0x79 => (Op::Adc, Operand::AbsoluteY, 4),
+        0x7a => (Op::Ply, Operand::Implied, 4),
+        0x7b => (Op::Tdc, Operand::Implied, 2),
+        0x7c => (Op::Jmp, Operand::AbsoluteIndirectX, 6),
+        0x7d => (Op::Adc, Operand::AbsoluteX, 4),
+        0x7e => (Op::Ror, Operand::AbsoluteX, 7),
+        0x7f => (Op::Adc, Operand::AbsoluteLongX, 5),
+        0x80 => (Op::Bra, Operand::Relative, 3),
+        0x81 => (Op::Sta, Operand::DirectIndirectX, 6),
+        0x82 => (Op::Brl, Operand::RelativeLong, 4),
+        0x83 => (Op::Sta, Operand::StackRelative, 4),
+        0x84 => (Op::Sty, Operand::Direct, 3),
+        0x85 => (Op::Sta, Operand::Direct, 3),
+        0x86 => (Op::Stx, Operand::Direct, 3),
+        0x87 => (Op::Sta, Operand::DirectIndirectLong, 6),
+        0x88 => (Op::Dey, Operand::Implied, 2),
+        0x89 => (Op::Bit, Operand::Immediate, 2),
+        0x8a => (Op::Txa, Operand::Implied, 2),
+        0x8b => (Op::Phb, Operand::Implied, 3),
+        0x8c => (Op::Sty, Operand::Absolute, 4),
+        0x8d => (Op::Sta, Operand::Absolute, 4),
+        0x8e => (Op::Stx, Operand::Absolute, 4),
+        0x8f => (Op::Sta, Operand::AbsoluteLong, 5),
+        0x90 => (Op::Bcc, Operand::Relative, 2),
+        0x91 => (Op::Sta, Operand::DirectIndirectY, 6),
+        0x92 => (Op::Sta, Operand::DirectIndirect, 5),
+        0x93 => (Op::Sta, Operand::StackRelativeIndirectY, 7),
+        0x94 => (Op::Sty, Operand::DirectX, 4),
+        0x95 => (Op::Sta, Operand::DirectX, 4),
+        0x96 => (Op::Stx, Operand::DirectY, 4),
+        0x97 => (Op::Sta, Operand::DirectIndirectLongY, 6),
+        0x98 => (Op::Tya, Operand::Implied, 2),
+        0x99 => (Op::Sta, Operand::AbsoluteY, 5),
+        0x9a => (Op::Txs, Operand::Implied, 2),
+        0x9b => (Op::Txy, Operand::Implied, 2),
+        0x9c => (Op::Stz, Operand::Absolute, 4),
+        0x9d => (Op::Sta, Operand::AbsoluteX, 5),
+        0x9e => (Op::Stz, Operand::AbsoluteX, 5),
+        0x9f => (Op::Sta, Operand::AbsoluteLongX, 5),
+        0xa0 => (Op::Ldy, Operand::Immediate, 2),
+        0xa1 => (Op::Lda, Operand::DirectIndirectX, 6),
+        0xa2 => (Op::Ldx, Operand::Immediate, 2),
+        0xa3 => (Op::Lda, Operand::StackRelative, 4),
+        0xa4 => (Op::Ldy, Operand::Direct, 3),
+        0xa5 => (Op::Lda, Operand::Direct, 3),
+        0xa6 => (Op::Ldx, Operand::Direct, 3),
+        0xa7 => (Op::Lda, Operand::DirectIndirectLong, 6),
+        0xa8 => (Op::Tay, Operand::Implied, 2),
+        0xa9 => (Op::Lda, Operand::Immediate, 2),
+        0xaa => (Op::Tax, Operand::Implied, 2),
+        0xab => (Op::Plb, Operand::Implied, 4),
+        0xac => (Op::Ldy, Operand::Absolute, 4),
+        0xad => (Op::Lda, Operand::Absolute, 4),
+        0xae => (Op::Ldx, Operand::Absolute, 4),
+        0xaf => (Op::Lda, Operand::AbsoluteLong, 5),
+        0xb0 => (Op::Bcs, Operand::Relative, 2),
+        0xb1 => (Op::Lda, Operand::DirectIndirectY, 5),
+        0xb2 => (Op::Lda, Operand::DirectIndirect, 5),
+        0xb3 => (Op::Lda, Operand::StackRelativeIndirectY, 7),
+        0xb4 => (Op::Ldy, Operand::DirectX, 4),
+        0xb5 => (Op::Lda, Operand::DirectX, 4),
+        0xb6 => (Op::Ldx, Operand::DirectY, 4),
+        0xb7 => (Op::Lda, Operand::DirectIndirectLongY, 6),
+        0xb8 => (Op::Clv, Operand::Implied, 2),
+        0xb9 => (Op::Lda, Operand::AbsoluteY, 4),
+        0xba => (Op::Tsx, Operand::Implied, 2),
+        0xbb => (Op::Tyx, Operand::Implied, 2),
+        0xbc => (Op::Ldy, Operand::AbsoluteX, 4),
+        0xbd => (Op::Lda, Operand::AbsoluteX, 4),
+        0xbe => (Op::Ldx, Operand::AbsoluteY, 4),
+        0xbf => (Op::Lda, Operand::AbsoluteLongX, 5),
+        0xc0 => (Op::Cpy, Operand::Immediate, 2),
+        0xc1 => (Op::Cmp, Operand::DirectIndirectX, 6),
+        0xc2 => (Op::Rep, Operand::Immediate8, 3),
+        0xc3 => (Op::Cmp, Operand::StackRelative, 4),
+        0xc4 => (Op::Cpy, Operand::Direct, 3),
+        0xc5 => (Op::Cmp, Operand::Direct, 3),
+        0xc6 => (Op::Dec, Operand::Direct, 5),
+        0xc7 => (Op::Cmp, Operand::DirectIndirectLong, 6),
+        0xc8 => (Op::Iny, Operand::Implied, 2),
+        0xc9 => (Op::Cmp, Operand::Immediate, 2),
+        0xca => (Op::Dex, Operand::Implied, 2),
+        0xcb => (Op::Wai, Operand::Implied, 3),
+        0xcc => (Op::Cpy, Operand::Absolute, 4),
+        0xcd => (Op::Cmp, Operand::Absolute, 4),
+        0xce => (Op::Dec, Operand::Absolute, 6),
+        0xcf => (Op::Cmp, Operand::AbsoluteLong, 5),
+        0xd0 => (Op::Bne, Operand::Relative, 2),
+        0xd1 => (Op::Cmp, Operand::DirectIndirectY, 5),
+        0xd2 => (Op::Cmp, Operand::DirectIndirect, 5),
+        0xd3 => (Op::Cmp, Operand::StackRelativeIndirectY, 7),
+        0xd4 => (Op::Pei, Operand::Direct, 6),
+        0xd5 => (Op::Cmp, Operand::DirectX, 4),
+        0xd6 => (Op::Dec, Operand::DirectX, 6),
+        0xd7 => (Op::Cmp, Operand::DirectIndirectLongY, 6),
+        0xd8 => (Op::Cld, Operand::Implied, 2),
+        0xd9 => (Op::Cmp, Operand::AbsoluteY, 4),
+        0xda => (Op::Phx, Operand::Implied, 3),
+        0xdb => (Op::Stp, Operand::Implied, 3),
+        0xdc => (Op::Jml, Operand::AbsoluteIndirectLong, 6),
+        0xdd => (Op::Cmp, Operand::AbsoluteX, 4),
+        0xde => (Op::Dec, Operand::AbsoluteX, 7),
+        0xdf => (Op::Cmp, Operand::AbsoluteLongX, 5),
+        0xe0 => (Op::Cpx, Operand::Immediate, 2),
+        0xe1 => (Op::Sbc, Operand::DirectIndirectX, 6),
+        0xe2 => (Op::Sep, Operand::Immediate8, 3),
+        0xe3 => (Op::Sbc, Operand::StackRelative, 4),
+        0xe4 => (Op::Cpx, Operand::Direct, 3),
+        0xe5 => (Op::Sbc, Operand::Direct, 3),
+        0xe6 => (Op::Inc, Operand::Direct, 5),
+        0xe7 => (Op::Sbc, Operand::DirectIndirectLong, 6),
+        0xe8 => (Op::Inx, Operand::Implied, 2),
+        0xe9 => (Op::Sbc, Operand::Immediate, 2),
+        0xea => (Op::Nop, Operand::Implied, 2),
+        0xeb => (Op::Xba, Operand::Implied, 3),
+        0xec => (Op::Cpx, Operand::Absolute, 4),
+        0xed => (Op::Sbc, Operand::Absolute, 4),
+        0xee => (Op::Inc, Operand::Absolute, 6),
+        0xef => (Op::Sbc, Operand::AbsoluteLong, 5),
+        0xf0 => (Op::Beq, Operand::Relative, 2),
+        0xf1 => (Op::Sbc, Operand::DirectIndirectY, 5),
+        0xf2 => (Op::Sbc, Operand::DirectIndirect, 5),
+        0xf3 => (Op::Sbc, Operand::StackRelativeIndirectY, 7),
+        0xf4 => (Op::Pea, Operand::Immediate16, 5),
+        0xf5 => (Op::Sbc, Operand::DirectX, 4),
+        0xf6 => (Op::Inc, Operand::DirectX, 6),
+        0xf7 => (Op::Sbc, Operand::DirectIndirectLongY, 6),
+        0xf8 => (Op::Sed, Operand::Implied, 2),
+        0xf9 => (Op::Sbc, Operand::AbsoluteY, 4),
+        0xfa => (Op::Plx, Operand::Implied, 4),
+        0xfb => (Op::Xce, Operand::Implied, 2),
+        0xfc => (Op::Jsr, Operand::AbsoluteIndirectX, 8),
+        0xfd => (Op::Sbc, Operand::AbsoluteX, 4),
+        0xfe => (Op::Inc, Operand::AbsoluteX, 7),
+        0xff => (Op::Sbc, Operand::AbsoluteLongX, 5),
+    }
+}
+
+pub struct Instruction {
+    pub op: Op,
+    pub addr_result: LongAddrResult,
+    pub cycles: u8,
+    branch_taken: bool,
+}
+
+impl fmt::Debug for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {:?}", self.op.name(), self.addr_result)
+    }
+}
+
+fn should_branch(cpu: &Cpu816, op: Op) -> bool {
+    let status = &cpu.reg_status;
+
+    match op {
+        Op::Bcc => !status.carry,
+        Op::Bcs => status.carry,
+        Op::Beq => status.zero,
+        Op::Bne => !status.zero,
+        Op::Bmi => status.negative,
+        Op::Bpl => !status.negative,
+        Op::Bvc => !status.overflow,
+        Op::Bvs => status.overflow,
+        Op::Bra | Op::Brl => true,
+        _ => false,
+    }
+}
+
+pub fn decode(cpu: &mut Cpu816, opcode: u8) -> Instruction {
+    let (op, operand, base_cycles) = lookup(opcode);
+
+    let wide = match (op.acc_sized(), op.index_sized()) {
+        (true, _) => !cpu.flag_m,
+        (_, true) => !cpu.flag_x,
+        _ => false,
+    };
+
+    let addr_result = match operand {
+        Operand::Implied => addr::implicit(),
+        Operand::Accumulator => addr::acc(),
+        Operand::Immediate => addr::imm(cpu, wide),
+        Operand::Immediate8 => addr::imm(cpu, false),
+        Operand::Immediate16 => {
+            let mut addr_result = addr::imm(cpu, true);
+            addr_result.addr_mode = AddrMode::Absolute;
+
+            addr_result
+        }
+        Operand::Direct => addr::direct(cpu),
+        Operand::DirectX => addr::direct_x(cpu),
+        Operand::DirectY => addr::direct_y(cpu),
+        Operand::DirectIndirect => addr::direct_ind(cpu),
+        Operand::DirectIndirectX => addr::direct_ind_x(cpu),
+        Operand::DirectIndirectY => addr::direct_ind_y(cpu),
+        Operand::DirectIndirectLong => addr::direct_ind_long(cpu),
+        Operand::DirectIndirectLongY => addr::direct_ind_long_y(cpu),
+        Operand::Absolute => addr::abs(cpu),
+        Operand::AbsoluteProgram => addr::abs_program(cpu),
+        Operand::AbsoluteX => addr::abs_x(cpu),
+        Operand::AbsoluteY => addr::abs_y(cpu),
+        Operand::AbsoluteLong => addr::abs_long(cpu),
+        Operand::AbsoluteLongX => addr::abs_long_x(cpu),
+        Operand::Indirect => addr::ind(cpu),
+        Operand::AbsoluteIndirectX => addr::abs_ind_x(cpu),
+        Operand::AbsoluteIndirectLong => addr::abs_ind_long(cpu),
+        Operand::StackRelative => addr::stack_rel(cpu),
+        Operand::StackRelativeIndirectY => addr::stack_rel_ind_y(cpu),
+        Operand::Relative => addr::rel(cpu),
+        Operand::RelativeLong => addr::rel_long(cpu),
+        Operand::BlockMove => addr::block_move(cpu),
+    };
+
+    let branch_taken = should_branch(cpu, op);
+    let mut cycles = base_cycles;
+
+    // 16-bit operands cost an extra cycle per byte touched (twice for read-modify-write)
+    if wide && operand != Operand::Accumulator {
+        cycles += match op.is_rmw() {
+            true => 2,
+            false => 1,
+        };
+    }
+
+    match op {
+        Op::Pha | Op::Pla if !cpu.flag_m => cycles += 1,
+        Op::Phx | Op::Phy | Op::Plx | Op::Ply if !cpu.flag_x => cycles += 1,
+        Op::Brk | Op::Cop | Op::Rti if !cpu.emulation => cycles += 1,
+        _ => {}
+    }
+
+    match operand {
+        Operand::Direct | Operand::DirectX | Operand::DirectY | Operand::DirectIndirect | Operand::DirectIndirectX |
+        Operand::DirectIndirectY | Operand::DirectIndirectLong | Operand::DirectIndirectLongY if cpu.reg_dp & 0x00ff != 0 => cycles += 1,
+        _ => {}
+    }
+
+    match operand {
+        Operand::AbsoluteX | Operand::AbsoluteY | Operand::DirectIndirectY if op.is_read() => {
+            if addr_result.crosses_boundary.unwrap_or(false) || !cpu.flag_x {
+                cycles += 1;
+            }
+        }
+        Operand::Relative | Operand::RelativeLong if branch_taken && op != Op::Bra && op != Op::Brl => {
+            cycles += 1;
+
+            if cpu.emulation && addr_result.crosses_boundary.unwrap_or(false) {
+                cycles += 1;
+            }
+        }
+        _ => {}
+    }
+
+    Instruction {
+        op: op,
+        addr_result: addr_result,
+        cycles: cycles,
+        branch_taken: branch_taken,
+    }
+}
+
+fn width_mask(wide: bool) -> (u32, u32) {
+    match wide {
+        true => (0xffff, 0x8000),
+        false => (0x00ff, 0x0080),
+    }
+}
+
+fn index_mask(cpu: &Cpu816, val: u16) -> u16 {
+    match cpu.flag_x {
+        true => val & 0x00ff,
+        false => val,
+    }
+}
+
+fn adc(cpu: &mut Cpu816, val: u16, wide: bool) {
+    let (mask, sign) = width_mask(wide);
+
+    let acc = cpu.acc(wide) as u32;
+    let val = val as u32;
+    let carry = cpu.reg_status.carry as u32;
+
+    let binary = acc + val + carry;
+
+    let (result, carry_out) = match cpu.reg_status.decimal_mode {
+        false => (binary & mask, binary > mask),
+        true => {
+            let digits = match wide {
+                true => 4,
+                false => 2,
+            };
+
+            let mut result = 0;
+            let mut digit_carry = carry;
+
+            for i in 0..digits {
+                let shift = i * 4;
+                let mut digit = ((acc >> shift) & 0xf) + ((val >> shift) & 0xf) + digit_carry;
+
+                if digit > 9 {
+                    digit += 6;
+                }
+
+                digit_carry = (digit > 0xf) as u32;
+                result |= (digit & 0xf) << shift;
+            }
+
+            (result, digit_carry == 1)
+        }
+    };
+
+    cpu.reg_status.overflow = (!(acc ^ val) & (acc ^ binary) & sign) != 0;
+    cpu.reg_status.carry = carry_out;
+
+    cpu.set_acc(result as u16, wide);
+    cpu.set_nz(result as u16, wide);
+}
+
+fn sbc(cpu: &mut Cpu816, val: u16, wide: bool) {
+    let (mask, sign) = width_mask(wide);
+
+    if !cpu.reg_status.decimal_mode {
+        // a - m - !c is the same as a + !m + c
+        return adc(cpu, (!val as u32 & mask) as u16, wide);
+    }
+
+    let acc = cpu.acc(wide) as u32;
+    let val = val as u32;
+    let inverted = !val & mask;
+    let binary = acc + inverted + cpu.reg_status.carry as u32;
+
+    let digits = match wide {
+        true => 4,
+        false => 2,
+    };
+
+    let mut result = 0;
+    let mut borrow = !cpu.reg_status.carry as i32;
+
+    for i in 0..digits {
+        let shift = i * 4;
+        let mut digit = ((acc >> shift) & 0xf) as i32 - ((val >> shift) & 0xf) as i32 - borrow;
+
+        borrow = 0;
+        if digit < 0 {
+            digit += 10;
+            borrow = 1;
+        }
+
+        result |= ((digit as u32) & 0xf) << shift;
+    }
+
+    cpu.reg_status.overflow = (!(acc ^ inverted) & (acc ^ binary) & sign) != 0;
+    cpu.reg_status.carry = borrow == 0;
+
+    cpu.set_acc(result as u16, wide);
+    cpu.set_nz(result as u16, wide);
+}
+
+fn compare(cpu: &mut Cpu816, reg: u16, val: u16, wide: bool) {
+    let (mask, _) = width_mask(wide);
+
+    let reg = reg as u32 & mask;
+    let val = val as u32 & mask;
+
+    cpu.reg_status.carry = reg >= val;
+    cpu.set_nz(reg.wrapping_sub(val) as u16, wide);
+}
+
+fn shift(cpu: &mut Cpu816, op: Op, addr_result: &LongAddrResult, wide: bool) {
+    let (mask, sign) = width_mask(wide);
+
+    let val = addr_result.resolve(cpu, wide) as u32;
+    let carry_in = cpu.reg_status.carry;
+
+    let (result, carry_out) = match op {
+        Op::Asl => ((val << 1) & mask, val & sign != 0),
+        Op::Lsr => (val >> 1, val & 0x1 != 0),
+        Op::Rol => (((val << 1) | carry_in as u32) & mask, val & sign != 0),
+        Op::Ror => ((val >> 1) | if carry_in { sign } else { 0 }, val & 0x1 != 0),
+        _ => panic!("{:?} is not a shift!", op),
+    };
+
+    addr_result.write(cpu, result as u16, wide);
+
+    cpu.reg_status.carry = carry_out;
+    cpu.set_nz(result as u16, wide);
+}
+
+fn block_move(cpu: &mut Cpu816, addr_result: &LongAddrResult, step: u16) {
+    let dest_bank = (addr_result.value >> 8) & 0xff;
+    let src_bank = addr_result.value & 0xff;
+
+    let val = cpu.memory.read_u8((src_bank << 16) | cpu.reg_x as u32);
+    cpu.memory.write_u8((dest_bank << 16) | cpu.reg_y as u32, val);
+
+    cpu.reg_dbr = dest_bank as u8;
+    cpu.reg_x = index_mask(cpu, cpu.reg_x.wrapping_add(step));
+    cpu.reg_y = index_mask(cpu, cpu.reg_y.wrapping_add(step));
+    cpu.reg_acc = cpu.reg_acc.wrapping_sub(1);
+
+    // the instruction re-executes itself until the full 16-bit count underflows
+    if cpu.reg_acc != 0xffff {
+        cpu.reg_pc = cpu.reg_pc.wrapping_sub(3);
+    }
+}
+
+impl Instruction {
+    pub fn run(&self, cpu: &mut Cpu816) {
+        let m16 = !cpu.flag_m;
+        let x16 = !cpu.flag_x;
+        let addr_result = &self.addr_result;
+
+        match self.op {
+            Op::Adc => {
+                let val = addr_result.resolve(cpu, m16);
+                adc(cpu, val, m16);
+            }
+            Op::Sbc => {
+                let val = addr_result.resolve(cpu, m16);
+                sbc(cpu, val, m16);
+            }
+            Op::And | Op::Ora | Op::Eor => {
+                let val = addr_result.resolve(cpu, m16);
+                let acc = cpu.acc(m16);
+
+                let result = match self.op {
+                    Op::And => acc & val,
+                    Op::Ora => acc | val,
+                    _ => acc ^ val,
+                };
+
+                cpu.set_acc(result, m16);
+                cpu.set_nz(result, m16);
+            }
+            Op::Bit => {
+                let (mask, sign) = width_mask(m16);
+                let val = addr_result.resolve(cpu, m16) as u32;
+
+                cpu.reg_status.zero = (cpu.acc(m16) as u32 & val & mask) == 0;
+
+                // bit #imm only ever touches z
+                if addr_result.addr_mode != AddrMode::Immediate {
+                    cpu.reg_status.negative = val & sign != 0;
+                    cpu.reg_status.overflow = val & (sign >> 1) != 0;
+                }
+            }
+            Op::Cmp => {
+                let val = addr_result.resolve(cpu, m16);
+                let acc = cpu.acc(m16);
+                compare(cpu, acc, val, m16);
+            }
+            Op::Cpx => {
+                let val = addr_result.resolve(cpu, x16);
+                let reg = cpu.reg_x;
+                compare(cpu, reg, val, x16);
+            }
+            Op::Cpy => {
+                let val = addr_result.resolve(cpu, x16);
+                let reg = cpu.reg_y;
+                compare(cpu, reg, val, x16);
+            }
+            Op::Lda => {
+                let val = addr_result.resolve(cpu, m16);
+                cpu.set_acc(val, m16);
+                cpu.set_nz(val, m16);
+            }
+            Op::Ldx => {
+                let val = addr_result.resolve(cpu, x16);
+                cpu.reg_x = val;
+                cpu.set_nz(val, x16);
+            }
+            Op::Ldy => {
+                let val = addr_result.resolve(cpu, x16);
+                cpu.reg_y = val;
+                cpu.set_nz(val, x16);
+            }
+            Op::Sta => {
+                let val = cpu.acc(m16);
+                addr_result.write(cpu, val, m16);
+            }
+            Op::Stx => {
+                let val = cpu.reg_x;
+                addr_result.write(cpu, val, x16);
+            }
+            Op::Sty => {
+                let val = cpu.reg_y;
+                addr_result.write(cpu, val, x16);
+            }
+            Op::Stz => addr_result.write(cpu, 0, m16),
+            Op::Asl | Op::Lsr | Op::Rol | Op::Ror => shift(cpu, self.op, addr_result, m16),
+            Op::Inc | Op::Dec => {
+                let (mask, _) = width_mask(m16);
+                let val = addr_result.resolve(cpu, m16);
+
+                let result = match self.op {
+                    Op::Inc => val.wrapping_add(1),
+                    _ => val.wrapping_sub(1),
+                } & mask as u16;
+
+                addr_result.write(cpu, result, m16);
+                cpu.set_nz(result, m16);
+            }
+            Op::Tsb | Op::Trb => {
+                let val = addr_result.resolve(cpu, m16);
+                let acc = cpu.acc(m16);
+
+                cpu.reg_status.zero = (acc & val) == 0;
+
+                let result = match self.op {
+                    Op::Tsb => val | acc,
+                    _ => val & !acc,
+                };
+
+                addr_result.write(cpu, result, m16);
+            }
+            Op::Inx | Op::Dex => {
+                let result = match self.op {
+                    Op::Inx => cpu.reg_x.wrapping_add(1),
+                    _ => cpu.reg_x.wrapping_sub(1),
+                };
+
+                cpu.reg_x = index_mask(cpu, result);
+                let val = cpu.reg_x;
+                cpu.set_nz(val, x16);
+            }
+            Op::Iny | Op::Dey => {
+                let result = match self.op {
+                    Op::Iny => cpu.reg_y.wrapping_add(1),
+                    _ => cpu.reg_y.wrapping_sub(1),
+                };
+
+                cpu.reg_y = index_mask(cpu, result);
+                let val = cpu.reg_y;
+                cpu.set_nz(val, x16);
+            }
+            Op::Bcc | Op::Bcs | Op::Beq | Op::Bne | Op::Bmi | Op::Bpl | Op::Bvc | Op::Bvs | Op::Bra | Op::Brl => {
+                if self.branch_taken {
+                    cpu.reg_pc = addr_result.value as u16;
+                }
+            }
+            Op::Brk => cpu.interrupt(Interrupt::Brk),
+            Op::Cop => cpu.interrupt(Interrupt::Cop),
+            Op::Clc => cpu.reg_status.carry = false,
+            Op::Cld => cpu.reg_status.decimal_mode = false,
+            Op::Cli => cpu.reg_status.irq_disable = false,
+            Op::Clv => cpu.reg_status.overflow = false,
+            Op::Sec => cpu.reg_status.carry = true,
+            Op::Sed => cpu.reg_status.decimal_mode = true,
+            Op::Sei => cpu.reg_status.irq_disable = true,
+            Op::Rep | Op::Sep => {
+                let mask = addr_result.value as u8;
+                let status = match self.op {
+                    Op::Rep => cpu.status_byte() & !mask,
+                    _ => cpu.status_byte() | mask,
+                };
+
+                cpu.set_status_byte(status);
+            }
+            Op::Jmp => cpu.reg_pc = addr_result.value as u16,
+            Op::Jml => {
+                cpu.reg_pbr = (addr_result.value >> 16) as u8;
+                cpu.reg_pc = addr_result.value as u16;
+            }
+            Op::Jsr => {
+                let rts_addr = cpu.reg_pc.wrapping_sub(1);
+                cpu.push_u16(rts_addr);
+
+                cpu.reg_pc = addr_result.value as u16;
+            }
+            Op::Jsl => {
+                let pbr = cpu.reg_pbr;
+                let rtl_addr = cpu.reg_pc.wrapping_sub(1);
+
+                cpu.push_u8(pbr);
+                cpu.push_u16(rtl_addr);
+
+                cpu.reg_pbr = (addr_result.value >> 16) as u8;
+                cpu.reg_pc = addr_result.value as u16;
+            }
+            Op::Rts => cpu.reg_pc = cpu.pop_u16().wrapping_add(1),
+            Op::Rtl => {
+                cpu.reg_pc = cpu.pop_u16().wrapping_add(1);
+                cpu.reg_pbr = cpu.pop_u8();
+            }
+            Op::Rti => {
+                let status = cpu.pop_u8();
+                cpu.set_status_byte(status);
+
+                cpu.reg_pc = cpu.pop_u16();
+
+                if !cpu.emulation {
+                    cpu.reg_pbr = cpu.pop_u8();
+                }
+            }
+            Op::Pha => {
+                let acc = cpu.acc(m16);
+
+                match m16 {
+                    true => cpu.push_u16(acc),
+                    false => cpu.push_u8(acc as u8),
+                }
+            }
+            Op::Phx | Op::Phy => {
+                let val = match self.op {
+                    Op::Phx => cpu.reg_x,
+                    _ => cpu.reg_y,
+                };
+
+                match x16 {
+                    true => cpu.push_u16(val),
+                    false => cpu.push_u8(val as u8),
+                }
+            }
+            Op::Php => {
+                let mut status = cpu.status_byte();
+
+                // like the 6502, php always pushes b set in emulation mode
+                if cpu.emulation {
+                    status |= 0b0001_0000;
+                }
+
+                cpu.push_u8(status);
+            }
+            Op::Phb => {
+                let dbr = cpu.reg_dbr;
+                cpu.push_u8(dbr);
+            }
+            Op::Phk => {
+                let pbr = cpu.reg_pbr;
+                cpu.push_u8(pbr);
+            }
+            Op::Phd => {
+                let dp = cpu.reg_dp;
+                cpu.push_u16(dp);
+            }
+            Op::Pea | Op::Pei | Op::Per => cpu.push_u16(addr_result.value as u16),
+            Op::Pla => {
+                let val = match m16 {
+                    true => cpu.pop_u16(),
+                    false => cpu.pop_u8() as u16,
+                };
+
+                cpu.set_acc(val, m16);
+                cpu.set_nz(val, m16);
+            }
+            Op::Plx | Op::Ply => {
+                let val = match x16 {
+                    true => cpu.pop_u16(),
+                    false => cpu.pop_u8() as u16,
+                };
+
+                match self.op {
+                    Op::Plx => cpu.reg_x = val,
+                    _ => cpu.reg_y = val,
+                }
+
+                cpu.set_nz(val, x16);
+            }
+            Op::Plb => {
+                let val = cpu.pop_u8();
+                cpu.reg_dbr = val;
+                cpu.set_nz(val as u16, false);
+            }
+            Op::Pld => {
+                let val = cpu.pop_u16();
+                cpu.reg_dp = val;
+                cpu.set_nz(val, true);
+            }
+            Op::Plp => {
+                let status = cpu.pop_u8();
+                cpu.set_status_byte(status);
+            }
+            Op::Tax | Op::Tay => {
+                let val = index_mask(cpu, cpu.reg_acc);
+
+                match self.op {
+                    Op::Tax => cpu.reg_x = val,
+                    _ => cpu.reg_y = val,
+                }
+
+                cpu.set_nz(val, x16);
+            }
+            Op::Txa | Op::Tya => {
+                let val = match self.op {
+                    Op::Txa => cpu.reg_x,
+                    _ => cpu.reg_y,
+                };
+
+                cpu.set_acc(val, m16);
+                cpu.set_nz(val, m16);
+            }
+            Op::Txy => {
+                cpu.reg_y = cpu.reg_x;
+                let val = cpu.reg_y;
+                cpu.set_nz(val, x16);
+            }
+            Op::Tyx => {
+                cpu.reg_x = cpu.reg_y;
+                let val = cpu.reg_x;
+                cpu.set_nz(val, x16);
+            }
+            Op::Tcd => {
+                cpu.reg_dp = cpu.reg_acc;
+                let val = cpu.reg_dp;
+                cpu.set_nz(val, true);
+            }
+            Op::Tdc => {
+                cpu.reg_acc = cpu.reg_dp;
+                let val = cpu.reg_acc;
+                cpu.set_nz(val, true);
+            }
+            Op::Tcs => {
+                cpu.reg_sp = match cpu.emulation {
+                    true => 0x0100 | (cpu.reg_acc & 0x00ff),
+                    false => cpu.reg_acc,
+                };
+            }
+            Op::Tsc => {
+                cpu.reg_acc = cpu.reg_sp;
+                let val = cpu.reg_acc;
+                cpu.set_nz(val, true);
+            }
+            Op::Tsx => {
+                cpu.reg_x = index_mask(cpu, cpu.reg_sp);
+                let val = cpu.reg_x;
+                cpu.set_nz(val, x16);
+            }
+            Op::Txs => {
+                cpu.reg_sp = match cpu.emulation {
+                    true => 0x0100 | (cpu.reg_x & 0x00ff),
+                    false => cpu.reg_x,
+                };
+            }
+            Op::Xba => {
+                cpu.reg_acc = cpu.reg_acc.rotate_left(8);
+                let val = cpu.reg_acc;
+                cpu.set_nz(val, false);
+            }
+            Op::Xce => {
+                let carry = cpu.reg_status.carry;
+                cpu.reg_status.carry = cpu.emulation;
+                cpu.set_emulation(carry);
+            }
+            Op::Mvn => block_move(cpu, addr_result, 1),
+            Op::Mvp => block_move(cpu, addr_result, 0xffff),
+            Op::Wai => cpu.waiting = true,
+            Op::Stp => cpu.stopped = true,
+            Op::Nop | Op::Wdm => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::lookup;
+
+    #[test]
+    fn every_opcode_is_defined() {
+        for opcode in 0..0x100 {
+            lookup(opcode as u8);
+        }
+    }
+}
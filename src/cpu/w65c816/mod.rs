@@ -0,0 +1,557 @@
+pub mod addr;
+pub mod instr;
+
+use cpu::{Processor, ProcessorStatusRegister};
+use cpu::mem::{Bus, LongMemoryMap};
+use util;
+
+const EMU_COP_VECTOR_ADDR: u32 = 0xfff4;
+const EMU_NMI_VECTOR_ADDR: u32 = 0xfffa;
+const EMU_RESET_VECTOR_ADDR: u32 = 0xfffc;
+const EMU_IRQ_BRK_VECTOR_ADDR: u32 = 0xfffe;
+
+const NATIVE_COP_VECTOR_ADDR: u32 = 0xffe4;
+const NATIVE_BRK_VECTOR_ADDR: u32 = 0xffe6;
+const NATIVE_NMI_VECTOR_ADDR: u32 = 0xffea;
+const NATIVE_IRQ_VECTOR_ADDR: u32 = 0xffee;
+
+pub enum Interrupt {
+    Brk,
+    Cop,
+    Irq,
+    Nmi,
+}
+
+pub struct Cpu816 {
+    // the full 16-bit accumulator (B:A); only the low byte is used while m is set
+    pub reg_acc: u16,
+    pub reg_x: u16,
+    pub reg_y: u16,
+    pub reg_pc: u16,
+    pub reg_sp: u16,
+    pub reg_dp: u16,
+    pub reg_pbr: u8,
+    pub reg_dbr: u8,
+
+    pub reg_status: ProcessorStatusRegister,
+    pub emulation: bool,
+    pub flag_m: bool,
+    pub flag_x: bool,
+
+    pub memory: Box<Bus>,
+
+    pub pending_cycles: Option<u8>,
+    pub waiting: bool,
+    pub stopped: bool,
+
+    // print every instruction as it's executed
+    pub trace: bool,
+}
+
+impl Default for Cpu816 {
+    fn default() -> Self {
+        Cpu816::with_bus(Box::new(LongMemoryMap::default()))
+    }
+}
+
+impl Cpu816 {
+    pub fn new() -> Self {
+        Cpu816::default()
+    }
+
+    pub fn with_bus(bus: Box<Bus>) -> Self {
+        let mut reg_status = ProcessorStatusRegister::default();
+        reg_status.irq_disable = true;
+
+        Cpu816 {
+            reg_acc: 0,
+            reg_x: 0,
+            reg_y: 0,
+            reg_pc: 0,
+            reg_sp: 0x01fd,
+            reg_dp: 0,
+            reg_pbr: 0,
+            reg_dbr: 0,
+
+            reg_status: reg_status,
+            emulation: true,
+            flag_m: true,
+            flag_x: true,
+
+            memory: bus,
+
+            pending_cycles: None,
+            waiting: false,
+            stopped: false,
+
+            trace: true,
+        }
+    }
+
+    pub fn load_program(&mut self, start_addr: u32, bytes: &[u8]) {
+        for (i, byte) in bytes.iter().enumerate() {
+            self.memory.write_u8(start_addr.wrapping_add(i as u32), *byte);
+        }
+
+        // the cpu always comes out of reset in emulation mode in bank 0
+        self.write_u16_bank0(EMU_RESET_VECTOR_ADDR, start_addr as u16);
+
+        // point brk at the end of bank 0 in both modes so programs will exit on brk by default
+        self.write_u16_bank0(EMU_IRQ_BRK_VECTOR_ADDR, 0xffff);
+        self.write_u16_bank0(NATIVE_BRK_VECTOR_ADDR, 0xffff);
+    }
+
+    pub fn reset(&mut self) {
+        self.emulation = true;
+        self.flag_m = true;
+        self.flag_x = true;
+        self.reg_dp = 0;
+        self.reg_pbr = 0;
+        self.reg_dbr = 0;
+        self.reg_sp = 0x0100 | (self.reg_sp & 0x00ff);
+        self.reg_status.irq_disable = true;
+        self.reg_status.decimal_mode = false;
+        self.waiting = false;
+        self.stopped = false;
+
+        self.update_widths();
+
+        self.reg_pc = self.read_u16_bank0(EMU_RESET_VECTOR_ADDR);
+    }
+
+    pub fn pc_addr(&self) -> u32 {
+        ((self.reg_pbr as u32) << 16) | self.reg_pc as u32
+    }
+
+    pub fn read_u8(&mut self) -> u8 {
+        let val = self.memory.read_u8(self.pc_addr());
+
+        // the program counter wraps within the current bank
+        self.reg_pc = self.reg_pc.wrapping_add(1);
+
+        val
+    }
+
+    pub fn read_u16(&mut self) -> u16 {
+        let lo = self.read_u8() as u16;
+        let hi = self.read_u8() as u16;
+
+        (hi << 8) | lo
+    }
+
+    pub fn read_u24(&mut self) -> u32 {
+        let addr = self.read_u16() as u32;
+        let bank = self.read_u8() as u32;
+
+        (bank << 16) | addr
+    }
+
+    pub fn read_data(&mut self, addr: u32, wide: bool) -> u16 {
+        let lo = self.memory.read_u8(addr) as u16;
+
+        match wide {
+            true => lo | ((self.memory.read_u8((addr + 1) & 0xff_ffff) as u16) << 8),
+            false => lo,
+        }
+    }
+
+    pub fn write_data(&mut self, addr: u32, val: u16, wide: bool) {
+        self.memory.write_u8(addr, val as u8);
+
+        if wide {
+            self.memory.write_u8((addr + 1) & 0xff_ffff, (val >> 8) as u8);
+        }
+    }
+
+    fn read_u16_bank0(&mut self, addr: u32) -> u16 {
+        self.read_data(addr, true)
+    }
+
+    fn write_u16_bank0(&mut self, addr: u32, val: u16) {
+        self.write_data(addr, val, true)
+    }
+
+    pub fn acc(&self, wide: bool) -> u16 {
+        match wide {
+            true => self.reg_acc,
+            false => self.reg_acc & 0x00ff,
+        }
+    }
+
+    // 8-bit writes leave the hidden b accumulator alone
+    pub fn set_acc(&mut self, val: u16, wide: bool) {
+        self.reg_acc = match wide {
+            true => val,
+            false => (self.reg_acc & 0xff00) | (val & 0x00ff),
+        };
+    }
+
+    pub fn set_nz(&mut self, val: u16, wide: bool) {
+        let (val, sign) = match wide {
+            true => (val, 0x8000),
+            false => (val & 0x00ff, 0x0080),
+        };
+
+        self.reg_status.zero = val == 0;
+        self.reg_status.negative = val & sign != 0;
+    }
+
+    pub fn status_byte(&self) -> u8 {
+        let val: u8 = self.reg_status.clone().into();
+
+        match self.emulation {
+            true => val,
+            false => util::set_bit(util::set_bit(val, 5, self.flag_m), 4, self.flag_x),
+        }
+    }
+
+    pub fn set_status_byte(&mut self, val: u8) {
+        self.reg_status = ProcessorStatusRegister::from(val);
+
+        if !self.emulation {
+            self.reg_status.brk = false;
+            self.flag_m = util::test_bit_set(val, 5);
+            self.flag_x = util::test_bit_set(val, 4);
+        }
+
+        self.update_widths();
+    }
+
+    pub fn set_emulation(&mut self, emulation: bool) {
+        self.emulation = emulation;
+
+        if emulation {
+            self.reg_sp = 0x0100 | (self.reg_sp & 0x00ff);
+        }
+
+        self.update_widths();
+    }
+
+    // keeps the register file consistent with the e, m and x flags
+    pub fn update_widths(&mut self) {
+        if self.emulation {
+            self.flag_m = true;
+            self.flag_x = true;
+        }
+
+        if self.flag_x {
+            self.reg_x &= 0x00ff;
+            self.reg_y &= 0x00ff;
+        }
+    }
+
+    fn fix_sp(&mut self) {
+        if self.emulation {
+            self.reg_sp = 0x0100 | (self.reg_sp & 0x00ff);
+        }
+    }
+
+    pub fn push_u8(&mut self, val: u8) {
+        let addr = self.reg_sp as u32;
+        self.memory.write_u8(addr, val);
+
+        self.reg_sp = self.reg_sp.wrapping_sub(1);
+        self.fix_sp();
+    }
+
+    pub fn pop_u8(&mut self) -> u8 {
+        self.reg_sp = self.reg_sp.wrapping_add(1);
+        self.fix_sp();
+
+        let addr = self.reg_sp as u32;
+        self.memory.read_u8(addr)
+    }
+
+    pub fn push_u16(&mut self, val: u16) {
+        self.push_u8((val >> 8) as u8);
+        self.push_u8(val as u8);
+    }
+
+    pub fn pop_u16(&mut self) -> u16 {
+        let lo = self.pop_u8() as u16;
+        let hi = self.pop_u8() as u16;
+
+        (hi << 8) | lo
+    }
+
+    pub fn interrupt(&mut self, interrupt: Interrupt) {
+        let is_software = match interrupt {
+            Interrupt::Brk | Interrupt::Cop => true,
+            _ => false,
+        };
+
+        if !self.emulation {
+            let pbr = self.reg_pbr;
+            self.push_u8(pbr);
+        }
+
+        let pc = self.reg_pc;
+        self.push_u16(pc);
+
+        let mut status = self.status_byte();
+        if self.emulation {
+            status = util::set_bit(status, 4, is_software);
+        }
+        self.push_u8(status);
+
+        let vector = match (interrupt, self.emulation) {
+            (Interrupt::Cop, true) => EMU_COP_VECTOR_ADDR,
+            (Interrupt::Nmi, true) => EMU_NMI_VECTOR_ADDR,
+            (_, true) => EMU_IRQ_BRK_VECTOR_ADDR,
+            (Interrupt::Brk, false) => NATIVE_BRK_VECTOR_ADDR,
+            (Interrupt::Cop, false) => NATIVE_COP_VECTOR_ADDR,
+            (Interrupt::Nmi, false) => NATIVE_NMI_VECTOR_ADDR,
+            (Interrupt::Irq, false) => NATIVE_IRQ_VECTOR_ADDR,
+        };
+
+        self.reg_status.irq_disable = true;
+        self.reg_status.decimal_mode = false;
+        self.reg_pbr = 0;
+        self.reg_pc = self.read_u16_bank0(vector);
+        self.waiting = false;
+    }
+
+    pub fn irq(&mut self) {
+        if self.reg_status.irq_disable {
+            // wai still wakes up on a masked irq, it just doesn't take the vector
+            self.waiting = false;
+            return;
+        }
+
+        self.interrupt(Interrupt::Irq);
+    }
+
+    pub fn nmi(&mut self) {
+        self.interrupt(Interrupt::Nmi);
+    }
+
+    pub fn run(&mut self) {
+        println!("starting execution...");
+
+        self.reset();
+
+        'main: loop {
+            match self.step() {
+                true => {}
+                false => break 'main,
+            }
+        }
+
+        println!("finished execution!");
+    }
+
+    // false once the cpu can't carry on by itself: it's hit stp or the end of bank 0, or it's waiting on an
+    // interrupt (which `irq` or `nmi` can deliver before stepping again)
+    pub fn step(&mut self) -> bool {
+        let should_delay = match self.pending_cycles {
+            None => false,
+            Some(cycles) => {
+                self.pending_cycles = match cycles - 1 {
+                    0 => None,
+                    cycles => Some(cycles),
+                };
+
+                true
+            }
+        };
+
+        if should_delay {
+            return true;
+        }
+
+        if self.waiting || self.stopped || (self.reg_pbr == 0 && self.reg_pc == 0xffff) {
+            return false;
+        }
+
+        let start_addr = self.pc_addr();
+        let opcode = self.read_u8();
+
+        let instr = instr::decode(self, opcode);
+
+        if self.trace {
+            let instr_str = format!("{0:<6x}\t{1:<2x}\t{2:?}", start_addr, opcode, instr);
+            println!("{0:<35}\tA:{1:04x}, X:{2:04x}, Y:{3:04x}, P:{4:02x}, SP:{5:04x}, D:{6:04x}, DB:{7:02x}, E:{8}",
+                     instr_str,
+                     self.reg_acc,
+                     self.reg_x,
+                     self.reg_y,
+                     self.status_byte(),
+                     self.reg_sp,
+                     self.reg_dp,
+                     self.reg_dbr,
+                     self.emulation as u8);
+        }
+
+        instr.run(self);
+
+        let cycles = self.pending_cycles.unwrap_or(0) + instr.cycles;
+        self.pending_cycles = Some(cycles);
+
+        true
+    }
+}
+
+impl Processor for Cpu816 {
+    fn reset(&mut self) {
+        Cpu816::reset(self)
+    }
+
+    fn step(&mut self) -> bool {
+        Cpu816::step(self)
+    }
+
+    fn run(&mut self) {
+        Cpu816::run(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Cpu816;
+
+    fn run_program(program: &[u8]) -> Cpu816 {
+        let mut cpu = Cpu816::new();
+        cpu.trace = false;
+        cpu.load_program(0x8000, program);
+
+        cpu.run();
+
+        cpu
+    }
+
+    #[test]
+    fn native_mode_switch() {
+        // clc / xce / rep #$30 / lda #$1234 / ldx #$beef
+        let cpu = run_program(&[0x18, 0xfb, 0xc2, 0x30, 0xa9, 0x34, 0x12, 0xa2, 0xef, 0xbe]);
+
+        assert!(!cpu.emulation);
+        assert!(!cpu.flag_m);
+        assert!(!cpu.flag_x);
+        assert_eq!(cpu.reg_acc, 0x1234);
+        assert_eq!(cpu.reg_x, 0xbeef);
+    }
+
+    #[test]
+    fn wai_stops_run_until_an_interrupt() {
+        // wai / nop
+        let mut cpu = run_program(&[0xcb, 0xea]);
+
+        assert!(cpu.waiting);
+        assert_eq!(cpu.reg_pc, 0x8001);
+        assert!(!cpu.step());
+
+        // a masked irq still wakes it
+        cpu.irq();
+        while cpu.step() {}
+
+        assert!(!cpu.waiting);
+        assert_eq!(cpu.reg_pc, 0xffff);
+    }
+
+    #[test]
+    fn emulation_mode_forces_8bit() {
+        // clc / xce / rep #$30 / ldx #$1234 / sec / xce
+        let cpu = run_program(&[0x18, 0xfb, 0xc2, 0x30, 0xa2, 0x34, 0x12, 0x38, 0xfb]);
+
+        assert!(cpu.emulation);
+        assert!(cpu.flag_m);
+        assert!(cpu.flag_x);
+        assert_eq!(cpu.reg_x, 0x34);
+        assert_eq!(cpu.reg_sp & 0xff00, 0x0100);
+    }
+
+    #[test]
+    fn sep_clears_index_high_bytes() {
+        // clc / xce / rep #$10 / ldy #$abcd / sep #$10
+        let cpu = run_program(&[0x18, 0xfb, 0xc2, 0x10, 0xa0, 0xcd, 0xab, 0xe2, 0x10]);
+
+        assert!(cpu.flag_x);
+        assert_eq!(cpu.reg_y, 0xcd);
+    }
+
+    #[test]
+    fn eight_bit_acc_preserves_b() {
+        // clc / xce / rep #$20 / lda #$1234 / sep #$20 / lda #$ff / xba
+        let cpu = run_program(&[0x18, 0xfb, 0xc2, 0x20, 0xa9, 0x34, 0x12, 0xe2, 0x20, 0xa9, 0xff, 0xeb]);
+
+        assert_eq!(cpu.reg_acc, 0xff12);
+        assert!(!cpu.reg_status.negative);
+    }
+
+    #[test]
+    fn long_addressing() {
+        // clc / xce / rep #$20 / lda #$beef / sta $7e1234 / lda #$0000 / lda $7e1234
+        let cpu = run_program(&[0x18, 0xfb, 0xc2, 0x20, 0xa9, 0xef, 0xbe, 0x8f, 0x34, 0x12, 0x7e, 0xa9, 0x00, 0x00, 0xaf, 0x34, 0x12, 0x7e]);
+
+        assert_eq!(cpu.reg_acc, 0xbeef);
+    }
+
+    #[test]
+    fn direct_page_and_data_bank() {
+        // lda #$7e / pha / plb / pea $0300 / pld / lda #$42 / sta $10 / sta $2000
+        let mut cpu = run_program(&[0xa9, 0x7e, 0x48, 0xab, 0xf4, 0x00, 0x03, 0x2b, 0xa9, 0x42, 0x85, 0x10, 0x8d, 0x00, 0x20]);
+
+        assert_eq!(cpu.reg_dbr, 0x7e);
+        assert_eq!(cpu.reg_dp, 0x0300);
+        assert_eq!(cpu.memory.read_u8(0x0310), 0x42);
+        assert_eq!(cpu.memory.read_u8(0x7e_2000), 0x42);
+    }
+
+    #[test]
+    fn block_move_mvn() {
+        let mut cpu = Cpu816::new();
+        cpu.load_program(0x8000,
+                         // clc / xce / rep #$30 / lda #$0003 / ldx #$1000 / ldy #$2000 / mvn $7f,$7e
+                         &[0x18, 0xfb, 0xc2, 0x30, 0xa9, 0x03, 0x00, 0xa2, 0x00, 0x10, 0xa0, 0x00, 0x20, 0x54, 0x7f, 0x7e]);
+
+        for (i, byte) in [0x11, 0x22, 0x33, 0x44].iter().enumerate() {
+            cpu.memory.write_u8(0x7e_1000 + i as u32, *byte);
+        }
+
+        cpu.run();
+
+        for (i, byte) in [0x11, 0x22, 0x33, 0x44].iter().enumerate() {
+            assert_eq!(cpu.memory.read_u8(0x7f_2000 + i as u32), *byte);
+        }
+
+        assert_eq!(cpu.reg_acc, 0xffff);
+        assert_eq!(cpu.reg_x, 0x1004);
+        assert_eq!(cpu.reg_y, 0x2004);
+        assert_eq!(cpu.reg_dbr, 0x7f);
+    }
+
+    #[test]
+    fn stack_relative() {
+        // clc / xce / rep #$20 / pea $abcd / lda $01,s
+        let cpu = run_program(&[0x18, 0xfb, 0xc2, 0x20, 0xf4, 0xcd, 0xab, 0xa3, 0x01]);
+
+        assert_eq!(cpu.reg_acc, 0xabcd);
+    }
+
+    #[test]
+    fn jsl_rtl() {
+        let mut cpu = Cpu816::new();
+
+        // jsl $018000 / lda #$02
+        cpu.load_program(0x8000, &[0x22, 0x00, 0x80, 0x01, 0xa9, 0x02]);
+
+        // lda #$01 / sta $10 / rtl
+        for (i, byte) in [0xa9, 0x01, 0x85, 0x10, 0x6b].iter().enumerate() {
+            cpu.memory.write_u8(0x01_8000 + i as u32, *byte);
+        }
+
+        cpu.run();
+
+        assert_eq!(cpu.memory.read_u8(0x10), 0x01);
+        assert_eq!(cpu.reg_acc & 0xff, 0x02);
+    }
+
+    #[test]
+    fn decimal_adc_16bit() {
+        // clc / xce / rep #$20 / sed / clc / lda #$1999 / adc #$0001
+        let cpu = run_program(&[0x18, 0xfb, 0xc2, 0x20, 0xf8, 0x18, 0xa9, 0x99, 0x19, 0x69, 0x01, 0x00]);
+
+        assert_eq!(cpu.reg_acc, 0x2000);
+        assert!(!cpu.reg_status.carry);
+    }
+}
@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+
+use cpu::Cpu;
+use cpu::breakpoint::{BreakpointId, StopReason};
+
+use super::expr::{Expr, ExprError, Symbols};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Point {
+    Breakpoint(BreakpointId),
+    Watchpoint(BreakpointId),
+}
+
+impl Point {
+    pub fn from_stop(reason: &StopReason) -> Option<Point> {
+        match *reason {
+            StopReason::Halted => None,
+            StopReason::Breakpoint { id, .. } => Some(Point::Breakpoint(id)),
+            StopReason::Watchpoint { id, .. } => Some(Point::Watchpoint(id)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Hex,
+    Decimal,
+    Binary,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+    Text(String),
+    Value(Expr, Format),
+}
+
+// a logpoint message: text with `{expr}` holes, printed as $hex by default or with `{expr:d}` / `{expr:b}`.
+// `{{` and `}}` are literal braces
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogTemplate {
+    parts: Vec<Part>,
+}
+
+impl LogTemplate {
+    pub fn parse(input: &str) -> Result<LogTemplate, ExprError> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut rest = input;
+
+        while let Some(c) = rest.chars().next() {
+            if rest.starts_with("{{") || rest.starts_with("}}") {
+                text.push(c);
+                rest = &rest[2..];
+                continue;
+            }
+
+            if c != '{' {
+                text.push(c);
+                rest = &rest[c.len_utf8()..];
+                continue;
+            }
+
+            let offset = input.len() - rest.len();
+            let end = match rest.find('}') {
+                Some(end) => end,
+                None => {
+                    return Err(ExprError::Parse {
+                        pos: offset,
+                        msg: String::from("unterminated '{'"),
+                    })
+                }
+            };
+
+            let hole = &rest[1..end];
+            let (source, format) = match hole.rfind(':') {
+                Some(i) if &hole[i + 1..] == "d" => (&hole[..i], Format::Decimal),
+                Some(i) if &hole[i + 1..] == "b" => (&hole[..i], Format::Binary),
+                Some(i) if &hole[i + 1..] == "x" => (&hole[..i], Format::Hex),
+                _ => (hole, Format::Hex),
+            };
+
+            let expr = Expr::parse(source).map_err(|err| match err {
+                    ExprError::Parse { pos, msg } => {
+                        ExprError::Parse {
+                            pos: offset + 1 + pos,
+                            msg: msg,
+                        }
+                    }
+                    err => err,
+                })?;
+
+            if !text.is_empty() {
+                parts.push(Part::Text(text.clone()));
+                text.clear();
+            }
+
+            parts.push(Part::Value(expr, format));
+            rest = &rest[end + 1..];
+        }
+
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+
+        Ok(LogTemplate { parts: parts })
+    }
+
+    pub fn render(&self, cpu: &Cpu, symbols: &Symbols) -> Result<String, ExprError> {
+        let mut out = String::new();
+
+        for part in &self.parts {
+            match *part {
+                Part::Text(ref text) => out.push_str(text),
+                Part::Value(ref expr, format) => {
+                    let val = expr.eval(cpu, symbols)?;
+
+                    out.push_str(&match format {
+                        Format::Hex => format!("${:x}", val),
+                        Format::Decimal => format!("{}", val),
+                        Format::Binary => format!("%{:b}", val),
+                    });
+                }
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Hook {
+    condition: Option<Expr>,
+    log: Option<LogTemplate>,
+}
+
+// conditions and logpoints layered over the core's breakpoints and watchpoints. the core stops on every hit;
+// this decides which of those the user actually gets to see
+#[derive(Clone, Debug, Default)]
+pub struct Conditions {
+    hooks: HashMap<Point, Hook>,
+
+    // logpoint output, oldest first
+    pub log: Vec<String>,
+}
+
+impl Conditions {
+    pub fn set_condition(&mut self, point: Point, condition: Option<Expr>) {
+        self.hooks.entry(point).or_insert_with(Hook::default).condition = condition;
+    }
+
+    // a point with a log message prints it and keeps going instead of stopping
+    pub fn set_log(&mut self, point: Point, log: Option<LogTemplate>) {
+        self.hooks.entry(point).or_insert_with(Hook::default).log = log;
+    }
+
+    pub fn remove(&mut self, point: Point) {
+        self.hooks.remove(&point);
+    }
+
+    pub fn should_stop(&mut self, cpu: &Cpu, symbols: &Symbols, reason: &StopReason) -> Result<bool, ExprError> {
+        let hook = match Point::from_stop(reason).and_then(|point| self.hooks.get(&point)) {
+            Some(hook) => hook,
+            None => return Ok(true),
+        };
+
+        if let Some(ref condition) = hook.condition {
+            if !condition.is_true(cpu, symbols)? {
+                return Ok(false);
+            }
+        }
+
+        match hook.log {
+            Some(ref log) => {
+                self.log.push(log.render(cpu, symbols)?);
+                Ok(false)
+            }
+            None => Ok(true),
+        }
+    }
+
+    // like `Cpu::resume`, but skips over hits whose condition doesn't hold and logpoints
+    pub fn resume(&mut self, cpu: &mut Cpu, symbols: &Symbols) -> Result<StopReason, ExprError> {
+        loop {
+            let reason = cpu.resume();
+
+            if self.should_stop(cpu, symbols, &reason)? {
+                return Ok(reason);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Conditions, LogTemplate, Point};
+    use cpu::Cpu;
+    use cpu::breakpoint::StopReason;
+    use debug::expr::{Expr, Symbols};
+
+    fn program() -> Cpu {
+        let mut cpu = Cpu::new();
+        cpu.trace = false;
+
+        cpu.load_program(0x6000,
+                         &[// ldx #$00
+                           0xa2, 0x00,
+                           // inx
+                           0xe8,
+                           // stx $20
+                           0x86, 0x20,
+                           // cpx #$05
+                           0xe0, 0x05,
+                           // bne $6002
+                           0xd0, 0xf9]);
+        cpu.reset();
+
+        cpu
+    }
+
+    #[test]
+    fn conditional_breakpoint() {
+        let mut cpu = program();
+        let mut conditions = Conditions::default();
+        let symbols = Symbols::new();
+
+        let id = cpu.breakpoints.add(0x6003, 0x6003);
+        conditions.set_condition(Point::Breakpoint(id), Some(Expr::parse("X == 3 && [$20] == 2").unwrap()));
+
+        assert_eq!(conditions.resume(&mut cpu, &symbols).unwrap(), StopReason::Breakpoint { id: id, pc: 0x6003 });
+        assert_eq!(cpu.reg_x, 3);
+
+        assert_eq!(conditions.resume(&mut cpu, &symbols).unwrap(), StopReason::Halted);
+    }
+
+    #[test]
+    fn logpoint() {
+        let mut cpu = program();
+        let mut conditions = Conditions::default();
+        let symbols = Symbols::new();
+
+        let id = cpu.breakpoints.add(0x6005, 0x6005);
+        conditions.set_log(Point::Breakpoint(id), Some(LogTemplate::parse("x={X:d} {{$20}}={[$20]} c={P.C:b}").unwrap()));
+
+        assert_eq!(conditions.resume(&mut cpu, &symbols).unwrap(), StopReason::Halted);
+        assert_eq!(conditions.log.len(), 5);
+        assert_eq!(conditions.log[2], "x=3 {$20}=$3 c=%0");
+    }
+
+    #[test]
+    fn bad_template() {
+        assert!(LogTemplate::parse("x={X").is_err());
+        assert!(LogTemplate::parse("x={X +}").is_err());
+    }
+}
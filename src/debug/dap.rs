@@ -0,0 +1,954 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+use asm;
+use cpu::{Cpu, ProcessorStatusRegister};
+use cpu::breakpoint::{BreakpointId, StopReason};
+
+use super::condition::{LogTemplate, Point};
+use super::disasm;
+use super::expr::{Expr, ExprError};
+use super::json::Json;
+use super::session::{self, Session};
+use super::sourcemap::SourceMap;
+
+const DEFAULT_LOAD_ADDR: u16 = 0x6000;
+const THREAD_ID: i64 = 1;
+const STACK_PAGE: u16 = 0x0100;
+
+const REGISTERS_REF: i64 = 1;
+const FLAGS_REF: i64 = 2;
+const STACK_REF: i64 = 3;
+
+const FLAGS: [&'static str; 7] = ["N", "V", "B", "D", "I", "Z", "C"];
+
+const BASE64: &'static [u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// a debug adapter protocol server, so editors can launch programs and drive them through a session. source
+// lines come from the source map `asm --dbg` writes
+pub struct DebugAdapter {
+    session: Session,
+    source_map: SourceMap,
+
+    // where the program started, which stands in for the function of the outermost frame
+    entry: u16,
+    stop_on_entry: bool,
+
+    // setBreakpoints replaces everything in a file at once, so remember what each file asked for
+    source_breakpoints: HashMap<String, Vec<BreakpointId>>,
+    instruction_breakpoints: Vec<BreakpointId>,
+
+    seq: i64,
+    outgoing: Vec<Json>,
+    done: bool,
+}
+
+impl DebugAdapter {
+    pub fn new() -> Self {
+        DebugAdapter {
+            session: Session::new(Cpu::new()),
+            source_map: SourceMap::new(),
+            entry: 0,
+            stop_on_entry: false,
+            source_breakpoints: HashMap::new(),
+            instruction_breakpoints: Vec::new(),
+            seq: 0,
+            outgoing: Vec::new(),
+            done: false,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    // handles one request, returning the response and whatever events it caused, in the order they're sent
+    pub fn handle(&mut self, request: &Json) -> Vec<Json> {
+        let command = request.get("command").as_str().unwrap_or("");
+        let args = request.get("arguments");
+
+        let result = match command {
+            "initialize" => Ok(self.capabilities()),
+            "launch" => self.launch(args),
+            "setBreakpoints" => self.set_breakpoints(args),
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(args),
+            "setExceptionBreakpoints" => Ok(Json::object(vec![("breakpoints", Json::Array(Vec::new()))])),
+            "configurationDone" | "continue" | "next" | "stepIn" | "stepOut" => Ok(Json::object(vec![])),
+            "threads" => {
+                let thread = Json::object(vec![("id", Json::from(THREAD_ID)), ("name", Json::from("6502"))]);
+                Ok(Json::object(vec![("threads", Json::Array(vec![thread]))]))
+            }
+            "stackTrace" => Ok(self.stack_trace()),
+            "scopes" => Ok(self.scopes()),
+            "variables" => self.variables(args),
+            "setVariable" => self.set_variable(args),
+            "evaluate" => self.evaluate(args),
+            "readMemory" => self.read_memory(args),
+            "writeMemory" => self.write_memory(args),
+            "disassemble" => self.disassemble(args),
+            "disconnect" | "terminate" => {
+                self.done = true;
+                Ok(Json::object(vec![]))
+            }
+            _ => Err(format!("unsupported request '{}'", command)),
+        };
+
+        self.respond(request, result);
+
+        // anything that runs the program answers first and reports where it stopped afterwards
+        match command {
+            "initialize" => self.event("initialized", Json::object(vec![])),
+            "configurationDone" if self.stop_on_entry => self.stopped("entry", None),
+            "configurationDone" | "continue" => self.resume(|adapter| adapter.session.continue_()),
+            "next" if DebugAdapter::by_instruction(args) => self.resume(|adapter| adapter.session.step_over()),
+            "next" => self.resume(|adapter| adapter.step_line(true)),
+            "stepIn" if DebugAdapter::by_instruction(args) => self.resume(|adapter| adapter.session.step()),
+            "stepIn" => self.resume(|adapter| adapter.step_line(false)),
+            "stepOut" => self.resume(|adapter| adapter.session.step_out()),
+            "terminate" => self.event("terminated", Json::object(vec![])),
+            _ => {}
+        }
+
+        self.outgoing.drain(..).collect()
+    }
+
+    fn respond(&mut self, request: &Json, result: Result<Json, String>) {
+        self.seq += 1;
+
+        let mut fields = vec![("seq", Json::from(self.seq)),
+                              ("type", Json::from("response")),
+                              ("request_seq", request.get("seq").clone()),
+                              ("command", request.get("command").clone()),
+                              ("success", Json::from(result.is_ok()))];
+
+        match result {
+            Ok(body) => fields.push(("body", body)),
+            Err(msg) => fields.push(("message", Json::from(msg))),
+        }
+
+        self.outgoing.push(Json::object(fields));
+    }
+
+    fn event(&mut self, event: &str, body: Json) {
+        self.seq += 1;
+
+        self.outgoing.push(Json::object(vec![("seq", Json::from(self.seq)),
+                                             ("type", Json::from("event")),
+                                             ("event", Json::from(event)),
+                                             ("body", body)]));
+    }
+
+    fn stopped(&mut self, reason: &str, breakpoint: Option<BreakpointId>) {
+        let mut body = vec![("reason", Json::from(reason)),
+                            ("threadId", Json::from(THREAD_ID)),
+                            ("allThreadsStopped", Json::from(true))];
+
+        if let Some(id) = breakpoint {
+            body.push(("hitBreakpointIds", Json::Array(vec![Json::from(id as i64)])));
+        }
+
+        self.event("stopped", Json::object(body));
+    }
+
+    fn output(&mut self, text: String) {
+        self.event("output",
+                   Json::object(vec![("category", Json::from("console")), ("output", Json::from(text + "\n"))]));
+    }
+
+    fn capabilities(&self) -> Json {
+        let supported = ["supportsConfigurationDoneRequest",
+                         "supportsConditionalBreakpoints",
+                         "supportsLogPoints",
+                         "supportsInstructionBreakpoints",
+                         "supportsSteppingGranularity",
+                         "supportsSetVariable",
+                         "supportsReadMemoryRequest",
+                         "supportsWriteMemoryRequest",
+                         "supportsDisassembleRequest",
+                         "supportsTerminateRequest"];
+
+        Json::Object(supported.iter().map(|name| (String::from(*name), Json::from(true))).collect())
+    }
+
+    // `program` is a raw binary (loaded at `loadAddress`) or an iNES image. `sourceMap` defaults to the program
+    // with a .dbg extension if there is one, and `symbols` takes the same `name = value` file as the repl
+    fn launch(&mut self, args: &Json) -> Result<Json, String> {
+        let program = args.get("program").as_str().ok_or_else(|| String::from("launch needs a 'program'"))?;
+        let bytes = fs::read(program).map_err(|err| format!("{}: {}", program, err))?;
+
+        let load_addr = match *args.get("loadAddress") {
+            Json::Null => DEFAULT_LOAD_ADDR,
+            Json::String(ref addr) => parse_reference(addr).ok_or_else(|| format!("bad loadAddress '{}'", addr))? as u16,
+            ref addr => addr.as_i64().ok_or_else(|| String::from("bad loadAddress"))? as u16,
+        };
+
+        let mut cpu = Cpu::new();
+        session::load_image(&mut cpu, &bytes, load_addr).map_err(|err| format!("{}: {}", program, err))?;
+
+        self.entry = cpu.reg_pc;
+        self.session = Session::new(cpu);
+        self.stop_on_entry = args.get("stopOnEntry").as_bool().unwrap_or(false);
+
+        let default_map = Path::new(program).with_extension("dbg");
+        let source_map = match args.get("sourceMap").as_str() {
+            Some(path) => Some(Path::new(path).to_path_buf()),
+            None if default_map.exists() => Some(default_map),
+            None => None,
+        };
+
+        if let Some(path) = source_map {
+            let text = fs::read_to_string(&path).map_err(|err| format!("{}: {}", path.display(), err))?;
+            self.source_map = SourceMap::parse(&text, path.parent()).map_err(|err| format!("{}: {}", path.display(), err))?;
+        }
+
+        if let Some(path) = args.get("symbols").as_str() {
+            let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+            self.session.symbols = session::parse_symbols(&text).map_err(|err| format!("{}: {}", path, err))?;
+        }
+
+        Ok(Json::object(vec![]))
+    }
+
+    fn remove_breakpoints(&mut self, ids: Vec<BreakpointId>) {
+        for id in ids {
+            self.session.cpu.breakpoints.remove(id);
+            self.session.conditions.remove(Point::Breakpoint(id));
+        }
+    }
+
+    // adds a breakpoint with an optional condition and log message, returning its id or why it can't be
+    fn add_breakpoint(&mut self, addr: u16, spec: &Json) -> Result<BreakpointId, String> {
+        let condition = match spec.get("condition").as_str() {
+            Some(text) if !text.trim().is_empty() => Some(Expr::parse(text).map_err(|err| err.to_string())?),
+            _ => None,
+        };
+
+        let log = match spec.get("logMessage").as_str() {
+            Some(text) => Some(LogTemplate::parse(text).map_err(|err| err.to_string())?),
+            None => None,
+        };
+
+        let id = self.session.cpu.breakpoints.add(addr, addr);
+        self.session.conditions.set_condition(Point::Breakpoint(id), condition);
+        self.session.conditions.set_log(Point::Breakpoint(id), log);
+
+        Ok(id)
+    }
+
+    fn set_breakpoints(&mut self, args: &Json) -> Result<Json, String> {
+        let path = args.get("source").get("path").as_str().ok_or_else(|| String::from("breakpoints need a source path"))?;
+
+        let old = self.source_breakpoints.remove(path).unwrap_or_default();
+        self.remove_breakpoints(old);
+
+        let mut ids = Vec::new();
+        let mut results = Vec::new();
+
+        for spec in args.get("breakpoints").as_array() {
+            let line = spec.get("line").as_i64().unwrap_or(0);
+            let location = self.source_map.addr_of(path, line as u32).cloned();
+
+            let result = match location {
+                Some(location) => {
+                    self.add_breakpoint(location.addr, spec).map(|id| (id, location.line as i64, location.addr))
+                }
+                None => Err(String::from("no code at this line")),
+            };
+
+            results.push(match result {
+                Ok((id, line, addr)) => {
+                    ids.push(id);
+
+                    Json::object(vec![("id", Json::from(id as i64)),
+                                      ("verified", Json::from(true)),
+                                      ("line", Json::from(line)),
+                                      ("instructionReference", Json::from(reference(addr)))])
+                }
+                Err(msg) => {
+                    Json::object(vec![("verified", Json::from(false)),
+                                      ("line", Json::from(line)),
+                                      ("message", Json::from(msg))])
+                }
+            });
+        }
+
+        self.source_breakpoints.insert(String::from(path), ids);
+
+        Ok(Json::object(vec![("breakpoints", Json::Array(results))]))
+    }
+
+    fn set_instruction_breakpoints(&mut self, args: &Json) -> Result<Json, String> {
+        let old = self.instruction_breakpoints.drain(..).collect();
+        self.remove_breakpoints(old);
+
+        let mut results = Vec::new();
+
+        for spec in args.get("breakpoints").as_array() {
+            let addr = spec.get("instructionReference")
+                .as_str()
+                .and_then(parse_reference)
+                .map(|addr| addr + spec.get("offset").as_i64().unwrap_or(0));
+
+            let result = match addr {
+                Some(addr) if (0..=0xffff).contains(&addr) => self.add_breakpoint(addr as u16, spec),
+                _ => Err(String::from("bad instruction reference")),
+            };
+
+            results.push(match result {
+                Ok(id) => {
+                    self.instruction_breakpoints.push(id);
+                    Json::object(vec![("id", Json::from(id as i64)), ("verified", Json::from(true))])
+                }
+                Err(msg) => Json::object(vec![("verified", Json::from(false)), ("message", Json::from(msg))]),
+            });
+        }
+
+        Ok(Json::object(vec![("breakpoints", Json::Array(results))]))
+    }
+
+    fn by_instruction(args: &Json) -> bool {
+        args.get("granularity").as_str() == Some("instruction")
+    }
+
+    // steps until pc lands on a different source line. without a source map there are no lines, so it's the
+    // same as stepping an instruction
+    fn step_line(&mut self, over: bool) -> Result<Option<StopReason>, ExprError> {
+        let line_at = |adapter: &DebugAdapter| {
+            adapter.source_map.location_of(adapter.session.cpu.reg_pc).map(|location| (location.file.clone(), location.line))
+        };
+
+        let start = line_at(self);
+
+        loop {
+            let stop = match over {
+                true => self.session.step_over()?,
+                false => self.session.step()?,
+            };
+
+            let line = line_at(self);
+            if stop.is_some() || self.source_map.is_empty() || (line.is_some() && line != start) {
+                return Ok(stop);
+            }
+        }
+    }
+
+    fn resume<F>(&mut self, motion: F)
+        where F: FnOnce(&mut DebugAdapter) -> Result<Option<StopReason>, ExprError>
+    {
+        let stop = motion(self);
+
+        let log: Vec<String> = self.session.conditions.log.drain(..).collect();
+        for line in log {
+            self.output(line);
+        }
+
+        match stop {
+            Ok(None) => self.stopped("step", None),
+            Ok(Some(StopReason::Breakpoint { id, .. })) => self.stopped("breakpoint", Some(id)),
+            Ok(Some(StopReason::Watchpoint { .. })) => self.stopped("data breakpoint", None),
+            Ok(Some(StopReason::Halted)) => {
+                self.event("exited", Json::object(vec![("exitCode", Json::from(0))]));
+                self.event("terminated", Json::object(vec![]));
+            }
+            Err(err) => {
+                self.output(format!("error: {}", err));
+                self.stopped("exception", None);
+            }
+        }
+    }
+
+    fn function_name(&self, addr: u16) -> String {
+        match self.session.symbol_at(addr) {
+            Some(name) => String::from(name),
+            None => format!("${:04x}", addr),
+        }
+    }
+
+    // the innermost frame is wherever pc is, and each call on the cpu's call stack adds the instruction that made it
+    fn stack_trace(&self) -> Json {
+        let frames = self.session.cpu.call_stack.frames();
+
+        let mut pcs = vec![self.session.cpu.reg_pc];
+        pcs.extend(frames.iter().rev().map(|frame| frame.caller));
+
+        let mut functions: Vec<u16> = frames.iter().rev().map(|frame| frame.target).collect();
+        functions.push(self.entry);
+
+        let stack: Vec<Json> = pcs.iter()
+            .zip(functions)
+            .enumerate()
+            .map(|(i, (&pc, function))| {
+                let mut frame = vec![("id", Json::from(i as i64)),
+                                     ("name", Json::from(self.function_name(function))),
+                                     ("instructionPointerReference", Json::from(reference(pc)))];
+
+                match self.source_map.location_of(pc) {
+                    Some(location) => {
+                        let name = Path::new(&location.file).file_name().map(|name| name.to_string_lossy().into_owned());
+
+                        frame.push(("source",
+                                    Json::object(vec![("name", Json::from(name.unwrap_or_default())),
+                                                      ("path", Json::from(location.file.as_str()))])));
+                        frame.push(("line", Json::from(location.line as i64)));
+                        frame.push(("column", Json::from(1)));
+                    }
+                    None => {
+                        frame.push(("line", Json::from(0)));
+                        frame.push(("column", Json::from(0)));
+                    }
+                }
+
+                Json::object(frame)
+            })
+            .collect();
+
+        Json::object(vec![("totalFrames", Json::from(stack.len() as i64)), ("stackFrames", Json::Array(stack))])
+    }
+
+    fn scopes(&self) -> Json {
+        let scope = |name: &str, reference: i64| {
+            Json::object(vec![("name", Json::from(name)),
+                              ("variablesReference", Json::from(reference)),
+                              ("expensive", Json::from(false))])
+        };
+
+        Json::object(vec![("scopes",
+                           Json::Array(vec![scope("Registers", REGISTERS_REF),
+                                            scope("Flags", FLAGS_REF),
+                                            scope("Stack", STACK_REF)]))])
+    }
+
+    fn variables(&self, args: &Json) -> Result<Json, String> {
+        let cpu = &self.session.cpu;
+        let variable = |name: String, value: String| {
+            Json::object(vec![("name", Json::from(name)), ("value", Json::from(value)), ("variablesReference", Json::from(0))])
+        };
+
+        let variables = match args.get("variablesReference").as_i64() {
+            Some(REGISTERS_REF) => {
+                let status: u8 = cpu.reg_status.clone().into();
+
+                vec![variable(String::from("A"), format!("${:02x}", cpu.reg_acc as u8)),
+                     variable(String::from("X"), format!("${:02x}", cpu.reg_x as u8)),
+                     variable(String::from("Y"), format!("${:02x}", cpu.reg_y as u8)),
+                     variable(String::from("SP"), format!("${:02x}", cpu.reg_sp)),
+                     variable(String::from("PC"), format!("${:04x}", cpu.reg_pc)),
+                     variable(String::from("P"), format!("${:02x}", status))]
+            }
+            Some(FLAGS_REF) => {
+                let status = &cpu.reg_status;
+                let flags = [status.negative,
+                             status.overflow,
+                             status.brk,
+                             status.decimal_mode,
+                             status.irq_disable,
+                             status.zero,
+                             status.carry];
+
+                FLAGS.iter().zip(flags.iter()).map(|(name, set)| variable(String::from(*name), format!("{}", *set as u8))).collect()
+            }
+            Some(STACK_REF) => {
+                (cpu.reg_sp as u16 + 1..0x100)
+                    .map(|offset| {
+                        let addr = STACK_PAGE + offset;
+                        variable(format!("${:04x}", addr), format!("${:02x}", cpu.memory.peek_u8_at(&addr)))
+                    })
+                    .collect()
+            }
+            _ => return Err(String::from("unknown variables reference")),
+        };
+
+        Ok(Json::object(vec![("variables", Json::Array(variables))]))
+    }
+
+    fn set_variable(&mut self, args: &Json) -> Result<Json, String> {
+        let name = args.get("name").as_str().unwrap_or("");
+        let val = self.eval(args.get("value").as_str().unwrap_or(""))?;
+
+        let cpu = &mut self.session.cpu;
+        let text = match (args.get("variablesReference").as_i64(), name) {
+            (Some(REGISTERS_REF), "A") => {
+                cpu.reg_acc = val as i8;
+                format!("${:02x}", val as u8)
+            }
+            (Some(REGISTERS_REF), "X") => {
+                cpu.reg_x = val as i8;
+                format!("${:02x}", val as u8)
+            }
+            (Some(REGISTERS_REF), "Y") => {
+                cpu.reg_y = val as i8;
+                format!("${:02x}", val as u8)
+            }
+            (Some(REGISTERS_REF), "SP") => {
+                cpu.reg_sp = val as u8;
+                format!("${:02x}", val as u8)
+            }
+            (Some(REGISTERS_REF), "PC") => {
+                cpu.reg_pc = val as u16;
+                format!("${:04x}", val as u16)
+            }
+            (Some(REGISTERS_REF), "P") => {
+                cpu.reg_status = ProcessorStatusRegister::from(val as u8);
+                format!("${:02x}", val as u8)
+            }
+            (Some(FLAGS_REF), _) => {
+                let set = val != 0;
+                match name {
+                    "N" => cpu.reg_status.negative = set,
+                    "V" => cpu.reg_status.overflow = set,
+                    "B" => cpu.reg_status.brk = set,
+                    "D" => cpu.reg_status.decimal_mode = set,
+                    "I" => cpu.reg_status.irq_disable = set,
+                    "Z" => cpu.reg_status.zero = set,
+                    "C" => cpu.reg_status.carry = set,
+                    _ => return Err(format!("unknown flag '{}'", name)),
+                }
+
+                format!("{}", set as u8)
+            }
+            (Some(STACK_REF), _) => {
+                let addr = asm::parse_number(name).ok_or_else(|| format!("bad stack slot '{}'", name))?;
+                cpu.memory.write_at(&(addr as u16), &[val as u8]);
+
+                format!("${:02x}", val as u8)
+            }
+            _ => return Err(format!("can't set '{}'", name)),
+        };
+
+        Ok(Json::object(vec![("value", Json::from(text))]))
+    }
+
+    fn eval(&self, text: &str) -> Result<i64, String> {
+        Expr::parse(text)
+            .and_then(|expr| expr.eval(&self.session.cpu, &self.session.symbols))
+            .map_err(|err| err.to_string())
+    }
+
+    fn evaluate(&self, args: &Json) -> Result<Json, String> {
+        let val = self.eval(args.get("expression").as_str().unwrap_or(""))?;
+
+        Ok(Json::object(vec![("result", Json::from(format!("${:x} ({})", val, val))),
+                             ("variablesReference", Json::from(0))]))
+    }
+
+    // the address a request's memoryReference plus offset points at
+    fn address(args: &Json) -> Result<i64, String> {
+        let base = args.get("memoryReference").as_str().and_then(parse_reference).ok_or_else(|| String::from("bad memory reference"))?;
+
+        Ok(base + args.get("offset").as_i64().unwrap_or(0))
+    }
+
+    fn read_memory(&self, args: &Json) -> Result<Json, String> {
+        let addr = DebugAdapter::address(args)?;
+        let count = args.get("count").as_i64().unwrap_or(0).max(0);
+
+        // only the part that's inside the address space can be read
+        let start = addr.clamp(0, 0x10000);
+        let end = (addr + count).clamp(start, 0x10000);
+
+        let bytes: Vec<u8> = (start..end).map(|addr| self.session.cpu.memory.peek_u8_at(&(addr as u16))).collect();
+
+        Ok(Json::object(vec![("address", Json::from(reference(start as u16))),
+                             ("data", Json::from(base64_encode(&bytes))),
+                             ("unreadableBytes", Json::from(count - bytes.len() as i64))]))
+    }
+
+    fn write_memory(&mut self, args: &Json) -> Result<Json, String> {
+        let addr = DebugAdapter::address(args)?;
+        let bytes = args.get("data").as_str().and_then(base64_decode).ok_or_else(|| String::from("bad data"))?;
+
+        if addr < 0 || addr + bytes.len() as i64 > 0x10000 {
+            return Err(String::from("write is outside memory"));
+        }
+
+        self.session.cpu.memory.write_at(&(addr as u16), &bytes);
+
+        Ok(Json::object(vec![("bytesWritten", Json::from(bytes.len() as i64))]))
+    }
+
+    // instructions can't be decoded backwards, so a negative instructionOffset searches for a start that
+    // decodes cleanly into the reference address. there are never more instructions than bytes of memory, so
+    // offsets and counts past that are cut down to it
+    fn disassemble(&self, args: &Json) -> Result<Json, String> {
+        let addr = DebugAdapter::address(args)?;
+        let offset = args.get("instructionOffset").as_i64().unwrap_or(0).clamp(-0x10000, 0x10000);
+        let count = args.get("instructionCount").as_i64().unwrap_or(0).clamp(0, 0x10000) as usize;
+
+        let cpu = &self.session.cpu;
+        let addr = addr.clamp(0, 0xffff) as u16;
+
+        let start = match offset < 0 {
+            true => {
+                let back = (-offset) as usize;
+                let earliest = (addr as usize).saturating_sub(back * 3) as u16;
+                let candidates = (0..3).map(|skew| earliest.saturating_add(skew));
+
+                candidates.filter_map(|start| {
+                        // every instruction is at least a byte, so this many always gets there if anything does
+                        let span = (addr as usize).saturating_sub(start as usize) + 1;
+                        let lines = disasm::disassemble_range(cpu, start, span);
+                        let i = lines.iter().position(|line| line.addr == addr)?;
+
+                        Some(lines[i.saturating_sub(back)].addr)
+                    })
+                    .next()
+                    .unwrap_or_else(|| (addr as usize).saturating_sub(back) as u16)
+            }
+            false => {
+                let lines = disasm::disassemble_range(cpu, addr, offset as usize + 1);
+                lines.last().map(|line| line.addr).unwrap_or(addr)
+            }
+        };
+
+        let instructions: Vec<Json> = disasm::disassemble_range(cpu, start, count)
+            .into_iter()
+            .map(|line| {
+                let bytes: Vec<String> = line.bytes.iter().map(|b| format!("{:02x}", b)).collect();
+                let mut fields = vec![("address", Json::from(reference(line.addr))),
+                                      ("instructionBytes", Json::from(bytes.join(" "))),
+                                      ("instruction", Json::from(line.text))];
+
+                if let Some(name) = self.session.symbol_at(line.addr) {
+                    fields.push(("symbol", Json::from(name)));
+                }
+
+                if let Some(location) = self.source_map.location_of(line.addr) {
+                    fields.push(("location", Json::object(vec![("path", Json::from(location.file.as_str()))])));
+                    fields.push(("line", Json::from(location.line as i64)));
+                }
+
+                Json::object(fields)
+            })
+            .collect();
+
+        Ok(Json::object(vec![("instructions", Json::Array(instructions))]))
+    }
+}
+
+// memory and instruction references go over the wire as `0x` hex strings
+fn reference(addr: u16) -> String {
+    format!("0x{:04x}", addr)
+}
+
+fn parse_reference(text: &str) -> Option<i64> {
+    match text.strip_prefix("0x") {
+        Some(hex) => i64::from_str_radix(hex, 16).ok(),
+        None => asm::parse_number(text),
+    }
+}
+
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::new();
+
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => out.push(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize] as char),
+                false => out.push('='),
+            }
+        }
+    }
+
+    out
+}
+
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut n = 0u32;
+    let mut bits = 0;
+
+    for c in text.bytes().filter(|c| *c != b'=') {
+        n = n << 6 | BASE64.iter().position(|b| *b == c)? as u32;
+        bits += 6;
+
+        if bits >= 8 {
+            bits -= 8;
+            out.push((n >> bits) as u8);
+        }
+    }
+
+    Some(out)
+}
+
+// dap messages are json bodies behind a `Content-Length` header
+fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<Json>> {
+    let mut len = None;
+
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some(val) = line.strip_prefix("Content-Length:") {
+            len = val.trim().parse().ok();
+        }
+    }
+
+    let len = len.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))?;
+
+    let mut body = vec![0; len];
+    input.read_exact(&mut body)?;
+
+    Json::parse(&String::from_utf8_lossy(&body)).map(Some).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn write_message<W: Write>(output: &mut W, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+pub fn serve<R: BufRead, W: Write>(mut input: R, mut output: W) -> io::Result<()> {
+    let mut adapter = DebugAdapter::new();
+
+    while let Some(request) = read_message(&mut input)? {
+        for message in adapter.handle(&request) {
+            write_message(&mut output, &message)?;
+        }
+
+        if adapter.is_done() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+// `dap`: speaks the protocol over stdin and stdout, which is how editors launch adapters
+pub fn main() {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+
+    if let Err(err) = serve(stdin.lock(), stdout.lock()) {
+        eprintln!("{}", err);
+        ::std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{base64_decode, base64_encode, read_message, serve, DebugAdapter};
+    use asm;
+    use debug::json::Json;
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+
+    fn request(seq: i64, command: &str, args: Json) -> Json {
+        Json::object(vec![("seq", Json::from(seq)),
+                          ("type", Json::from("request")),
+                          ("command", Json::from(command)),
+                          ("arguments", args)])
+    }
+
+    // writes the program and its source map somewhere the adapter can launch them from
+    fn launch(name: &str) -> DebugAdapter {
+        let dir = env::temp_dir().join(format!("sixty-five-oh-too-dap-{}", name));
+        fs::create_dir_all(&dir).unwrap();
+
+        fs::write(dir.join("prog.bin"),
+                  &[// main: jsr sub
+                    0x20, 0x08, 0x60,
+                    // ldx #$01
+                    0xa2, 0x01,
+                    // jmp $ffff
+                    0x4c, 0xff, 0xff,
+                    // sub: lda #$05
+                    0xa9, 0x05,
+                    // sta $10
+                    0x85, 0x10,
+                    // rts
+                    0x60])
+            .unwrap();
+        fs::write(dir.join("prog.dbg"),
+                  "$6000 prog.s:2\n$6003 prog.s:3\n$6005 prog.s:4\n$6008 prog.s:7\n$600a prog.s:8\n$600c prog.s:9\n")
+            .unwrap();
+        fs::write(dir.join("prog.sym"), "main = $6000\nsub = $6008\n").unwrap();
+
+        let mut adapter = DebugAdapter::new();
+        let args = Json::object(vec![("program", Json::from(dir.join("prog.bin").to_string_lossy().into_owned())),
+                                     ("symbols", Json::from(dir.join("prog.sym").to_string_lossy().into_owned()))]);
+
+        let reply = adapter.handle(&request(1, "launch", args));
+        assert_eq!(reply[0].get("success").as_bool(), Some(true), "{}", reply[0]);
+
+        adapter
+    }
+
+    #[test]
+    fn assembled_program() {
+        let dir = env::temp_dir().join("sixty-five-oh-too-dap-assembled");
+        fs::create_dir_all(&dir).unwrap();
+
+        let source = dir.join("prog.s");
+        fs::write(&source, ".org $6000\nmain:   jsr sub\n        jmp $ffff\n\nsub:    lda #$05\n        rts\n").unwrap();
+
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        let args: Vec<String> = vec![path("prog.s"), String::from("-o"), path("prog.bin"), String::from("--dbg"),
+                                     path("prog.dbg"), String::from("--symbols"), path("prog.sym")];
+        asm::cli::main(&args);
+
+        // the source map is picked up from next to the program
+        let mut adapter = DebugAdapter::new();
+        let args = Json::object(vec![("program", Json::from(path("prog.bin"))), ("symbols", Json::from(path("prog.sym")))]);
+        assert_eq!(adapter.handle(&request(1, "launch", args))[0].get("success").as_bool(), Some(true));
+
+        let args = Json::object(vec![("source", Json::object(vec![("path", Json::from(path("prog.s")))])),
+                                     ("breakpoints", Json::Array(vec![Json::object(vec![("line", Json::from(5))])]))]);
+        let reply = adapter.handle(&request(2, "setBreakpoints", args));
+        assert_eq!(reply[0].get("body").get("breakpoints").as_array()[0].get("instructionReference").as_str(),
+                   Some("0x6006"));
+
+        let reply = adapter.handle(&request(3, "configurationDone", Json::object(vec![])));
+        assert_eq!(reply[1].get("body").get("reason").as_str(), Some("breakpoint"));
+
+        let reply = adapter.handle(&request(4, "stackTrace", Json::object(vec![])));
+        let frames = reply[0].get("body").get("stackFrames").as_array();
+
+        assert_eq!(frames[0].get("name").as_str(), Some("sub"));
+        assert_eq!(frames[0].get("line").as_i64(), Some(5));
+        assert_eq!(frames[1].get("name").as_str(), Some("main"));
+        assert_eq!(frames[1].get("line").as_i64(), Some(2));
+    }
+
+    #[test]
+    fn breakpoints_and_stack() {
+        let mut adapter = launch("stack");
+
+        let source = env::temp_dir().join("sixty-five-oh-too-dap-stack").join("prog.s");
+        let args = Json::object(vec![("source", Json::object(vec![("path", Json::from(source.to_string_lossy().into_owned()))])),
+                                     ("breakpoints",
+                                      Json::Array(vec![Json::object(vec![("line", Json::from(8))]),
+                                                       Json::object(vec![("line", Json::from(5))]),
+                                                       Json::object(vec![("line", Json::from(20))])]))]);
+
+        let reply = adapter.handle(&request(2, "setBreakpoints", args));
+        let breakpoints = reply[0].get("body").get("breakpoints").as_array();
+
+        assert_eq!(breakpoints[0].get("instructionReference").as_str(), Some("0x600a"));
+        // slid down to the next line with code
+        assert_eq!(breakpoints[1].get("line").as_i64(), Some(7));
+        assert_eq!(breakpoints[2].get("verified").as_bool(), Some(false));
+
+        let reply = adapter.handle(&request(3, "configurationDone", Json::object(vec![])));
+        assert_eq!(reply[1].get("event").as_str(), Some("stopped"));
+        assert_eq!(reply[1].get("body").get("reason").as_str(), Some("breakpoint"));
+
+        let reply = adapter.handle(&request(4, "stackTrace", Json::object(vec![])));
+        let frames = reply[0].get("body").get("stackFrames").as_array();
+
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].get("name").as_str(), Some("sub"));
+        assert_eq!(frames[0].get("line").as_i64(), Some(7));
+        assert_eq!(frames[1].get("name").as_str(), Some("main"));
+        assert_eq!(frames[1].get("line").as_i64(), Some(2));
+
+        // line stepping lands on line 8, whose breakpoint only fires when execution continues from it
+        let reply = adapter.handle(&request(5, "next", Json::object(vec![])));
+        assert_eq!(reply[1].get("body").get("reason").as_str(), Some("step"));
+
+        let reply = adapter.handle(&request(6, "stackTrace", Json::object(vec![])));
+        assert_eq!(reply[0].get("body").get("stackFrames").as_array()[0].get("line").as_i64(), Some(8));
+
+        let reply = adapter.handle(&request(7, "stepOut", Json::object(vec![])));
+        assert_eq!(reply[1].get("body").get("reason").as_str(), Some("step"));
+
+        let reply = adapter.handle(&request(8, "continue", Json::object(vec![])));
+        assert_eq!(reply[1].get("event").as_str(), Some("exited"));
+        assert_eq!(reply[2].get("event").as_str(), Some("terminated"));
+    }
+
+    #[test]
+    fn registers_and_memory() {
+        let mut adapter = launch("memory");
+
+        let reply = adapter.handle(&request(2,
+                                            "setVariable",
+                                            Json::object(vec![("variablesReference", Json::from(1)),
+                                                              ("name", Json::from("X")),
+                                                              ("value", Json::from("$40 + 2"))])));
+        assert_eq!(reply[0].get("body").get("value").as_str(), Some("$42"));
+
+        let reply = adapter.handle(&request(3, "variables", Json::object(vec![("variablesReference", Json::from(1))])));
+        let registers = reply[0].get("body").get("variables").as_array();
+        assert_eq!(registers[1].get("value").as_str(), Some("$42"));
+        assert_eq!(registers[4].get("value").as_str(), Some("$6000"));
+
+        let reply = adapter.handle(&request(4,
+                                            "writeMemory",
+                                            Json::object(vec![("memoryReference", Json::from("0x0010")),
+                                                              ("data", Json::from(base64_encode(&[1, 2, 3])))])));
+        assert_eq!(reply[0].get("body").get("bytesWritten").as_i64(), Some(3));
+
+        let reply = adapter.handle(&request(5,
+                                            "readMemory",
+                                            Json::object(vec![("memoryReference", Json::from("0x000f")),
+                                                              ("offset", Json::from(1)),
+                                                              ("count", Json::from(2))])));
+        assert_eq!(base64_decode(reply[0].get("body").get("data").as_str().unwrap()), Some(vec![1, 2]));
+
+        let reply = adapter.handle(&request(6,
+                                            "disassemble",
+                                            Json::object(vec![("memoryReference", Json::from("0x6005")),
+                                                              ("instructionOffset", Json::from(-2)),
+                                                              ("instructionCount", Json::from(3))])));
+        let instructions = reply[0].get("body").get("instructions").as_array();
+        assert_eq!(instructions[0].get("address").as_str(), Some("0x6000"));
+        assert_eq!(instructions[0].get("symbol").as_str(), Some("main"));
+        assert_eq!(instructions[2].get("instruction").as_str(), Some("jmp $ffff"));
+        assert_eq!(instructions[2].get("line").as_i64(), Some(4));
+
+        // offsets and counts far outside memory are cut down rather than overflowing
+        for &(offset, count) in [(-30000, 2), (i64::MIN, 1), (i64::MAX, 1)].iter() {
+            let args = Json::object(vec![("memoryReference", Json::from("0x6005")),
+                                         ("instructionOffset", Json::from(offset)),
+                                         ("instructionCount", Json::from(count))]);
+            let reply = adapter.handle(&request(6, "disassemble", args));
+            assert_eq!(reply[0].get("success").as_bool(), Some(true));
+        }
+
+        let reply = adapter.handle(&request(7, "evaluate", Json::object(vec![("expression", Json::from("X + sub"))])));
+        assert_eq!(reply[0].get("body").get("result").as_str(), Some("$604a (24650)"));
+
+        let reply = adapter.handle(&request(8, "bogus", Json::object(vec![])));
+        assert_eq!(reply[0].get("success").as_bool(), Some(false));
+    }
+
+    #[test]
+    fn framing() {
+        let mut input = Vec::new();
+        for (seq, command) in [(1, "initialize"), (2, "disconnect"), (3, "threads")].iter() {
+            let body = request(*seq, command, Json::object(vec![])).to_string();
+            write!(input, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+        }
+
+        let mut output = Vec::new();
+        serve(&input[..], &mut output).unwrap();
+
+        let mut output = &output[..];
+        let initialize = read_message(&mut output).unwrap().unwrap();
+        assert_eq!(initialize.get("body").get("supportsLogPoints").as_bool(), Some(true));
+
+        let initialized = read_message(&mut output).unwrap().unwrap();
+        assert_eq!(initialized.get("event").as_str(), Some("initialized"));
+
+        // nothing is answered after disconnecting
+        assert_eq!(read_message(&mut output).unwrap().unwrap().get("request_seq").as_i64(), Some(2));
+        assert!(read_message(&mut output).unwrap().is_none());
+
+        assert_eq!(base64_encode(b"6502!"), "NjUwMiE=");
+        assert_eq!(base64_decode("NjUwMiE="), Some(b"6502!".to_vec()));
+    }
+}
@@ -0,0 +1,81 @@
+use cpu::Cpu;
+use cpu::instr::resolver;
+
+// the longest 6502 instruction; anything starting closer to the end of memory than this is shown as data
+const MAX_INSTR_BYTES: u16 = 3;
+
+pub struct Line {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    pub text: String,
+}
+
+// decodes the instruction at `addr` by running the real decoder on a throwaway fork, so the output matches what
+// `Cpu::step` traces
+pub fn disassemble(cpu: &Cpu, addr: u16) -> Line {
+    let opcode = cpu.memory.peek_u8_at(&addr);
+
+    let decoded = match resolver::resolve(opcode) {
+        Some(instr) if addr <= 0xffff - MAX_INSTR_BYTES => {
+            let mut fork = cpu.fork();
+            fork.trace = false;
+            fork.reg_pc = addr.wrapping_add(1);
+
+            let instr_result = (instr)(&mut fork);
+
+            // implied operands leave a trailing space behind in the trace format
+            let text = format!("{:?}", instr_result).trim_end().to_string();
+
+            Some((text, fork.reg_pc.wrapping_sub(addr)))
+        }
+        _ => None,
+    };
+
+    let (text, len) = decoded.unwrap_or_else(|| (format!(".byte ${:02x}", opcode), 1));
+
+    Line {
+        addr: addr,
+        bytes: (0..len).map(|i| cpu.memory.peek_u8_at(&addr.wrapping_add(i))).collect(),
+        text: text,
+    }
+}
+
+pub fn disassemble_range(cpu: &Cpu, addr: u16, count: usize) -> Vec<Line> {
+    let mut lines = Vec::with_capacity(count);
+    let mut addr = addr;
+
+    for _ in 0..count {
+        let line = disassemble(cpu, addr);
+        addr = addr.wrapping_add(line.bytes.len() as u16);
+
+        lines.push(line);
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod test {
+    use super::disassemble_range;
+    use cpu::Cpu;
+
+    #[test]
+    fn disassemble() {
+        let mut cpu = Cpu::new();
+
+        // lda #$11 / sta $0200 / bne $6000 / (undefined)
+        cpu.load_program(0x6000, &[0xa9, 0x11, 0x8d, 0x00, 0x02, 0xd0, 0xf9, 0x02]);
+
+        let lines = disassemble_range(&cpu, 0x6000, 4);
+
+        assert_eq!(lines[0].text, "lda #$11");
+        assert_eq!(lines[1].text, "sta $200");
+        assert_eq!(lines[1].bytes, vec![0x8d, 0x00, 0x02]);
+        assert_eq!(lines[2].text, "bne $6000");
+        assert_eq!(lines[3].addr, 0x6007);
+        assert_eq!(lines[3].text, ".byte $02");
+
+        // decoding doesn't move the real cpu
+        assert_eq!(cpu.reg_pc, 0);
+    }
+}
@@ -0,0 +1,455 @@
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+
+use asm;
+use cpu::Cpu;
+
+// labels and other names an expression can refer to
+pub type Symbols = HashMap<String, i64>;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Register {
+    A,
+    X,
+    Y,
+    SP,
+    PC,
+    P,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Flag {
+    Carry,
+    Zero,
+    IrqDisable,
+    Decimal,
+    Break,
+    Overflow,
+    Negative,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+    BitNot,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BinaryOp {
+    Mul,
+    Div,
+    Mod,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    BitAnd,
+    BitXor,
+    BitOr,
+    And,
+    Or,
+}
+
+impl BinaryOp {
+    fn precedence(&self) -> u8 {
+        match *self {
+            BinaryOp::Or => 1,
+            BinaryOp::And => 2,
+            BinaryOp::BitOr => 3,
+            BinaryOp::BitXor => 4,
+            BinaryOp::BitAnd => 5,
+            BinaryOp::Eq | BinaryOp::Ne => 6,
+            BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => 7,
+            BinaryOp::Shl | BinaryOp::Shr => 8,
+            BinaryOp::Add | BinaryOp::Sub => 9,
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => 10,
+        }
+    }
+}
+
+// longest operators first so `<=` isn't read as `<`
+const BINARY_OPS: &'static [(&'static str, BinaryOp)] = &[("||", BinaryOp::Or),
+                                                         ("&&", BinaryOp::And),
+                                                         ("==", BinaryOp::Eq),
+                                                         ("!=", BinaryOp::Ne),
+                                                         ("<=", BinaryOp::Le),
+                                                         (">=", BinaryOp::Ge),
+                                                         ("<<", BinaryOp::Shl),
+                                                         (">>", BinaryOp::Shr),
+                                                         ("<", BinaryOp::Lt),
+                                                         (">", BinaryOp::Gt),
+                                                         ("|", BinaryOp::BitOr),
+                                                         ("^", BinaryOp::BitXor),
+                                                         ("&", BinaryOp::BitAnd),
+                                                         ("+", BinaryOp::Add),
+                                                         ("-", BinaryOp::Sub),
+                                                         ("*", BinaryOp::Mul),
+                                                         ("/", BinaryOp::Div),
+                                                         ("%", BinaryOp::Mod)];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Number(i64),
+    Register(Register),
+    Flag(Flag),
+    Symbol(String),
+    // [addr]
+    Byte(Box<Expr>),
+    // w[addr], little endian
+    Word(Box<Expr>),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ExprError {
+    Parse { pos: usize, msg: String },
+    UnknownSymbol(String),
+    DivideByZero,
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ExprError::Parse { pos, ref msg } => write!(f, "{} at column {}", msg, pos + 1),
+            ExprError::UnknownSymbol(ref name) => write!(f, "unknown symbol '{}'", name),
+            ExprError::DivideByZero => write!(f, "division by zero"),
+        }
+    }
+}
+
+impl error::Error for ExprError {}
+
+impl Expr {
+    pub fn parse(input: &str) -> Result<Expr, ExprError> {
+        let mut parser = ExprParser {
+            input: input,
+            pos: 0,
+        };
+
+        let expr = parser.expr(0)?;
+
+        parser.skip_whitespace();
+        if parser.pos != input.len() {
+            return Err(parser.error("unexpected input"));
+        }
+
+        Ok(expr)
+    }
+
+    // memory is peeked, so evaluating a watch never trips a watchpoint
+    pub fn eval(&self, cpu: &Cpu, symbols: &Symbols) -> Result<i64, ExprError> {
+        let val = match *self {
+            Expr::Number(val) => val,
+            Expr::Register(reg) => {
+                match reg {
+                    Register::A => cpu.reg_acc as u8 as i64,
+                    Register::X => cpu.reg_x as u8 as i64,
+                    Register::Y => cpu.reg_y as u8 as i64,
+                    Register::SP => cpu.reg_sp as i64,
+                    Register::PC => cpu.reg_pc as i64,
+                    Register::P => {
+                        let status: u8 = cpu.reg_status.clone().into();
+                        status as i64
+                    }
+                }
+            }
+            Expr::Flag(flag) => {
+                let status = &cpu.reg_status;
+                let set = match flag {
+                    Flag::Carry => status.carry,
+                    Flag::Zero => status.zero,
+                    Flag::IrqDisable => status.irq_disable,
+                    Flag::Decimal => status.decimal_mode,
+                    Flag::Break => status.brk,
+                    Flag::Overflow => status.overflow,
+                    Flag::Negative => status.negative,
+                };
+
+                set as i64
+            }
+            Expr::Symbol(ref name) => {
+                match symbols.get(name) {
+                    Some(val) => *val,
+                    None => return Err(ExprError::UnknownSymbol(name.clone())),
+                }
+            }
+            Expr::Byte(ref addr) => cpu.memory.peek_u8_at(&(addr.eval(cpu, symbols)? as u16)) as i64,
+            Expr::Word(ref addr) => cpu.memory.peek_u16_at(&(addr.eval(cpu, symbols)? as u16)) as i64,
+            Expr::Unary(op, ref operand) => {
+                let val = operand.eval(cpu, symbols)?;
+
+                match op {
+                    UnaryOp::Neg => val.wrapping_neg(),
+                    UnaryOp::Not => (val == 0) as i64,
+                    UnaryOp::BitNot => !val,
+                }
+            }
+            // short circuit so `X != 0 && [$10] / X > 2` is safe
+            Expr::Binary(BinaryOp::And, ref lhs, ref rhs) => {
+                (lhs.eval(cpu, symbols)? != 0 && rhs.eval(cpu, symbols)? != 0) as i64
+            }
+            Expr::Binary(BinaryOp::Or, ref lhs, ref rhs) => {
+                (lhs.eval(cpu, symbols)? != 0 || rhs.eval(cpu, symbols)? != 0) as i64
+            }
+            Expr::Binary(op, ref lhs, ref rhs) => {
+                let lhs = lhs.eval(cpu, symbols)?;
+                let rhs = rhs.eval(cpu, symbols)?;
+
+                match op {
+                    BinaryOp::Mul => lhs.wrapping_mul(rhs),
+                    BinaryOp::Div | BinaryOp::Mod if rhs == 0 => return Err(ExprError::DivideByZero),
+                    BinaryOp::Div => lhs.wrapping_div(rhs),
+                    BinaryOp::Mod => lhs.wrapping_rem(rhs),
+                    BinaryOp::Add => lhs.wrapping_add(rhs),
+                    BinaryOp::Sub => lhs.wrapping_sub(rhs),
+                    BinaryOp::Shl => lhs.wrapping_shl(rhs as u32),
+                    BinaryOp::Shr => lhs.wrapping_shr(rhs as u32),
+                    BinaryOp::Lt => (lhs < rhs) as i64,
+                    BinaryOp::Le => (lhs <= rhs) as i64,
+                    BinaryOp::Gt => (lhs > rhs) as i64,
+                    BinaryOp::Ge => (lhs >= rhs) as i64,
+                    BinaryOp::Eq => (lhs == rhs) as i64,
+                    BinaryOp::Ne => (lhs != rhs) as i64,
+                    BinaryOp::BitAnd => lhs & rhs,
+                    BinaryOp::BitXor => lhs ^ rhs,
+                    BinaryOp::BitOr => lhs | rhs,
+                    BinaryOp::And | BinaryOp::Or => unreachable!(),
+                }
+            }
+        };
+
+        Ok(val)
+    }
+
+    pub fn is_true(&self, cpu: &Cpu, symbols: &Symbols) -> Result<bool, ExprError> {
+        Ok(self.eval(cpu, symbols)? != 0)
+    }
+}
+
+struct ExprParser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> ExprParser<'a> {
+    fn error(&self, msg: &str) -> ExprError {
+        ExprError::Parse {
+            pos: self.pos,
+            msg: String::from(msg),
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let trimmed = self.rest().trim_start();
+        self.pos = self.input.len() - trimmed.len();
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+
+        match self.rest().starts_with(token) {
+            true => {
+                self.pos += token.len();
+                true
+            }
+            false => false,
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), ExprError> {
+        match self.eat(token) {
+            true => Ok(()),
+            false => Err(self.error(&format!("expected '{}'", token))),
+        }
+    }
+
+    // precedence climbing: only binds operators that are at least as tight as `min_precedence`
+    fn expr(&mut self, min_precedence: u8) -> Result<Expr, ExprError> {
+        let mut lhs = self.unary()?;
+
+        loop {
+            self.skip_whitespace();
+
+            let op = BINARY_OPS.iter().find(|&&(token, _)| self.rest().starts_with(token));
+            let (token, op) = match op {
+                Some(&(token, op)) if op.precedence() >= min_precedence => (token, op),
+                _ => break,
+            };
+
+            self.pos += token.len();
+
+            let rhs = self.expr(op.precedence() + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ExprError> {
+        let op = if self.eat("-") {
+            UnaryOp::Neg
+        } else if self.eat("!") {
+            UnaryOp::Not
+        } else if self.eat("~") {
+            UnaryOp::BitNot
+        } else {
+            return self.primary();
+        };
+
+        Ok(Expr::Unary(op, Box::new(self.unary()?)))
+    }
+
+    fn primary(&mut self) -> Result<Expr, ExprError> {
+        self.skip_whitespace();
+
+        if self.eat("(") {
+            let expr = self.expr(0)?;
+            self.expect(")")?;
+
+            return Ok(expr);
+        }
+
+        if self.eat("[") {
+            let addr = self.expr(0)?;
+            self.expect("]")?;
+
+            return Ok(Expr::Byte(Box::new(addr)));
+        }
+
+        let start = self.pos;
+        let first = match self.rest().chars().next() {
+            Some(c) => c,
+            None => return Err(self.error("expected an expression")),
+        };
+
+        // a leading % is only ever binary here; as an operator it was already taken by `expr`
+        if first == '$' || first == '%' || first.is_ascii_digit() {
+            let len = 1 + self.rest()[1..].chars().take_while(|c| c.is_ascii_alphanumeric()).count();
+            let text = &self.rest()[..len];
+
+            return match asm::parse_number(text) {
+                Some(val) => {
+                    self.pos += len;
+                    Ok(Expr::Number(val))
+                }
+                None => Err(self.error(&format!("bad number '{}'", text))),
+            };
+        }
+
+        if first.is_ascii_alphabetic() || first == '_' {
+            let len = self.rest().chars().take_while(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '.').count();
+            let name = &self.input[start..start + len];
+            self.pos += len;
+
+            if name == "w" && self.eat("[") {
+                let addr = self.expr(0)?;
+                self.expect("]")?;
+
+                return Ok(Expr::Word(Box::new(addr)));
+            }
+
+            return Ok(ExprParser::name(name));
+        }
+
+        Err(self.error(&format!("unexpected '{}'", first)))
+    }
+
+    // registers and flags win over symbols of the same name
+    fn name(name: &str) -> Expr {
+        let reg = match name.to_uppercase().as_str() {
+            "A" => Some(Register::A),
+            "X" => Some(Register::X),
+            "Y" => Some(Register::Y),
+            "S" | "SP" => Some(Register::SP),
+            "PC" => Some(Register::PC),
+            "P" => Some(Register::P),
+            _ => None,
+        };
+
+        if let Some(reg) = reg {
+            return Expr::Register(reg);
+        }
+
+        let flag = match name.to_uppercase().as_str() {
+            "P.C" => Some(Flag::Carry),
+            "P.Z" => Some(Flag::Zero),
+            "P.I" => Some(Flag::IrqDisable),
+            "P.D" => Some(Flag::Decimal),
+            "P.B" => Some(Flag::Break),
+            "P.V" => Some(Flag::Overflow),
+            "P.N" => Some(Flag::Negative),
+            _ => None,
+        };
+
+        match flag {
+            Some(flag) => Expr::Flag(flag),
+            None => Expr::Symbol(String::from(name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Expr, ExprError, Symbols};
+    use cpu::Cpu;
+
+    fn eval(input: &str, cpu: &Cpu) -> Result<i64, ExprError> {
+        let mut symbols = Symbols::new();
+        symbols.insert(String::from("counter"), 0x0200);
+
+        Expr::parse(input)?.eval(cpu, &symbols)
+    }
+
+    #[test]
+    fn arithmetic_and_precedence() {
+        let cpu = Cpu::new();
+
+        assert_eq!(eval("1 + 2 * 3", &cpu), Ok(7));
+        assert_eq!(eval("(1 + 2) * 3", &cpu), Ok(9));
+        assert_eq!(eval("$10 | %0001 << 1", &cpu), Ok(0x12));
+        assert_eq!(eval("7 % 4", &cpu), Ok(3));
+        assert_eq!(eval("-1 + ~0", &cpu), Ok(-2));
+        assert_eq!(eval("1 < 2 == 1 && !0", &cpu), Ok(1));
+        assert_eq!(eval("1 / 0", &cpu), Err(ExprError::DivideByZero));
+    }
+
+    #[test]
+    fn machine_state() {
+        let mut cpu = Cpu::new();
+        cpu.reg_acc = 0x10;
+        cpu.reg_status.carry = true;
+        cpu.memory.write_at(&0x0000, &[0x04]);
+        cpu.memory.write_at(&0x0200, &[0x34, 0x12]);
+
+        assert_eq!(eval("A == $10 && [$00] > 3", &cpu), Ok(1));
+        assert_eq!(eval("P.C + P.Z", &cpu), Ok(1));
+        assert_eq!(eval("w[counter]", &cpu), Ok(0x1234));
+        assert_eq!(eval("[counter + 1]", &cpu), Ok(0x12));
+        assert_eq!(eval("missing", &cpu), Err(ExprError::UnknownSymbol(String::from("missing"))));
+    }
+
+    #[test]
+    fn parse_errors() {
+        match Expr::parse("1 + ") {
+            Err(ExprError::Parse { pos: 4, .. }) => {}
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+
+        assert!(Expr::parse("[$10").is_err());
+        assert!(Expr::parse("$zz").is_err());
+        assert!(Expr::parse("1 2").is_err());
+    }
+}
@@ -0,0 +1,697 @@
+use std::collections::HashMap;
+use std::io;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::str;
+
+use cpu::ProcessorStatusRegister;
+use cpu::breakpoint::{BreakpointId, StopReason, WatchKind};
+
+use super::session::Session;
+
+const MAX_PACKET: usize = 0x1000;
+
+// instructions to run between checks for a ^C from the client while continuing
+const INTERRUPT_SLICE: u64 = 10000;
+
+const INTERRUPT: u8 = 0x03;
+const ESCAPE: u8 = b'}';
+
+// gdb has no idea what a 6502 is, so it gets told the register layout instead:
+// a, x, y and sp are a byte each, pc is two (little endian on the wire), then p
+const TARGET_XML: &'static str = "<?xml version=\"1.0\"?>\
+<!DOCTYPE target SYSTEM \"gdb-target.dtd\">\
+<target version=\"1.0\">\
+<feature name=\"org.sixty-five-oh-too.mos6502\">\
+<reg name=\"a\" bitsize=\"8\" type=\"uint8\" regnum=\"0\"/>\
+<reg name=\"x\" bitsize=\"8\" type=\"uint8\"/>\
+<reg name=\"y\" bitsize=\"8\" type=\"uint8\"/>\
+<reg name=\"sp\" bitsize=\"8\" type=\"uint8\"/>\
+<reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\"/>\
+<reg name=\"p\" bitsize=\"8\" type=\"uint8\"/>\
+</feature>\
+</target>";
+
+const REGISTER_SIZES: [usize; 6] = [1, 1, 1, 1, 2, 1];
+
+// signals for stop replies
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum PointKind {
+    Software,
+    Hardware,
+    Write,
+    Read,
+    Access,
+}
+
+impl PointKind {
+    fn from_z(kind: &str) -> Option<PointKind> {
+        match kind {
+            "0" => Some(PointKind::Software),
+            "1" => Some(PointKind::Hardware),
+            "2" => Some(PointKind::Write),
+            "3" => Some(PointKind::Read),
+            "4" => Some(PointKind::Access),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Reply {
+    Send(String),
+    // send this (if anything) and hang up
+    Close(Option<String>),
+}
+
+enum Incoming {
+    Packet(Vec<u8>),
+    BadChecksum,
+    Interrupt,
+}
+
+// a gdb remote serial protocol server over a session. both breakpoint flavours gdb knows about land on the
+// core's breakpoints, and write/read/access watchpoints on its watchpoints
+pub struct GdbStub {
+    pub session: Session,
+
+    // what each Z packet created, so the matching z packet can take it away again
+    points: HashMap<(PointKind, u16, u16), BreakpointId>,
+    no_ack: bool,
+}
+
+impl GdbStub {
+    pub fn new(session: Session) -> Self {
+        GdbStub {
+            session: session,
+            points: HashMap::new(),
+            no_ack: false,
+        }
+    }
+
+    // answers packets until the client detaches, kills the target or goes away. `interrupted` is polled while
+    // continuing and should say whether the client has sent a ^C
+    pub fn serve<R, W, I>(&mut self, mut input: R, mut output: W, mut interrupted: I) -> io::Result<()>
+        where R: Read,
+              W: Write,
+              I: FnMut() -> bool
+    {
+        loop {
+            let packet = match read_packet(&mut input)? {
+                Some(Incoming::Packet(packet)) => packet,
+                Some(Incoming::BadChecksum) => {
+                    if !self.no_ack {
+                        output.write_all(b"-")?;
+                        output.flush()?;
+                    }
+
+                    continue;
+                }
+                // nothing is running between packets, so there's nothing to interrupt
+                Some(Incoming::Interrupt) => {
+                    write_packet(&mut output, &stop_signal(SIGINT))?;
+                    continue;
+                }
+                None => return Ok(()),
+            };
+
+            if !self.no_ack {
+                output.write_all(b"+")?;
+            }
+
+            match self.handle(&packet, &mut interrupted) {
+                Reply::Send(reply) => write_packet(&mut output, &reply)?,
+                Reply::Close(reply) => {
+                    if let Some(reply) = reply {
+                        write_packet(&mut output, &reply)?;
+                    }
+
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    pub fn handle<I>(&mut self, packet: &[u8], interrupted: &mut I) -> Reply
+        where I: FnMut() -> bool
+    {
+        // `X` is the one packet with raw bytes in it; everything else is ascii
+        if let Some(args) = packet.strip_prefix(b"X") {
+            return Reply::Send(self.write_binary(args));
+        }
+
+        let packet = String::from_utf8_lossy(packet);
+        let packet = packet.as_ref();
+
+        let (command, args) = match packet.chars().next() {
+            Some(c) => packet.split_at(c.len_utf8()),
+            None => return Reply::Send(String::new()),
+        };
+
+        let reply = match command {
+            "?" => stop_signal(SIGTRAP),
+            "g" => self.read_registers(),
+            "G" => self.write_registers(args),
+            "p" => self.read_register(args),
+            "P" => self.write_register(args),
+            "m" => self.read_memory(args),
+            "M" => self.write_memory(args),
+            "s" => self.resume(args, true, interrupted),
+            "c" => self.resume(args, false, interrupted),
+            "Z" => self.insert_point(args),
+            "z" => self.remove_point(args),
+            "H" | "T" => String::from("OK"),
+            "D" => return Reply::Close(Some(String::from("OK"))),
+            "k" => return Reply::Close(None),
+            "q" | "Q" | "v" => self.query(packet, interrupted),
+            _ => String::new(),
+        };
+
+        Reply::Send(reply)
+    }
+
+    fn query<I>(&mut self, packet: &str, interrupted: &mut I) -> String
+        where I: FnMut() -> bool
+    {
+        if packet.starts_with("qSupported") {
+            return format!("PacketSize={:x};qXfer:features:read+;QStartNoAckMode+;swbreak+;hwbreak+;vContSupported+",
+                           MAX_PACKET);
+        }
+
+        if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            return read_chunk(TARGET_XML, range);
+        }
+
+        if let Some(actions) = packet.strip_prefix("vCont;") {
+            // there's only ever one thread, so the first action is the one that applies to it
+            let action = actions.split(';').next().unwrap_or("");
+
+            return match action.chars().next() {
+                Some('s') => self.resume("", true, interrupted),
+                Some('c') => self.resume("", false, interrupted),
+                _ => String::from("E01"),
+            };
+        }
+
+        match packet {
+            "QStartNoAckMode" => {
+                self.no_ack = true;
+                String::from("OK")
+            }
+            "qAttached" => String::from("1"),
+            "qC" => String::from("QC1"),
+            "qfThreadInfo" => String::from("m1"),
+            "qsThreadInfo" => String::from("l"),
+            "vCont?" => String::from("vCont;c;s"),
+            _ => String::new(),
+        }
+    }
+
+    fn read_registers(&self) -> String {
+        (0..REGISTER_SIZES.len()).map(|reg| self.register(reg)).collect()
+    }
+
+    fn write_registers(&mut self, args: &str) -> String {
+        let bytes = match decode_hex(args) {
+            Some(ref bytes) if bytes.len() >= REGISTER_SIZES.iter().sum() => bytes.clone(),
+            _ => return String::from("E01"),
+        };
+
+        let mut offset = 0;
+        for (reg, size) in REGISTER_SIZES.iter().enumerate() {
+            self.set_register(reg, &bytes[offset..offset + size]);
+            offset += size;
+        }
+
+        String::from("OK")
+    }
+
+    fn read_register(&self, args: &str) -> String {
+        match usize::from_str_radix(args, 16) {
+            Ok(reg) if reg < REGISTER_SIZES.len() => self.register(reg),
+            _ => String::from("E01"),
+        }
+    }
+
+    fn write_register(&mut self, args: &str) -> String {
+        let mut parts = args.splitn(2, '=');
+        let reg = parts.next().and_then(|reg| usize::from_str_radix(reg, 16).ok());
+        let val = parts.next().and_then(decode_hex);
+
+        match (reg, val) {
+            (Some(reg), Some(val)) if reg < REGISTER_SIZES.len() && val.len() == REGISTER_SIZES[reg] => {
+                self.set_register(reg, &val);
+                String::from("OK")
+            }
+            _ => String::from("E01"),
+        }
+    }
+
+    fn register(&self, reg: usize) -> String {
+        let cpu = &self.session.cpu;
+
+        match reg {
+            0 => format!("{:02x}", cpu.reg_acc as u8),
+            1 => format!("{:02x}", cpu.reg_x as u8),
+            2 => format!("{:02x}", cpu.reg_y as u8),
+            3 => format!("{:02x}", cpu.reg_sp),
+            4 => format!("{:02x}{:02x}", cpu.reg_pc as u8, (cpu.reg_pc >> 8) as u8),
+            _ => format!("{:02x}", Into::<u8>::into(cpu.reg_status.clone())),
+        }
+    }
+
+    fn set_register(&mut self, reg: usize, bytes: &[u8]) {
+        let cpu = &mut self.session.cpu;
+
+        match reg {
+            0 => cpu.reg_acc = bytes[0] as i8,
+            1 => cpu.reg_x = bytes[0] as i8,
+            2 => cpu.reg_y = bytes[0] as i8,
+            3 => cpu.reg_sp = bytes[0],
+            4 => cpu.reg_pc = bytes[0] as u16 | (bytes[1] as u16) << 8,
+            _ => cpu.reg_status = ProcessorStatusRegister::from(bytes[0]),
+        }
+    }
+
+    fn read_memory(&self, args: &str) -> String {
+        let (addr, len) = match parse_range(args) {
+            Some(range) => range,
+            None => return String::from("E01"),
+        };
+
+        (0..len.min(MAX_PACKET / 2))
+            .map(|i| format!("{:02x}", self.session.cpu.memory.peek_u8_at(&addr.wrapping_add(i as u16))))
+            .collect()
+    }
+
+    // `M addr,len:hex`
+    fn write_memory(&mut self, args: &str) -> String {
+        let mut parts = args.splitn(2, ':');
+        let range = parts.next().and_then(parse_range);
+        let data = parts.next().and_then(decode_hex);
+
+        self.store(range, data)
+    }
+
+    // `X addr,len:bytes`, the bytes already unescaped by `read_packet`
+    fn write_binary(&mut self, args: &[u8]) -> String {
+        let colon = match args.iter().position(|&b| b == b':') {
+            Some(colon) => colon,
+            None => return String::from("E01"),
+        };
+
+        let range = str::from_utf8(&args[..colon]).ok().and_then(parse_range);
+
+        self.store(range, Some(args[colon + 1..].to_vec()))
+    }
+
+    fn store(&mut self, range: Option<(u16, usize)>, data: Option<Vec<u8>>) -> String {
+        match (range, data) {
+            (Some((addr, len)), Some(ref bytes)) if bytes.len() == len => {
+                self.session.cpu.memory.write_at(&addr, bytes);
+                String::from("OK")
+            }
+            _ => String::from("E01"),
+        }
+    }
+
+    // `s [addr]` and `c [addr]`
+    fn resume<I>(&mut self, args: &str, single: bool, interrupted: &mut I) -> String
+        where I: FnMut() -> bool
+    {
+        if !args.is_empty() {
+            match u16::from_str_radix(args, 16) {
+                Ok(addr) => self.session.cpu.reg_pc = addr,
+                Err(_) => return String::from("E01"),
+            }
+        }
+
+        let result = match single {
+            true => self.session.step(),
+            false => {
+                let mut count = 0;
+
+                self.session.run_until(|_, _| {
+                    count += 1;
+                    count % INTERRUPT_SLICE == 0 && interrupted()
+                })
+            }
+        };
+
+        match result {
+            Ok(Some(reason)) => self.stop_reply(&reason),
+            Ok(None) if single => stop_signal(SIGTRAP),
+            Ok(None) => stop_signal(SIGINT),
+            Err(_) => String::from("E02"),
+        }
+    }
+
+    fn stop_reply(&self, reason: &StopReason) -> String {
+        match *reason {
+            StopReason::Halted => String::from("W00"),
+            StopReason::Breakpoint { id, .. } => {
+                let kind = match self.kind_of(id, false) {
+                    Some(PointKind::Hardware) => "hwbreak",
+                    _ => "swbreak",
+                };
+
+                format!("T{:02x}{}:;", SIGTRAP, kind)
+            }
+            StopReason::Watchpoint { id, addr, .. } => {
+                let kind = match self.session.cpu.memory.watchpoints.get(id).map(|point| point.kind) {
+                    Some(WatchKind::Read) => "rwatch",
+                    Some(WatchKind::Access) => "awatch",
+                    _ => "watch",
+                };
+
+                format!("T{:02x}{}:{:x};", SIGTRAP, kind, addr)
+            }
+        }
+    }
+
+    fn kind_of(&self, id: BreakpointId, watch: bool) -> Option<PointKind> {
+        self.points
+            .iter()
+            .find(|&(&(kind, _, _), point)| *point == id && watch == (kind != PointKind::Software && kind != PointKind::Hardware))
+            .map(|(&(kind, _, _), _)| kind)
+    }
+
+    // `Z type,addr,kind`; for watchpoints `kind` is the length in bytes
+    fn parse_point(args: &str) -> Option<(PointKind, u16, u16)> {
+        let parts: Vec<&str> = args.split(';').next().unwrap_or("").split(',').collect();
+        if parts.len() != 3 {
+            return None;
+        }
+
+        let kind = PointKind::from_z(parts[0])?;
+        let addr = u32::from_str_radix(parts[1], 16).ok()? as u16;
+        let len = u16::from_str_radix(parts[2], 16).ok()?;
+
+        Some((kind, addr, len))
+    }
+
+    fn insert_point(&mut self, args: &str) -> String {
+        let key = match GdbStub::parse_point(args) {
+            Some(key) => key,
+            None => return String::from("E01"),
+        };
+
+        if self.points.contains_key(&key) {
+            return String::from("OK");
+        }
+
+        let (kind, addr, len) = key;
+        let end = addr.wrapping_add(len.max(1) - 1);
+
+        let id = match kind {
+            PointKind::Software | PointKind::Hardware => self.session.cpu.breakpoints.add(addr, addr),
+            PointKind::Write => self.session.cpu.memory.watchpoints.add(addr, end, WatchKind::Write, None),
+            PointKind::Read => self.session.cpu.memory.watchpoints.add(addr, end, WatchKind::Read, None),
+            PointKind::Access => self.session.cpu.memory.watchpoints.add(addr, end, WatchKind::Access, None),
+        };
+
+        self.points.insert(key, id);
+
+        String::from("OK")
+    }
+
+    fn remove_point(&mut self, args: &str) -> String {
+        let key = match GdbStub::parse_point(args) {
+            Some(key) => key,
+            None => return String::from("E01"),
+        };
+
+        // gdb removes what it inserted, but removing something that isn't there isn't worth failing over
+        if let Some(id) = self.points.remove(&key) {
+            match key.0 {
+                PointKind::Software | PointKind::Hardware => self.session.cpu.breakpoints.remove(id),
+                _ => self.session.cpu.memory.watchpoints.remove(id),
+            };
+        }
+
+        String::from("OK")
+    }
+}
+
+fn stop_signal(signal: u8) -> String {
+    format!("S{:02x}", signal)
+}
+
+// `addr,len` in hex. gdb may hand us wider addresses than we have, which just wrap
+fn parse_range(args: &str) -> Option<(u16, usize)> {
+    let mut parts = args.splitn(2, ',');
+    let addr = u64::from_str_radix(parts.next()?, 16).ok()? as u16;
+    let len = usize::from_str_radix(parts.next()?, 16).ok()?;
+
+    Some((addr, len))
+}
+
+// `offset,len` slice of an object for qXfer; `l` marks the last chunk
+fn read_chunk(object: &str, args: &str) -> String {
+    let mut parts = args.splitn(2, ',');
+    let offset = parts.next().and_then(|offset| usize::from_str_radix(offset, 16).ok());
+    let len = parts.next().and_then(|len| usize::from_str_radix(len, 16).ok());
+
+    match (offset, len) {
+        (Some(offset), Some(len)) if offset <= object.len() => {
+            let end = offset.saturating_add(len).min(object.len());
+
+            match end == object.len() {
+                true => format!("l{}", &object[offset..end]),
+                false => format!("m{}", &object[offset..end]),
+            }
+        }
+        _ => String::from("E01"),
+    }
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if text.len() % 2 != 0 {
+        return None;
+    }
+
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok()).collect()
+}
+
+fn read_byte<R: Read>(input: &mut R) -> io::Result<Option<u8>> {
+    let mut buf = [0];
+
+    match input.read(&mut buf)? {
+        0 => Ok(None),
+        _ => Ok(Some(buf[0])),
+    }
+}
+
+// reads up to the next `$data#xx` packet, skipping acks and unescaping `}` sequences
+fn read_packet<R: Read>(input: &mut R) -> io::Result<Option<Incoming>> {
+    loop {
+        match read_byte(input)? {
+            Some(b'$') => break,
+            Some(INTERRUPT) => return Ok(Some(Incoming::Interrupt)),
+            Some(_) => continue,
+            None => return Ok(None),
+        }
+    }
+
+    let mut data = Vec::new();
+    let mut sum: u8 = 0;
+
+    loop {
+        let byte = match read_byte(input)? {
+            Some(byte) => byte,
+            None => return Ok(None),
+        };
+
+        if byte == b'#' {
+            break;
+        }
+
+        sum = sum.wrapping_add(byte);
+
+        if byte == ESCAPE {
+            let escaped = match read_byte(input)? {
+                Some(escaped) => escaped,
+                None => return Ok(None),
+            };
+
+            sum = sum.wrapping_add(escaped);
+            data.push(escaped ^ 0x20);
+        } else {
+            data.push(byte);
+        }
+    }
+
+    let mut checksum = [0; 2];
+    input.read_exact(&mut checksum)?;
+
+    let expected = ::std::str::from_utf8(&checksum).ok().and_then(|text| u8::from_str_radix(text, 16).ok());
+
+    match expected == Some(sum) {
+        true => Ok(Some(Incoming::Packet(data))),
+        false => Ok(Some(Incoming::BadChecksum)),
+    }
+}
+
+fn write_packet<W: Write>(output: &mut W, data: &str) -> io::Result<()> {
+    let mut escaped = Vec::with_capacity(data.len());
+
+    for &byte in data.as_bytes() {
+        match byte {
+            b'$' | b'#' | b'}' | b'*' => {
+                escaped.push(ESCAPE);
+                escaped.push(byte ^ 0x20);
+            }
+            _ => escaped.push(byte),
+        }
+    }
+
+    let sum = escaped.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+
+    output.write_all(b"$")?;
+    output.write_all(&escaped)?;
+    write!(output, "#{:02x}", sum)?;
+    output.flush()
+}
+
+// a ^C from the client shows up as a lone byte on the socket while the target is running
+fn poll_interrupt(stream: &TcpStream) -> bool {
+    let mut buf = [0];
+
+    if stream.set_nonblocking(true).is_err() {
+        return false;
+    }
+
+    let interrupted = match stream.peek(&mut buf) {
+        Ok(1) if buf[0] == INTERRUPT => {
+            let mut stream = stream;
+            stream.read_exact(&mut buf).is_ok()
+        }
+        _ => false,
+    };
+
+    stream.set_nonblocking(false).is_ok() && interrupted
+}
+
+// waits for one client on localhost and serves it
+pub fn serve_tcp(session: Session, port: u16) -> io::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    eprintln!("waiting for gdb on {}", listener.local_addr()?);
+
+    let (stream, addr) = listener.accept()?;
+    eprintln!("gdb connected from {}", addr);
+
+    let input = stream.try_clone()?;
+    let output = stream.try_clone()?;
+
+    GdbStub::new(session).serve(input, output, || poll_interrupt(&stream))
+}
+
+// for `target remote | ...`, where stdin and stdout are the connection
+pub fn serve_stdio(session: Session) -> io::Result<()> {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+
+    GdbStub::new(session).serve(stdin.lock(), stdout.lock(), || false)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{GdbStub, Reply};
+    use cpu::Cpu;
+    use debug::session::Session;
+
+    fn stub() -> GdbStub {
+        let mut cpu = Cpu::new();
+
+        cpu.load_program(0x6000,
+                         &[// ldx #$00
+                           0xa2, 0x00,
+                           // inx
+                           0xe8,
+                           // stx $20
+                           0x86, 0x20,
+                           // cpx #$03
+                           0xe0, 0x03,
+                           // bne $6002
+                           0xd0, 0xf9]);
+        cpu.reset();
+
+        GdbStub::new(Session::new(cpu))
+    }
+
+    fn send<P: AsRef<[u8]>>(stub: &mut GdbStub, packet: P) -> String {
+        match stub.handle(packet.as_ref(), &mut || false) {
+            Reply::Send(reply) => reply,
+            Reply::Close(reply) => reply.unwrap_or_default(),
+        }
+    }
+
+    #[test]
+    fn registers_and_memory() {
+        let mut stub = stub();
+
+        assert_eq!(send(&mut stub, "g"), "000000fd006020");
+        assert_eq!(send(&mut stub, "G11223344026081"), "OK");
+        assert_eq!(stub.session.cpu.reg_pc, 0x6002);
+        assert_eq!(stub.session.cpu.reg_x, 0x22);
+        assert!(stub.session.cpu.reg_status.negative);
+
+        assert_eq!(send(&mut stub, "P1=05"), "OK");
+        assert_eq!(send(&mut stub, "p1"), "05");
+        assert_eq!(send(&mut stub, "p4"), "0260");
+
+        assert_eq!(send(&mut stub, "m6000,3"), "a200e8");
+        assert_eq!(send(&mut stub, "M10,2:beef"), "OK");
+        assert_eq!(send(&mut stub, "X12,1:#"), "OK");
+        assert_eq!(send(&mut stub, "m10,3"), "beef23");
+
+        // binary data is bytes, not text
+        assert_eq!(send(&mut stub, b"X6000,2:\xa9\x80"), "OK");
+        assert_eq!(send(&mut stub, "m6000,2"), "a980");
+        assert_eq!(send(&mut stub, "M10,2:be"), "E01");
+    }
+
+    #[test]
+    fn breakpoints_and_watchpoints() {
+        let mut stub = stub();
+
+        assert_eq!(send(&mut stub, "s"), "S05");
+        assert_eq!(stub.session.cpu.reg_pc, 0x6002);
+
+        assert_eq!(send(&mut stub, "Z1,6005,1"), "OK");
+        assert_eq!(send(&mut stub, "c"), "T05hwbreak:;");
+        assert_eq!(stub.session.cpu.reg_pc, 0x6005);
+        assert_eq!(send(&mut stub, "z1,6005,1"), "OK");
+
+        assert_eq!(send(&mut stub, "Z2,20,1"), "OK");
+        assert_eq!(send(&mut stub, "vCont;c"), "T05watch:20;");
+        assert_eq!(send(&mut stub, "z2,20,1"), "OK");
+
+        assert_eq!(send(&mut stub, "c"), "W00");
+    }
+
+    #[test]
+    fn packets() {
+        let mut stub = stub();
+        let mut output = Vec::new();
+
+        // a bad checksum gets a nak and no reply, then gdb stops wanting acks altogether
+        let input: &[u8] = b"+$?#00$?#3f$QStartNoAckMode#b0$qXfer:features:read:target.xml:0,10#XX$m6000,1#90$k#6b";
+
+        stub.serve(input, &mut output, || false).unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(),
+                   "-+$S05#b8+$OK#9a$a2#93");
+    }
+
+    #[test]
+    fn read_chunk() {
+        assert_eq!(super::read_chunk("target", "0,3"), "mtar");
+        assert_eq!(super::read_chunk("target", "3,10"), "lget");
+        assert_eq!(super::read_chunk("target", "2,ffffffffffffffff"), "lrget");
+        assert_eq!(super::read_chunk("target", "7,1"), "E01");
+    }
+}
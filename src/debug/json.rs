@@ -0,0 +1,358 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+// just enough json for the debug adapter protocol
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(BTreeMap<String, Json>),
+}
+
+impl Json {
+    pub fn parse(text: &str) -> Result<Json, String> {
+        let mut parser = Parser {
+            chars: text.chars().collect(),
+            pos: 0,
+        };
+
+        let val = parser.value()?;
+
+        parser.skip_whitespace();
+        match parser.pos == parser.chars.len() {
+            true => Ok(val),
+            false => Err(parser.error("trailing characters")),
+        }
+    }
+
+    pub fn object(fields: Vec<(&str, Json)>) -> Json {
+        Json::Object(fields.into_iter().map(|(key, val)| (String::from(key), val)).collect())
+    }
+
+    // `Null` for anything that isn't an object or doesn't have `key`, so lookups can be chained
+    pub fn get(&self, key: &str) -> &Json {
+        match *self {
+            Json::Object(ref fields) => fields.get(key).unwrap_or(&Json::Null),
+            _ => &Json::Null,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Json::String(ref val) => Some(val),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Json::Number(val) if val.fract() == 0.0 => Some(val as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Json::Bool(val) => Some(val),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> &[Json] {
+        match *self {
+            Json::Array(ref vals) => vals,
+            _ => &[],
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        *self == Json::Null
+    }
+}
+
+impl<'a> From<&'a str> for Json {
+    fn from(val: &'a str) -> Json {
+        Json::String(String::from(val))
+    }
+}
+
+impl From<String> for Json {
+    fn from(val: String) -> Json {
+        Json::String(val)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(val: bool) -> Json {
+        Json::Bool(val)
+    }
+}
+
+impl From<i64> for Json {
+    fn from(val: i64) -> Json {
+        Json::Number(val as f64)
+    }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(vals: Vec<Json>) -> Json {
+        Json::Array(vals)
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(val) => write!(f, "{}", val),
+            Json::Number(val) => write!(f, "{}", val),
+            Json::String(ref val) => write_string(f, val),
+            Json::Array(ref vals) => {
+                write!(f, "[")?;
+
+                for (i, val) in vals.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+
+                    write!(f, "{}", val)?;
+                }
+
+                write!(f, "]")
+            }
+            Json::Object(ref fields) => {
+                write!(f, "{{")?;
+
+                for (i, (key, val)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+
+                    write_string(f, key)?;
+                    write!(f, ":{}", val)?;
+                }
+
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, val: &str) -> fmt::Result {
+    write!(f, "\"")?;
+
+    for c in val.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+
+    write!(f, "\"")
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn error(&self, msg: &str) -> String {
+        format!("{} at offset {}", msg, self.pos)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += 1;
+
+        c
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect_word(&mut self, word: &str, val: Json) -> Result<Json, String> {
+        for expected in word.chars() {
+            if self.next() != Some(expected) {
+                return Err(self.error(&format!("expected '{}'", word)));
+            }
+        }
+
+        Ok(val)
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+
+        match self.peek() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => self.string().map(Json::String),
+            Some('t') => self.expect_word("true", Json::Bool(true)),
+            Some('f') => self.expect_word("false", Json::Bool(false)),
+            Some('n') => self.expect_word("null", Json::Null),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        let mut fields = BTreeMap::new();
+        self.pos += 1;
+
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(Json::Object(fields));
+        }
+
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('"') {
+                return Err(self.error("expected a key"));
+            }
+
+            let key = self.string()?;
+
+            self.skip_whitespace();
+            if self.next() != Some(':') {
+                return Err(self.error("expected ':'"));
+            }
+
+            let val = self.value()?;
+            fields.insert(key, val);
+
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some('}') => return Ok(Json::Object(fields)),
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        let mut vals = Vec::new();
+        self.pos += 1;
+
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.pos += 1;
+            return Ok(Json::Array(vals));
+        }
+
+        loop {
+            vals.push(self.value()?);
+
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some(']') => return Ok(Json::Array(vals)),
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let mut val = String::new();
+        self.pos += 1;
+
+        loop {
+            match self.next() {
+                Some('"') => return Ok(val),
+                Some('\\') => {
+                    let escaped = match self.next() {
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('u') => self.unicode_escape()?,
+                        Some(c) => c,
+                        None => return Err(self.error("unterminated string")),
+                    };
+
+                    val.push(escaped);
+                }
+                Some(c) => val.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    // `\uXXXX`, pairing up surrogates
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex4()?;
+
+        if (0xd800..0xdc00).contains(&high) && self.peek() == Some('\\') && self.chars.get(self.pos + 1) == Some(&'u') {
+            self.pos += 2;
+            let low = self.hex4()?;
+
+            let code = 0x10000 + ((high - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff);
+            return Ok(::std::char::from_u32(code).unwrap_or('\u{fffd}'));
+        }
+
+        Ok(::std::char::from_u32(high).unwrap_or('\u{fffd}'))
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits: String = (0..4).filter_map(|_| self.next()).collect();
+
+        u32::from_str_radix(&digits, 16).map_err(|_| self.error("bad \\u escape"))
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.pos;
+
+        while self.peek().is_some_and(|c| c.is_ascii_digit() || "+-.eE".contains(c)) {
+            self.pos += 1;
+        }
+
+        let text: String = self.chars[start..self.pos].iter().collect();
+        text.parse().map(Json::Number).map_err(|_| self.error("bad number"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Json;
+
+    #[test]
+    fn round_trip() {
+        let text = r#" {"seq": 1, "args": {"lines": [1, 2.5, -3], "ok": true, "none": null}, "name": "a\"bé\n"} "#;
+        let json = Json::parse(text).unwrap();
+
+        assert_eq!(json.get("seq").as_i64(), Some(1));
+        assert_eq!(json.get("args").get("lines").as_array().len(), 3);
+        assert_eq!(json.get("args").get("ok").as_bool(), Some(true));
+        assert!(json.get("args").get("none").is_null());
+        assert!(json.get("missing").get("deeper").is_null());
+        assert_eq!(json.get("name").as_str(), Some("a\"b\u{e9}\n"));
+
+        assert_eq!(json.to_string(), r#"{"args":{"lines":[1,2.5,-3],"none":null,"ok":true},"name":"a\"bé\n","seq":1}"#);
+        assert_eq!(Json::parse(&json.to_string()).unwrap(), json);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(Json::parse("{\"a\" 1}").is_err());
+        assert!(Json::parse("[1, 2").is_err());
+        assert!(Json::parse("\"open").is_err());
+        assert!(Json::parse("1 2").is_err());
+    }
+}
@@ -0,0 +1,11 @@
+pub mod reverse;
+pub mod expr;
+pub mod condition;
+pub mod disasm;
+pub mod session;
+pub mod repl;
+pub mod tui;
+pub mod gdb;
+pub mod json;
+pub mod sourcemap;
+pub mod dap;
@@ -0,0 +1,745 @@
+use std::fmt::Write as FmtWrite;
+use std::fs;
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::process;
+
+use asm;
+use cpu::{Cpu, ProcessorStatusRegister};
+use cpu::breakpoint::{StopReason, WatchKind};
+use cpu::callstack::FrameKind;
+
+use super::condition::{LogTemplate, Point};
+use super::disasm;
+use super::gdb;
+use super::expr::{Expr, ExprError};
+use super::session::{self, Session};
+use super::tui;
+
+const DEFAULT_LOAD_ADDR: u16 = 0x6000;
+const DEFAULT_DUMP_LEN: i64 = 0x40;
+const DEFAULT_DISASM_LINES: i64 = 10;
+const BYTES_PER_ROW: usize = 16;
+
+const HELP: &'static str = "\
+step (s) [n]                     execute n instructions
+next (n) [n]                     step, running over jsr calls
+finish (o)                       run until the current subroutine returns
+continue (c)                     run until a breakpoint, watchpoint or halt
+back (bs)                        step back one instruction
+rc                               run backwards to the previous breakpoint or watched change
+break (b) <addr> [end] [if c]    break on pc in addr..end
+watch (w) [r|w|rw] <addr> [end] [= val] [if c]
+                                 stop on memory reads/writes
+log <addr> <message>             print message at addr without stopping, e.g. log $6000 x={X:d}
+cond <id> [expr]                 set or clear a breakpoint condition (w<id> for watchpoints)
+delete|enable|disable <id>       manage breakpoints (w<id> for watchpoints)
+breaks                           list breakpoints and watchpoints
+bt                               show the calls and interrupts that led to pc
+regs (r) [reg=val ...]           show or set registers (A X Y SP PC P P.C ...)
+mem (m) <addr> [len]             dump memory
+fill (f) <start> <end> <val>     fill memory
+poke (e) <addr> <val> [val ...]  write bytes
+disasm (d) [addr] [n]            disassemble at pc or addr
+print (p) <expr>                 evaluate an expression, e.g. p w[$fffc] + 1
+sym [name]                       list symbols or look one up
+quit (q)";
+
+pub enum Control {
+    Continue,
+    Quit,
+}
+
+pub struct Repl {
+    pub session: Session,
+
+    last_command: String,
+}
+
+impl Repl {
+    pub fn new(session: Session) -> Self {
+        Repl {
+            session: session,
+            last_command: String::new(),
+        }
+    }
+
+    // reads commands until eof or `quit`. scripts echo each command so the transcript reads like a session
+    pub fn run<R: BufRead, W: Write>(&mut self, mut input: R, out: &mut W, interactive: bool) -> io::Result<()> {
+        loop {
+            if interactive {
+                write!(out, "({:04x})> ", self.session.cpu.reg_pc)?;
+                out.flush()?;
+            }
+
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+
+            let line = line.trim();
+            if !interactive {
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+
+                writeln!(out, "> {}", line)?;
+            }
+
+            if let Control::Quit = self.execute(line, out)? {
+                return Ok(());
+            }
+        }
+    }
+
+    // an empty line repeats the last command, like most monitors
+    pub fn execute<W: Write>(&mut self, line: &str, out: &mut W) -> io::Result<Control> {
+        let line = match line.is_empty() {
+            true => self.last_command.clone(),
+            false => String::from(line),
+        };
+        self.last_command = line.clone();
+
+        let mut output = String::new();
+        let control = match self.command(&line, &mut output) {
+            Ok(control) => control,
+            Err(msg) => {
+                writeln!(output, "error: {}", msg).unwrap();
+                Control::Continue
+            }
+        };
+
+        write!(out, "{}", output)?;
+
+        Ok(control)
+    }
+
+    fn command(&mut self, line: &str, out: &mut String) -> Result<Control, String> {
+        let mut parts = line.splitn(2, char::is_whitespace);
+        let name = parts.next().unwrap_or("");
+        let rest = parts.next().unwrap_or("").trim();
+        let args: Vec<&str> = rest.split_whitespace().collect();
+
+        match name {
+            "" => {}
+            "help" | "h" | "?" => writeln!(out, "{}", HELP).unwrap(),
+            "quit" | "q" => return Ok(Control::Quit),
+
+            "step" | "s" => self.motion(out, &args, Session::step)?,
+            "next" | "n" => self.motion(out, &args, Session::step_over)?,
+            "finish" | "o" => self.motion(out, &[], Session::step_out)?,
+            "continue" | "c" => self.motion(out, &[], Session::continue_)?,
+            "back" | "bs" => {
+                match self.session.step_back()? {
+                    true => self.print_current(out),
+                    false => writeln!(out, "no history before this point").unwrap(),
+                }
+            }
+            "rc" => {
+                match self.session.reverse_continue()? {
+                    Some(_) => {}
+                    None => writeln!(out, "reached the start of history").unwrap(),
+                }
+
+                self.print_current(out);
+            }
+
+            "break" | "b" => self.add_breakpoint(out, rest)?,
+            "watch" | "w" => self.add_watchpoint(out, rest)?,
+            "log" => self.add_logpoint(out, rest)?,
+            "cond" => self.set_condition(rest)?,
+            "delete" | "del" => {
+                let point = self.point(&args)?;
+                self.session.conditions.remove(point);
+
+                let removed = match point {
+                    Point::Breakpoint(id) => self.session.cpu.breakpoints.remove(id),
+                    Point::Watchpoint(id) => self.session.cpu.memory.watchpoints.remove(id),
+                };
+
+                if !removed {
+                    return Err(String::from("no such breakpoint"));
+                }
+            }
+            "enable" | "disable" => {
+                let enabled = name == "enable";
+                let found = match self.point(&args)? {
+                    Point::Breakpoint(id) => self.session.cpu.breakpoints.set_enabled(id, enabled),
+                    Point::Watchpoint(id) => self.session.cpu.memory.watchpoints.set_enabled(id, enabled),
+                };
+
+                if !found {
+                    return Err(String::from("no such breakpoint"));
+                }
+            }
+            "breaks" => self.list_breakpoints(out),
+            "bt" => self.backtrace(out),
+
+            "regs" | "r" => {
+                for assignment in &args {
+                    self.set_register(assignment)?;
+                }
+
+                self.print_registers(out);
+            }
+            "mem" | "m" => {
+                let addr = self.arg(&args, 0)? as u16;
+                let len = self.optional_arg(&args, 1, DEFAULT_DUMP_LEN)? as usize;
+
+                self.dump(out, addr, len);
+            }
+            "fill" | "f" => {
+                let start = self.arg(&args, 0)? as u16;
+                let end = self.arg(&args, 1)? as u16;
+                let val = self.arg(&args, 2)? as u8;
+
+                for addr in start as u32..end as u32 + 1 {
+                    self.session.cpu.memory.write_at(&(addr as u16), &[val]);
+                }
+            }
+            "poke" | "e" => {
+                let addr = self.arg(&args, 0)? as u16;
+                if args.len() < 2 {
+                    return Err(String::from("expected bytes to write"));
+                }
+
+                let bytes = (1..args.len()).map(|i| self.arg(&args, i).map(|val| val as u8)).collect::<Result<Vec<u8>, String>>()?;
+                self.session.cpu.memory.write_at(&addr, &bytes);
+            }
+            "disasm" | "d" => {
+                let pc = self.session.cpu.reg_pc as i64;
+                let addr = self.optional_arg(&args, 0, pc)? as u16;
+                let count = self.optional_arg(&args, 1, DEFAULT_DISASM_LINES)? as usize;
+
+                self.disassemble(out, addr, count);
+            }
+            "print" | "p" => {
+                let val = self.eval(rest)?;
+                writeln!(out, "${:x} ({})", val, val).unwrap();
+            }
+            "sym" => {
+                match args.get(0) {
+                    Some(name) => {
+                        match self.session.symbols.get(*name) {
+                            Some(val) => writeln!(out, "{} = ${:x}", name, val).unwrap(),
+                            None => return Err(format!("unknown symbol '{}'", name)),
+                        }
+                    }
+                    None => {
+                        let mut symbols: Vec<_> = self.session.symbols.iter().collect();
+                        symbols.sort_by_key(|&(_, val)| *val);
+
+                        for (name, val) in symbols {
+                            writeln!(out, "{} = ${:x}", name, val).unwrap();
+                        }
+                    }
+                }
+            }
+
+            _ => return Err(format!("unknown command '{}' (try help)", name)),
+        }
+
+        Ok(Control::Continue)
+    }
+
+    fn motion<F>(&mut self, out: &mut String, args: &[&str], motion: F) -> Result<(), String>
+        where F: Fn(&mut Session) -> Result<Option<StopReason>, ExprError>
+    {
+        let count = self.optional_arg(args, 0, 1)?;
+
+        let mut stop = None;
+        for _ in 0..count {
+            stop = motion(&mut self.session).map_err(|err| err.to_string())?;
+
+            if stop.is_some() {
+                break;
+            }
+        }
+
+        for line in self.session.conditions.log.drain(..) {
+            writeln!(out, "{}", line).unwrap();
+        }
+
+        if let Some(reason) = stop {
+            writeln!(out, "{}", describe_stop(&reason)).unwrap();
+        }
+
+        self.print_current(out);
+
+        Ok(())
+    }
+
+    // splits off a trailing `if <condition>`
+    fn split_condition(text: &str) -> Result<(&str, Option<Expr>), String> {
+        match text.find(" if ") {
+            Some(i) => {
+                let condition = Expr::parse(text[i + 4..].trim()).map_err(|err| err.to_string())?;
+                Ok((&text[..i], Some(condition)))
+            }
+            None => Ok((text, None)),
+        }
+    }
+
+    fn add_breakpoint(&mut self, out: &mut String, rest: &str) -> Result<(), String> {
+        let (range, condition) = Repl::split_condition(rest)?;
+        let args: Vec<&str> = range.split_whitespace().collect();
+
+        let start = self.arg(&args, 0)? as u16;
+        let end = self.optional_arg(&args, 1, start as i64)? as u16;
+
+        let id = self.session.cpu.breakpoints.add(start, end);
+        self.session.conditions.set_condition(Point::Breakpoint(id), condition);
+
+        writeln!(out, "breakpoint {} at ${:04x}", id, start).unwrap();
+
+        Ok(())
+    }
+
+    fn add_watchpoint(&mut self, out: &mut String, rest: &str) -> Result<(), String> {
+        let (spec, condition) = Repl::split_condition(rest)?;
+
+        let (range, value) = match spec.find('=') {
+            Some(i) => (&spec[..i], Some(self.eval(spec[i + 1..].trim())? as u8)),
+            None => (spec, None),
+        };
+
+        let mut args: Vec<&str> = range.split_whitespace().collect();
+        let kind = match args.first().cloned() {
+            Some("r") => WatchKind::Read,
+            Some("w") => WatchKind::Write,
+            Some("rw") => WatchKind::Access,
+            _ => {
+                args.insert(0, "w");
+                WatchKind::Write
+            }
+        };
+        args.remove(0);
+
+        let start = self.arg(&args, 0)? as u16;
+        let end = self.optional_arg(&args, 1, start as i64)? as u16;
+
+        let id = self.session.cpu.memory.watchpoints.add(start, end, kind, value);
+        self.session.conditions.set_condition(Point::Watchpoint(id), condition);
+
+        writeln!(out, "watchpoint w{} on ${:04x}-${:04x}", id, start, end).unwrap();
+
+        Ok(())
+    }
+
+    fn add_logpoint(&mut self, out: &mut String, rest: &str) -> Result<(), String> {
+        let mut parts = rest.splitn(2, char::is_whitespace);
+        let addr = self.eval(parts.next().unwrap_or(""))? as u16;
+        let template = LogTemplate::parse(parts.next().unwrap_or("").trim()).map_err(|err| err.to_string())?;
+
+        let id = self.session.cpu.breakpoints.add(addr, addr);
+        self.session.conditions.set_log(Point::Breakpoint(id), Some(template));
+
+        writeln!(out, "logpoint {} at ${:04x}", id, addr).unwrap();
+
+        Ok(())
+    }
+
+    fn set_condition(&mut self, rest: &str) -> Result<(), String> {
+        let mut parts = rest.splitn(2, char::is_whitespace);
+        let point = self.point(&[parts.next().unwrap_or("")])?;
+
+        let condition = match parts.next().map(|text| text.trim()) {
+            Some(text) if !text.is_empty() => Some(Expr::parse(text).map_err(|err| err.to_string())?),
+            _ => None,
+        };
+
+        self.session.conditions.set_condition(point, condition);
+
+        Ok(())
+    }
+
+    // `3` is breakpoint 3, `w3` is watchpoint 3
+    fn point(&self, args: &[&str]) -> Result<Point, String> {
+        let arg = args.get(0).cloned().unwrap_or("");
+
+        let (is_watch, digits) = match arg.starts_with('w') {
+            true => (true, &arg[1..]),
+            false => (false, arg),
+        };
+
+        let id = digits.parse().map_err(|_| format!("bad breakpoint id '{}'", arg))?;
+
+        match is_watch {
+            true => Ok(Point::Watchpoint(id)),
+            false => Ok(Point::Breakpoint(id)),
+        }
+    }
+
+    fn list_breakpoints(&self, out: &mut String) {
+        for point in self.session.cpu.breakpoints.iter() {
+            writeln!(out,
+                     "{:>3}  ${:04x}-${:04x}  {:<8} hits: {}",
+                     point.id,
+                     point.start,
+                     point.end,
+                     enabled_str(point.enabled),
+                     point.hits)
+                .unwrap();
+        }
+
+        for point in self.session.cpu.memory.watchpoints.iter() {
+            let value = point.value.map(|val| format!(" = ${:02x}", val)).unwrap_or_default();
+
+            writeln!(out,
+                     "{:>3}  ${:04x}-${:04x}  {:<8} hits: {}  {:?}{}",
+                     format!("w{}", point.id),
+                     point.start,
+                     point.end,
+                     enabled_str(point.enabled),
+                     point.hits(),
+                     point.kind,
+                     value)
+                .unwrap();
+        }
+    }
+
+    fn set_register(&mut self, assignment: &str) -> Result<(), String> {
+        let mut parts = assignment.splitn(2, '=');
+        let reg = parts.next().unwrap_or("").to_uppercase();
+        let val = self.eval(parts.next().ok_or_else(|| format!("expected {}=value", reg))?)?;
+
+        let cpu = &mut self.session.cpu;
+        match reg.as_str() {
+            "A" => cpu.reg_acc = val as i8,
+            "X" => cpu.reg_x = val as i8,
+            "Y" => cpu.reg_y = val as i8,
+            "S" | "SP" => cpu.reg_sp = val as u8,
+            "PC" => cpu.reg_pc = val as u16,
+            "P" => cpu.reg_status = ProcessorStatusRegister::from(val as u8),
+            "P.C" => cpu.reg_status.carry = val != 0,
+            "P.Z" => cpu.reg_status.zero = val != 0,
+            "P.I" => cpu.reg_status.irq_disable = val != 0,
+            "P.D" => cpu.reg_status.decimal_mode = val != 0,
+            "P.B" => cpu.reg_status.brk = val != 0,
+            "P.V" => cpu.reg_status.overflow = val != 0,
+            "P.N" => cpu.reg_status.negative = val != 0,
+            _ => return Err(format!("unknown register '{}'", reg)),
+        }
+
+        Ok(())
+    }
+
+    fn print_registers(&self, out: &mut String) {
+        writeln!(out, "{}", format_registers(&self.session.cpu)).unwrap();
+    }
+
+    fn print_current(&self, out: &mut String) {
+        self.print_registers(out);
+
+        let pc = self.session.cpu.reg_pc;
+        self.disassemble(out, pc, 1);
+    }
+
+    fn dump(&self, out: &mut String, addr: u16, len: usize) {
+        let bytes: Vec<u8> = (0..len).map(|i| self.session.cpu.memory.peek_u8_at(&addr.wrapping_add(i as u16))).collect();
+
+        for (row, chunk) in bytes.chunks(BYTES_PER_ROW).enumerate() {
+            let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+            let ascii: String = chunk.iter()
+                .map(|b| match *b {
+                    0x20..=0x7e => *b as char,
+                    _ => '.',
+                })
+                .collect();
+
+            writeln!(out,
+                     "${:04x}  {:<width$}  {}",
+                     addr.wrapping_add((row * BYTES_PER_ROW) as u16),
+                     hex.join(" "),
+                     ascii,
+                     width = BYTES_PER_ROW * 3 - 1)
+                .unwrap();
+        }
+    }
+
+    // innermost first, each frame showing the instruction that made the call and where it went
+    fn backtrace(&self, out: &mut String) {
+        writeln!(out, "#0  ${:04x}", self.session.cpu.reg_pc).unwrap();
+
+        for (i, frame) in self.session.cpu.call_stack.frames().iter().rev().enumerate() {
+            let kind = match frame.kind {
+                FrameKind::Subroutine => "jsr",
+                FrameKind::Brk => "brk",
+                FrameKind::Irq => "irq",
+                FrameKind::Nmi => "nmi",
+            };
+
+            let target = match self.session.symbol_at(frame.target) {
+                Some(name) => String::from(name),
+                None => format!("${:04x}", frame.target),
+            };
+
+            writeln!(out, "#{}  ${:04x}  {} {}", i + 1, frame.caller, kind, target).unwrap();
+        }
+    }
+
+    fn disassemble(&self, out: &mut String, addr: u16, count: usize) {
+        let pc = self.session.cpu.reg_pc;
+
+        for line in disasm::disassemble_range(&self.session.cpu, addr, count) {
+            if let Some(name) = self.session.symbol_at(line.addr) {
+                writeln!(out, "{}:", name).unwrap();
+            }
+
+            let bytes: Vec<String> = line.bytes.iter().map(|b| format!("{:02x}", b)).collect();
+            let marker = match line.addr == pc {
+                true => '>',
+                false => ' ',
+            };
+
+            writeln!(out, "{} ${:04x}  {:<8}  {}", marker, line.addr, bytes.join(" "), line.text).unwrap();
+        }
+    }
+
+    fn eval(&self, text: &str) -> Result<i64, String> {
+        Expr::parse(text)
+            .and_then(|expr| expr.eval(&self.session.cpu, &self.session.symbols))
+            .map_err(|err| err.to_string())
+    }
+
+    fn arg(&self, args: &[&str], index: usize) -> Result<i64, String> {
+        match args.get(index) {
+            Some(arg) => self.eval(arg),
+            None => Err(format!("missing argument {}", index + 1)),
+        }
+    }
+
+    fn optional_arg(&self, args: &[&str], index: usize, default: i64) -> Result<i64, String> {
+        match args.get(index) {
+            Some(arg) => self.eval(arg),
+            None => Ok(default),
+        }
+    }
+}
+
+pub fn format_registers(cpu: &Cpu) -> String {
+    let status: u8 = cpu.reg_status.clone().into();
+    let flags: String = "NV-BDIZC"
+        .chars()
+        .enumerate()
+        .map(|(i, flag)| match status & (0x80 >> i) {
+            0 => flag.to_ascii_lowercase(),
+            _ => flag,
+        })
+        .collect();
+
+    format!("PC:{:04x} A:{:02x} X:{:02x} Y:{:02x} SP:{:02x} P:{:02x} {} CYC:{}",
+            cpu.reg_pc,
+            cpu.reg_acc,
+            cpu.reg_x,
+            cpu.reg_y,
+            cpu.reg_sp,
+            status,
+            flags,
+            cpu.cycles)
+}
+
+pub fn describe_stop(reason: &StopReason) -> String {
+    match *reason {
+        StopReason::Halted => String::from("halted"),
+        StopReason::Breakpoint { id, pc } => format!("breakpoint {} at ${:04x}", id, pc),
+        StopReason::Watchpoint { id, addr, value, kind } => {
+            let access = match kind {
+                WatchKind::Read => "read",
+                _ => "write",
+            };
+
+            format!("watchpoint w{}: {} ${:02x} at ${:04x}", id, access, value, addr)
+        }
+    }
+}
+
+fn enabled_str(enabled: bool) -> &'static str {
+    match enabled {
+        true => "enabled",
+        false => "disabled",
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("usage: debug <program> [--at <addr>] [--symbols <file>] [--script <file>] [--tui] [--gdb <port|->]");
+    process::exit(1);
+}
+
+fn fail(msg: String) -> ! {
+    eprintln!("{}", msg);
+    process::exit(1);
+}
+
+// `debug <program> [--at <addr>] [--symbols <file>] [--script <file>] [--tui] [--gdb <port|->]`
+pub fn main(args: &[String]) {
+    let mut program = None;
+    let mut load_addr = DEFAULT_LOAD_ADDR;
+    let mut symbols = None;
+    let mut script = None;
+    let mut full_screen = false;
+    let mut gdb = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--at" => {
+                load_addr = match args.next().and_then(|addr| asm::parse_number(addr)) {
+                    Some(addr) => addr as u16,
+                    None => usage(),
+                }
+            }
+            "--symbols" => symbols = Some(args.next().unwrap_or_else(|| usage()).clone()),
+            "--script" => script = Some(args.next().unwrap_or_else(|| usage()).clone()),
+            "--tui" => full_screen = true,
+            "--gdb" => gdb = Some(args.next().unwrap_or_else(|| usage()).clone()),
+            _ if program.is_none() => program = Some(arg.clone()),
+            _ => usage(),
+        }
+    }
+
+    let program = program.unwrap_or_else(|| usage());
+    let bytes = fs::read(&program).unwrap_or_else(|err| fail(format!("{}: {}", program, err)));
+
+    let mut cpu = Cpu::new();
+    session::load_image(&mut cpu, &bytes, load_addr).unwrap_or_else(|err| fail(format!("{}: {}", program, err)));
+
+    let mut session = Session::new(cpu);
+    if let Some(path) = symbols {
+        let text = fs::read_to_string(&path).unwrap_or_else(|err| fail(format!("{}: {}", path, err)));
+        session.symbols = session::parse_symbols(&text).unwrap_or_else(|err| fail(format!("{}: {}", path, err)));
+    }
+
+    // `-` serves gdb over stdin/stdout, anything else is a port on localhost
+    if let Some(target) = gdb {
+        let result = match target.as_str() {
+            "-" => gdb::serve_stdio(session),
+            port => gdb::serve_tcp(session, port.parse().unwrap_or_else(|_| usage())),
+        };
+
+        if let Err(err) = result {
+            fail(err.to_string());
+        }
+
+        return;
+    }
+
+    if full_screen {
+        if let Err(err) = tui::run(session) {
+            fail(err.to_string());
+        }
+
+        return;
+    }
+
+    let mut repl = Repl::new(session);
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    let result = match script {
+        Some(path) => {
+            let file = fs::File::open(&path).unwrap_or_else(|err| fail(format!("{}: {}", path, err)));
+            repl.run(BufReader::new(file), &mut out, false)
+        }
+        None => {
+            let stdin = io::stdin();
+            let input = stdin.lock();
+            repl.run(input, &mut out, true)
+        }
+    };
+
+    if let Err(err) = result {
+        fail(err.to_string());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Repl;
+    use cpu::Cpu;
+    use debug::session::Session;
+
+    fn run(script: &str) -> String {
+        let mut cpu = Cpu::new();
+
+        cpu.load_program(0x6000,
+                         &[// ldx #$00
+                           0xa2, 0x00,
+                           // inx
+                           0xe8,
+                           // stx $20
+                           0x86, 0x20,
+                           // cpx #$05
+                           0xe0, 0x05,
+                           // bne $6002
+                           0xd0, 0xf9]);
+        cpu.reset();
+
+        let mut session = Session::new(cpu);
+        session.symbols.insert(String::from("loop"), 0x6002);
+
+        let mut out = Vec::new();
+        Repl::new(session).run(script.as_bytes(), &mut out, false).unwrap();
+
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn step_and_registers() {
+        let out = run("s 2\nr X=$42 P.C=1\n");
+
+        assert!(out.contains("> s 2\nPC:6003 A:00 X:01"));
+        assert!(out.contains("> $6003  86 20     stx $20"));
+        assert!(out.contains("PC:6003 A:00 X:42 Y:00 SP:fd P:21 nv-bdizC"));
+    }
+
+    #[test]
+    fn breakpoints_and_watchpoints() {
+        let out = run("b loop if X == 3\nc\nw $20 = 4\nbreaks\nc\nm $20 1\nq\nc\n");
+
+        assert!(out.contains("breakpoint 1 at $6002"));
+        assert!(out.contains("breakpoint 1 at $6002\nPC:6002 A:00 X:03"));
+        assert!(out.contains("w1  $0020-$0020  enabled  hits: 0  Write = $04"));
+        assert!(out.contains("watchpoint w1: write $04 at $0020"));
+        assert!(out.contains("$0020  04"));
+
+        // nothing runs after quit
+        assert_eq!(out.matches("> c").count(), 2);
+    }
+
+    #[test]
+    fn memory_and_disassembly() {
+        let out = run("f $10 $13 $ea\ne $12 1 2\nm $10 4\nd loop 2\np [loop] + 1\nsym\nfoo\n");
+
+        assert!(out.contains("$0010  ea ea 01 02"));
+        assert!(out.contains("loop:\n  $6002  e8        inx\n  $6003  86 20     stx $20"));
+        assert!(out.contains("$e9 (233)"));
+        assert!(out.contains("loop = $6002"));
+        assert!(out.contains("error: unknown command 'foo'"));
+    }
+
+    #[test]
+    fn logpoints_and_reverse() {
+        let out = run("log $6005 x={X:d}\nc\nbs\nr\n");
+
+        assert!(out.contains("x=1\nx=2\nx=3\nx=4\nx=5\nhalted"));
+        assert!(out.contains("> bs\nPC:6009 A:00 X:05"));
+    }
+
+    #[test]
+    fn backtrace() {
+        let mut cpu = Cpu::new();
+
+        // jsr $6004 / brk / jsr $6007 / nop
+        cpu.load_program(0x6000, &[0x20, 0x04, 0x60, 0x00, 0x20, 0x07, 0x60, 0xea]);
+        cpu.reset();
+
+        let mut session = Session::new(cpu);
+        session.symbols.insert(String::from("inner"), 0x6007);
+
+        let mut out = Vec::new();
+        Repl::new(session).run("s 2\nbt\n".as_bytes(), &mut out, false).unwrap();
+
+        assert!(String::from_utf8(out).unwrap().contains("> bt\n#0  $6007\n#1  $6004  jsr inner\n#2  $6000  jsr $6004\n"));
+    }
+}
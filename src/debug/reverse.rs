@@ -0,0 +1,255 @@
+use cpu::Cpu;
+use cpu::rewind::RewindBuffer;
+use cpu::snapshot::SnapshotError;
+
+pub const DEFAULT_SNAPSHOT_INTERVAL: u64 = 10_000;
+pub const DEFAULT_CAPACITY: usize = 1000;
+
+// steps backwards by restoring the closest snapshot before the target and replaying forward to it. `Cpu::step` is
+// deterministic, so the replay always lands on exactly the state the cpu was in the first time around
+pub struct ReverseDebugger {
+    pub history: RewindBuffer,
+}
+
+impl Default for ReverseDebugger {
+    fn default() -> Self {
+        ReverseDebugger::new(DEFAULT_SNAPSHOT_INTERVAL, DEFAULT_CAPACITY)
+    }
+}
+
+impl ReverseDebugger {
+    pub fn new(interval: u64, capacity: usize) -> Self {
+        ReverseDebugger { history: RewindBuffer::new(interval, capacity) }
+    }
+
+    // forgets any history and starts recording from the cpu's current state
+    pub fn attach(&mut self, cpu: &Cpu) {
+        self.history.clear();
+        self.history.record(cpu);
+    }
+
+    // runs the cpu through its next instruction, recording history as it goes. returns false once the cpu halts
+    pub fn step(&mut self, cpu: &mut Cpu) -> bool {
+        let running = ReverseDebugger::next_instruction(cpu);
+        self.history.tick(cpu);
+
+        running
+    }
+
+    pub fn step_back(&mut self, cpu: &mut Cpu) -> Result<bool, SnapshotError> {
+        match cpu.instructions {
+            0 => Ok(false),
+            instructions => self.goto_instruction(cpu, instructions - 1),
+        }
+    }
+
+    // moves the cpu to just after its `target`th instruction; false if that's outside the recorded history
+    pub fn goto_instruction(&mut self, cpu: &mut Cpu, target: u64) -> Result<bool, SnapshotError> {
+        if self.history.rewind_to_instruction(cpu, target)?.is_none() {
+            return Ok(false);
+        }
+
+        let trace = cpu.trace;
+        cpu.trace = false;
+
+        let mut running = true;
+        while running && cpu.instructions < target {
+            running = ReverseDebugger::next_instruction(cpu);
+        }
+
+        cpu.trace = trace;
+        cpu.stop_reason = None;
+
+        Ok(cpu.instructions == target)
+    }
+
+    // runs backwards to the most recent instruction boundary where `hit` holds (e.g. pc sitting on a breakpoint).
+    // returns the instruction count it stopped at; if nothing matched the cpu is left at the oldest recorded state
+    pub fn reverse_continue<F>(&mut self, cpu: &mut Cpu, mut hit: F) -> Result<Option<u64>, SnapshotError>
+        where F: FnMut(&Cpu) -> bool
+    {
+        let trace = cpu.trace;
+        cpu.trace = false;
+
+        let mut end = cpu.instructions;
+        let mut found = None;
+        let mut restored = false;
+
+        // scan one snapshot's worth of history at a time, newest first, keeping the last hit in each
+        while end > 0 && found.is_none() {
+            if self.history.rewind_to_instruction(cpu, end - 1)?.is_none() {
+                break;
+            }
+
+            restored = true;
+
+            let start = cpu.instructions;
+            loop {
+                if hit(cpu) {
+                    found = Some(cpu.instructions);
+                }
+
+                if cpu.instructions + 1 >= end || !ReverseDebugger::next_instruction(cpu) {
+                    break;
+                }
+            }
+
+            end = start;
+        }
+
+        cpu.trace = trace;
+        cpu.stop_reason = None;
+
+        match found {
+            Some(target) => {
+                self.goto_instruction(cpu, target)?;
+            }
+            None if restored => {
+                self.history.rewind(cpu, 1)?;
+            }
+            None => {}
+        }
+
+        Ok(found)
+    }
+
+    // finds the instruction that last changed `addr` and stops just after it. writes that store the value that was
+    // already there can't be told apart from no write at all this way
+    pub fn last_write(&mut self, cpu: &mut Cpu, addr: u16) -> Result<Option<u64>, SnapshotError> {
+        let val = cpu.memory.peek_u8_at(&addr);
+
+        match self.reverse_continue(cpu, |cpu| cpu.memory.peek_u8_at(&addr) != val)? {
+            Some(before_write) => {
+                self.goto_instruction(cpu, before_write + 1)?;
+
+                Ok(Some(before_write + 1))
+            }
+            None => Ok(None),
+        }
+    }
+
+    // also returns early, without having executed anything, when a breakpoint stops the cpu first
+    fn next_instruction(cpu: &mut Cpu) -> bool {
+        let instructions = cpu.instructions;
+
+        while cpu.instructions == instructions {
+            if !cpu.step() {
+                return false;
+            }
+
+            if cpu.stop_reason.is_some() {
+                break;
+            }
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ReverseDebugger;
+    use cpu::Cpu;
+
+    fn program() -> Cpu {
+        let mut cpu = Cpu::new();
+        cpu.trace = false;
+
+        cpu.load_program(0x6000,
+                         &[// ldx #$00
+                           0xa2, 0x00,
+                           // inx
+                           0xe8,
+                           // stx $20
+                           0x86, 0x20,
+                           // cpx #$0a
+                           0xe0, 0x0a,
+                           // bne $6002
+                           0xd0, 0xf9,
+                           // lda #$aa
+                           0xa9, 0xaa,
+                           // sta $10
+                           0x85, 0x10,
+                           // lda #$01
+                           0xa9, 0x01,
+                           // sta $11
+                           0x85, 0x11]);
+        cpu.reset();
+
+        cpu
+    }
+
+    fn run_to_end(debugger: &mut ReverseDebugger, cpu: &mut Cpu) {
+        debugger.attach(cpu);
+
+        while debugger.step(cpu) {}
+    }
+
+    #[test]
+    fn step_back() {
+        let mut cpu = program();
+        let mut debugger = ReverseDebugger::new(20, 100);
+        debugger.attach(&cpu);
+
+        for _ in 0..20 {
+            debugger.step(&mut cpu);
+        }
+
+        let (pc, x, cycles) = (cpu.reg_pc, cpu.reg_x, cpu.cycles);
+
+        debugger.step(&mut cpu);
+        debugger.step(&mut cpu);
+
+        assert!(debugger.step_back(&mut cpu).unwrap());
+        assert!(debugger.step_back(&mut cpu).unwrap());
+
+        assert_eq!(cpu.instructions, 20);
+        assert_eq!(cpu.reg_pc, pc);
+        assert_eq!(cpu.reg_x, x);
+        assert_eq!(cpu.cycles, cycles);
+    }
+
+    #[test]
+    fn reverse_continue_to_breakpoint() {
+        let mut cpu = program();
+        let mut debugger = ReverseDebugger::new(16, 100);
+        run_to_end(&mut debugger, &mut cpu);
+
+        let hit = debugger.reverse_continue(&mut cpu, |cpu| cpu.reg_pc == 0x6003).unwrap();
+
+        assert_eq!(hit, Some(cpu.instructions));
+        assert_eq!(cpu.reg_pc, 0x6003);
+        assert_eq!(cpu.reg_x, 10);
+
+        // and once more lands on the previous iteration
+        debugger.reverse_continue(&mut cpu, |cpu| cpu.reg_pc == 0x6003).unwrap();
+        assert_eq!(cpu.reg_x, 9);
+    }
+
+    #[test]
+    fn reverse_continue_without_a_hit() {
+        let mut cpu = program();
+        let mut debugger = ReverseDebugger::new(16, 100);
+        run_to_end(&mut debugger, &mut cpu);
+
+        assert_eq!(debugger.reverse_continue(&mut cpu, |cpu| cpu.reg_pc == 0x1234).unwrap(), None);
+        assert_eq!(cpu.instructions, 0);
+        assert_eq!(cpu.reg_pc, 0x6000);
+    }
+
+    #[test]
+    fn last_write() {
+        let mut cpu = program();
+        let mut debugger = ReverseDebugger::new(16, 100);
+        run_to_end(&mut debugger, &mut cpu);
+
+        debugger.last_write(&mut cpu, 0x0010).unwrap().unwrap();
+        assert_eq!(cpu.reg_pc, 0x600d);
+        assert_eq!(cpu.memory.mem[0x0011], 0x00);
+
+        debugger.last_write(&mut cpu, 0x0020).unwrap().unwrap();
+        assert_eq!(cpu.reg_pc, 0x6005);
+        assert_eq!(cpu.memory.mem[0x0020], 10);
+        assert_eq!(cpu.memory.mem[0x0010], 0x00);
+    }
+}
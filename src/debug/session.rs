@@ -0,0 +1,317 @@
+use asm;
+use cpu::Cpu;
+use cpu::breakpoint::StopReason;
+
+use super::condition::Conditions;
+use super::expr::{ExprError, Symbols};
+use super::reverse::ReverseDebugger;
+
+const RTS_OPCODE: u8 = 0x60;
+
+const INES_MAGIC: &'static [u8] = b"NES\x1a";
+const INES_HEADER_LEN: usize = 16;
+const INES_TRAINER_LEN: usize = 512;
+const PRG_BANK_SIZE: usize = 0x4000;
+
+// everything a debugger front end drives: the machine, its symbols, breakpoint conditions and the history that
+// makes stepping backwards possible
+pub struct Session {
+    pub cpu: Cpu,
+    pub symbols: Symbols,
+    pub conditions: Conditions,
+    pub history: ReverseDebugger,
+}
+
+impl Session {
+    pub fn new(mut cpu: Cpu) -> Self {
+        cpu.trace = false;
+
+        let mut history = ReverseDebugger::default();
+        history.attach(&cpu);
+
+        Session {
+            cpu: cpu,
+            symbols: Symbols::new(),
+            conditions: Conditions::default(),
+            history: history,
+        }
+    }
+
+    pub fn step(&mut self) -> Result<Option<StopReason>, ExprError> {
+        self.run_until(|_, _| true)
+    }
+
+    // steps one instruction, and if that made a call (or an interrupt came in), runs until it's returned from
+    pub fn step_over(&mut self) -> Result<Option<StopReason>, ExprError> {
+        let depth = self.cpu.call_stack.depth();
+
+        self.run_until(|cpu, _| cpu.call_stack.depth() <= depth)
+    }
+
+    // runs until the current frame is returned from. with no frame to go by (the session started partway into a
+    // subroutine) that's the first rts that pops past where the stack is now
+    pub fn step_out(&mut self) -> Result<Option<StopReason>, ExprError> {
+        let depth = self.cpu.call_stack.depth();
+        let sp = self.cpu.reg_sp;
+
+        match depth {
+            0 => self.run_until(|cpu, opcode| opcode == RTS_OPCODE && cpu.reg_sp > sp),
+            _ => self.run_until(|cpu, _| cpu.call_stack.depth() < depth),
+        }
+    }
+
+    pub fn continue_(&mut self) -> Result<Option<StopReason>, ExprError> {
+        self.run_until(|_, _| false)
+    }
+
+    pub fn step_back(&mut self) -> Result<bool, String> {
+        self.history.step_back(&mut self.cpu).map_err(|err| err.to_string())
+    }
+
+    // runs backwards to the last place a breakpoint would have stopped, or to just before the last instruction
+    // that changed a watched range
+    pub fn reverse_continue(&mut self) -> Result<Option<u64>, String> {
+        let breakpoints = self.cpu.breakpoints.clone();
+        let watchpoints = self.cpu.memory.watchpoints.clone();
+
+        let watched = |cpu: &Cpu| -> Vec<u8> {
+            watchpoints.iter()
+                .filter(|point| point.enabled)
+                .flat_map(|point| (point.start as u32..point.end as u32 + 1).map(|addr| cpu.memory.peek_u8_at(&(addr as u16))))
+                .collect()
+        };
+
+        let current = watched(&self.cpu);
+        let hit = |cpu: &Cpu| {
+            let pc = cpu.reg_pc;
+
+            breakpoints.iter().any(|point| point.enabled && point.start <= pc && pc <= point.end) || watched(cpu) != current
+        };
+
+        self.history.reverse_continue(&mut self.cpu, hit).map_err(|err| err.to_string())
+    }
+
+    pub fn symbol_at(&self, addr: u16) -> Option<&str> {
+        self.symbols.iter().find(|&(_, val)| *val == addr as i64).map(|(name, _)| name.as_str())
+    }
+
+    // runs whole instructions, recording history, until `done` says so (given the state after the instruction and
+    // its opcode) or a breakpoint or watchpoint stops things first. `None` means `done` finished it
+    pub fn run_until<F>(&mut self, mut done: F) -> Result<Option<StopReason>, ExprError>
+        where F: FnMut(&Cpu, u8) -> bool
+    {
+        self.cpu.stop_reason = None;
+
+        // whatever we're sitting on has already been reported
+        self.cpu.skip_breakpoint();
+
+        loop {
+            let pc = self.cpu.reg_pc;
+            let opcode = self.cpu.memory.peek_u8_at(&pc);
+            let instructions = self.cpu.instructions;
+
+            let running = self.history.step(&mut self.cpu);
+
+            // `done` sees every instruction that ran, even one a watchpoint then stops on
+            let finished = self.cpu.instructions != instructions && done(&self.cpu, opcode);
+
+            if let Some(reason) = self.cpu.stop_reason.take() {
+                if self.conditions.should_stop(&self.cpu, &self.symbols, &reason)? {
+                    return Ok(Some(reason));
+                }
+            }
+
+            if !running {
+                return Ok(Some(StopReason::Halted));
+            }
+
+            if finished {
+                return Ok(None);
+            }
+        }
+    }
+}
+
+// puts a program in memory and resets onto it. iNES images have their prg rom mapped at $8000 like an nrom cart
+// (a single bank is mirrored at $c000, and bigger images get their first and last banks, which is where most
+// mappers boot from) and start at their own reset vector; anything else is a raw binary loaded at `load_addr`
+pub fn load_image(cpu: &mut Cpu, bytes: &[u8], load_addr: u16) -> Result<(), String> {
+    if !bytes.starts_with(INES_MAGIC) {
+        cpu.load_program(load_addr, bytes);
+        cpu.reset();
+
+        return Ok(());
+    }
+
+    if bytes.len() < INES_HEADER_LEN {
+        return Err(String::from("truncated iNES header"));
+    }
+
+    let banks = bytes[4] as usize;
+    let start = INES_HEADER_LEN + if bytes[6] & 0x04 != 0 { INES_TRAINER_LEN } else { 0 };
+    let end = start + banks * PRG_BANK_SIZE;
+
+    if banks == 0 || bytes.len() < end {
+        return Err(String::from("iNES image is missing prg rom"));
+    }
+
+    let first = &bytes[start..start + PRG_BANK_SIZE];
+    let last = &bytes[end - PRG_BANK_SIZE..end];
+
+    cpu.memory.write_at(&0x8000, first);
+    cpu.memory.write_at(&0xc000, last);
+    cpu.reset();
+
+    Ok(())
+}
+
+// symbol files are `name = value` lines, the same thing the assembler writes out; `;` starts a comment
+pub fn parse_symbols(text: &str) -> Result<Symbols, String> {
+    let mut symbols = Symbols::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split(';').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut parts = line.splitn(2, '=');
+        let name = parts.next().unwrap_or("").trim();
+        let val = parts.next().map(|val| val.trim()).and_then(asm::parse_number);
+
+        match val {
+            Some(val) if !name.is_empty() => {
+                symbols.insert(String::from(name), val);
+            }
+            _ => return Err(format!("line {}: expected 'name = value'", i + 1)),
+        }
+    }
+
+    Ok(symbols)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{load_image, parse_symbols, Session};
+    use cpu::Cpu;
+    use cpu::breakpoint::StopReason;
+
+    fn new_session() -> Session {
+        let mut cpu = Cpu::new();
+
+        cpu.load_program(0x6000,
+                         &[// jsr $6008
+                           0x20, 0x08, 0x60,
+                           // ldx #$01
+                           0xa2, 0x01,
+                           // jmp $ffff
+                           0x4c, 0xff, 0xff,
+                           // lda #$05
+                           0xa9, 0x05,
+                           // sta $10
+                           0x85, 0x10,
+                           // rts
+                           0x60]);
+        cpu.reset();
+
+        Session::new(cpu)
+    }
+
+    #[test]
+    fn step_over_and_out() {
+        let mut session = new_session();
+
+        assert_eq!(session.step_over().unwrap(), None);
+        assert_eq!(session.cpu.reg_pc, 0x6003);
+        assert_eq!(session.cpu.reg_acc, 0x05);
+
+        let mut session = new_session();
+        session.step().unwrap();
+        assert_eq!(session.cpu.reg_pc, 0x6008);
+
+        assert_eq!(session.cpu.call_stack.depth(), 1);
+        assert_eq!(session.cpu.call_stack.frames()[0].caller, 0x6000);
+
+        assert_eq!(session.step_out().unwrap(), None);
+        assert_eq!(session.cpu.reg_pc, 0x6003);
+        assert_eq!(session.cpu.call_stack.depth(), 0);
+
+        // the frame comes back with the state that had it
+        session.step_back().unwrap();
+        assert_eq!(session.cpu.reg_pc, 0x600c);
+        assert_eq!(session.cpu.call_stack.depth(), 1);
+    }
+
+    #[test]
+    fn step_over_an_interrupt() {
+        let mut session = new_session();
+
+        // nmi handler: inc $11 / rti
+        session.cpu.memory.write_at(&0x7000, &[0xe6, 0x11, 0x40]);
+        session.cpu.memory.write_at(&0xfffa, &[0x00, 0x70]);
+        session.step().unwrap();
+
+        // the handler runs through, rather than the step stopping in it, and leaves pc where the interrupt came in
+        session.cpu.set_nmi(true);
+        assert_eq!(session.step_over().unwrap(), None);
+
+        assert_eq!(session.cpu.reg_pc, 0x6008);
+        assert_eq!(session.cpu.memory.peek_u8_at(&0x0011), 0x01);
+        assert_eq!(session.cpu.call_stack.depth(), 1);
+    }
+
+    #[test]
+    fn stops_inside_step_over() {
+        let mut session = new_session();
+        let id = session.cpu.breakpoints.add(0x600a, 0x600a);
+
+        assert_eq!(session.step_over().unwrap(), Some(StopReason::Breakpoint { id: id, pc: 0x600a }));
+
+        // continuing from a breakpoint doesn't hit it again straight away
+        assert_eq!(session.continue_().unwrap(), Some(StopReason::Halted));
+        assert_eq!(session.cpu.reg_x, 0x01);
+    }
+
+    #[test]
+    fn reverse_continue() {
+        let mut session = new_session();
+        session.cpu.breakpoints.add(0x600a, 0x600a);
+
+        session.continue_().unwrap();
+        session.continue_().unwrap();
+
+        assert!(session.reverse_continue().unwrap().is_some());
+        assert_eq!(session.cpu.reg_pc, 0x600a);
+        assert_eq!(session.cpu.memory.peek_u8_at(&0x0010), 0x00);
+    }
+
+    #[test]
+    fn load_ines() {
+        let mut image = vec![0; 16 + 0x8000];
+        image[..4].copy_from_slice(b"NES\x1a");
+        image[4] = 2;
+
+        // reset vector in the second bank
+        image[16 + 0x7ffc] = 0x34;
+        image[16 + 0x7ffd] = 0xc2;
+        image[16 + 0x4000] = 0xea;
+
+        let mut cpu = Cpu::new();
+        load_image(&mut cpu, &image, 0x6000).unwrap();
+
+        assert_eq!(cpu.reg_pc, 0xc234);
+        assert_eq!(cpu.memory.peek_u8_at(&0xc000), 0xea);
+
+        image.truncate(0x5000);
+        assert!(load_image(&mut Cpu::new(), &image, 0x6000).is_err());
+    }
+
+    #[test]
+    fn symbols() {
+        let symbols = parse_symbols("start = $6000 ; entry point\n\ncount=%101\n").unwrap();
+
+        assert_eq!(symbols["start"], 0x6000);
+        assert_eq!(symbols["count"], 5);
+        assert!(parse_symbols("nope").is_err());
+    }
+}
@@ -0,0 +1,127 @@
+use std::fmt;
+use std::path::Path;
+
+use asm;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Location {
+    pub addr: u16,
+    pub file: String,
+    pub line: u32,
+}
+
+// which source line each instruction came from. on disk it's one `<addr> <file>:<line>` per line, e.g.
+// `$6000 src/main.s:12`, as `asm --dbg` writes it; `;` starts a comment
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SourceMap {
+    locations: Vec<Location>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        SourceMap::default()
+    }
+
+    // relative file names are taken to be relative to `base`, normally the directory the map was read from
+    pub fn parse(text: &str, base: Option<&Path>) -> Result<SourceMap, String> {
+        let mut map = SourceMap::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let err = || format!("line {}: expected '<addr> <file>:<line>'", i + 1);
+
+            let mut parts = line.splitn(2, char::is_whitespace);
+            let addr = parts.next().and_then(asm::parse_number).ok_or_else(&err)?;
+            let location = parts.next().map(|location| location.trim()).ok_or_else(&err)?;
+
+            let split = location.rfind(':').ok_or_else(&err)?;
+            let file = &location[..split];
+            let line_num = location[split + 1..].parse().map_err(|_| err())?;
+
+            let file = match base {
+                Some(base) if Path::new(file).is_relative() => base.join(file).to_string_lossy().into_owned(),
+                _ => String::from(file),
+            };
+
+            map.add(addr as u16, &file, line_num);
+        }
+
+        Ok(map)
+    }
+
+    pub fn add(&mut self, addr: u16, file: &str, line: u32) {
+        self.locations.push(Location {
+            addr: addr,
+            file: String::from(file),
+            line: line,
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.locations.is_empty()
+    }
+
+    pub fn locations(&self) -> &[Location] {
+        &self.locations
+    }
+
+    pub fn location_of(&self, addr: u16) -> Option<&Location> {
+        self.locations.iter().find(|location| location.addr == addr)
+    }
+
+    // the first address for `line`, or failing that for the nearest line after it with any code on it, the way
+    // editors expect a breakpoint on a blank line or comment to slide down
+    pub fn addr_of(&self, file: &str, line: u32) -> Option<&Location> {
+        self.locations
+            .iter()
+            .filter(|location| same_file(&location.file, file) && location.line >= line)
+            .min_by_key(|location| (location.line, location.addr))
+    }
+}
+
+impl fmt::Display for SourceMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for location in &self.locations {
+            writeln!(f, "${:04x} {}:{}", location.addr, location.file, location.line)?;
+        }
+
+        Ok(())
+    }
+}
+
+// editors hand over absolute paths while maps are often written with relative ones, so either being a
+// path-suffix of the other is good enough
+fn same_file(a: &str, b: &str) -> bool {
+    let (a, b) = (Path::new(a), Path::new(b));
+
+    a == b || a.ends_with(b) || b.ends_with(a)
+}
+
+#[cfg(test)]
+mod test {
+    use super::SourceMap;
+    use std::path::Path;
+
+    #[test]
+    fn lookups() {
+        let map = SourceMap::parse("$6000 main.s:3 ; entry\n$6002 main.s:4\n$6005 main.s:7\n$6010 lib/util.s:2\n",
+                                   Some(Path::new("/src")))
+            .unwrap();
+
+        assert_eq!(map.location_of(0x6002).unwrap().line, 4);
+        assert_eq!(map.location_of(0x6010).unwrap().file, "/src/lib/util.s");
+        assert!(map.location_of(0x6003).is_none());
+
+        assert_eq!(map.addr_of("/src/main.s", 4).unwrap().addr, 0x6002);
+        assert_eq!(map.addr_of("main.s", 5).unwrap().addr, 0x6005);
+        assert_eq!(map.addr_of("util.s", 1).unwrap().addr, 0x6010);
+        assert!(map.addr_of("main.s", 8).is_none());
+
+        assert_eq!(SourceMap::parse(&map.to_string(), None).unwrap(), map);
+        assert!(SourceMap::parse("$6000 main.s", None).is_err());
+    }
+}
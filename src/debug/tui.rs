@@ -0,0 +1,628 @@
+use std::io;
+use std::io::{BufRead, Read, Write};
+use std::process::{Command, Stdio};
+
+use cpu::Cpu;
+use cpu::breakpoint::StopReason;
+
+use super::disasm;
+use super::expr::ExprError;
+use super::repl::{self, Control, Repl};
+use super::session::Session;
+
+const DISASM_WIDTH: usize = 44;
+const MEMORY_ROWS: usize = 6;
+const OUTPUT_ROWS: usize = 3;
+const BYTES_PER_ROW: u16 = 16;
+const STACK_PAGE: u16 = 0x0100;
+
+const DEFAULT_SIZE: (usize, usize) = (80, 24);
+
+const INVERSE: &'static str = "\x1b[7m";
+const BOLD: &'static str = "\x1b[1m";
+const DIM: &'static str = "\x1b[2m";
+const CHANGED: &'static str = "\x1b[1;33m";
+const RESET: &'static str = "\x1b[0m";
+
+const KEYS: &'static str = "s step  n next  o finish  c continue  b back  r rev-cont  \
+                            space break  \u{2191}\u{2193} pgup pgdn memory  : command  q quit";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Key {
+    Char(char),
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Other,
+}
+
+// a full-screen front end over the same session the repl drives. everything it shows is built by `render`, which
+// knows nothing about the terminal, so the layout can be tested as plain strings
+pub struct Tui {
+    repl: Repl,
+
+    // the machine as it was before the last key, for highlighting what that key changed
+    before: Cpu,
+
+    disasm_top: u16,
+    mem_addr: u16,
+    output: Vec<String>,
+}
+
+impl Tui {
+    pub fn new(session: Session) -> Self {
+        let before = session.cpu.fork();
+        let pc = session.cpu.reg_pc;
+
+        Tui {
+            repl: Repl::new(session),
+            before: before,
+            disasm_top: pc,
+            mem_addr: 0,
+            output: Vec::new(),
+        }
+    }
+
+    pub fn session(&self) -> &Session {
+        &self.repl.session
+    }
+
+    pub fn handle_key(&mut self, key: Key) -> Control {
+        match key {
+            Key::Up => self.mem_addr = self.mem_addr.wrapping_sub(BYTES_PER_ROW),
+            Key::Down => self.mem_addr = self.mem_addr.wrapping_add(BYTES_PER_ROW),
+            Key::PageUp => self.mem_addr = self.mem_addr.wrapping_sub(BYTES_PER_ROW * MEMORY_ROWS as u16),
+            Key::PageDown => self.mem_addr = self.mem_addr.wrapping_add(BYTES_PER_ROW * MEMORY_ROWS as u16),
+
+            Key::Char('q') | Key::Char('\x03') => return Control::Quit,
+
+            Key::Char('s') => self.motion(Session::step),
+            Key::Char('n') => self.motion(Session::step_over),
+            Key::Char('o') => self.motion(Session::step_out),
+            Key::Char('c') => self.motion(Session::continue_),
+            Key::Char('b') => {
+                self.before = self.repl.session.cpu.fork();
+
+                match self.repl.session.step_back() {
+                    Ok(true) => {}
+                    Ok(false) => self.say("no history before this point"),
+                    Err(err) => self.say(&format!("error: {}", err)),
+                }
+            }
+            Key::Char('r') => {
+                self.before = self.repl.session.cpu.fork();
+
+                match self.repl.session.reverse_continue() {
+                    Ok(Some(_)) => {}
+                    Ok(None) => self.say("reached the start of history"),
+                    Err(err) => self.say(&format!("error: {}", err)),
+                }
+            }
+            Key::Char(' ') => self.toggle_breakpoint(),
+
+            _ => {}
+        }
+
+        Control::Continue
+    }
+
+    // runs a repl command and keeps whatever it printed
+    pub fn command(&mut self, line: &str) -> Control {
+        self.before = self.repl.session.cpu.fork();
+
+        let mut out = Vec::new();
+        let control = self.repl.execute(line, &mut out).unwrap_or(Control::Continue);
+
+        for line in String::from_utf8_lossy(&out).lines() {
+            self.say(line);
+        }
+
+        control
+    }
+
+    fn motion<F>(&mut self, motion: F)
+        where F: Fn(&mut Session) -> Result<Option<StopReason>, ExprError>
+    {
+        self.before = self.repl.session.cpu.fork();
+
+        let stop = motion(&mut self.repl.session);
+
+        let log: Vec<String> = self.repl.session.conditions.log.drain(..).collect();
+        for line in log {
+            self.say(&line);
+        }
+
+        match stop {
+            Ok(Some(reason)) => self.say(&repl::describe_stop(&reason)),
+            Ok(None) => {}
+            Err(err) => self.say(&format!("error: {}", err)),
+        }
+    }
+
+    fn toggle_breakpoint(&mut self) {
+        let pc = self.repl.session.cpu.reg_pc;
+        let existing = self.repl.session.cpu.breakpoints.iter().find(|point| point.start == pc && point.end == pc).map(|point| point.id);
+
+        match existing {
+            Some(id) => {
+                self.repl.session.cpu.breakpoints.remove(id);
+                self.say(&format!("removed breakpoint {}", id));
+            }
+            None => {
+                let id = self.repl.session.cpu.breakpoints.add(pc, pc);
+                self.say(&format!("breakpoint {} at ${:04x}", id, pc));
+            }
+        }
+    }
+
+    fn say(&mut self, line: &str) {
+        self.output.push(String::from(line));
+
+        let excess = self.output.len().saturating_sub(OUTPUT_ROWS);
+        self.output.drain(..excess);
+    }
+
+    // lays out the whole screen as `height` lines of at most `width` visible columns
+    pub fn render(&mut self, width: usize, height: usize) -> Vec<String> {
+        // title, memory pane, output pane and key line around the panes in the middle
+        let pane_rows = height.saturating_sub(1 + 1 + MEMORY_ROWS + 1 + OUTPUT_ROWS + 1);
+
+        let left = self.disasm_pane(pane_rows);
+
+        let mut right = self.register_pane();
+        let stack_rows = pane_rows.saturating_sub(right.len()) / 2;
+        right.extend(self.stack_pane(stack_rows));
+        let breakpoint_rows = pane_rows.saturating_sub(right.len());
+        right.extend(self.breakpoint_pane(breakpoint_rows));
+
+        let cpu = &self.repl.session.cpu;
+        let mut screen = vec![format!("{}{}{}", INVERSE, pad(&format!(" {}", repl::format_registers(cpu)), width), RESET)];
+
+        for row in 0..pane_rows {
+            let left = left.get(row).map(|line| line.as_str()).unwrap_or("");
+            let right = right.get(row).map(|line| line.as_str()).unwrap_or("");
+
+            screen.push(format!("{}{}", pad(left, DISASM_WIDTH), right));
+        }
+
+        screen.extend(self.memory_pane());
+        screen.push(heading("output"));
+
+        for row in 0..OUTPUT_ROWS {
+            screen.push(self.output.get(row).cloned().unwrap_or_default());
+        }
+
+        screen.push(format!("{}{}{}", DIM, KEYS, RESET));
+
+        screen.iter().map(|line| truncate(line, width)).collect()
+    }
+
+    fn disasm_pane(&mut self, rows: usize) -> Vec<String> {
+        let session = &self.repl.session;
+        let pc = session.cpu.reg_pc;
+        let lines = rows.saturating_sub(1);
+
+        // keep the listing still while pc moves around inside it, so stepping through a loop doesn't scroll
+        let mut listing = disasm::disassemble_range(&session.cpu, self.disasm_top, lines);
+        if !listing.iter().take(lines.saturating_sub(2)).any(|line| line.addr == pc) {
+            self.disasm_top = pc;
+            listing = disasm::disassemble_range(&session.cpu, pc, lines);
+        }
+
+        let mut pane = vec![heading("disassembly")];
+
+        for line in listing {
+            let breakpoint = session.cpu.breakpoints.iter().any(|point| point.enabled && point.start <= line.addr && line.addr <= point.end);
+
+            let bytes: Vec<String> = line.bytes.iter().map(|b| format!("{:02x}", b)).collect();
+            let label = session.symbol_at(line.addr).map(|name| format!("{}:", name)).unwrap_or_default();
+            let text = format!("{}{} ${:04x}  {:<8}  {:<10} {}",
+                               if line.addr == pc { '>' } else { ' ' },
+                               if breakpoint { '*' } else { ' ' },
+                               line.addr,
+                               bytes.join(" "),
+                               line.text,
+                               label);
+
+            pane.push(match line.addr == pc {
+                true => format!("{}{}{}", INVERSE, pad(&text, DISASM_WIDTH - 1), RESET),
+                false => text,
+            });
+        }
+
+        pane
+    }
+
+    fn register_pane(&self) -> Vec<String> {
+        let cpu = &self.repl.session.cpu;
+        let before = &self.before;
+
+        let regs = [("PC", format!("{:04x}", cpu.reg_pc), cpu.reg_pc != before.reg_pc),
+                    ("A", format!("{:02x}", cpu.reg_acc), cpu.reg_acc != before.reg_acc),
+                    ("X", format!("{:02x}", cpu.reg_x), cpu.reg_x != before.reg_x),
+                    ("Y", format!("{:02x}", cpu.reg_y), cpu.reg_y != before.reg_y),
+                    ("SP", format!("{:02x}", cpu.reg_sp), cpu.reg_sp != before.reg_sp)];
+
+        let regs: Vec<String> = regs.iter()
+            .map(|&(name, ref val, changed)| format!("{} {}", name, highlight(val, changed)))
+            .collect();
+
+        let status = &cpu.reg_status;
+        let flags = [('N', status.negative),
+                     ('V', status.overflow),
+                     ('-', true),
+                     ('B', status.brk),
+                     ('D', status.decimal_mode),
+                     ('I', status.irq_disable),
+                     ('Z', status.zero),
+                     ('C', status.carry)];
+
+        let flags: Vec<String> = flags.iter()
+            .map(|&(name, set)| match set {
+                true => format!("{}{}{}", BOLD, name, RESET),
+                false => format!("{}{}{}", DIM, name.to_ascii_lowercase(), RESET),
+            })
+            .collect();
+
+        let status: u8 = cpu.reg_status.clone().into();
+
+        vec![heading("registers"),
+             regs.join("  "),
+             format!("P {:02x}  {}  CYC {}", status, flags.join(" "), cpu.cycles)]
+    }
+
+    // page 1 from just above sp, top of stack first
+    fn stack_pane(&self, rows: usize) -> Vec<String> {
+        let cpu = &self.repl.session.cpu;
+        let mut pane = vec![heading("stack")];
+
+        let top = cpu.reg_sp as u16 + 1;
+        for offset in top..(top + rows.saturating_sub(1) as u16).min(0x100) {
+            let addr = STACK_PAGE + offset;
+            let val = cpu.memory.peek_u8_at(&addr);
+            let changed = val != self.before.memory.peek_u8_at(&addr);
+
+            pane.push(format!("${:04x}  {}", addr, highlight(&format!("{:02x}", val), changed)));
+        }
+
+        pane
+    }
+
+    fn breakpoint_pane(&self, rows: usize) -> Vec<String> {
+        let cpu = &self.repl.session.cpu;
+        let mut pane = vec![heading("breakpoints")];
+
+        for point in cpu.breakpoints.iter() {
+            pane.push(format!("{:>3} {} ${:04x}-${:04x}  hits: {}",
+                              point.id,
+                              if point.enabled { '*' } else { ' ' },
+                              point.start,
+                              point.end,
+                              point.hits));
+        }
+
+        for point in cpu.memory.watchpoints.iter() {
+            pane.push(format!("{:>3} {} ${:04x}-${:04x}  {:?}",
+                              format!("w{}", point.id),
+                              if point.enabled { '*' } else { ' ' },
+                              point.start,
+                              point.end,
+                              point.kind));
+        }
+
+        pane.truncate(rows);
+        pane
+    }
+
+    fn memory_pane(&self) -> Vec<String> {
+        let cpu = &self.repl.session.cpu;
+        let mut pane = vec![heading("memory")];
+
+        for row in 0..MEMORY_ROWS as u16 {
+            let start = self.mem_addr.wrapping_add(row * BYTES_PER_ROW);
+
+            let bytes: Vec<String> = (0..BYTES_PER_ROW)
+                .map(|i| {
+                    let addr = start.wrapping_add(i);
+                    let val = cpu.memory.peek_u8_at(&addr);
+
+                    match val != self.before.memory.peek_u8_at(&addr) {
+                        true => format!("{}{:02x}{}", INVERSE, val, RESET),
+                        false => format!("{:02x}", val),
+                    }
+                })
+                .collect();
+
+            let ascii: String = (0..BYTES_PER_ROW)
+                .map(|i| match cpu.memory.peek_u8_at(&start.wrapping_add(i)) {
+                    b @ 0x20..=0x7e => b as char,
+                    _ => '.',
+                })
+                .collect();
+
+            pane.push(format!("${:04x}  {}  {}", start, bytes.join(" "), ascii));
+        }
+
+        pane
+    }
+}
+
+fn heading(title: &str) -> String {
+    format!("{}\u{2500} {}{}", DIM, title, RESET)
+}
+
+fn highlight(text: &str, changed: bool) -> String {
+    match changed {
+        true => format!("{}{}{}", CHANGED, text, RESET),
+        false => String::from(text),
+    }
+}
+
+// the number of columns `text` takes up on screen, not counting escape sequences
+fn visible_len(text: &str) -> usize {
+    let mut len = 0;
+    let mut escape = false;
+
+    for c in text.chars() {
+        match c {
+            '\x1b' => escape = true,
+            'm' if escape => escape = false,
+            _ if escape => {}
+            _ => len += 1,
+        }
+    }
+
+    len
+}
+
+fn pad(text: &str, width: usize) -> String {
+    let len = visible_len(text);
+
+    match len < width {
+        true => format!("{}{}", text, " ".repeat(width - len)),
+        false => truncate(text, width),
+    }
+}
+
+// cuts `text` down to `width` visible columns, keeping escape sequences intact
+fn truncate(text: &str, width: usize) -> String {
+    if visible_len(text) <= width {
+        return String::from(text);
+    }
+
+    let mut out = String::new();
+    let mut len = 0;
+    let mut escape = false;
+
+    for c in text.chars() {
+        match c {
+            '\x1b' => escape = true,
+            'm' if escape => escape = false,
+            _ if escape => {}
+            _ if len == width => continue,
+            _ => len += 1,
+        }
+
+        out.push(c);
+    }
+
+    out.push_str(RESET);
+    out
+}
+
+// puts the controlling terminal into raw mode on the alternate screen for as long as it lives. `stty` does the
+// termios work so there's nothing platform specific in here beyond the escape codes
+struct Terminal {
+    saved: String,
+}
+
+impl Terminal {
+    fn enter() -> io::Result<Terminal> {
+        let saved = stty(&["-g"])?;
+        stty(&["raw", "-echo"])?;
+
+        print!("\x1b[?1049h\x1b[?25l");
+        io::stdout().flush()?;
+
+        Ok(Terminal { saved: saved.trim().to_string() })
+    }
+
+    fn size(&self) -> (usize, usize) {
+        let size = stty(&["size"]).unwrap_or_default();
+        let dims: Vec<usize> = size.split_whitespace().filter_map(|dim| dim.parse().ok()).collect();
+
+        match dims.as_slice() {
+            &[rows, cols] if rows > 0 && cols > 0 => (cols, rows),
+            _ => DEFAULT_SIZE,
+        }
+    }
+
+    fn draw(&self, screen: &[String]) -> io::Result<()> {
+        let mut frame = String::from("\x1b[H");
+
+        for (i, line) in screen.iter().enumerate() {
+            if i > 0 {
+                frame.push_str("\r\n");
+            }
+
+            frame.push_str(line);
+            frame.push_str("\x1b[K");
+        }
+
+        frame.push_str("\x1b[J");
+
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        out.write_all(frame.as_bytes())?;
+        out.flush()
+    }
+
+    fn read_key(&self) -> io::Result<Option<Key>> {
+        let stdin = io::stdin();
+        let mut input = stdin.lock();
+
+        let byte = match read_byte(&mut input)? {
+            Some(byte) => byte,
+            None => return Ok(None),
+        };
+
+        if byte != 0x1b {
+            return Ok(Some(Key::Char(byte as char)));
+        }
+
+        // csi sequences for the arrow and page keys
+        if read_byte(&mut input)? != Some(b'[') {
+            return Ok(Some(Key::Other));
+        }
+
+        let key = match read_byte(&mut input)? {
+            Some(b'A') => Key::Up,
+            Some(b'B') => Key::Down,
+            Some(b'5') if read_byte(&mut input)? == Some(b'~') => Key::PageUp,
+            Some(b'6') if read_byte(&mut input)? == Some(b'~') => Key::PageDown,
+            _ => Key::Other,
+        };
+
+        Ok(Some(key))
+    }
+
+    // drops back to a cooked terminal on the bottom line to read a command
+    fn read_line(&self, prompt: &str, height: usize) -> io::Result<String> {
+        stty(&[&self.saved])?;
+        print!("\x1b[{};1H\x1b[K\x1b[?25h{}", height, prompt);
+        io::stdout().flush()?;
+
+        let mut line = String::new();
+        let stdin = io::stdin();
+        stdin.lock().read_line(&mut line)?;
+
+        stty(&["raw", "-echo"])?;
+        print!("\x1b[?25l");
+
+        Ok(line.trim().to_string())
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = stty(&[&self.saved]);
+
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+    }
+}
+
+fn read_byte<R: Read>(input: &mut R) -> io::Result<Option<u8>> {
+    let mut buf = [0];
+
+    match input.read(&mut buf)? {
+        0 => Ok(None),
+        _ => Ok(Some(buf[0])),
+    }
+}
+
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty").args(args).stdin(Stdio::inherit()).output()?;
+
+    match output.status.success() {
+        true => Ok(String::from_utf8_lossy(&output.stdout).into_owned()),
+        false => Err(io::Error::other(String::from_utf8_lossy(&output.stderr).trim().to_string())),
+    }
+}
+
+pub fn run(session: Session) -> io::Result<()> {
+    let terminal = Terminal::enter()?;
+    let mut tui = Tui::new(session);
+
+    loop {
+        let (width, height) = terminal.size();
+        terminal.draw(&tui.render(width, height))?;
+
+        let control = match terminal.read_key()? {
+            Some(Key::Char(':')) => {
+                let line = terminal.read_line(":", height)?;
+                tui.command(&line)
+            }
+            Some(key) => tui.handle_key(key),
+            None => Control::Quit,
+        };
+
+        if let Control::Quit = control {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{visible_len, Key, Tui};
+    use cpu::Cpu;
+    use debug::session::Session;
+
+    fn tui() -> Tui {
+        let mut cpu = Cpu::new();
+
+        cpu.load_program(0x6000,
+                         &[// ldx #$00
+                           0xa2, 0x00,
+                           // inx
+                           0xe8,
+                           // stx $20
+                           0x86, 0x20,
+                           // bne $6002
+                           0xd0, 0xfb]);
+        cpu.reset();
+
+        Tui::new(Session::new(cpu))
+    }
+
+    #[test]
+    fn render_fits() {
+        let mut tui = tui();
+        let screen = tui.render(80, 24);
+
+        assert_eq!(screen.len(), 24);
+        assert!(screen.iter().all(|line| visible_len(line) <= 80));
+
+        let text = screen.join("\n");
+        assert!(text.contains(">  $6000  a2 00     ldx #$0"));
+        assert!(text.contains("$01fe  "));
+        assert!(text.contains("$0000  00 00"));
+    }
+
+    #[test]
+    fn highlights_changes() {
+        let mut tui = tui();
+
+        for _ in 0..3 {
+            tui.handle_key(Key::Char('s'));
+        }
+
+        let screen = tui.render(80, 24).join("\n");
+
+        // stx $20 just wrote the byte at $0020
+        assert_eq!(tui.session().cpu.reg_pc, 0x6005);
+        assert!(screen.contains("$0020  \x1b[7m01\x1b[0m 00"));
+        assert!(screen.contains("PC \x1b[1;33m6005"));
+
+        tui.handle_key(Key::Char('s'));
+        let screen = tui.render(80, 24).join("\n");
+        assert!(screen.contains("$0020  01 00"));
+    }
+
+    #[test]
+    fn breakpoints_and_commands() {
+        let mut tui = tui();
+
+        tui.command("s");
+        tui.handle_key(Key::Char(' '));
+        tui.handle_key(Key::Char('c'));
+
+        assert_eq!(tui.session().cpu.reg_pc, 0x6002);
+        assert!(tui.render(80, 24).join("\n").contains("  1 * $6002-$6002  hits: 1"));
+
+        tui.handle_key(Key::Char(' '));
+        assert!(tui.session().cpu.breakpoints.iter().next().is_none());
+    }
+}
@@ -6,11 +6,24 @@ extern crate lazy_static;
 mod cpu;
 mod asm;
 mod util;
+mod debug;
+
+use std::env;
 
 fn main() {
-    let mut cpu = cpu::Cpu::new();
+    let args: Vec<String> = env::args().collect();
+
+    match args.get(1).map(|arg| arg.as_str()) {
+        Some("debug") => debug::repl::main(&args[2..]),
+        Some("dap") => debug::dap::main(),
+        Some("asm") => asm::cli::main(&args[2..]),
+        Some("link") => asm::cli::link(&args[2..]),
+        _ => {
+            let mut cpu = cpu::Cpu::new();
 
-    cpu.run();
+            cpu.run();
+        }
+    }
 }
 
 #[cfg(test)]
@@ -19,28 +32,26 @@ pub mod test {
     use std::io;
     use std::io::{ Read, Seek };
     use super::cpu;
+    use super::asm;
 
     #[test]
     fn basic_program() {
-        let program = &[// LDA #$01
-                        0xa9,
-                        0x01,
-                        // STA $0200
-                        0x8d,
-                        0x00,
-                        0x02,
-                        // LDA #$05
-                        0xa9,
-                        0x05,
-                        // STA $0201
-                        0x8d,
-                        0x01,
-                        0x02];
+        let program = asm::Parser::new(0x6000)
+            .assemble("
+                LDA #$01
+                STA $0200
+                LDA #$05
+                STA $0201
+            ")
+            .unwrap();
 
         let mut cpu = cpu::Cpu::new();
-        cpu.load_program(0x6000, program);
+        cpu.load_program(0x6000, &program);
 
         cpu.run();
+
+        assert_eq!(cpu.memory.peek_u8_at(&0x0200), 0x01);
+        assert_eq!(cpu.memory.peek_u8_at(&0x0201), 0x05);
     }
 
     #[test]